@@ -0,0 +1,15 @@
+#![no_main]
+
+use bluez_hci::codec::{EventBody, Packet};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(packet) = Packet::parse(data) else { return };
+    if let Packet::Event { code, params } = packet {
+        if let Ok(body) = EventBody::parse(code, params) {
+            if let Some(handle) = body.handle() {
+                assert!(body.has_handle(handle));
+            }
+        }
+    }
+});
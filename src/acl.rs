@@ -0,0 +1,77 @@
+use libc::EIO;
+use std::io::{Error, Result};
+
+use super::codec::{self, Packet};
+use super::filter::HciFilter;
+use super::socket::{HCI_ACLDATA_PKT, Socket, Timestamp};
+use super::trace;
+
+const HCI_MAX_FRAME_SIZE: usize = 1028;
+
+/// ACL data packet received from the controller
+#[derive(Clone, Debug)]
+pub struct AclPacket {
+    pub handle: u16,
+    /// Packet boundary flag
+    pub packet_boundary: u8,
+    /// Broadcast flag
+    pub broadcast: u8,
+    pub data: Box<[u8]>,
+}
+
+impl Socket {
+    /// Receive the next ACL data packet, discarding packets of other types.
+    ///
+    /// The socket filter passes only ACL data packets for the call, so no events are read and
+    /// dropped meanwhile, and the filter is restored afterwards. Events waited for by other
+    /// calls need another socket while this one waits for data. The result of the receive is
+    /// returned even if the filter cannot be restored; that failure is only logged.
+    ///
+    /// If host flow control is enabled, the packet is acknowledged to the controller with Host
+    /// Number Of Completed Packets before it is returned.
+    pub fn recv_acl(&mut self) -> Result<AclPacket> {
+        let old_filter = self.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_ACLDATA_PKT)?;
+        self.set_filter(&filter)?;
+
+        let result = self.recv_acl_filtered();
+
+        if let Err(e) = self.set_filter(&old_filter) {
+            trace::filter_restore_failed(&e);
+        }
+        result
+    }
+
+    /// Receive the next ACL data packet, like `recv_acl`, and return it with the time it was
+    /// received.
+    pub fn recv_acl_timestamped(&mut self) -> Result<(AclPacket, Timestamp)> {
+        let packet = self.recv_acl()?;
+        let timestamp = self.last_event_timestamp().ok_or_else(|| Error::from_raw_os_error(EIO))?;
+        Ok((packet, timestamp))
+    }
+
+    /// Receive the next ACL data packet through a filter passing them.
+    fn recv_acl_filtered(&mut self) -> Result<AclPacket> {
+        let mut buf = [0u8; HCI_MAX_FRAME_SIZE + 1];
+        loop {
+            let size = self.recv_timestamped(&mut buf)?;
+            let packet = match Packet::parse(&buf[..size]) {
+                Ok(Packet::Acl { handle, packet_boundary, broadcast, data }) =>
+                    AclPacket { handle, packet_boundary, broadcast, data: data.into() },
+                Ok(_) | Err(codec::Error::UnknownPacketType(_)) => continue,
+                Err(err) => {
+                    self.stats.malformed();
+                    return Err(err.into());
+                }
+            };
+            self.stats.acl_received(packet.data.len());
+
+            if self.host_flow_control {
+                self.host_number_of_completed_packets(&[(packet.handle, 1)])?;
+            }
+
+            return Ok(packet);
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! Advertising and extended inquiry response data.
+//!
+//! Both are sequences of structures, each made of a length byte, a type byte and data.
+
+use bitflags::bitflags;
+use std::io::{Error, Result, Write};
+use std::io::ErrorKind::{InvalidInput};
+
+use crate::company::CompanyId;
+use crate::error::MalformedPacket;
+use crate::io::{WriteTo};
+use crate::uuid::Uuid;
+
+const AD_FLAGS: u8 = 0x01;
+const AD_INCOMPLETE_UUIDS_16: u8 = 0x02;
+const AD_COMPLETE_UUIDS_16: u8 = 0x03;
+const AD_INCOMPLETE_UUIDS_32: u8 = 0x04;
+const AD_COMPLETE_UUIDS_32: u8 = 0x05;
+const AD_INCOMPLETE_UUIDS_128: u8 = 0x06;
+const AD_COMPLETE_UUIDS_128: u8 = 0x07;
+const AD_SHORTENED_LOCAL_NAME: u8 = 0x08;
+const AD_COMPLETE_LOCAL_NAME: u8 = 0x09;
+const AD_TX_POWER_LEVEL: u8 = 0x0A;
+const AD_SERVICE_DATA_16: u8 = 0x16;
+const AD_APPEARANCE: u8 = 0x19;
+const AD_SERVICE_DATA_32: u8 = 0x20;
+const AD_SERVICE_DATA_128: u8 = 0x21;
+const AD_MANUFACTURER_DATA: u8 = 0xFF;
+
+/// Maximum length of legacy advertising or scan response data
+pub const MAX_ADV_DATA_LEN: usize = 31;
+
+/// Maximum length of extended advertising or scan response data
+pub const MAX_EXT_ADV_DATA_LEN: usize = 1650;
+
+bitflags! {
+    /// Discoverability and capability flags of an advertiser
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct AdFlags: u8 {
+        const LE_LIMITED_DISCOVERABLE = 0x01;
+        const LE_GENERAL_DISCOVERABLE = 0x02;
+        const BR_EDR_NOT_SUPPORTED = 0x04;
+        const SIMULTANEOUS_LE_BR_EDR_CONTROLLER = 0x08;
+        const SIMULTANEOUS_LE_BR_EDR_HOST = 0x10;
+    }
+}
+
+/// A single decoded advertising data structure
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdStructure {
+    Flags(AdFlags),
+    /// 16-bit service UUIDs, and whether the list is complete
+    ServiceUuids16 { complete: bool, uuids: Vec<u16> },
+    /// 32-bit service UUIDs, and whether the list is complete
+    ServiceUuids32 { complete: bool, uuids: Vec<u32> },
+    /// 128-bit service UUIDs, and whether the list is complete
+    ServiceUuids128 { complete: bool, uuids: Vec<u128> },
+    ShortenedLocalName(String),
+    CompleteLocalName(String),
+    /// Transmit power level in dBm
+    TxPowerLevel(i8),
+    ServiceData16 { uuid: u16, data: Box<[u8]> },
+    ServiceData32 { uuid: u32, data: Box<[u8]> },
+    ServiceData128 { uuid: u128, data: Box<[u8]> },
+    Appearance(u16),
+    ManufacturerData { company_id: CompanyId, data: Box<[u8]> },
+    /// Structure of a type without a decoder
+    Unknown { ad_type: u8, data: Box<[u8]> },
+}
+
+/// Split data into fixed-size little-endian integers.
+fn read_uuids<const N: usize, T>(data: &[u8], convert: fn([u8; N]) -> T) -> Result<Vec<T>> {
+    if data.len() % N != 0 {
+        return Err(MalformedPacket.into());
+    }
+    Ok(data.chunks_exact(N)
+        .map(|chunk| convert(chunk.try_into().unwrap()))
+        .collect())
+}
+
+/// Split a fixed-size little-endian field, such as a UUID, from the front of data.
+fn split_prefix<const N: usize>(data: &[u8]) -> Result<([u8; N], Box<[u8]>)> {
+    if data.len() < N {
+        return Err(MalformedPacket.into());
+    }
+    let (uuid, rest) = data.split_at(N);
+    Ok((uuid.try_into().unwrap(), rest.into()))
+}
+
+impl AdStructure {
+    /// Decode the data of a structure of type `ad_type`.
+    ///
+    /// Structures too short or of the wrong length for their type fail with `MalformedPacket`.
+    pub fn decode(ad_type: u8, data: &[u8]) -> Result<Self> {
+        Ok(match ad_type {
+            AD_FLAGS => match data.first() {
+                Some(&flags) => AdStructure::Flags(AdFlags::from_bits_retain(flags)),
+                None => return Err(MalformedPacket.into()),
+            },
+            AD_INCOMPLETE_UUIDS_16 | AD_COMPLETE_UUIDS_16 => AdStructure::ServiceUuids16 {
+                complete: ad_type == AD_COMPLETE_UUIDS_16,
+                uuids: read_uuids(data, u16::from_le_bytes)?,
+            },
+            AD_INCOMPLETE_UUIDS_32 | AD_COMPLETE_UUIDS_32 => AdStructure::ServiceUuids32 {
+                complete: ad_type == AD_COMPLETE_UUIDS_32,
+                uuids: read_uuids(data, u32::from_le_bytes)?,
+            },
+            AD_INCOMPLETE_UUIDS_128 | AD_COMPLETE_UUIDS_128 => AdStructure::ServiceUuids128 {
+                complete: ad_type == AD_COMPLETE_UUIDS_128,
+                uuids: read_uuids(data, u128::from_le_bytes)?,
+            },
+            AD_SHORTENED_LOCAL_NAME => AdStructure::ShortenedLocalName(String::from_utf8_lossy(data).into_owned()),
+            AD_COMPLETE_LOCAL_NAME => AdStructure::CompleteLocalName(String::from_utf8_lossy(data).into_owned()),
+            AD_TX_POWER_LEVEL => match data {
+                [level] => AdStructure::TxPowerLevel(*level as i8),
+                _ => return Err(MalformedPacket.into()),
+            },
+            AD_SERVICE_DATA_16 => {
+                let (uuid, data) = split_prefix(data)?;
+                AdStructure::ServiceData16 { uuid: u16::from_le_bytes(uuid), data }
+            },
+            AD_SERVICE_DATA_32 => {
+                let (uuid, data) = split_prefix(data)?;
+                AdStructure::ServiceData32 { uuid: u32::from_le_bytes(uuid), data }
+            },
+            AD_SERVICE_DATA_128 => {
+                let (uuid, data) = split_prefix(data)?;
+                AdStructure::ServiceData128 { uuid: u128::from_le_bytes(uuid), data }
+            },
+            AD_APPEARANCE => match data {
+                [b0, b1] => AdStructure::Appearance(u16::from_le_bytes([*b0, *b1])),
+                _ => return Err(MalformedPacket.into()),
+            },
+            AD_MANUFACTURER_DATA => {
+                let (company_id, data) = split_prefix(data)?;
+                AdStructure::ManufacturerData { company_id: CompanyId(u16::from_le_bytes(company_id)), data }
+            },
+            _ => AdStructure::Unknown { ad_type, data: data.into() },
+        })
+    }
+}
+
+impl AdStructure {
+    /// Return the AD type of the structure
+    pub fn ad_type(&self) -> u8 {
+        match self {
+            AdStructure::Flags(_) => AD_FLAGS,
+            AdStructure::ServiceUuids16 { complete: false, .. } => AD_INCOMPLETE_UUIDS_16,
+            AdStructure::ServiceUuids16 { complete: true, .. } => AD_COMPLETE_UUIDS_16,
+            AdStructure::ServiceUuids32 { complete: false, .. } => AD_INCOMPLETE_UUIDS_32,
+            AdStructure::ServiceUuids32 { complete: true, .. } => AD_COMPLETE_UUIDS_32,
+            AdStructure::ServiceUuids128 { complete: false, .. } => AD_INCOMPLETE_UUIDS_128,
+            AdStructure::ServiceUuids128 { complete: true, .. } => AD_COMPLETE_UUIDS_128,
+            AdStructure::ShortenedLocalName(_) => AD_SHORTENED_LOCAL_NAME,
+            AdStructure::CompleteLocalName(_) => AD_COMPLETE_LOCAL_NAME,
+            AdStructure::TxPowerLevel(_) => AD_TX_POWER_LEVEL,
+            AdStructure::ServiceData16 { .. } => AD_SERVICE_DATA_16,
+            AdStructure::ServiceData32 { .. } => AD_SERVICE_DATA_32,
+            AdStructure::ServiceData128 { .. } => AD_SERVICE_DATA_128,
+            AdStructure::Appearance(_) => AD_APPEARANCE,
+            AdStructure::ManufacturerData { .. } => AD_MANUFACTURER_DATA,
+            AdStructure::Unknown { ad_type, .. } => *ad_type,
+        }
+    }
+
+    /// Encode the data of the structure, without its length and type.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            AdStructure::Flags(flags) => vec![flags.bits()],
+            AdStructure::ServiceUuids16 { uuids, .. } => uuids.iter().flat_map(|uuid| uuid.to_le_bytes()).collect(),
+            AdStructure::ServiceUuids32 { uuids, .. } => uuids.iter().flat_map(|uuid| uuid.to_le_bytes()).collect(),
+            AdStructure::ServiceUuids128 { uuids, .. } => uuids.iter().flat_map(|uuid| uuid.to_le_bytes()).collect(),
+            AdStructure::ShortenedLocalName(name) | AdStructure::CompleteLocalName(name) => name.as_bytes().to_vec(),
+            AdStructure::TxPowerLevel(level) => vec![*level as u8],
+            AdStructure::ServiceData16 { uuid, data } => [&uuid.to_le_bytes()[..], data].concat(),
+            AdStructure::ServiceData32 { uuid, data } => [&uuid.to_le_bytes()[..], data].concat(),
+            AdStructure::ServiceData128 { uuid, data } => [&uuid.to_le_bytes()[..], data].concat(),
+            AdStructure::Appearance(appearance) => appearance.to_le_bytes().to_vec(),
+            AdStructure::ManufacturerData { company_id, data } => [&company_id.0.to_le_bytes()[..], data].concat(),
+            AdStructure::Unknown { data, .. } => data.to_vec(),
+        }
+    }
+}
+
+impl WriteTo for &AdStructure {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let data = self.encode();
+        let len: u8 = (data.len() + 1).try_into()
+            .map_err(|_| Error::new(InvalidInput, "Advertising data structure too long"))?;
+        w.write_all(&[len, self.ad_type()])?;
+        w.write_all(&data)?;
+        Ok(data.len() + 2)
+    }
+}
+
+/// Iterate over the raw structures in advertising data, yielding each type and data.
+///
+/// Iteration stops at the first zero length, which marks the start of padding.
+pub fn structures(data: &[u8]) -> impl Iterator<Item = Result<(u8, &[u8])>> + '_ {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let (&len, tail) = rest.split_first()?;
+        if len == 0 {
+            rest = &[];
+            return None;
+        }
+        let len = len as usize;
+        if tail.len() < len {
+            rest = &[];
+            return Some(Err(MalformedPacket.into()));
+        }
+        let (structure, tail) = tail.split_at(len);
+        rest = tail;
+        Some(Ok((structure[0], &structure[1..])))
+    })
+}
+
+/// Parse advertising or extended inquiry response data into structures.
+pub fn parse(data: &[u8]) -> Result<Vec<AdStructure>> {
+    structures(data)
+        .map(|structure| structure.and_then(|(ad_type, data)| AdStructure::decode(ad_type, data)))
+        .collect()
+}
+
+/// Return the complete local name, or the shortened one if there is no complete name.
+pub fn local_name(structures: &[AdStructure]) -> Option<&str> {
+    structures.iter()
+        .find_map(|structure| match structure {
+            AdStructure::CompleteLocalName(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .or_else(|| structures.iter().find_map(|structure| match structure {
+            AdStructure::ShortenedLocalName(name) => Some(name.as_str()),
+            _ => None,
+        }))
+}
+
+/// Return the service UUIDs listed in any form, complete or not.
+pub fn service_uuids(structures: &[AdStructure]) -> Vec<Uuid> {
+    structures.iter()
+        .flat_map(|structure| match structure {
+            AdStructure::ServiceUuids16 { uuids, .. } => uuids.iter().map(|&uuid| Uuid::from(uuid)).collect(),
+            AdStructure::ServiceUuids32 { uuids, .. } => uuids.iter().map(|&uuid| Uuid::from(uuid)).collect(),
+            AdStructure::ServiceUuids128 { uuids, .. } => uuids.iter().map(|&uuid| Uuid::from(uuid)).collect(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// Return the data of the service with UUID `uuid`, in whichever form the UUID was sent.
+pub fn service_data(structures: &[AdStructure], uuid: Uuid) -> Option<&[u8]> {
+    structures.iter().find_map(|structure| match structure {
+        AdStructure::ServiceData16 { uuid: u, data } if Uuid::from(*u) == uuid => Some(&data[..]),
+        AdStructure::ServiceData32 { uuid: u, data } if Uuid::from(*u) == uuid => Some(&data[..]),
+        AdStructure::ServiceData128 { uuid: u, data } if Uuid::from(*u) == uuid => Some(&data[..]),
+        _ => None,
+    })
+}
+
+/// Builder assembling advertising data from structures while enforcing a length limit.
+#[derive(Clone, Debug)]
+pub struct AdBuilder {
+    data: Vec<u8>,
+    limit: usize,
+}
+
+impl Default for AdBuilder {
+    fn default() -> Self {
+        AdBuilder::new()
+    }
+}
+
+impl AdBuilder {
+    /// Create a builder for legacy advertising or scan response data, limited to 31 bytes.
+    pub fn new() -> Self {
+        AdBuilder::with_limit(MAX_ADV_DATA_LEN)
+    }
+
+    /// Create a builder for extended advertising data.
+    pub fn extended() -> Self {
+        AdBuilder::with_limit(MAX_EXT_ADV_DATA_LEN)
+    }
+
+    /// Create a builder with a custom length limit.
+    pub fn with_limit(limit: usize) -> Self {
+        AdBuilder { data: Vec::new(), limit }
+    }
+
+    /// Return the number of bytes still available
+    pub fn remaining(&self) -> usize {
+        self.limit - self.data.len()
+    }
+
+    /// Append a structure, failing if it does not fit.
+    pub fn push(&mut self, structure: &AdStructure) -> Result<&mut Self> {
+        let bytes = structure.bytes()?;
+        if bytes.len() > self.remaining() {
+            return Err(Error::new(InvalidInput, "Advertising data too long"));
+        }
+        self.data.extend_from_slice(&bytes);
+        Ok(self)
+    }
+
+    /// Append the advertiser flags.
+    pub fn flags(&mut self, flags: AdFlags) -> Result<&mut Self> {
+        self.push(&AdStructure::Flags(flags))
+    }
+
+    /// Append the complete local name.
+    pub fn complete_local_name(&mut self, name: &str) -> Result<&mut Self> {
+        self.push(&AdStructure::CompleteLocalName(name.into()))
+    }
+
+    /// Append as much of the local name as fits, marking it shortened if it was cut.
+    pub fn local_name(&mut self, name: &str) -> Result<&mut Self> {
+        let available = self.remaining().saturating_sub(2);
+        if name.len() <= available {
+            return self.complete_local_name(name);
+        }
+        let mut end = available;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.push(&AdStructure::ShortenedLocalName(name[..end].into()))
+    }
+
+    /// Append a list of 16-bit service UUIDs.
+    pub fn service_uuids_16(&mut self, complete: bool, uuids: &[u16]) -> Result<&mut Self> {
+        self.push(&AdStructure::ServiceUuids16 { complete, uuids: uuids.to_vec() })
+    }
+
+    /// Append a list of 32-bit service UUIDs.
+    pub fn service_uuids_32(&mut self, complete: bool, uuids: &[u32]) -> Result<&mut Self> {
+        self.push(&AdStructure::ServiceUuids32 { complete, uuids: uuids.to_vec() })
+    }
+
+    /// Append a list of 128-bit service UUIDs.
+    pub fn service_uuids_128(&mut self, complete: bool, uuids: &[u128]) -> Result<&mut Self> {
+        self.push(&AdStructure::ServiceUuids128 { complete, uuids: uuids.to_vec() })
+    }
+
+    /// Append lists of service UUIDs, each in its shortest form.
+    ///
+    /// One structure is added for each form the UUIDs need, so 16-bit UUIDs are not sent in
+    /// their 128-bit form.
+    pub fn service_uuids(&mut self, complete: bool, uuids: &[Uuid]) -> Result<&mut Self> {
+        let uuids_16: Vec<u16> = uuids.iter().filter_map(Uuid::as_u16).collect();
+        let uuids_32: Vec<u32> = uuids.iter()
+            .filter(|uuid| uuid.encoded_len() == 4)
+            .filter_map(Uuid::as_u32)
+            .collect();
+        let uuids_128: Vec<u128> = uuids.iter()
+            .filter(|uuid| uuid.encoded_len() == 16)
+            .map(Uuid::as_u128)
+            .collect();
+        if !uuids_16.is_empty() {
+            self.service_uuids_16(complete, &uuids_16)?;
+        }
+        if !uuids_32.is_empty() {
+            self.service_uuids_32(complete, &uuids_32)?;
+        }
+        if !uuids_128.is_empty() {
+            self.service_uuids_128(complete, &uuids_128)?;
+        }
+        Ok(self)
+    }
+
+    /// Append service data for a 16-bit service UUID.
+    pub fn service_data_16(&mut self, uuid: u16, data: &[u8]) -> Result<&mut Self> {
+        self.push(&AdStructure::ServiceData16 { uuid, data: data.into() })
+    }
+
+    /// Append service data, with the UUID in its shortest form.
+    pub fn service_data(&mut self, uuid: Uuid, data: &[u8]) -> Result<&mut Self> {
+        let data = data.into();
+        match (uuid.as_u16(), uuid.as_u32()) {
+            (Some(uuid), _) => self.push(&AdStructure::ServiceData16 { uuid, data }),
+            (None, Some(uuid)) => self.push(&AdStructure::ServiceData32 { uuid, data }),
+            (None, None) => self.push(&AdStructure::ServiceData128 { uuid: uuid.as_u128(), data }),
+        }
+    }
+
+    /// Append the transmit power level in dBm.
+    pub fn tx_power_level(&mut self, level: i8) -> Result<&mut Self> {
+        self.push(&AdStructure::TxPowerLevel(level))
+    }
+
+    /// Append the appearance of the device.
+    pub fn appearance(&mut self, appearance: u16) -> Result<&mut Self> {
+        self.push(&AdStructure::Appearance(appearance))
+    }
+
+    /// Append manufacturer specific data.
+    pub fn manufacturer_data(&mut self, company_id: CompanyId, data: &[u8]) -> Result<&mut Self> {
+        self.push(&AdStructure::ManufacturerData { company_id, data: data.into() })
+    }
+
+    /// Return the assembled data
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Return the assembled data, consuming the builder
+    pub fn build(self) -> Box<[u8]> {
+        self.data.into_boxed_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_malformed(result: Result<Vec<AdStructure>>) -> bool {
+        result.is_err_and(|e| e.get_ref().is_some_and(|e| e.is::<MalformedPacket>()))
+    }
+
+    #[test]
+    fn built_data_parses_back() {
+        let mut builder = AdBuilder::extended();
+        builder.flags(AdFlags::LE_GENERAL_DISCOVERABLE | AdFlags::BR_EDR_NOT_SUPPORTED).unwrap()
+            .complete_local_name("Sensor").unwrap()
+            .service_uuids_16(true, &[0x180F, 0x181A]).unwrap()
+            .service_uuids_32(false, &[0x12345678]).unwrap()
+            .service_uuids_128(true, &[0x0123456789ABCDEF0123456789ABCDEF]).unwrap()
+            .service_data_16(0x180F, &[0x64]).unwrap()
+            .tx_power_level(-8).unwrap()
+            .appearance(0x0540).unwrap()
+            .manufacturer_data(CompanyId::APPLE, &[0x02, 0x15]).unwrap();
+        assert_eq!(parse(builder.as_bytes()).unwrap(), vec![
+            AdStructure::Flags(AdFlags::LE_GENERAL_DISCOVERABLE | AdFlags::BR_EDR_NOT_SUPPORTED),
+            AdStructure::CompleteLocalName("Sensor".into()),
+            AdStructure::ServiceUuids16 { complete: true, uuids: vec![0x180F, 0x181A] },
+            AdStructure::ServiceUuids32 { complete: false, uuids: vec![0x12345678] },
+            AdStructure::ServiceUuids128 { complete: true, uuids: vec![0x0123456789ABCDEF0123456789ABCDEF] },
+            AdStructure::ServiceData16 { uuid: 0x180F, data: [0x64].into() },
+            AdStructure::TxPowerLevel(-8),
+            AdStructure::Appearance(0x0540),
+            AdStructure::ManufacturerData { company_id: CompanyId::APPLE, data: [0x02, 0x15].into() },
+        ]);
+    }
+
+    #[test]
+    fn parsed_data_builds_back() {
+        let data = [0x02, 0x01, 0x06, 0x03, 0x03, 0x0F, 0x18, 0x04, 0x09, b'a', b'b', b'c'];
+        let mut builder = AdBuilder::new();
+        for structure in parse(&data).unwrap() {
+            builder.push(&structure).unwrap();
+        }
+        assert_eq!(builder.as_bytes(), &data);
+    }
+
+    #[test]
+    fn padding_ends_the_data() {
+        assert_eq!(parse(&[0x02, 0x0A, 0x04, 0x00, 0xFF, 0xFF]).unwrap(), vec![AdStructure::TxPowerLevel(4)]);
+    }
+
+    #[test]
+    fn builder_enforces_its_limit() {
+        let mut builder = AdBuilder::new();
+        assert!(builder.manufacturer_data(CompanyId::APPLE, &[0; 28]).is_err());
+        assert!(builder.as_bytes().is_empty());
+        builder.local_name("A name much too long for legacy advertising data").unwrap();
+        assert_eq!(builder.remaining(), 0);
+        assert!(matches!(parse(builder.as_bytes()).unwrap()[..], [AdStructure::ShortenedLocalName(_)]));
+    }
+
+    #[test]
+    fn truncated_structures_are_malformed() {
+        assert!(is_malformed(parse(&[0x03, 0x09, b'a'])));
+        assert!(is_malformed(parse(&[0x02, 0x01, 0x06, 0x05, 0xFF, 0x4C])));
+        assert!(is_malformed(parse(&[0x02, 0x16, 0x0F])));
+        assert!(is_malformed(parse(&[0x02, 0xFF, 0x4C])));
+    }
+
+    #[test]
+    fn odd_length_structures_are_malformed() {
+        assert!(is_malformed(parse(&[0x04, 0x03, 0x0F, 0x18, 0x0A])));
+        assert!(is_malformed(parse(&[0x04, 0x05, 0x01, 0x02, 0x03])));
+        assert!(is_malformed(parse(&[0x03, 0x07, 0x01, 0x02])));
+        assert!(is_malformed(parse(&[0x03, 0x0A, 0x01, 0x02])));
+        assert!(is_malformed(parse(&[0x02, 0x19, 0x01])));
+    }
+
+    #[test]
+    fn empty_structures_are_malformed() {
+        assert!(is_malformed(parse(&[0x01, 0x01])));
+        assert!(is_malformed(parse(&[0x01, 0x0A])));
+        assert!(is_malformed(parse(&[0x01, 0x19])));
+        assert!(is_malformed(parse(&[0x01, 0xFF])));
+        // An empty list of UUIDs is still a list.
+        assert_eq!(parse(&[0x01, 0x03]).unwrap(), vec![AdStructure::ServiceUuids16 { complete: true, uuids: vec![] }]);
+    }
+}
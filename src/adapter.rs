@@ -0,0 +1,75 @@
+use libc::{c_int};
+#[cfg(all(feature = "bredr", feature = "le"))]
+use std::collections::HashMap;
+use std::io::{Result};
+
+#[cfg(all(feature = "bredr", feature = "le"))]
+use super::addr::BdAddr;
+#[cfg(all(feature = "bredr", feature = "le"))]
+use super::discovery::DiscoveredDevice;
+#[cfg(all(feature = "bredr", feature = "le"))]
+use super::gap::GapState;
+use super::socket::Socket;
+use super::stats::Stats;
+
+/// Default time to wait for a command to complete, in milliseconds
+const DEFAULT_TIMEOUT: c_int = 1000;
+
+/// A local Bluetooth controller.
+///
+/// The adapter owns an HCI socket bound to the controller and applies the same timeout to every
+/// command it sends.
+pub struct Adapter {
+    pub(crate) socket: Socket,
+    pub(crate) timeout: c_int,
+    /// Devices found by discovery sessions, used to pick how to connect to them
+    #[cfg(all(feature = "bredr", feature = "le"))]
+    pub(crate) discovered: HashMap<BdAddr, DiscoveredDevice>,
+    /// Discoverable and connectable modes set through the adapter
+    #[cfg(all(feature = "bredr", feature = "le"))]
+    pub(crate) gap: GapState,
+}
+
+impl Adapter {
+    /// Open the controller with the given device id, such as 0 for hci0.
+    pub fn open(device_id: u16) -> Result<Self> {
+        Ok(Adapter::from_socket(Socket::new(device_id)?))
+    }
+
+    /// Create an adapter from an existing socket.
+    pub fn from_socket(socket: Socket) -> Self {
+        Adapter {
+            socket,
+            timeout: DEFAULT_TIMEOUT,
+            #[cfg(all(feature = "bredr", feature = "le"))]
+            discovered: HashMap::new(),
+            #[cfg(all(feature = "bredr", feature = "le"))]
+            gap: GapState::default(),
+        }
+    }
+
+    /// Return the socket of the adapter
+    pub fn socket(&mut self) -> &mut Socket {
+        &mut self.socket
+    }
+
+    /// Return the traffic counted on the socket of the adapter
+    pub fn stats(&self) -> Stats {
+        self.socket.stats()
+    }
+
+    /// Return the command timeout in milliseconds
+    pub fn timeout(&self) -> c_int {
+        self.timeout
+    }
+
+    /// Set the command timeout in milliseconds
+    pub fn set_timeout(&mut self, timeout: c_int) {
+        self.timeout = timeout;
+    }
+
+    /// Shut down and close the adapter and its socket, like `Socket::shutdown`.
+    pub fn close(self) -> Result<()> {
+        self.socket.shutdown()
+    }
+}
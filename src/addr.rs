@@ -0,0 +1,136 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::str::FromStr;
+
+use super::io::{read_bytes, ReadFrom, WriteTo};
+
+/// Bluetooth device address.
+///
+/// Bytes are stored in the order they are sent over HCI, least significant byte first.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BdAddr(pub [u8; 6]);
+
+impl BdAddr {
+    /// The all-zero address, BDADDR_ANY
+    pub const ANY: BdAddr = BdAddr([0u8; 6]);
+
+    /// Return the bytes of the address in HCI order
+    pub fn as_bytes(&self) -> &[u8; 6] {
+        &self.0
+    }
+}
+
+impl BdAddr {
+    /// Return the kind of random address this is, or `None` if it is not a valid random
+    /// address.
+    pub fn random_kind(&self) -> Option<RandomAddressKind> {
+        let value = u64::from_le_bytes([self.0[0], self.0[1], self.0[2], self.0[3], self.0[4], self.0[5], 0, 0]);
+        // Random part of a static or non-resolvable address, below the two type bits.
+        let random = value & 0x3FFF_FFFF_FFFF;
+        // Random part of the prand of a resolvable address.
+        let prand = (value >> 24) & 0x3F_FFFF;
+
+        match self.0[5] >> 6 {
+            0b11 if random != 0 && random != 0x3FFF_FFFF_FFFF => Some(RandomAddressKind::Static),
+            0b01 if prand != 0 && prand != 0x3F_FFFF => Some(RandomAddressKind::ResolvablePrivate),
+            0b00 if random != 0 && random != 0x3FFF_FFFF_FFFF => Some(RandomAddressKind::NonResolvablePrivate),
+            _ => None,
+        }
+    }
+}
+
+/// Kind of an LE random device address, given by its two most significant bits
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RandomAddressKind {
+    Static,
+    ResolvablePrivate,
+    NonResolvablePrivate,
+}
+
+impl Display for BdAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let b = &self.0;
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", b[5], b[4], b[3], b[2], b[1], b[0])
+    }
+}
+
+impl FromStr for BdAddr {
+    type Err = Error;
+
+    /// Parse an address written most significant byte first, like "00:11:22:33:44:55".
+    fn from_str(s: &str) -> Result<Self> {
+        let mut bytes = [0u8; 6];
+        let mut parts = s.split(':');
+        for byte in bytes.iter_mut().rev() {
+            let part = parts.next()
+                .filter(|part| part.len() == 2)
+                .ok_or(Error::new(InvalidInput, "Invalid address"))?;
+            *byte = u8::from_str_radix(part, 16)
+                .map_err(|_| Error::new(InvalidInput, "Invalid address"))?;
+        }
+        if parts.next().is_some() {
+            return Err(Error::new(InvalidInput, "Invalid address"));
+        }
+        Ok(BdAddr(bytes))
+    }
+}
+
+impl ReadFrom for BdAddr {
+    fn read_from<R: Read>(r: R) -> Result<(Self, usize)> {
+        let (bytes, size) = <[u8; 6]>::read_from(r)?;
+        Ok((BdAddr(bytes), size))
+    }
+}
+
+impl WriteTo for &BdAddr {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        w.write(&self.0)
+    }
+}
+
+/// Type of an LE device address
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    #[default]
+    Public = 0x00,
+    Random = 0x01,
+    /// Public identity address resolved from a resolvable private address
+    PublicIdentity = 0x02,
+    /// Random static identity address resolved from a resolvable private address
+    RandomIdentity = 0x03,
+}
+
+impl TryFrom<u8> for AddressType {
+    type Error = Error;
+
+    fn try_from(address_type: u8) -> Result<Self> {
+        match address_type {
+            0x00 => Ok(AddressType::Public),
+            0x01 => Ok(AddressType::Random),
+            0x02 => Ok(AddressType::PublicIdentity),
+            0x03 => Ok(AddressType::RandomIdentity),
+            _ => Err(Error::new(InvalidData, "Unknown address type")),
+        }
+    }
+}
+
+impl ReadFrom for AddressType {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let mut address_type = [0u8];
+        read_bytes(&mut r, &mut address_type)?;
+        Ok((AddressType::try_from(address_type[0])?, 1))
+    }
+}
+
+/// Address the local controller uses in LE procedures
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OwnAddressType {
+    #[default]
+    Public = 0x00,
+    Random = 0x01,
+    /// Resolvable private address from the resolving list, or the public address
+    ResolvableOrPublic = 0x02,
+    /// Resolvable private address from the resolving list, or the random address
+    ResolvableOrRandom = 0x03,
+}
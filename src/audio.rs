@@ -0,0 +1,145 @@
+use bitflags::bitflags;
+use std::io::{Read, Result, Write};
+
+use super::io::{ReadAs, ReadFrom, WriteAs, WriteTo};
+
+code_enum!(
+    /// Coding format identifier of a codec
+    CodingId {
+        MuLaw = 0x00,
+        ALaw = 0x01,
+        Cvsd = 0x02,
+        Transparent = 0x03,
+        LinearPcm = 0x04,
+        Msbc = 0x05,
+        Lc3 = 0x06,
+        G729a = 0x07,
+        VendorSpecific = 0xFF,
+    }
+);
+
+/// Coding format of audio data, identifying a standard or vendor-specific codec.
+///
+/// The company and vendor codec identifiers are only meaningful for vendor-specific codecs and
+/// are zero otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CodingFormat {
+    pub id: CodingId,
+    pub company_id: u16,
+    pub vendor_codec_id: u16,
+}
+
+impl CodingFormat {
+    /// Create a coding format for a standard codec.
+    pub fn new(id: CodingId) -> Self {
+        CodingFormat { id, company_id: 0, vendor_codec_id: 0 }
+    }
+
+    /// Create a coding format for a vendor-specific codec.
+    pub fn vendor(company_id: u16, vendor_codec_id: u16) -> Self {
+        CodingFormat { id: CodingId::VendorSpecific, company_id, vendor_codec_id }
+    }
+}
+
+impl From<CodingId> for CodingFormat {
+    fn from(id: CodingId) -> Self {
+        CodingFormat::new(id)
+    }
+}
+
+impl ReadFrom for CodingFormat {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (id, id_size) = r.read_as::<u8>()?;
+        let (company_id, company_id_size) = r.read_as::<u16>()?;
+        let (vendor_codec_id, vendor_codec_id_size) = r.read_as::<u16>()?;
+        Ok((CodingFormat { id: id.into(), company_id, vendor_codec_id },
+            id_size + company_id_size + vendor_codec_id_size))
+    }
+}
+
+impl WriteTo for &CodingFormat {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(u8::from(self.id))?
+            + w.write_as(self.company_id)?
+            + w.write_as(self.vendor_codec_id)?)
+    }
+}
+
+code_enum!(
+    /// Format of PCM samples exchanged with the host
+    PcmDataFormat {
+        NotApplicable = 0x00,
+        OnesComplement = 0x01,
+        TwosComplement = 0x02,
+        SignMagnitude = 0x03,
+        Unsigned = 0x04,
+    }
+);
+
+/// Path that audio data takes between the host and the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DataPath {
+    /// Audio data is carried in HCI packets
+    Hci,
+    /// Audio data is carried over a vendor-specific transport, such as I2S or PCM
+    Vendor(u8),
+    /// Audio test mode
+    Test,
+}
+
+impl From<u8> for DataPath {
+    fn from(path: u8) -> Self {
+        match path {
+            0x00 => DataPath::Hci,
+            0xFF => DataPath::Test,
+            id => DataPath::Vendor(id),
+        }
+    }
+}
+
+impl From<DataPath> for u8 {
+    fn from(path: DataPath) -> u8 {
+        match path {
+            DataPath::Hci => 0x00,
+            DataPath::Vendor(id) => id,
+            DataPath::Test => 0xFF,
+        }
+    }
+}
+
+bitflags! {
+    /// Logical transports a codec is supported on
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct CodecTransports: u8 {
+        const BR_EDR_ACL = 0x01;
+        const BR_EDR_SCO = 0x02;
+        const LE_CIS = 0x04;
+        const LE_BIS = 0x08;
+    }
+}
+
+/// A codec supported by the local controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SupportedCodec {
+    pub format: CodingFormat,
+    /// Transports the codec is supported on, if reported by the controller
+    pub transports: CodecTransports,
+}
+
+/// Logical transport type used when querying codec capabilities
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LogicalTransport {
+    BrEdrAcl = 0x00,
+    BrEdrSco = 0x01,
+    LeCis = 0x02,
+    LeBis = 0x03,
+}
+
+/// Direction of audio data relative to the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DataPathDirection {
+    /// Data flowing from the host to the controller
+    Input = 0x00,
+    /// Data flowing from the controller to the host
+    Output = 0x01,
+}
@@ -0,0 +1,244 @@
+//! Beacon formats carried in advertising data.
+
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidInput};
+
+use crate::ad::{AdBuilder, AdFlags, AdStructure};
+use crate::codec::Be;
+use crate::company::CompanyId;
+
+const IBEACON_TYPE: u8 = 0x02;
+const IBEACON_LEN: u8 = 0x15;
+
+const EDDYSTONE_UUID: u16 = 0xFEAA;
+const EDDYSTONE_UID: u8 = 0x00;
+const EDDYSTONE_URL: u8 = 0x10;
+const EDDYSTONE_MAX_URL_LEN: usize = 17;
+
+const URL_SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+const URL_EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/",
+    ".com", ".org", ".edu", ".net", ".info", ".biz", ".gov",
+];
+
+/// Apple iBeacon frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IBeacon {
+    pub uuid: u128,
+    pub major: u16,
+    pub minor: u16,
+    /// Received signal strength at 1 m, in dBm
+    pub measured_power: i8,
+}
+
+impl IBeacon {
+    /// Build the advertising data of the beacon.
+    pub fn to_ad(&self) -> Result<Box<[u8]>> {
+        let mut data = vec![IBEACON_TYPE, IBEACON_LEN];
+        Be(self.uuid).encode_into(&mut data);
+        Be(self.major).encode_into(&mut data);
+        Be(self.minor).encode_into(&mut data);
+        data.push(self.measured_power as u8);
+
+        let mut builder = AdBuilder::new();
+        builder
+            .flags(AdFlags::LE_GENERAL_DISCOVERABLE | AdFlags::BR_EDR_NOT_SUPPORTED)?
+            .manufacturer_data(CompanyId::APPLE, &data)?;
+        Ok(builder.build())
+    }
+
+    /// Decode a beacon from parsed advertising data, if it contains one.
+    pub fn from_structures(structures: &[AdStructure]) -> Option<Self> {
+        structures.iter().find_map(|structure| match structure {
+            AdStructure::ManufacturerData { company_id: CompanyId::APPLE, data } => match &data[..] {
+                [IBEACON_TYPE, IBEACON_LEN, rest @ ..] if rest.len() == 21 => Some(IBeacon {
+                    uuid: Be::<u128>::from_slice(rest)?.0,
+                    major: Be::<u16>::from_slice(&rest[16..])?.0,
+                    minor: Be::<u16>::from_slice(&rest[18..])?.0,
+                    measured_power: rest[20] as i8,
+                }),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+}
+
+/// Google Eddystone frame
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Eddystone {
+    /// Beacon identified by a namespace and instance
+    Uid {
+        /// Transmit power at 0 m, in dBm
+        tx_power: i8,
+        namespace: [u8; 10],
+        instance: [u8; 6],
+    },
+    /// Beacon broadcasting a URL
+    Url {
+        /// Transmit power at 0 m, in dBm
+        tx_power: i8,
+        url: String,
+    },
+}
+
+/// Compress a URL with the Eddystone scheme prefixes and expansion codes.
+fn encode_url(url: &str) -> Result<Vec<u8>> {
+    let (scheme, prefix) = URL_SCHEMES.iter()
+        .enumerate()
+        .find(|(_, prefix)| url.starts_with(*prefix))
+        .ok_or(Error::new(InvalidInput, "Unsupported URL scheme"))?;
+
+    let mut encoded = vec![scheme as u8];
+    let mut rest = &url[prefix.len()..];
+    while !rest.is_empty() {
+        match URL_EXPANSIONS.iter().enumerate().find(|(_, expansion)| rest.starts_with(*expansion)) {
+            Some((code, expansion)) => {
+                encoded.push(code as u8);
+                rest = &rest[expansion.len()..];
+            },
+            None => {
+                let c = rest.as_bytes()[0];
+                if !(0x21..0x7F).contains(&c) {
+                    return Err(Error::new(InvalidInput, "Invalid character in URL"));
+                }
+                encoded.push(c);
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    if encoded.len() > EDDYSTONE_MAX_URL_LEN + 1 {
+        return Err(Error::new(InvalidInput, "URL too long"));
+    }
+    Ok(encoded)
+}
+
+/// Expand a URL compressed with the Eddystone scheme prefixes and expansion codes.
+fn decode_url(encoded: &[u8]) -> Option<String> {
+    let (&scheme, rest) = encoded.split_first()?;
+    let mut url = String::from(*URL_SCHEMES.get(scheme as usize)?);
+    for &c in rest {
+        match URL_EXPANSIONS.get(c as usize) {
+            Some(expansion) => url.push_str(expansion),
+            None if (0x21..0x7F).contains(&c) => url.push(c as char),
+            None => return None,
+        }
+    }
+    Some(url)
+}
+
+impl Eddystone {
+    /// Build the advertising data of the beacon.
+    pub fn to_ad(&self) -> Result<Box<[u8]>> {
+        let frame = match self {
+            Eddystone::Uid { tx_power, namespace, instance } => {
+                let mut frame = vec![EDDYSTONE_UID, *tx_power as u8];
+                frame.extend_from_slice(namespace);
+                frame.extend_from_slice(instance);
+                frame.extend_from_slice(&[0, 0]); // Reserved
+                frame
+            },
+            Eddystone::Url { tx_power, url } => {
+                let mut frame = vec![EDDYSTONE_URL, *tx_power as u8];
+                frame.extend_from_slice(&encode_url(url)?);
+                frame
+            },
+        };
+
+        let mut builder = AdBuilder::new();
+        builder
+            .flags(AdFlags::LE_GENERAL_DISCOVERABLE | AdFlags::BR_EDR_NOT_SUPPORTED)?
+            .service_uuids_16(true, &[EDDYSTONE_UUID])?
+            .service_data_16(EDDYSTONE_UUID, &frame)?;
+        Ok(builder.build())
+    }
+
+    /// Decode a UID or URL frame from parsed advertising data, if it contains one.
+    pub fn from_structures(structures: &[AdStructure]) -> Option<Self> {
+        structures.iter().find_map(|structure| match structure {
+            AdStructure::ServiceData16 { uuid: EDDYSTONE_UUID, data } => match &data[..] {
+                [EDDYSTONE_UID, tx_power, rest @ ..] if rest.len() >= 16 => Some(Eddystone::Uid {
+                    tx_power: *tx_power as i8,
+                    namespace: rest[..10].try_into().unwrap(),
+                    instance: rest[10..16].try_into().unwrap(),
+                }),
+                [EDDYSTONE_URL, tx_power, rest @ ..] => Some(Eddystone::Url {
+                    tx_power: *tx_power as i8,
+                    url: decode_url(rest)?,
+                }),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ad;
+
+    #[test]
+    fn ibeacon_round_trips() {
+        let beacon = IBeacon {
+            uuid: 0xE2C56DB5_DFFB_48D2_B060_D0F5A71096E0,
+            major: 0x0102,
+            minor: 0x0304,
+            measured_power: -59,
+        };
+        let data = beacon.to_ad().unwrap();
+        // Flags, then the manufacturer data with the fields in big-endian order
+        assert_eq!(&data[..9], &[0x02, 0x01, 0x06, 0x1A, 0xFF, 0x4C, 0x00, IBEACON_TYPE, IBEACON_LEN]);
+        assert_eq!(&data[9..11], &[0xE2, 0xC5]);
+        assert_eq!(&data[25..], &[0x01, 0x02, 0x03, 0x04, 0xC5]);
+        assert_eq!(IBeacon::from_structures(&ad::parse(&data).unwrap()), Some(beacon));
+    }
+
+    #[test]
+    fn eddystone_uid_round_trips() {
+        let beacon = Eddystone::Uid {
+            tx_power: -20,
+            namespace: [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09],
+            instance: [0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F],
+        };
+        let data = beacon.to_ad().unwrap();
+        assert_eq!(Eddystone::from_structures(&ad::parse(&data).unwrap()), Some(beacon));
+    }
+
+    #[test]
+    fn eddystone_url_round_trips() {
+        let beacon = Eddystone::Url { tx_power: -20, url: "https://www.example.com/".into() };
+        let data = beacon.to_ad().unwrap();
+        assert_eq!(Eddystone::from_structures(&ad::parse(&data).unwrap()), Some(beacon));
+    }
+
+    #[test]
+    fn urls_are_compressed() {
+        assert_eq!(encode_url("https://www.example.com/").unwrap(),
+            [&[0x01][..], b"example", &[0x00]].concat());
+        assert_eq!(encode_url("http://a.org").unwrap(), [0x02, b'a', 0x08]);
+        assert_eq!(decode_url(&[0x03, b'a', 0x07]).as_deref(), Some("https://a.com"));
+        assert!(encode_url("ftp://example.com").is_err());
+        assert_eq!(decode_url(&[0x04, b'a']), None);
+    }
+
+    #[test]
+    fn urls_fit_in_17_bytes() {
+        assert_eq!(encode_url("https://abcdefghijklmnopq").unwrap().len(), 1 + EDDYSTONE_MAX_URL_LEN);
+        assert!(encode_url("https://abcdefghijklmnopqr").is_err());
+        // Expansions count as one byte.
+        assert!(encode_url("https://abcdefghijklmnop.com/").is_ok());
+    }
+
+    #[test]
+    fn reserved_codes_are_rejected() {
+        for code in 0x0E..=0x20 {
+            assert_eq!(decode_url(&[0x00, b'a', code]), None, "code {:#04x}", code);
+        }
+        assert!(decode_url(&[0x00, 0x0D]).is_some());
+        assert!(decode_url(&[0x00, 0x21]).is_some());
+        assert!(encode_url("https://a b").is_err());
+        assert!(encode_url("https://a\tb").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! Print the packets passing through a controller, with the names of commands and events.
+//!
+//! The crate does not support the monitor channel yet, so this shows what a raw socket sees:
+//! commands, events and data, but none of the management messages or notes of the monitor.
+//!
+//! Usage: hci-dump [hciN]
+
+use std::env;
+use std::io::{Read, Result};
+use std::process::ExitCode;
+
+use bluez_hci::codec::{event_name, le_event_name, opcode_name, Packet};
+use bluez_hci::Socket;
+
+const EVT_LE_META: u8 = 0x3E;
+/// Longest packet with its type indicator: an ACL data packet of 65535 bytes behind its
+/// 4-byte header. ISO data packets carry at most 16383 bytes behind theirs.
+const MAX_PACKET_SIZE: usize = 1 + 4 + 65535;
+
+/// Parse a device given as "hciN" or "N", defaulting to hci0.
+fn device_id(arg: Option<String>) -> Option<u16> {
+    match arg {
+        Some(arg) => arg.strip_prefix("hci").unwrap_or(&arg).parse().ok(),
+        None => Some(0),
+    }
+}
+
+fn print_packet(packet: &Packet<'_>) {
+    match *packet {
+        Packet::Command { opcode, params } => {
+            println!("< Command {} (0x{:04X}) {:02X?}", opcode_name(opcode).unwrap_or("Unknown"), opcode, params);
+        }
+        Packet::Event { code: EVT_LE_META, params } if !params.is_empty() => {
+            println!("> LE Meta {} (0x{:02X}) {:02X?}", le_event_name(params[0]).unwrap_or("Unknown"), params[0], &params[1..]);
+        }
+        Packet::Event { code, params } => {
+            println!("> Event {} (0x{:02X}) {:02X?}", event_name(code).unwrap_or("Unknown"), code, params);
+        }
+        Packet::Acl { handle, data, .. } => println!("  ACL handle 0x{:04X} {:02X?}", handle, data),
+        Packet::Sco { handle, data, .. } => println!("  SCO handle 0x{:04X} {:02X?}", handle, data),
+        Packet::Iso { handle, data, .. } => println!("  ISO handle 0x{:04X} {:02X?}", handle, data),
+    }
+}
+
+fn dump(device_id: u16) -> Result<()> {
+    let mut socket = Socket::new(device_id)?;
+    let mut filter = socket.get_filter()?;
+    filter.set_type_mask(!0);
+    filter.set_event_mask(!0);
+    socket.set_filter(&filter)?;
+
+    let mut buf = vec![0u8; MAX_PACKET_SIZE];
+    loop {
+        let size = (&mut socket).read(&mut buf)?;
+        match Packet::parse(&buf[..size]) {
+            Ok(packet) => print_packet(&packet),
+            Err(e) => println!("? {} {:02X?}", e, &buf[..size]),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let Some(device_id) = device_id(env::args().nth(1)) else {
+        eprintln!("usage: hci-dump [hciN]");
+        return ExitCode::FAILURE;
+    };
+    match dump(device_id) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("hci-dump: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
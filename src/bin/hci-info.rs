@@ -0,0 +1,48 @@
+//! Print the versions, name and class of a controller.
+//!
+//! Usage: hci-info [hciN]
+
+use std::env;
+use std::io::Result;
+use std::process::ExitCode;
+
+use bluez_hci::Adapter;
+
+/// Parse a device given as "hciN" or "N", defaulting to hci0.
+fn device_id(arg: Option<String>) -> Option<u16> {
+    match arg {
+        Some(arg) => arg.strip_prefix("hci").unwrap_or(&arg).parse().ok(),
+        None => Some(0),
+    }
+}
+
+fn info(device_id: u16) -> Result<()> {
+    let mut adapter = Adapter::open(device_id)?;
+    let timeout = adapter.timeout();
+    let socket = adapter.socket();
+
+    let version = socket.read_local_version(timeout)?;
+    println!("hci{}", device_id);
+    println!("  HCI version:  {} (revision 0x{:04X})", version.hci_version, version.hci_subversion);
+    println!("  LMP version:  {} (subversion 0x{:04X})", version.lmp_version, version.lmp_subversion);
+    println!("  Manufacturer: {}", version.company);
+    println!("  Name:         {:?}", socket.read_local_name(timeout)?);
+    println!("  Class:        {:?}", socket.read_class_of_device(timeout)?);
+    println!("  Scan enable:  {:?}", socket.read_scan_enable(timeout)?);
+    println!("  LE features:  {:?}", socket.le_read_local_supported_features(timeout)?);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let Some(device_id) = device_id(env::args().nth(1)) else {
+        eprintln!("usage: hci-info [hciN]");
+        return ExitCode::FAILURE;
+    };
+    match info(device_id) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("hci-info: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Discover nearby devices over BR/EDR and LE, printing each device when it is found or
+//! updated.
+//!
+//! Usage: hci-scan [hciN]
+
+use std::env;
+use std::io::Result;
+use std::process::ExitCode;
+
+use bluez_hci::{Adapter, DiscoveredDevice, DiscoveryOptions};
+
+/// Parse a device given as "hciN" or "N", defaulting to hci0.
+fn device_id(arg: Option<String>) -> Option<u16> {
+    match arg {
+        Some(arg) => arg.strip_prefix("hci").unwrap_or(&arg).parse().ok(),
+        None => Some(0),
+    }
+}
+
+fn print_device(device: &DiscoveredDevice) {
+    let rssi = match device.rssi {
+        Some(rssi) => format!("{} dBm", rssi),
+        None => "-".into(),
+    };
+    println!("{}  {:<8}  {:>8}  {}",
+        device.address,
+        format!("{:?}", device.technology),
+        rssi,
+        device.name.as_deref().unwrap_or(""));
+}
+
+fn scan(device_id: u16) -> Result<()> {
+    let mut adapter = Adapter::open(device_id)?;
+    let mut discovery = adapter.discover(&DiscoveryOptions::default())?;
+    loop {
+        print_device(&discovery.next_device()?);
+    }
+}
+
+fn main() -> ExitCode {
+    let Some(device_id) = device_id(env::args().nth(1)) else {
+        eprintln!("usage: hci-scan [hciN]");
+        return ExitCode::FAILURE;
+    };
+    match scan(device_id) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("hci-scan: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
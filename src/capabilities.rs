@@ -0,0 +1,108 @@
+use libc::{EBUSY};
+use std::io::{Result};
+
+use super::adapter::Adapter;
+use super::info::{LocalVersion, SupportedCommands};
+#[cfg(feature = "le")]
+use super::le::LeFeatures;
+#[cfg(feature = "bredr")]
+use super::remote::LmpFeatures;
+use super::socket::{bind_channel, HCI_CHANNEL_USER};
+
+// Octets and bits of commands in the supported commands
+#[cfg(feature = "bredr")]
+const READ_LOCAL_FEATURES_COMMAND: (usize, u8) = (14, 5);
+const LE_READ_LOCAL_FEATURES_COMMAND: (usize, u8) = (25, 2);
+
+/// Parts of the crate that can be used with an adapter, going by the kernel and the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: LocalVersion,
+    pub commands: SupportedCommands,
+    /// LMP features of the controller, empty if it does not report them
+    #[cfg(feature = "bredr")]
+    pub lmp_features: LmpFeatures,
+    /// LE features of the controller, empty if it does not support LE
+    #[cfg(feature = "le")]
+    pub le_features: LeFeatures,
+    /// BR/EDR commands, with the `bredr` feature
+    pub bredr: bool,
+    /// LE commands, with the `le` feature
+    pub le: bool,
+    /// Extended advertising and scanning, such as `ExtendedAdvertiser` and `ExtLeScan`
+    pub extended_advertising: bool,
+    /// Connected and broadcast isochronous streams, with the `iso` feature
+    pub iso: bool,
+    /// Exclusive access to the controller through the user channel, once it is down
+    pub user_channel: bool,
+}
+
+impl Adapter {
+    /// Find out which parts of the crate will work with this adapter.
+    ///
+    /// Reads the version, supported commands and features of the controller, and checks
+    /// whether the kernel lets this process take the user channel of the device. If the device
+    /// is down, the user channel is taken and released at once. ISO support only reflects the
+    /// controller, as the kernel cannot be asked about ISO data on raw sockets.
+    pub fn capabilities(&mut self) -> Result<Capabilities> {
+        let timeout = self.timeout;
+        let socket = &mut self.socket;
+
+        let version = socket.read_local_version(timeout)?;
+        let commands = socket.read_local_supported_commands(timeout)?;
+
+        #[cfg(feature = "bredr")]
+        let (lmp_features, bredr) = {
+            let (octet, bit) = READ_LOCAL_FEATURES_COMMAND;
+            if commands.contains(octet, bit) {
+                let features = socket.read_local_supported_features(timeout)?;
+                (features, !features.contains(LmpFeatures::BREDR_NOT_SUPPORTED))
+            } else {
+                (LmpFeatures::empty(), false)
+            }
+        };
+        #[cfg(not(feature = "bredr"))]
+        let bredr = false;
+
+        let (octet, bit) = LE_READ_LOCAL_FEATURES_COMMAND;
+        let le = cfg!(feature = "le") && commands.contains(octet, bit);
+        #[cfg(feature = "le")]
+        let le_features = if le {
+            socket.le_read_local_supported_features(timeout)?
+        } else {
+            LeFeatures::empty()
+        };
+        #[cfg(feature = "le")]
+        let extended_advertising = le_features.contains(LeFeatures::EXTENDED_ADVERTISING);
+        #[cfg(not(feature = "le"))]
+        let extended_advertising = false;
+        #[cfg(feature = "iso")]
+        let iso = le_features.intersects(LeFeatures::CIS_CENTRAL
+            | LeFeatures::CIS_PERIPHERAL
+            | LeFeatures::ISOCHRONOUS_BROADCASTER
+            | LeFeatures::SYNCHRONIZED_RECEIVER);
+        #[cfg(not(feature = "iso"))]
+        let iso = false;
+
+        let device_id = socket.device_id()?;
+        let user_channel = match bind_channel(device_id, HCI_CHANNEL_USER) {
+            Ok(_) => true,
+            // The kernel refuses the user channel of a device that is up.
+            Err(e) => e.raw_os_error() == Some(EBUSY),
+        };
+
+        Ok(Capabilities {
+            version,
+            commands,
+            #[cfg(feature = "bredr")]
+            lmp_features,
+            #[cfg(feature = "le")]
+            le_features,
+            bredr,
+            le,
+            extended_advertising,
+            iso,
+            user_channel,
+        })
+    }
+}
@@ -0,0 +1,267 @@
+use bitflags::bitflags;
+use std::io::{Read, Result, Write};
+
+use super::io::{ReadFrom, WriteTo};
+
+bitflags! {
+    /// Major service classes advertised in a class of device
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct ServiceClasses: u16 {
+        const LIMITED_DISCOVERABLE = 0x0001;
+        const LE_AUDIO = 0x0002;
+        const POSITIONING = 0x0008;
+        const NETWORKING = 0x0010;
+        const RENDERING = 0x0020;
+        const CAPTURING = 0x0040;
+        const OBJECT_TRANSFER = 0x0080;
+        const AUDIO = 0x0100;
+        const TELEPHONY = 0x0200;
+        const INFORMATION = 0x0400;
+    }
+}
+
+code_enum!(
+    /// Major device class
+    MajorDeviceClass {
+        Miscellaneous = 0x00,
+        Computer = 0x01,
+        Phone = 0x02,
+        NetworkAccessPoint = 0x03,
+        AudioVideo = 0x04,
+        Peripheral = 0x05,
+        Imaging = 0x06,
+        Wearable = 0x07,
+        Toy = 0x08,
+        Health = 0x09,
+        Uncategorized = 0x1F,
+    }
+);
+
+code_enum!(
+    /// Minor device class of a computer
+    ComputerClass {
+        Uncategorized = 0x00,
+        Desktop = 0x01,
+        Server = 0x02,
+        Laptop = 0x03,
+        HandheldOrganizer = 0x04,
+        PalmSizeOrganizer = 0x05,
+        Wearable = 0x06,
+        Tablet = 0x07,
+    }
+);
+
+code_enum!(
+    /// Minor device class of a phone
+    PhoneClass {
+        Uncategorized = 0x00,
+        Cellular = 0x01,
+        Cordless = 0x02,
+        Smartphone = 0x03,
+        WiredModem = 0x04,
+        Isdn = 0x05,
+    }
+);
+
+code_enum!(
+    /// Minor device class of an audio or video device
+    AudioVideoClass {
+        Uncategorized = 0x00,
+        WearableHeadset = 0x01,
+        HandsFree = 0x02,
+        Microphone = 0x04,
+        Loudspeaker = 0x05,
+        Headphones = 0x06,
+        PortableAudio = 0x07,
+        CarAudio = 0x08,
+        SetTopBox = 0x09,
+        HifiAudio = 0x0A,
+        Vcr = 0x0B,
+        VideoCamera = 0x0C,
+        Camcorder = 0x0D,
+        VideoMonitor = 0x0E,
+        VideoDisplayAndLoudspeaker = 0x0F,
+        VideoConferencing = 0x10,
+        GamingToy = 0x12,
+    }
+);
+
+code_enum!(
+    /// Minor device class of a wearable device
+    WearableClass {
+        Wristwatch = 0x01,
+        Pager = 0x02,
+        Jacket = 0x03,
+        Helmet = 0x04,
+        Glasses = 0x05,
+    }
+);
+
+code_enum!(
+    /// Minor device class of a toy
+    ToyClass {
+        Robot = 0x01,
+        Vehicle = 0x02,
+        Doll = 0x03,
+        Controller = 0x04,
+        Game = 0x05,
+    }
+);
+
+/// Minor device class, interpreted according to the major device class
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MinorDeviceClass {
+    Computer(ComputerClass),
+    Phone(PhoneClass),
+    AudioVideo(AudioVideoClass),
+    Wearable(WearableClass),
+    Toy(ToyClass),
+    /// Minor class of a major class without a decoded minor class
+    Other(u8),
+}
+
+impl MinorDeviceClass {
+    /// Decode a 6-bit minor device class for a major device class.
+    pub fn decode(major: MajorDeviceClass, minor: u8) -> Self {
+        match major {
+            MajorDeviceClass::Computer => MinorDeviceClass::Computer(minor.into()),
+            MajorDeviceClass::Phone => MinorDeviceClass::Phone(minor.into()),
+            MajorDeviceClass::AudioVideo => MinorDeviceClass::AudioVideo(minor.into()),
+            MajorDeviceClass::Wearable => MinorDeviceClass::Wearable(minor.into()),
+            MajorDeviceClass::Toy => MinorDeviceClass::Toy(minor.into()),
+            _ => MinorDeviceClass::Other(minor),
+        }
+    }
+}
+
+impl From<MinorDeviceClass> for u8 {
+    fn from(value: MinorDeviceClass) -> u8 {
+        match value {
+            MinorDeviceClass::Computer(minor) => minor.into(),
+            MinorDeviceClass::Phone(minor) => minor.into(),
+            MinorDeviceClass::AudioVideo(minor) => minor.into(),
+            MinorDeviceClass::Wearable(minor) => minor.into(),
+            MinorDeviceClass::Toy(minor) => minor.into(),
+            MinorDeviceClass::Other(minor) => minor,
+        }
+    }
+}
+
+/// Class of device, as sent in inquiry responses and connection requests.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ClassOfDevice(u32);
+
+impl ClassOfDevice {
+    /// Create a class of device from its components.
+    pub fn new(services: ServiceClasses, major: MajorDeviceClass, minor: MinorDeviceClass) -> Self {
+        let major = u8::from(major) as u32 & 0x1F;
+        let minor = u8::from(minor) as u32 & 0x3F;
+        ClassOfDevice(((services.bits() as u32 & 0x07FF) << 13) | (major << 8) | (minor << 2))
+    }
+
+    /// Create a class of device from its 24-bit value
+    pub fn from_raw(class: u32) -> Self {
+        ClassOfDevice(class & 0x00FF_FFFF)
+    }
+
+    /// Return the 24-bit value of the class
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+
+    /// Return the major service classes
+    pub fn service_classes(&self) -> ServiceClasses {
+        ServiceClasses::from_bits_retain((self.0 >> 13) as u16)
+    }
+
+    /// Return the major device class
+    pub fn major_device_class(&self) -> MajorDeviceClass {
+        (((self.0 >> 8) & 0x1F) as u8).into()
+    }
+
+    /// Return the minor device class
+    pub fn minor_device_class(&self) -> MinorDeviceClass {
+        MinorDeviceClass::decode(self.major_device_class(), ((self.0 >> 2) & 0x3F) as u8)
+    }
+}
+
+impl ReadFrom for ClassOfDevice {
+    fn read_from<R: Read>(r: R) -> Result<(Self, usize)> {
+        let ([b0, b1, b2], size) = <[u8; 3]>::read_from(r)?;
+        Ok((ClassOfDevice(u32::from_le_bytes([b0, b1, b2, 0])), size))
+    }
+}
+
+impl WriteTo for ClassOfDevice {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        w.write(&self.0.to_le_bytes()[..3])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::ReadAs;
+
+    #[test]
+    fn components_pack_into_their_fields() {
+        let class = ClassOfDevice::new(
+            ServiceClasses::TELEPHONY | ServiceClasses::OBJECT_TRANSFER
+                | ServiceClasses::CAPTURING | ServiceClasses::NETWORKING,
+            MajorDeviceClass::Phone,
+            MinorDeviceClass::Phone(PhoneClass::Smartphone));
+        assert_eq!(class.raw(), 0x5A020C);
+
+        let class = ClassOfDevice::new(
+            ServiceClasses::AUDIO | ServiceClasses::RENDERING,
+            MajorDeviceClass::AudioVideo,
+            MinorDeviceClass::AudioVideo(AudioVideoClass::WearableHeadset));
+        assert_eq!(class.raw(), 0x240404);
+    }
+
+    #[test]
+    fn fields_unpack_from_the_raw_value() {
+        let class = ClassOfDevice::from_raw(0x5A020C);
+        assert_eq!(class.service_classes(), ServiceClasses::TELEPHONY | ServiceClasses::OBJECT_TRANSFER
+            | ServiceClasses::CAPTURING | ServiceClasses::NETWORKING);
+        assert_eq!(class.major_device_class(), MajorDeviceClass::Phone);
+        assert_eq!(class.minor_device_class(), MinorDeviceClass::Phone(PhoneClass::Smartphone));
+    }
+
+    #[test]
+    fn minor_class_depends_on_the_major_class() {
+        let class = ClassOfDevice::from_raw(0x00010C);
+        assert_eq!(class.major_device_class(), MajorDeviceClass::Computer);
+        assert_eq!(class.minor_device_class(), MinorDeviceClass::Computer(ComputerClass::Laptop));
+
+        let class = ClassOfDevice::from_raw(0x00050C);
+        assert_eq!(class.major_device_class(), MajorDeviceClass::Peripheral);
+        assert_eq!(class.minor_device_class(), MinorDeviceClass::Other(0x03));
+    }
+
+    #[test]
+    fn out_of_range_bits_are_masked() {
+        assert_eq!(ClassOfDevice::from_raw(0xFF00_0000).raw(), 0);
+
+        let class = ClassOfDevice::new(
+            ServiceClasses::from_bits_retain(0xF800),
+            MajorDeviceClass::Other(0xE0),
+            MinorDeviceClass::Other(0xC0));
+        assert_eq!(class.raw(), 0);
+
+        let class = ClassOfDevice::new(
+            ServiceClasses::empty(),
+            MajorDeviceClass::Uncategorized,
+            MinorDeviceClass::Other(0xFF));
+        assert_eq!(class.raw(), 0x001FFC);
+    }
+
+    #[test]
+    fn wire_format_is_three_little_endian_bytes() {
+        let class = ClassOfDevice::from_raw(0x5A020C);
+        assert_eq!(&*class.bytes().unwrap(), &[0x0C, 0x02, 0x5A]);
+        let (parsed, size) = (&[0x0C, 0x02, 0x5A][..]).read_as::<ClassOfDevice>().unwrap();
+        assert_eq!(parsed, class);
+        assert_eq!(size, 3);
+    }
+}
@@ -0,0 +1,41 @@
+//! Multi-byte fields with an explicit byte order.
+
+use alloc::vec::Vec;
+
+/// A value sent least significant byte first, the order of every HCI field
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Le<T>(pub T);
+
+/// A value sent most significant byte first, as in some advertising data formats
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Be<T>(pub T);
+
+macro_rules! impl_endian {
+    ($wrapper:ident, $from_bytes:ident, $to_bytes:ident, $($t:ty),+) => {
+        $(
+            impl $wrapper<$t> {
+                /// Size of the value in bytes
+                pub const SIZE: usize = size_of::<$t>();
+
+                /// Decode the value from the start of `bytes`, if it is long enough.
+                pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+                    let bytes = bytes.get(..Self::SIZE)?.try_into().ok()?;
+                    Some($wrapper(<$t>::$from_bytes(bytes)))
+                }
+
+                /// Return the encoded value
+                pub fn to_bytes(self) -> [u8; size_of::<$t>()] {
+                    self.0.$to_bytes()
+                }
+
+                /// Append the encoded value to a buffer.
+                pub fn encode_into(self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_bytes());
+                }
+            }
+        )+
+    }
+}
+
+impl_endian!(Le, from_le_bytes, to_le_bytes, u16, i16, u32, i32, u64, u128);
+impl_endian!(Be, from_be_bytes, to_be_bytes, u16, i16, u32, i32, u64, u128);
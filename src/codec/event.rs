@@ -0,0 +1,247 @@
+use super::{Reader, Result};
+
+pub const EVT_CMD_COMPLETE: u8 = 0x0E;
+pub const EVT_CMD_STATUS: u8 = 0x0F;
+const EVT_NUM_COMP_PKTS: u8 = 0x13;
+const EVT_NUM_COMP_BLOCKS: u8 = 0x48;
+const EVT_LE_META_EVENT: u8 = 0x3E;
+
+/// Mask of the connection handle in handle fields, which may carry flags in the upper bits
+const HANDLE_MASK: u16 = 0x0FFF;
+
+/// Return the offset of the connection handle in the parameters of an event
+fn handle_offset(code: u8) -> Option<usize> {
+    match code {
+        // Events starting with a status followed by the handle
+        0x03 | 0x05 | 0x06 | 0x08 | 0x09 | 0x0A | 0x0B | 0x0C | 0x0D | 0x14 | 0x1C | 0x1D
+        | 0x21 | 0x23 | 0x2C | 0x2D | 0x2E | 0x30 | 0x59 => Some(1),
+        // Events starting with the handle
+        0x11 | 0x1B | 0x38 | 0x39 | 0x57 => Some(0),
+        _ => None,
+    }
+}
+
+/// Return the offset of the connection handle in the parameters of an LE Meta subevent,
+/// after the subevent code
+fn le_handle_offset(subevent: u8) -> Option<usize> {
+    match subevent {
+        // Subevents starting with a status followed by the handle
+        0x01 | 0x03 | 0x04 | 0x0A | 0x0C | 0x17 | 0x19 | 0x1F | 0x21 | 0x23 | 0x29 | 0x2A => Some(1),
+        // Advertising Set Terminated, with the handle after the status and advertising handle
+        0x12 => Some(2),
+        // Subevents starting with the handle
+        0x05 | 0x06 | 0x07 | 0x14 | 0x16 | 0x1A | 0x20 => Some(0),
+        _ => None,
+    }
+}
+
+/// Parameters of an event, with the responses to commands decoded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EventBody<'a> {
+    CommandComplete {
+        /// Number of commands the controller can accept
+        ncmd: u8,
+        opcode: u16,
+        /// Return parameters, usually starting with a status
+        params: &'a [u8],
+    },
+    CommandStatus {
+        status: u8,
+        /// Number of commands the controller can accept
+        ncmd: u8,
+        opcode: u16,
+    },
+    /// Any other event
+    Other {
+        code: u8,
+        params: &'a [u8],
+    },
+}
+
+impl<'a> EventBody<'a> {
+    /// Parse the parameters of an event with the given code.
+    ///
+    /// Like `Packet::parse`, this never panics on arbitrary input.
+    pub fn parse(code: u8, params: &'a [u8]) -> Result<Self> {
+        let mut r = Reader(params);
+        match code {
+            EVT_CMD_COMPLETE => {
+                let ncmd = r.u8()?;
+                let opcode = r.u16()?;
+                Ok(EventBody::CommandComplete { ncmd, opcode, params: r.rest() })
+            },
+            EVT_CMD_STATUS => {
+                let status = r.u8()?;
+                let ncmd = r.u8()?;
+                let opcode = r.u16()?;
+                Ok(EventBody::CommandStatus { status, ncmd, opcode })
+            },
+            _ => Ok(EventBody::Other { code, params }),
+        }
+    }
+
+    /// Return the event code
+    pub fn code(&self) -> u8 {
+        match self {
+            EventBody::CommandComplete { .. } => EVT_CMD_COMPLETE,
+            EventBody::CommandStatus { .. } => EVT_CMD_STATUS,
+            EventBody::Other { code, .. } => *code,
+        }
+    }
+
+    /// Return the parameters after the fields decoded in the body
+    pub fn params(&self) -> &'a [u8] {
+        match self {
+            EventBody::CommandComplete { params, .. } | EventBody::Other { params, .. } => params,
+            EventBody::CommandStatus { .. } => &[],
+        }
+    }
+
+    /// Return the connection handle the event is about, if it has one.
+    ///
+    /// Command Complete and Command Status events are matched to commands by opcode and have
+    /// no handle. Number of Completed Packets and Number of Completed Data Blocks events may
+    /// list several handles; use `has_handle` for them.
+    pub fn handle(&self) -> Option<u16> {
+        let (params, offset) = match *self {
+            EventBody::Other { code: EVT_LE_META_EVENT, params } => {
+                let (&subevent, params) = params.split_first()?;
+                (params, le_handle_offset(subevent)?)
+            }
+            EventBody::Other { code, params } => (params, handle_offset(code)?),
+            _ => return None,
+        };
+        let mut r = Reader(params.get(offset..)?);
+        r.u16().ok().map(|handle| handle & HANDLE_MASK)
+    }
+
+    /// Return whether the event is about the connection with the given handle
+    pub fn has_handle(&self, handle: u16) -> bool {
+        match *self {
+            EventBody::Other { code: EVT_NUM_COMP_PKTS, params } => {
+                let mut r = Reader(params);
+                let num_handles = r.u8().unwrap_or(0);
+                (0..num_handles).any(|_| {
+                    let entry = r.u16().and_then(|entry| r.u16().map(|_| entry));
+                    entry.is_ok_and(|entry| entry & HANDLE_MASK == handle)
+                })
+            }
+            EventBody::Other { code: EVT_NUM_COMP_BLOCKS, params } => {
+                let mut r = Reader(params);
+                let num_handles = r.u16().and_then(|_| r.u8()).unwrap_or(0);
+                (0..num_handles).any(|_| {
+                    let entry = r.u16().and_then(|entry| r.u16().and_then(|_| r.u16()).map(|_| entry));
+                    entry.is_ok_and(|entry| entry & HANDLE_MASK == handle)
+                })
+            }
+            _ => self.handle() == Some(handle),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Return the handle found in an event whose parameters hold 0x0123 at `offset`
+    fn handle_at(code: u8, offset: usize) -> Option<u16> {
+        let mut params = Vec::new();
+        params.resize(offset, 0xFF);
+        params.extend_from_slice(&[0x23, 0x01, 0xFF, 0xFF]);
+        EventBody::parse(code, &params).unwrap().handle()
+    }
+
+    /// Return the handle found in an LE Meta subevent holding 0x0123 at `offset`
+    fn le_handle_at(subevent: u8, offset: usize) -> Option<u16> {
+        let mut params = Vec::new();
+        params.push(subevent);
+        params.resize(1 + offset, 0xFF);
+        params.extend_from_slice(&[0x23, 0x01, 0xFF, 0xFF]);
+        EventBody::parse(EVT_LE_META_EVENT, &params).unwrap().handle()
+    }
+
+    #[test]
+    fn truncated_command_events_are_rejected() {
+        let complete = [0x01, 0x03, 0x0C, 0x00];
+        for len in 0..3 {
+            assert_eq!(EventBody::parse(EVT_CMD_COMPLETE, &complete[..len]), Err(super::super::Error::Truncated));
+        }
+        let status = [0x00, 0x01, 0x05, 0x04];
+        for len in 0..4 {
+            assert_eq!(EventBody::parse(EVT_CMD_STATUS, &status[..len]), Err(super::super::Error::Truncated));
+        }
+    }
+
+    #[test]
+    fn command_complete_keeps_extra_parameters() {
+        let body = EventBody::parse(EVT_CMD_COMPLETE, &[0x01, 0x03, 0x0C, 0x00, 0xAA]).unwrap();
+        assert_eq!(body, EventBody::CommandComplete { ncmd: 1, opcode: 0x0C03, params: &[0x00, 0xAA] });
+    }
+
+    #[test]
+    fn truncated_completed_packets_match_no_handle() {
+        // Two handles announced, one and a half present
+        let params = [0x02, 0x23, 0x01, 0x01, 0x00, 0x45];
+        let body = EventBody::parse(EVT_NUM_COMP_PKTS, &params).unwrap();
+        assert!(body.has_handle(0x0123));
+        assert!(!body.has_handle(0x0045));
+    }
+
+    #[test]
+    fn empty_le_meta_event_has_no_handle() {
+        assert_eq!(EventBody::parse(EVT_LE_META_EVENT, &[]).unwrap().handle(), None);
+    }
+
+    #[test]
+    fn hardware_error_has_no_handle() {
+        assert_eq!(handle_at(0x10, 0), None);
+    }
+
+    #[test]
+    fn flush_occurred_starts_with_handle() {
+        assert_eq!(handle_at(0x11, 0), Some(0x0123));
+    }
+
+    #[test]
+    fn advertising_set_terminated_has_handle_after_adv_handle() {
+        assert_eq!(le_handle_at(0x12, 2), Some(0x0123));
+    }
+
+    #[test]
+    fn connectionless_iq_report_has_no_handle() {
+        assert_eq!(le_handle_at(0x15, 0), None);
+    }
+
+    #[test]
+    fn connection_iq_report_starts_with_handle() {
+        assert_eq!(le_handle_at(0x16, 0), Some(0x0123));
+    }
+
+    #[test]
+    fn cte_request_failed_has_handle_after_status() {
+        assert_eq!(le_handle_at(0x17, 1), Some(0x0123));
+    }
+
+    #[test]
+    fn request_peer_sca_complete_has_handle_after_status() {
+        assert_eq!(le_handle_at(0x1F, 1), Some(0x0123));
+    }
+
+    #[test]
+    fn cis_established_v2_has_handle_after_status() {
+        assert_eq!(le_handle_at(0x2A, 1), Some(0x0123));
+    }
+
+    #[test]
+    fn handle_flags_are_masked() {
+        let params = [0x00, 0x23, 0x31];
+        assert_eq!(EventBody::parse(0x05, &params).unwrap().handle(), Some(0x0123));
+    }
+
+    #[test]
+    fn truncated_handle_is_none() {
+        assert_eq!(EventBody::parse(0x05, &[0x00, 0x23]).unwrap().handle(), None);
+    }
+}
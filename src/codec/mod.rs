@@ -0,0 +1,82 @@
+//! Encoding and decoding of HCI packets.
+//!
+//! This module only needs `core` and `alloc`, so hosts that talk to a controller over their
+//! own H4 transport can frame and parse packets without an operating system. The rest of the
+//! crate needs `std` and is left out when the `std` feature is disabled.
+
+use core::fmt::{Display, Formatter};
+
+mod endian;
+mod event;
+mod names;
+mod packet;
+
+pub use endian::{Be, Le};
+pub use event::{EventBody, EVT_CMD_COMPLETE, EVT_CMD_STATUS};
+pub use names::{event_name, le_event_name, opcode_name};
+pub use packet::{HCI_ACLDATA_PKT, HCI_COMMAND_PKT, HCI_EVENT_PKT, HCI_ISODATA_PKT, HCI_SCODATA_PKT, ocf, ogf, opcode, Packet};
+
+/// Error returned for packets that cannot be encoded or decoded
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// The packet ends before its header or the data its header declares
+    Truncated,
+    /// The packet starts with an unknown packet type indicator
+    UnknownPacketType(u8),
+    /// The data does not fit in the length field of the header
+    TooLong,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Truncated => write!(f, "Truncated HCI packet"),
+            Error::UnknownPacketType(packet_type) => write!(f, "Unknown HCI packet type {:#04x}", packet_type),
+            Error::TooLong => write!(f, "HCI packet data too long"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Packets that cannot be decoded become `MalformedPacket` errors.
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> std::io::Error {
+        match e {
+            Error::TooLong => std::io::Error::new(std::io::ErrorKind::InvalidInput, e),
+            _ => crate::error::MalformedPacket.into(),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Cursor over little-endian fields of a packet
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(Error::Truncated);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(Le::<u16>::SIZE)?;
+        let Le(value) = Le::<u16>::from_slice(bytes).ok_or(Error::Truncated)?;
+        Ok(value)
+    }
+
+    fn rest(self) -> &'a [u8] {
+        self.0
+    }
+}
@@ -0,0 +1,321 @@
+//! Names of commands and events, as written in the Core Specification, for logging.
+
+/// Return the name of the command with the given opcode, if it is known.
+///
+/// Vendor commands have no name, since their meaning depends on the manufacturer.
+pub fn opcode_name(opcode: u16) -> Option<&'static str> {
+    Some(match opcode {
+        0x0401 => "Inquiry",
+        0x0402 => "Inquiry Cancel",
+        0x0405 => "Create Connection",
+        0x0406 => "Disconnect",
+        0x0408 => "Create Connection Cancel",
+        0x040B => "Link Key Request Reply",
+        0x040C => "Link Key Request Negative Reply",
+        0x040D => "PIN Code Request Reply",
+        0x040E => "PIN Code Request Negative Reply",
+        0x0411 => "Authentication Requested",
+        0x041B => "Read Remote Supported Features",
+        0x041C => "Read Remote Extended Features",
+        0x041D => "Read Remote Version Information",
+        0x0428 => "Setup Synchronous Connection",
+        0x0429 => "Accept Synchronous Connection Request",
+        0x042A => "Reject Synchronous Connection Request",
+        0x042B => "IO Capability Request Reply",
+        0x042C => "User Confirmation Request Reply",
+        0x042D => "User Confirmation Request Negative Reply",
+        0x042E => "User Passkey Request Reply",
+        0x042F => "User Passkey Request Negative Reply",
+        0x0433 => "Remote OOB Data Request Negative Reply",
+        0x0434 => "IO Capability Request Negative Reply",
+        0x043D => "Enhanced Setup Synchronous Connection",
+        0x043E => "Enhanced Accept Synchronous Connection Request",
+        0x0441 => "Set Connectionless Peripheral Broadcast",
+        0x0442 => "Set Connectionless Peripheral Broadcast Receive",
+        0x0443 => "Start Synchronization Train",
+        0x0444 => "Receive Synchronization Train",
+        0x0801 => "Hold Mode",
+        0x0803 => "Sniff Mode",
+        0x0804 => "Exit Sniff Mode",
+        0x0805 => "Park Mode",
+        0x0806 => "Exit Park Mode",
+        0x0807 => "QoS Setup",
+        0x0809 => "Role Discovery",
+        0x080B => "Switch Role",
+        0x080C => "Read Link Policy",
+        0x080D => "Write Link Policy",
+        0x080E => "Read Default Link Policy",
+        0x080F => "Write Default Link Policy",
+        0x0810 => "Flow Specification",
+        0x0811 => "Sniff Subrating",
+        0x0C01 => "Set Event Mask",
+        0x0C03 => "Reset",
+        0x0C08 => "Flush",
+        0x0C09 => "Read PIN Type",
+        0x0C0A => "Write PIN Type",
+        0x0C13 => "Write Local Name",
+        0x0C14 => "Read Local Name",
+        0x0C19 => "Read Scan Enable",
+        0x0C1A => "Write Scan Enable",
+        0x0C1F => "Read Authentication Enable",
+        0x0C20 => "Write Authentication Enable",
+        0x0C23 => "Read Class of Device",
+        0x0C24 => "Write Class of Device",
+        0x0C25 => "Read Voice Setting",
+        0x0C26 => "Write Voice Setting",
+        0x0C31 => "Set Controller to Host Flow Control",
+        0x0C33 => "Host Buffer Size",
+        0x0C35 => "Host Number of Completed Packets",
+        0x0C36 => "Read Link Supervision Timeout",
+        0x0C37 => "Write Link Supervision Timeout",
+        0x0C38 => "Read Number of Supported IAC",
+        0x0C39 => "Read Current IAC LAP",
+        0x0C3A => "Write Current IAC LAP",
+        0x0C3F => "Set AFH Classification",
+        0x0C44 => "Read Inquiry Mode",
+        0x0C45 => "Write Inquiry Mode",
+        0x0C51 => "Read Extended Inquiry Response",
+        0x0C52 => "Write Extended Inquiry Response",
+        0x0C5A => "Read Default Erroneous Data Reporting",
+        0x0C5B => "Write Default Erroneous Data Reporting",
+        0x0C5F => "Enhanced Flush",
+        0x0C63 => "Set Event Mask Page 2",
+        0x0C66 => "Read Flow Control Mode",
+        0x0C67 => "Write Flow Control Mode",
+        0x0C74 => "Set Reserved LT_ADDR",
+        0x0C75 => "Delete Reserved LT_ADDR",
+        0x0C76 => "Set Connectionless Peripheral Broadcast Data",
+        0x0C77 => "Read Synchronization Train Parameters",
+        0x0C78 => "Write Synchronization Train Parameters",
+        0x0C83 => "Configure Data Path",
+        0x1001 => "Read Local Version Information",
+        0x1002 => "Read Local Supported Commands",
+        0x1003 => "Read Local Supported Features",
+        0x1005 => "Read Buffer Size",
+        0x1009 => "Read BD_ADDR",
+        0x100A => "Read Data Block Size",
+        0x100B => "Read Local Codecs",
+        0x100D => "Read Local Codecs [v2]",
+        0x100E => "Read Local Codec Capabilities",
+        0x100F => "Read Local Controller Delay",
+        0x1401 => "Read Failed Contact Counter",
+        0x1402 => "Reset Failed Contact Counter",
+        0x1405 => "Read RSSI",
+        0x1406 => "Read AFH Map",
+        0x1801 => "Read Loopback Mode",
+        0x1802 => "Write Loopback Mode",
+        0x1803 => "Enable Device Under Test Mode",
+        0x1804 => "Write Simple Pairing Debug Mode",
+        0x2001 => "LE Set Event Mask",
+        0x2002 => "LE Read Buffer Size",
+        0x2003 => "LE Read Local Supported Features",
+        0x2005 => "LE Set Random Address",
+        0x2006 => "LE Set Advertising Parameters",
+        0x2008 => "LE Set Advertising Data",
+        0x2009 => "LE Set Scan Response Data",
+        0x200A => "LE Set Advertising Enable",
+        0x200B => "LE Set Scan Parameters",
+        0x200C => "LE Set Scan Enable",
+        0x200D => "LE Create Connection",
+        0x200E => "LE Create Connection Cancel",
+        0x200F => "LE Read Filter Accept List Size",
+        0x2010 => "LE Clear Filter Accept List",
+        0x2011 => "LE Add Device to Filter Accept List",
+        0x2012 => "LE Remove Device from Filter Accept List",
+        0x2013 => "LE Connection Update",
+        0x2016 => "LE Read Remote Features",
+        0x2017 => "LE Encrypt",
+        0x2018 => "LE Rand",
+        0x2019 => "LE Enable Encryption",
+        0x201A => "LE LTK Request Reply",
+        0x201B => "LE LTK Request Negative Reply",
+        0x201C => "LE Read Supported States",
+        0x201D => "LE Receiver Test",
+        0x201E => "LE Transmitter Test",
+        0x201F => "LE Test End",
+        0x2020 => "LE Remote Connection Parameter Request Reply",
+        0x2021 => "LE Remote Connection Parameter Request Negative Reply",
+        0x2022 => "LE Set Data Length",
+        0x2023 => "LE Read Suggested Default Data Length",
+        0x2024 => "LE Write Suggested Default Data Length",
+        0x2027 => "LE Add Device to Resolving List",
+        0x2028 => "LE Remove Device from Resolving List",
+        0x2029 => "LE Clear Resolving List",
+        0x202A => "LE Read Resolving List Size",
+        0x202B => "LE Read Peer Resolvable Address",
+        0x202C => "LE Read Local Resolvable Address",
+        0x202D => "LE Set Address Resolution Enable",
+        0x202E => "LE Set Resolvable Private Address Timeout",
+        0x202F => "LE Read Maximum Data Length",
+        0x2030 => "LE Read PHY",
+        0x2031 => "LE Set Default PHY",
+        0x2032 => "LE Set PHY",
+        0x2033 => "LE Receiver Test [v2]",
+        0x2034 => "LE Transmitter Test [v2]",
+        0x2035 => "LE Set Advertising Set Random Address",
+        0x2036 => "LE Set Extended Advertising Parameters",
+        0x2037 => "LE Set Extended Advertising Data",
+        0x2038 => "LE Set Extended Scan Response Data",
+        0x2039 => "LE Set Extended Advertising Enable",
+        0x203A => "LE Read Maximum Advertising Data Length",
+        0x203B => "LE Read Number of Supported Advertising Sets",
+        0x203C => "LE Remove Advertising Set",
+        0x203D => "LE Clear Advertising Sets",
+        0x203E => "LE Set Periodic Advertising Parameters",
+        0x203F => "LE Set Periodic Advertising Data",
+        0x2040 => "LE Set Periodic Advertising Enable",
+        0x2041 => "LE Set Extended Scan Parameters",
+        0x2042 => "LE Set Extended Scan Enable",
+        0x2043 => "LE Extended Create Connection",
+        0x2044 => "LE Periodic Advertising Create Sync",
+        0x2045 => "LE Periodic Advertising Create Sync Cancel",
+        0x2046 => "LE Periodic Advertising Terminate Sync",
+        0x204C => "LE Read RF Path Compensation",
+        0x204D => "LE Write RF Path Compensation",
+        0x204E => "LE Set Privacy Mode",
+        0x204F => "LE Receiver Test [v3]",
+        0x2050 => "LE Transmitter Test [v3]",
+        0x2051 => "LE Set Connectionless CTE Transmit Parameters",
+        0x2052 => "LE Set Connectionless CTE Transmit Enable",
+        0x2053 => "LE Set Connectionless IQ Sampling Enable",
+        0x2054 => "LE Set Connection CTE Receive Parameters",
+        0x2055 => "LE Set Connection CTE Transmit Parameters",
+        0x2056 => "LE Connection CTE Request Enable",
+        0x2057 => "LE Connection CTE Response Enable",
+        0x2058 => "LE Read Antenna Information",
+        0x2059 => "LE Set Periodic Advertising Receive Enable",
+        0x2060 => "LE Read Buffer Size [v2]",
+        0x2062 => "LE Set CIG Parameters",
+        0x2064 => "LE Create CIS",
+        0x2065 => "LE Remove CIG",
+        0x2066 => "LE Accept CIS Request",
+        0x2067 => "LE Reject CIS Request",
+        0x2068 => "LE Create BIG",
+        0x206A => "LE Terminate BIG",
+        0x206B => "LE BIG Create Sync",
+        0x206C => "LE BIG Terminate Sync",
+        0x206E => "LE Setup ISO Data Path",
+        0x206F => "LE Remove ISO Data Path",
+        0x2070 => "LE ISO Transmit Test",
+        0x2071 => "LE ISO Receive Test",
+        0x2072 => "LE ISO Read Test Counters",
+        0x2073 => "LE ISO Test End",
+        0x2074 => "LE Set Host Feature",
+        0x2076 => "LE Enhanced Read Transmit Power Level",
+        0x2077 => "LE Read Remote Transmit Power Level",
+        0x2078 => "LE Set Path Loss Reporting Parameters",
+        0x2079 => "LE Set Path Loss Reporting Enable",
+        0x207A => "LE Set Transmit Power Reporting Enable",
+        0x207D => "LE Set Default Subrate",
+        0x207E => "LE Subrate Request",
+        _ => return None,
+    })
+}
+
+/// Return the name of the event with the given code, if it is known.
+pub fn event_name(code: u8) -> Option<&'static str> {
+    Some(match code {
+        0x01 => "Inquiry Complete",
+        0x02 => "Inquiry Result",
+        0x03 => "Connection Complete",
+        0x04 => "Connection Request",
+        0x05 => "Disconnection Complete",
+        0x06 => "Authentication Complete",
+        0x07 => "Remote Name Request Complete",
+        0x08 => "Encryption Change",
+        0x09 => "Change Connection Link Key Complete",
+        0x0B => "Read Remote Supported Features Complete",
+        0x0C => "Read Remote Version Information Complete",
+        0x0D => "QoS Setup Complete",
+        0x0E => "Command Complete",
+        0x0F => "Command Status",
+        0x10 => "Hardware Error",
+        0x11 => "Flush Occurred",
+        0x12 => "Role Change",
+        0x13 => "Number of Completed Packets",
+        0x14 => "Mode Change",
+        0x15 => "Return Link Keys",
+        0x16 => "PIN Code Request",
+        0x17 => "Link Key Request",
+        0x18 => "Link Key Notification",
+        0x19 => "Loopback Command",
+        0x1A => "Data Buffer Overflow",
+        0x1B => "Max Slots Change",
+        0x1C => "Read Clock Offset Complete",
+        0x1D => "Connection Packet Type Changed",
+        0x1E => "QoS Violation",
+        0x20 => "Page Scan Repetition Mode Change",
+        0x21 => "Flow Specification Complete",
+        0x22 => "Inquiry Result with RSSI",
+        0x23 => "Read Remote Extended Features Complete",
+        0x2C => "Synchronous Connection Complete",
+        0x2D => "Synchronous Connection Changed",
+        0x2E => "Sniff Subrating",
+        0x2F => "Extended Inquiry Result",
+        0x30 => "Encryption Key Refresh Complete",
+        0x31 => "IO Capability Request",
+        0x32 => "IO Capability Response",
+        0x33 => "User Confirmation Request",
+        0x34 => "User Passkey Request",
+        0x35 => "Remote OOB Data Request",
+        0x36 => "Simple Pairing Complete",
+        0x38 => "Link Supervision Timeout Changed",
+        0x39 => "Enhanced Flush Complete",
+        0x3B => "User Passkey Notification",
+        0x3C => "Keypress Notification",
+        0x3D => "Remote Host Supported Features Notification",
+        0x3E => "LE Meta",
+        0x48 => "Number of Completed Data Blocks",
+        0x4F => "Synchronization Train Complete",
+        0x50 => "Synchronization Train Received",
+        0x51 => "Connectionless Peripheral Broadcast Receive",
+        0x52 => "Connectionless Peripheral Broadcast Timeout",
+        0x55 => "Connectionless Peripheral Broadcast Channel Map Change",
+        0x57 => "Authenticated Payload Timeout Expired",
+        0xFF => "Vendor",
+        _ => return None,
+    })
+}
+
+/// Return the name of the LE Meta event with the given subevent code, if it is known.
+pub fn le_event_name(subevent: u8) -> Option<&'static str> {
+    Some(match subevent {
+        0x01 => "LE Connection Complete",
+        0x02 => "LE Advertising Report",
+        0x03 => "LE Connection Update Complete",
+        0x04 => "LE Read Remote Features Complete",
+        0x05 => "LE Long Term Key Request",
+        0x06 => "LE Remote Connection Parameter Request",
+        0x07 => "LE Data Length Change",
+        0x08 => "LE Read Local P-256 Public Key Complete",
+        0x09 => "LE Generate DHKey Complete",
+        0x0A => "LE Enhanced Connection Complete",
+        0x0B => "LE Directed Advertising Report",
+        0x0C => "LE PHY Update Complete",
+        0x0D => "LE Extended Advertising Report",
+        0x0E => "LE Periodic Advertising Sync Established",
+        0x0F => "LE Periodic Advertising Report",
+        0x10 => "LE Periodic Advertising Sync Lost",
+        0x11 => "LE Scan Timeout",
+        0x12 => "LE Advertising Set Terminated",
+        0x13 => "LE Scan Request Received",
+        0x14 => "LE Channel Selection Algorithm",
+        0x15 => "LE Connectionless IQ Report",
+        0x16 => "LE Connection IQ Report",
+        0x17 => "LE CTE Request Failed",
+        0x18 => "LE Periodic Advertising Sync Transfer Received",
+        0x19 => "LE CIS Established",
+        0x1A => "LE CIS Request",
+        0x1B => "LE Create BIG Complete",
+        0x1C => "LE Terminate BIG Complete",
+        0x1D => "LE BIG Sync Established",
+        0x1E => "LE BIG Sync Lost",
+        0x1F => "LE Request Peer SCA Complete",
+        0x20 => "LE Path Loss Threshold",
+        0x21 => "LE Transmit Power Reporting",
+        0x22 => "LE BIGInfo Advertising Report",
+        0x23 => "LE Subrate Change",
+        0x29 => "LE Enhanced Connection Complete [v2]",
+        _ => return None,
+    })
+}
@@ -0,0 +1,228 @@
+use alloc::vec::Vec;
+
+use super::{Error, Le, Reader, Result};
+
+pub const HCI_COMMAND_PKT: u8 = 0x01;
+pub const HCI_ACLDATA_PKT: u8 = 0x02;
+pub const HCI_SCODATA_PKT: u8 = 0x03;
+pub const HCI_EVENT_PKT: u8 = 0x04;
+pub const HCI_ISODATA_PKT: u8 = 0x05;
+
+/// Construct an opcode from its group and command fields.
+pub fn opcode(ogf: u16, ocf: u16) -> u16 {
+    (ocf & 0x03FF) | (ogf << 10)
+}
+
+/// Return the opcode group field of an opcode
+pub fn ogf(opcode: u16) -> u16 {
+    opcode >> 10
+}
+
+/// Return the opcode command field of an opcode
+pub fn ocf(opcode: u16) -> u16 {
+    opcode & 0x03FF
+}
+
+/// An HCI packet, borrowing its parameters or data
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Command {
+        opcode: u16,
+        params: &'a [u8],
+    },
+    Acl {
+        handle: u16,
+        /// Packet boundary flag
+        packet_boundary: u8,
+        /// Broadcast flag
+        broadcast: u8,
+        data: &'a [u8],
+    },
+    Sco {
+        handle: u16,
+        /// Packet status flag
+        status: u8,
+        data: &'a [u8],
+    },
+    Event {
+        code: u8,
+        params: &'a [u8],
+    },
+    Iso {
+        handle: u16,
+        /// Packet boundary flag
+        packet_boundary: u8,
+        /// Whether the data starts with a time stamp
+        has_timestamp: bool,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> Packet<'a> {
+    /// Parse a packet preceded by its H4 packet type indicator.
+    ///
+    /// Bytes after the length given in the header are ignored. Any input is either parsed or
+    /// rejected with an error, without panicking, so this can be fed arbitrary data.
+    pub fn parse(buf: &'a [u8]) -> Result<Self> {
+        let mut r = Reader(buf);
+        match r.u8()? {
+            HCI_COMMAND_PKT => {
+                let opcode = r.u16()?;
+                let plen = r.u8()?;
+                Ok(Packet::Command { opcode, params: r.bytes(plen.into())? })
+            },
+            HCI_ACLDATA_PKT => {
+                let handle_flags = r.u16()?;
+                let dlen = r.u16()?;
+                Ok(Packet::Acl {
+                    handle: handle_flags & 0x0FFF,
+                    packet_boundary: ((handle_flags >> 12) & 0x03) as u8,
+                    broadcast: ((handle_flags >> 14) & 0x03) as u8,
+                    data: r.bytes(dlen.into())?,
+                })
+            },
+            HCI_SCODATA_PKT => {
+                let handle_flags = r.u16()?;
+                let dlen = r.u8()?;
+                Ok(Packet::Sco {
+                    handle: handle_flags & 0x0FFF,
+                    status: ((handle_flags >> 12) & 0x03) as u8,
+                    data: r.bytes(dlen.into())?,
+                })
+            },
+            HCI_EVENT_PKT => {
+                let code = r.u8()?;
+                let plen = r.u8()?;
+                Ok(Packet::Event { code, params: r.bytes(plen.into())? })
+            },
+            HCI_ISODATA_PKT => {
+                let handle_flags = r.u16()?;
+                let dlen = r.u16()?;
+                Ok(Packet::Iso {
+                    handle: handle_flags & 0x0FFF,
+                    packet_boundary: ((handle_flags >> 12) & 0x03) as u8,
+                    has_timestamp: handle_flags & (1 << 14) != 0,
+                    data: r.bytes((dlen & 0x3FFF).into())?,
+                })
+            },
+            packet_type => Err(Error::UnknownPacketType(packet_type)),
+        }
+    }
+
+    /// Return the H4 packet type indicator of the packet
+    pub fn packet_type(&self) -> u8 {
+        match self {
+            Packet::Command { .. } => HCI_COMMAND_PKT,
+            Packet::Acl { .. } => HCI_ACLDATA_PKT,
+            Packet::Sco { .. } => HCI_SCODATA_PKT,
+            Packet::Event { .. } => HCI_EVENT_PKT,
+            Packet::Iso { .. } => HCI_ISODATA_PKT,
+        }
+    }
+
+    /// Encode the packet, preceded by its H4 packet type indicator.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.encode_into(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Append the packet to a buffer, preceded by its H4 packet type indicator.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let len_u8 = |data: &[u8]| u8::try_from(data.len()).map_err(|_| Error::TooLong);
+        let len_u16 = |data: &[u8], max: usize| {
+            if data.len() > max {
+                return Err(Error::TooLong);
+            }
+            Ok(data.len() as u16)
+        };
+
+        buf.push(self.packet_type());
+        match *self {
+            Packet::Command { opcode, params } => {
+                Le(opcode).encode_into(buf);
+                buf.push(len_u8(params)?);
+                buf.extend_from_slice(params);
+            },
+            Packet::Acl { handle, packet_boundary, broadcast, data } => {
+                let handle_flags = (handle & 0x0FFF)
+                    | u16::from(packet_boundary & 0x03) << 12
+                    | u16::from(broadcast & 0x03) << 14;
+                Le(handle_flags).encode_into(buf);
+                Le(len_u16(data, 0xFFFF)?).encode_into(buf);
+                buf.extend_from_slice(data);
+            },
+            Packet::Sco { handle, status, data } => {
+                let handle_flags = (handle & 0x0FFF) | u16::from(status & 0x03) << 12;
+                Le(handle_flags).encode_into(buf);
+                buf.push(len_u8(data)?);
+                buf.extend_from_slice(data);
+            },
+            Packet::Event { code, params } => {
+                buf.push(code);
+                buf.push(len_u8(params)?);
+                buf.extend_from_slice(params);
+            },
+            Packet::Iso { handle, packet_boundary, has_timestamp, data } => {
+                let handle_flags = (handle & 0x0FFF)
+                    | u16::from(packet_boundary & 0x03) << 12
+                    | u16::from(has_timestamp) << 14;
+                Le(handle_flags).encode_into(buf);
+                Le(len_u16(data, 0x3FFF)?).encode_into(buf);
+                buf.extend_from_slice(data);
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One valid packet of each type
+    const PACKETS: &[&[u8]] = &[
+        &[HCI_COMMAND_PKT, 0x03, 0x0C, 0x00],
+        &[HCI_ACLDATA_PKT, 0x01, 0x20, 0x03, 0x00, 0xAA, 0xBB, 0xCC],
+        &[HCI_SCODATA_PKT, 0x01, 0x00, 0x02, 0xAA, 0xBB],
+        &[HCI_EVENT_PKT, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00],
+        &[HCI_ISODATA_PKT, 0x01, 0x20, 0x02, 0x00, 0xAA, 0xBB],
+    ];
+
+    #[test]
+    fn valid_packets_round_trip() {
+        for &packet in PACKETS {
+            assert_eq!(Packet::parse(packet).unwrap().encode().unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn truncated_packets_are_rejected() {
+        for &packet in PACKETS {
+            for len in 0..packet.len() {
+                assert_eq!(Packet::parse(&packet[..len]), Err(Error::Truncated), "{:02x?}", &packet[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn bytes_after_the_declared_length_are_ignored() {
+        for &packet in PACKETS {
+            let mut oversized = packet.to_vec();
+            oversized.extend_from_slice(&[0xFF; 300]);
+            assert_eq!(Packet::parse(&oversized), Packet::parse(packet));
+        }
+    }
+
+    #[test]
+    fn lengths_larger_than_the_data_are_rejected() {
+        assert_eq!(Packet::parse(&[HCI_ACLDATA_PKT, 0x01, 0x00, 0xFF, 0xFF, 0x00]), Err(Error::Truncated));
+        assert_eq!(Packet::parse(&[HCI_ISODATA_PKT, 0x01, 0x00, 0xFF, 0x3F, 0x00]), Err(Error::Truncated));
+        assert_eq!(Packet::parse(&[HCI_EVENT_PKT, 0x3E, 0xFF, 0x00]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn unknown_packet_types_are_rejected() {
+        assert_eq!(Packet::parse(&[0x00, 0x01, 0x02]), Err(Error::UnknownPacketType(0x00)));
+    }
+}
@@ -0,0 +1,163 @@
+use libc::{c_int, ETIMEDOUT};
+use std::collections::VecDeque;
+use std::io::{Error, Result};
+
+use super::codec::{self, EventBody};
+use super::error::{MalformedPacket, StatusError};
+use super::filter::HciFilter;
+use super::socket::{EVT_CMD_COMPLETE, EVT_CMD_STATUS, HCI_EVENT_PKT, Socket};
+
+/// A command and the decoding of its return parameters, for sending in a batch
+pub trait Command {
+    /// Value decoded from the return parameters
+    type Response;
+
+    /// Return the opcode of the command.
+    fn opcode(&self) -> u16;
+
+    /// Encode the parameters of the command.
+    fn params(&self) -> Result<Vec<u8>>;
+
+    /// Decode the return parameters of the Command Complete event, following the status.
+    fn parse_response(&self, data: &[u8]) -> Result<Self::Response>;
+}
+
+/// A command given by its opcode and encoded parameters, whose response is the undecoded
+/// return parameters
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawCommand {
+    pub opcode: u16,
+    pub params: Vec<u8>,
+}
+
+impl RawCommand {
+    pub fn new(ogf: u16, ocf: u16, params: &[u8]) -> Self {
+        RawCommand { opcode: codec::opcode(ogf, ocf), params: params.into() }
+    }
+}
+
+impl Command for RawCommand {
+    type Response = Box<[u8]>;
+
+    fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    fn params(&self) -> Result<Vec<u8>> {
+        Ok(self.params.clone())
+    }
+
+    fn parse_response(&self, data: &[u8]) -> Result<Box<[u8]>> {
+        Ok(data.into())
+    }
+}
+
+/// Result of a command of a batch that the controller accepted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchResponse<T> {
+    /// The command completed, with its decoded return parameters
+    Complete(T),
+    /// The command was started, as reported by a successful Command Status. Its outcome is
+    /// reported later by another event.
+    Status,
+}
+
+/// The part of an event needed to finish a command of a batch
+enum Answer {
+    Complete(Box<[u8]>),
+    Status(u8),
+}
+
+impl Socket {
+    /// Send several commands without waiting for each response before sending the next, and
+    /// return one result per command, in the order of the commands.
+    ///
+    /// Commands are sent as long as the controller has credits for them, going by the number
+    /// of commands it last reported it could accept. Responses are decoded like in `execute`:
+    /// a failure status becomes a `StatusError` for that command alone. Commands answered with
+    /// a successful Command Status get `BatchResponse::Status`. The batch stops at the first
+    /// error of the socket or timeout, which waits up to `timeout` milliseconds for each
+    /// response. That error is then the result of the first command left without a response,
+    /// and a copy of it the result of the others, while the responses already received are
+    /// kept.
+    pub fn send_batch<C: Command>(&mut self, commands: &[C], timeout: c_int) -> Result<Vec<Result<BatchResponse<C::Response>>>> {
+        let old_filter = self.get_filter()?;
+
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_CMD_STATUS)?;
+        filter.set_event(EVT_CMD_COMPLETE)?;
+        self.set_filter(&filter)?;
+
+        let mut responses: Vec<Option<Result<BatchResponse<C::Response>>>> = commands.iter().map(|_| None).collect();
+        let stopped = self.run_batch(commands, &mut responses, timeout);
+
+        // The responses received are returned even if the filter cannot be restored.
+        let _ = self.set_filter(&old_filter);
+
+        // The first command left without a response gets the error itself, the others a copy.
+        let mut stopped = stopped.err();
+        Ok(responses.into_iter()
+            .map(|response| response.unwrap_or_else(|| Err(match stopped.take() {
+                Some(e) => {
+                    stopped = Some(Error::new(e.kind(), e.to_string()));
+                    e
+                }
+                None => Error::from_raw_os_error(ETIMEDOUT),
+            })))
+            .collect())
+    }
+
+    /// Send the commands and store their responses, until all are answered or an error stops
+    /// the batch.
+    fn run_batch<C: Command>(&mut self, commands: &[C], responses: &mut [Option<Result<BatchResponse<C::Response>>>], timeout: c_int) -> Result<()> {
+        // Indices of the commands sent and not yet answered, in the order they were sent
+        let mut pending = VecDeque::new();
+        let mut next = 0;
+        // Assume a single credit until the controller reports its own count.
+        let mut credits = 1u8;
+
+        while next < commands.len() || !pending.is_empty() {
+            while credits > 0 && next < commands.len() {
+                let command = &commands[next];
+                let opcode = command.opcode();
+                self.send_cmd(codec::ogf(opcode), codec::ocf(opcode), &command.params()?)?;
+                pending.push_back(next);
+                next += 1;
+                credits -= 1;
+            }
+
+            let (opcode, ncmd, response) = self.recv_event_body(timeout, |event| match *event {
+                EventBody::CommandComplete { ncmd, opcode, params } =>
+                    (opcode, ncmd, Answer::Complete(params.into())),
+                EventBody::CommandStatus { status, ncmd, opcode } =>
+                    (opcode, ncmd, Answer::Status(status)),
+                EventBody::Other { .. } => (0, credits, Answer::Status(0)),
+            })?;
+            credits = ncmd;
+
+            // Commands with the same opcode are answered in the order they were sent. Events
+            // with opcode 0 only hand back credits.
+            if let Some(position) = pending.iter().position(|&i| commands[i].opcode() == opcode) {
+                let index = pending.remove(position).unwrap();
+                responses[index] = Some(match response {
+                    Answer::Complete(data) => match data.split_first() {
+                        Some((0, params)) => commands[index].parse_response(params).map(BatchResponse::Complete),
+                        Some((&status, _)) => Err(StatusError(status).into()),
+                        None => Err(MalformedPacket.into()),
+                    },
+                    Answer::Status(0) => Ok(BatchResponse::Status),
+                    Answer::Status(status) => Err(StatusError(status).into()),
+                });
+            }
+
+            // With nothing outstanding, no event would hand back credits the controller
+            // reported as 0, so send the next command anyway.
+            if pending.is_empty() {
+                credits = credits.max(1);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! Company identifiers assigned by the Bluetooth SIG.
+//!
+//! They identify the manufacturer of a controller, the owner of manufacturer specific
+//! advertising data and the vendor of a codec. Names are only available with the
+//! `company-names` feature, which adds a partial table of names to the crate.
+
+use std::fmt::{self, Debug, Display, Formatter};
+
+#[cfg(feature = "company-names")]
+mod names;
+
+#[cfg(not(feature = "company-names"))]
+mod names {
+    pub(super) fn company_name(_id: u16) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Identifier of a company, assigned by the Bluetooth SIG
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CompanyId(pub u16);
+
+impl CompanyId {
+    pub const ERICSSON: CompanyId = CompanyId(0x0000);
+    pub const INTEL: CompanyId = CompanyId(0x0002);
+    pub const MICROSOFT: CompanyId = CompanyId(0x0006);
+    pub const QUALCOMM_TECHNOLOGIES_INTERNATIONAL: CompanyId = CompanyId(0x000A);
+    pub const BROADCOM: CompanyId = CompanyId(0x000F);
+    pub const APPLE: CompanyId = CompanyId(0x004C);
+    pub const NORDIC_SEMICONDUCTOR: CompanyId = CompanyId(0x0059);
+    pub const REALTEK: CompanyId = CompanyId(0x005D);
+    pub const SAMSUNG: CompanyId = CompanyId(0x0075);
+    pub const GOOGLE: CompanyId = CompanyId(0x00E0);
+    /// Reserved for testing and for data of no particular company
+    pub const INTERNAL_USE: CompanyId = CompanyId(0xFFFF);
+
+    /// Return the name of the company, if the `company-names` feature is enabled and the
+    /// identifier is in its table, which only holds some of the assigned identifiers
+    pub fn name(&self) -> Option<&'static str> {
+        names::company_name(self.0)
+    }
+}
+
+impl From<u16> for CompanyId {
+    fn from(id: u16) -> Self {
+        CompanyId(id)
+    }
+}
+
+impl From<CompanyId> for u16 {
+    fn from(id: CompanyId) -> Self {
+        id.0
+    }
+}
+
+impl Display for CompanyId {
+    /// Format the name of the company if it is known, or the identifier otherwise.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "0x{:04X}", self.0),
+        }
+    }
+}
+
+impl Debug for CompanyId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "CompanyId(0x{:04X} {:?})", self.0, name),
+            None => write!(f, "CompanyId(0x{:04X})", self.0),
+        }
+    }
+}
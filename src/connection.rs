@@ -0,0 +1,136 @@
+use libc::{c_ulong};
+use std::io::{Error, Read, Result};
+use std::io::ErrorKind::{InvalidData, NotFound};
+use std::mem::zeroed;
+use std::os::fd::AsRawFd;
+
+use super::addr::BdAddr;
+use super::io::{ReadAs, ReadFrom};
+#[cfg(feature = "le")]
+use super::le::{LeConnectionComplete, LeConnectionUpdate};
+use super::socket::Socket;
+
+// _IOR('H', 212, int)
+const HCIGETCONNLIST: c_ulong = 0x800448D4;
+/// Most connections listed by one connection list request
+const MAX_CONN_LIST: usize = 32;
+
+/// struct hci_conn_info
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct HciConnInfo {
+    handle: u16,
+    bdaddr: [u8; 6],
+    link_type: u8,
+    out: u8,
+    state: u16,
+    link_mode: u32,
+}
+
+/// struct hci_conn_list_req, with room for `MAX_CONN_LIST` connections
+#[repr(C)]
+struct HciConnListReq {
+    dev_id: u16,
+    conn_num: u16,
+    conn_info: [HciConnInfo; MAX_CONN_LIST],
+}
+
+/// An ACL connection on a controller, identified by its connection handle.
+///
+/// Commands on the connection are sent through the borrowed socket.
+pub struct Connection<'a> {
+    pub(crate) socket: &'a mut Socket,
+    handle: u16,
+    #[cfg(feature = "le")]
+    le: Option<LeConnectionComplete>,
+}
+
+impl <'a> Connection<'a> {
+    /// Create a connection object for an existing connection handle.
+    pub fn new(socket: &'a mut Socket, handle: u16) -> Self {
+        Connection {
+            socket,
+            handle,
+            #[cfg(feature = "le")]
+            le: None,
+        }
+    }
+
+    /// Create a connection object for a newly established LE connection.
+    #[cfg(feature = "le")]
+    pub(crate) fn from_le(socket: &'a mut Socket, complete: LeConnectionComplete) -> Self {
+        Connection { socket, handle: complete.handle, le: Some(complete) }
+    }
+
+    /// Return the connection handle
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// Return the parameters an LE connection was established with, if known
+    #[cfg(feature = "le")]
+    pub fn le_info(&self) -> Option<&LeConnectionComplete> {
+        self.le.as_ref()
+    }
+
+    /// Record new parameters of an LE connection.
+    #[cfg(feature = "le")]
+    pub(crate) fn le_updated(&mut self, update: &LeConnectionUpdate) {
+        if let Some(le) = self.le.as_mut() {
+            le.interval = update.interval;
+            le.latency = update.latency;
+            le.supervision_timeout = update.supervision_timeout;
+        }
+    }
+
+    /// Return the address of the remote device.
+    ///
+    /// For BR/EDR connections, the address is looked up in the kernel's list of connections
+    /// of the device, failing with `NotFound` if the handle is not in it.
+    pub fn peer_address(&self) -> Result<BdAddr> {
+        #[cfg(feature = "le")]
+        if let Some(le) = &self.le {
+            return Ok(le.peer_address);
+        }
+        // All fields are integers, for which zero is valid.
+        let mut req: HciConnListReq = unsafe { zeroed() };
+        req.dev_id = self.socket.device_id()?;
+        req.conn_num = MAX_CONN_LIST as u16;
+        syscall!(ioctl(self.socket.as_raw_fd(), HCIGETCONNLIST as _, &mut req as *mut HciConnListReq))?;
+        req.conn_info[..usize::from(req.conn_num).min(MAX_CONN_LIST)].iter()
+            .find(|info| info.handle == self.handle)
+            .map(|info| BdAddr(info.bdaddr))
+            .ok_or_else(|| Error::new(NotFound, "Connection handle not listed by the kernel"))
+    }
+
+    /// Return the socket used by the connection
+    pub fn socket(&mut self) -> &mut Socket {
+        self.socket
+    }
+}
+
+/// Role of the local device on a link
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    Central = 0x00,
+    Peripheral = 0x01,
+}
+
+impl TryFrom<u8> for Role {
+    type Error = Error;
+
+    fn try_from(role: u8) -> Result<Self> {
+        match role {
+            0x00 => Ok(Role::Central),
+            0x01 => Ok(Role::Peripheral),
+            _ => Err(Error::new(InvalidData, "Unknown role")),
+        }
+    }
+}
+
+impl ReadFrom for Role {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (role, size) = r.read_as::<u8>()?;
+        Ok((Role::try_from(role)?, size))
+    }
+}
@@ -0,0 +1,492 @@
+use libc::{c_int};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::time::Duration;
+
+use super::addr::BdAddr;
+use super::codec::{self, EventBody};
+use super::error::{MalformedPacket, StatusError};
+use super::filter::HciFilter;
+use super::host_ctl::ChannelMap;
+use super::io::{ReadAs, ReadFrom, WriteAs, WriteTo};
+use super::link_ctl::AclPacketTypes;
+use super::socket::{EVT_CMD_STATUS, HCI_EVENT_PKT, Socket};
+use super::units::{from_units, to_units, to_units_u32, SLOT_US};
+
+const OGF_LINK_CTL: u16 = 0x01;
+const OCF_SET_CSB: u16 = 0x0041;
+const OCF_SET_CSB_RECEIVE: u16 = 0x0042;
+const OCF_START_SYNC_TRAIN: u16 = 0x0043;
+const OCF_RECEIVE_SYNC_TRAIN: u16 = 0x0044;
+
+const OGF_HOST_CTL: u16 = 0x03;
+const OCF_SET_RESERVED_LT_ADDR: u16 = 0x0074;
+const OCF_DELETE_RESERVED_LT_ADDR: u16 = 0x0075;
+const OCF_SET_CSB_DATA: u16 = 0x0076;
+const OCF_READ_SYNC_TRAIN_PARAMS: u16 = 0x0077;
+const OCF_WRITE_SYNC_TRAIN_PARAMS: u16 = 0x0078;
+
+const EVT_SYNC_TRAIN_COMPLETE: u8 = 0x4F;
+const EVT_SYNC_TRAIN_RECEIVED: u8 = 0x50;
+const EVT_CSB_RECEIVE: u8 = 0x51;
+const EVT_CSB_TIMEOUT: u8 = 0x52;
+const EVT_CSB_CHANNEL_MAP_CHANGE: u8 = 0x55;
+
+/// Bits of an event code the kernel filter tests
+const FILTER_EVENT_BITS: u8 = 0x3F;
+
+/// Most data carried by one Set Connectionless Peripheral Broadcast Data command
+const MAX_CSB_FRAGMENT_LEN: usize = 252;
+
+/// Parameters for transmitting Connectionless Peripheral Broadcast
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CsbParams {
+    /// Logical transport address reserved with `set_reserved_lt_addr`
+    pub lt_addr: u8,
+    /// Whether the controller may sleep on its low power oscillator between broadcasts
+    pub lpo_allowed: bool,
+    pub packet_type: AclPacketTypes,
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    /// Time without a successful broadcast after which the controller reports a timeout
+    pub supervision_timeout: Duration,
+}
+
+impl WriteTo for &CsbParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(self.lt_addr)?
+            + w.write_as(self.lpo_allowed as u8)?
+            + w.write_as(self.packet_type.bits())?
+            + w.write_as(to_units(self.interval_min, SLOT_US)?)?
+            + w.write_as(to_units(self.interval_max, SLOT_US)?)?
+            + w.write_as(to_units(self.supervision_timeout, SLOT_US)?)?)
+    }
+}
+
+/// Parameters for receiving Connectionless Peripheral Broadcast from a transmitter.
+///
+/// The timing comes from the synchronization train of the transmitter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CsbReceiveParams {
+    pub bdaddr: BdAddr,
+    pub lt_addr: u8,
+    pub interval: Duration,
+    /// Offset of the clock of the transmitter from the local clock, in units of 312.5 µs
+    pub clock_offset: u32,
+    /// Clock of the transmitter at the next broadcast, in units of 312.5 µs
+    pub next_csb_clock: u32,
+    pub supervision_timeout: Duration,
+    /// Timing accuracy of the transmitter in ppm
+    pub remote_timing_accuracy: u8,
+    /// Number of broadcasts to skip after receiving one
+    pub skip: u8,
+    pub packet_type: AclPacketTypes,
+    pub channel_map: ChannelMap,
+}
+
+impl WriteTo for &CsbReceiveParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(&self.bdaddr)?
+            + w.write_as(self.lt_addr)?
+            + w.write_as(to_units(self.interval, SLOT_US)?)?
+            + w.write_as(self.clock_offset)?
+            + w.write_as(self.next_csb_clock)?
+            + w.write_as(to_units(self.supervision_timeout, SLOT_US)?)?
+            + w.write_as(self.remote_timing_accuracy)?
+            + w.write_as(self.skip)?
+            + w.write_as(self.packet_type.bits())?
+            + w.write_as(&self.channel_map)?)
+    }
+}
+
+/// Position of a fragment of broadcast data
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CsbFragment {
+    Continuation = 0x00,
+    Start = 0x01,
+    End = 0x02,
+    /// The data is not fragmented
+    Complete = 0x03,
+}
+
+impl TryFrom<u8> for CsbFragment {
+    type Error = Error;
+
+    fn try_from(fragment: u8) -> Result<Self> {
+        match fragment {
+            0x00 => Ok(CsbFragment::Continuation),
+            0x01 => Ok(CsbFragment::Start),
+            0x02 => Ok(CsbFragment::End),
+            0x03 => Ok(CsbFragment::Complete),
+            _ => Err(Error::new(InvalidData, "Unknown fragment")),
+        }
+    }
+}
+
+/// Parameters of a Connectionless Peripheral Broadcast Receive event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsbReceive {
+    pub bdaddr: BdAddr,
+    pub lt_addr: u8,
+    /// Clock of the transmitter when the broadcast was received, in units of 312.5 µs
+    pub clock: u32,
+    /// Offset of the clock of the transmitter from the local clock, in units of 312.5 µs
+    pub offset: u32,
+    /// Whether the packet was received without errors. Data of failed receptions is empty.
+    pub received: bool,
+    pub fragment: CsbFragment,
+    pub data: Box<[u8]>,
+}
+
+impl ReadFrom for CsbReceive {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (bdaddr, bdaddr_size) = r.read_as::<BdAddr>()?;
+        let (lt_addr, lt_addr_size) = r.read_as::<u8>()?;
+        let (clock, clock_size) = r.read_as::<u32>()?;
+        let (offset, offset_size) = r.read_as::<u32>()?;
+        let (rx_status, rx_status_size) = r.read_as::<u8>()?;
+        let (fragment, fragment_size) = r.read_as::<u8>()?;
+        let (data_len, data_len_size) = r.read_as::<u8>()?;
+        let mut data = vec![0u8; data_len.into()];
+        r.read_exact(&mut data)?;
+        Ok((CsbReceive {
+            bdaddr,
+            lt_addr,
+            clock,
+            offset,
+            received: rx_status == 0,
+            fragment: CsbFragment::try_from(fragment)?,
+            data: data.into(),
+        }, bdaddr_size + lt_addr_size + clock_size + offset_size + rx_status_size + fragment_size
+            + data_len_size + usize::from(data_len)))
+    }
+}
+
+/// Parameters of the synchronization train, which tells receivers the timing of the
+/// broadcasts
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyncTrainParams {
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    /// How long the train is sent after it is started
+    pub timeout: Duration,
+    /// Data of the profile using the broadcast, sent in the train
+    pub service_data: u8,
+}
+
+/// Parameters of the synchronization train in use, read from the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyncTrainSettings {
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub service_data: u8,
+}
+
+/// How to scan for the synchronization train of a transmitter
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyncScanParams {
+    /// How long to scan before giving up
+    pub timeout: Duration,
+    pub window: Duration,
+    pub interval: Duration,
+}
+
+/// Parameters of a successful Synchronization Train Received event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyncTrainReceived {
+    pub bdaddr: BdAddr,
+    /// Offset of the clock of the transmitter from the local clock, in units of 312.5 µs
+    pub clock_offset: u32,
+    pub channel_map: ChannelMap,
+    pub lt_addr: u8,
+    /// Clock of the transmitter at the next broadcast, in units of 312.5 µs
+    pub next_broadcast_instant: u32,
+    pub csb_interval: Duration,
+    pub service_data: u8,
+}
+
+impl ReadFrom for SyncTrainReceived {
+    /// Read the parameters after the status.
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (bdaddr, bdaddr_size) = r.read_as::<BdAddr>()?;
+        let (clock_offset, clock_offset_size) = r.read_as::<u32>()?;
+        let (channel_map, channel_map_size) = r.read_as::<ChannelMap>()?;
+        let (lt_addr, lt_addr_size) = r.read_as::<u8>()?;
+        let (next_broadcast_instant, next_broadcast_instant_size) = r.read_as::<u32>()?;
+        let (csb_interval, csb_interval_size) = r.read_as::<u16>()?;
+        let (service_data, service_data_size) = r.read_as::<u8>()?;
+        Ok((SyncTrainReceived {
+            bdaddr,
+            clock_offset,
+            channel_map,
+            lt_addr,
+            next_broadcast_instant,
+            csb_interval: from_units(csb_interval.into(), SLOT_US),
+            service_data,
+        }, bdaddr_size + clock_offset_size + channel_map_size + lt_addr_size
+            + next_broadcast_instant_size + csb_interval_size + service_data_size))
+    }
+}
+
+impl SyncTrainReceived {
+    /// Return parameters for receiving the broadcasts announced by the train.
+    ///
+    /// Every broadcast is received, the transmitter is assumed to be accurate to 250 ppm, the
+    /// worst allowed, and all single-slot to five-slot basic rate packets are accepted. These
+    /// can be changed before starting to receive.
+    pub fn csb_receive_params(&self, supervision_timeout: Duration) -> CsbReceiveParams {
+        CsbReceiveParams {
+            bdaddr: self.bdaddr,
+            lt_addr: self.lt_addr,
+            interval: self.csb_interval,
+            clock_offset: self.clock_offset,
+            next_csb_clock: self.next_broadcast_instant,
+            supervision_timeout,
+            remote_timing_accuracy: 250,
+            skip: 0,
+            packet_type: AclPacketTypes::DM1 | AclPacketTypes::DH1 | AclPacketTypes::DM3
+                | AclPacketTypes::DH3 | AclPacketTypes::DM5 | AclPacketTypes::DH5,
+            channel_map: self.channel_map,
+        }
+    }
+}
+
+/// An event about Connectionless Peripheral Broadcast
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CsbEvent {
+    /// A broadcast was received, or was due and not received
+    Receive(CsbReceive),
+    /// No broadcast was sent or received within the supervision timeout, which stops
+    /// transmitting or receiving. The address is that of the transmitter.
+    Timeout { bdaddr: BdAddr, lt_addr: u8 },
+    /// The transmitter changed the channels it uses
+    ChannelMapChange(ChannelMap),
+    /// The synchronization train stopped after its timeout, or failed to start with the status
+    SyncTrainComplete { status: u8 },
+}
+
+impl CsbEvent {
+    /// Parse an event with the given code, or return `None` if it is not about broadcasts.
+    pub fn parse(code: u8, params: &[u8]) -> Result<Option<Self>> {
+        let mut r = params;
+        Ok(Some(match code {
+            EVT_CSB_RECEIVE => CsbEvent::Receive(r.read_as::<CsbReceive>()?.0),
+            EVT_CSB_TIMEOUT => {
+                let (bdaddr, _) = r.read_as::<BdAddr>()?;
+                let (lt_addr, _) = r.read_as::<u8>()?;
+                CsbEvent::Timeout { bdaddr, lt_addr }
+            }
+            EVT_CSB_CHANNEL_MAP_CHANGE => CsbEvent::ChannelMapChange(r.read_as::<ChannelMap>()?.0),
+            EVT_SYNC_TRAIN_COMPLETE => CsbEvent::SyncTrainComplete { status: r.read_as::<u8>()?.0 },
+            _ => return Ok(None),
+        }))
+    }
+}
+
+impl Socket {
+    /// Reserve a logical transport address for Connectionless Peripheral Broadcast, so the
+    /// controller does not give it to a connection.
+    pub fn set_reserved_lt_addr(&mut self, lt_addr: u8, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_SET_RESERVED_LT_ADDR, &[lt_addr], timeout)
+            .map(|_| ())
+    }
+
+    /// Free a logical transport address reserved with `set_reserved_lt_addr`.
+    pub fn delete_reserved_lt_addr(&mut self, lt_addr: u8, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_DELETE_RESERVED_LT_ADDR, &[lt_addr], timeout)
+            .map(|_| ())
+    }
+
+    /// Set the data to broadcast on a logical transport address, fragmenting it as needed.
+    ///
+    /// The data is repeated in every broadcast until it is replaced.
+    pub fn set_csb_data(&mut self, lt_addr: u8, data: &[u8], timeout: c_int) -> Result<()> {
+        let fragments: Vec<&[u8]> = if data.is_empty() {
+            vec![data]
+        } else {
+            data.chunks(MAX_CSB_FRAGMENT_LEN).collect()
+        };
+        let last = fragments.len() - 1;
+        for (i, fragment) in fragments.iter().enumerate() {
+            let position = match (i, last) {
+                (0, 0) => CsbFragment::Complete,
+                (0, _) => CsbFragment::Start,
+                (i, last) if i == last => CsbFragment::End,
+                _ => CsbFragment::Continuation,
+            };
+            let mut param = vec![lt_addr, position as u8, fragment.len() as u8];
+            param.extend_from_slice(fragment);
+            self.execute(OGF_HOST_CTL, OCF_SET_CSB_DATA, &param, timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Start transmitting Connectionless Peripheral Broadcast.
+    ///
+    /// Returns the interval chosen by the controller.
+    pub fn start_csb(&mut self, params: &CsbParams, timeout: c_int) -> Result<Duration> {
+        if params.interval_min > params.interval_max {
+            return Err(Error::new(InvalidInput, "Minimum interval is greater than maximum interval"));
+        }
+        let mut param = vec![1u8];
+        param.extend_from_slice(&params.bytes()?);
+        let data = self.execute(OGF_LINK_CTL, OCF_SET_CSB, &param, timeout)?;
+        let mut r: &[u8] = &data;
+        let (_lt_addr, _) = r.read_as::<u8>()?;
+        let (interval, _) = r.read_as::<u16>()?;
+        Ok(from_units(interval.into(), SLOT_US))
+    }
+
+    /// Stop transmitting Connectionless Peripheral Broadcast on a logical transport address.
+    pub fn stop_csb(&mut self, lt_addr: u8, timeout: c_int) -> Result<()> {
+        let params = CsbParams {
+            lt_addr,
+            lpo_allowed: false,
+            packet_type: AclPacketTypes::empty(),
+            interval_min: Duration::ZERO,
+            interval_max: Duration::ZERO,
+            supervision_timeout: Duration::ZERO,
+        };
+        let mut param = vec![0u8];
+        param.extend_from_slice(&params.bytes()?);
+        self.execute(OGF_LINK_CTL, OCF_SET_CSB, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Start receiving Connectionless Peripheral Broadcast from a transmitter.
+    ///
+    /// Broadcasts are reported with Connectionless Peripheral Broadcast Receive events.
+    pub fn start_csb_receive(&mut self, params: &CsbReceiveParams, timeout: c_int) -> Result<()> {
+        let mut param = vec![1u8];
+        param.extend_from_slice(&params.bytes()?);
+        self.execute(OGF_LINK_CTL, OCF_SET_CSB_RECEIVE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Stop receiving Connectionless Peripheral Broadcast from a transmitter.
+    pub fn stop_csb_receive(&mut self, bdaddr: BdAddr, lt_addr: u8, timeout: c_int) -> Result<()> {
+        let params = CsbReceiveParams {
+            bdaddr,
+            lt_addr,
+            interval: Duration::ZERO,
+            clock_offset: 0,
+            next_csb_clock: 0,
+            supervision_timeout: Duration::ZERO,
+            remote_timing_accuracy: 0,
+            skip: 0,
+            packet_type: AclPacketTypes::empty(),
+            channel_map: ChannelMap::none(),
+        };
+        let mut param = vec![0u8];
+        param.extend_from_slice(&params.bytes()?);
+        self.execute(OGF_LINK_CTL, OCF_SET_CSB_RECEIVE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Wait for the next event about Connectionless Peripheral Broadcast.
+    ///
+    /// The events are masked by default. They are enabled with `set_event_mask_page_2`.
+    pub fn wait_csb_event(&mut self, timeout: c_int) -> Result<CsbEvent> {
+        let old_filter = self.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        // The kernel filter has bits for 64 event codes and tests higher codes by their low six
+        // bits, so events sharing those bits pass too and are skipped by `CsbEvent::parse`.
+        filter.set_event(EVT_CSB_RECEIVE & FILTER_EVENT_BITS)?;
+        filter.set_event(EVT_CSB_TIMEOUT & FILTER_EVENT_BITS)?;
+        filter.set_event(EVT_CSB_CHANNEL_MAP_CHANGE & FILTER_EVENT_BITS)?;
+        filter.set_event(EVT_SYNC_TRAIN_COMPLETE & FILTER_EVENT_BITS)?;
+        self.set_filter(&filter)?;
+
+        let result = (|s: &mut Socket| loop {
+            if let Some(event) = s.recv_event_map(timeout, CsbEvent::parse)?? {
+                return Ok(event);
+            }
+        })(self);
+
+        self.set_filter(&old_filter)?;
+        result
+    }
+
+    /// Read the parameters of the synchronization train.
+    pub fn read_sync_train_params(&mut self, timeout: c_int) -> Result<SyncTrainSettings> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_SYNC_TRAIN_PARAMS, &[], timeout)?;
+        let mut r: &[u8] = &data;
+        let (interval, _) = r.read_as::<u16>()?;
+        let (train_timeout, _) = r.read_as::<u32>()?;
+        let (service_data, _) = r.read_as::<u8>()?;
+        Ok(SyncTrainSettings {
+            interval: from_units(interval.into(), SLOT_US),
+            timeout: from_units(train_timeout, SLOT_US),
+            service_data,
+        })
+    }
+
+    /// Write the parameters of the synchronization train.
+    ///
+    /// Returns the interval chosen by the controller.
+    pub fn write_sync_train_params(&mut self, params: &SyncTrainParams, timeout: c_int) -> Result<Duration> {
+        if params.interval_min > params.interval_max {
+            return Err(Error::new(InvalidInput, "Minimum interval is greater than maximum interval"));
+        }
+        let mut param = to_units(params.interval_min, SLOT_US)?.to_le_bytes().to_vec();
+        param.extend_from_slice(&to_units(params.interval_max, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units_u32(params.timeout, SLOT_US)?.to_le_bytes());
+        param.push(params.service_data);
+        let data = self.execute(OGF_HOST_CTL, OCF_WRITE_SYNC_TRAIN_PARAMS, &param, timeout)?;
+        let (interval, _) = (&*data).read_as::<u16>()?;
+        Ok(from_units(interval.into(), SLOT_US))
+    }
+
+    /// Start sending the synchronization train, so receivers can find the broadcasts.
+    ///
+    /// Broadcasting must be started first. The train stops after the timeout set with
+    /// `write_sync_train_params`, which is reported by a Synchronization Train Complete event.
+    pub fn start_sync_train(&mut self, timeout: c_int) -> Result<()> {
+        self.send_req(OGF_LINK_CTL, OCF_START_SYNC_TRAIN, EVT_CMD_STATUS as c_int, &[], timeout)
+            .map(|_| ())
+    }
+
+    /// Scan for the synchronization train of a transmitter, and wait until it is received.
+    ///
+    /// Waits up to `timeout` milliseconds for each event, so it should be longer than the
+    /// scan timeout. The Synchronization Train Received event is masked by default and is
+    /// enabled with `set_event_mask_page_2`.
+    pub fn receive_sync_train(&mut self, bdaddr: BdAddr, params: &SyncScanParams, timeout: c_int) -> Result<SyncTrainReceived> {
+        let mut param = bdaddr.bytes()?.into_vec();
+        param.extend_from_slice(&to_units(params.timeout, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(params.window, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(params.interval, SLOT_US)?.to_le_bytes());
+
+        // Requests filter on the event code, which the kernel filter cannot hold for this
+        // event, so the status and the result are waited for here.
+        let old_filter = self.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_CMD_STATUS)?;
+        filter.set_event(EVT_SYNC_TRAIN_RECEIVED & FILTER_EVENT_BITS)?;
+        self.set_filter(&filter)?;
+
+        let opcode = codec::opcode(OGF_LINK_CTL, OCF_RECEIVE_SYNC_TRAIN);
+        let result = self.send_cmd(OGF_LINK_CTL, OCF_RECEIVE_SYNC_TRAIN, &param)
+            .and_then(|_| loop {
+                let found = self.recv_event_body(timeout, |event| match *event {
+                    EventBody::CommandStatus { status, opcode: r_opcode, .. }
+                    if r_opcode == opcode && status != 0 => Some(Err(StatusError(status).into())),
+                    EventBody::Other { code: EVT_SYNC_TRAIN_RECEIVED, params }
+                    if params.get(1..7) == Some(&bdaddr.as_bytes()[..]) => Some(match params.split_first() {
+                        Some((0, mut r)) => r.read_as::<SyncTrainReceived>().map(|(received, _)| received),
+                        Some((&status, _)) => Err(StatusError(status).into()),
+                        None => Err(MalformedPacket.into()),
+                    }),
+                    _ => None,
+                })?;
+                if let Some(found) = found {
+                    break found;
+                }
+            });
+
+        self.set_filter(&old_filter)?;
+        result
+    }
+}
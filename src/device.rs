@@ -0,0 +1,148 @@
+use libc::{AF_BLUETOOTH, c_int, c_ulong, EALREADY, ioctl, SOCK_CLOEXEC, SOCK_RAW};
+use std::fmt;
+use std::io::{Error, Result};
+use std::mem::zeroed;
+use std::os::fd::AsRawFd;
+use socket2::{Domain, Protocol, Socket as Socket2, Type};
+
+use super::error::{Capability, PermissionDenied};
+use super::socket::Socket;
+
+const PROTO_HCI: c_int = 1;
+
+// Device ioctls, _IOW('H', 201, int), _IOW('H', 202, int) and _IOR('H', 211, int)
+const HCIDEVUP: c_ulong = 0x400448C9;
+const HCIDEVDOWN: c_ulong = 0x400448CA;
+const HCIGETDEVINFO: c_ulong = 0x800448D3;
+
+/// Flag of `hci_dev_info` set while the device is up
+const HCI_UP: u32 = 1 << 0;
+
+/// struct hci_dev_stats
+#[repr(C)]
+struct HciDevStats {
+    err_rx: u32,
+    err_tx: u32,
+    cmd_tx: u32,
+    evt_rx: u32,
+    acl_tx: u32,
+    acl_rx: u32,
+    sco_tx: u32,
+    sco_rx: u32,
+    byte_rx: u32,
+    byte_tx: u32,
+}
+
+/// struct hci_dev_info
+#[repr(C)]
+struct HciDevInfo {
+    dev_id: u16,
+    name: [u8; 8],
+    bdaddr: [u8; 6],
+    flags: u32,
+    dev_type: u8,
+    features: [u8; 8],
+    pkt_type: u32,
+    link_policy: u32,
+    link_mode: u32,
+    acl_mtu: u16,
+    acl_pkts: u16,
+    sco_mtu: u16,
+    sco_pkts: u16,
+    stat: HciDevStats,
+}
+
+/// Index of a controller known to the kernel, such as 0 for hci0
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceId(pub u16);
+
+impl DeviceId {
+    /// Open a socket bound to the device.
+    pub fn open(self) -> Result<Socket> {
+        Socket::new(self.0)
+    }
+
+    /// Run a device ioctl on a socket not bound to any device.
+    fn ioctl(self, request: c_ulong, arg: c_ulong) -> Result<()> {
+        let socket = Socket2::new(Domain::from(AF_BLUETOOTH), Type::from(SOCK_RAW | SOCK_CLOEXEC), Some(Protocol::from(PROTO_HCI)))
+            .map_err(PermissionDenied::check(Capability::CapNetRaw))?;
+        let res = unsafe { ioctl(socket.as_raw_fd(), request as _, arg) };
+        if res == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Return whether the device is up.
+    pub fn is_up(self) -> Result<bool> {
+        // All fields are integers, for which zero is valid.
+        let mut info: HciDevInfo = unsafe { zeroed() };
+        info.dev_id = self.0;
+        self.ioctl(HCIGETDEVINFO, &mut info as *mut HciDevInfo as c_ulong)?;
+        Ok(info.flags & HCI_UP != 0)
+    }
+
+    /// Bring the device up, initializing the controller. Needs `CAP_NET_ADMIN`.
+    ///
+    /// Succeeds if the device is already up.
+    pub fn up(self) -> Result<()> {
+        match self.ioctl(HCIDEVUP, self.0 as c_ulong) {
+            Err(e) if e.raw_os_error() == Some(EALREADY) => Ok(()),
+            result => result.map_err(PermissionDenied::check(Capability::CapNetAdmin)),
+        }
+    }
+
+    /// Bring the device down, closing its connections. Needs `CAP_NET_ADMIN`.
+    pub fn down(self) -> Result<()> {
+        self.ioctl(HCIDEVDOWN, self.0 as c_ulong)
+            .map_err(PermissionDenied::check(Capability::CapNetAdmin))
+    }
+
+    /// Bring the device up until the returned guard is dropped, which brings it back down if
+    /// it was down before.
+    pub fn up_guard(self) -> Result<UpGuard> {
+        let was_up = self.is_up()?;
+        self.up()?;
+        Ok(UpGuard { device: self, was_up })
+    }
+}
+
+impl From<u16> for DeviceId {
+    fn from(id: u16) -> Self {
+        DeviceId(id)
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hci{}", self.0)
+    }
+}
+
+/// Keeps a device up, restoring its previous power state when dropped
+#[derive(Debug)]
+pub struct UpGuard {
+    device: DeviceId,
+    was_up: bool,
+}
+
+impl UpGuard {
+    /// Return the device kept up
+    pub fn device(&self) -> DeviceId {
+        self.device
+    }
+
+    /// Return whether the device was already up when the guard was created
+    pub fn was_up(&self) -> bool {
+        self.was_up
+    }
+}
+
+impl Drop for UpGuard {
+    fn drop(&mut self) {
+        if !self.was_up {
+            let _ = self.device.down();
+        }
+    }
+}
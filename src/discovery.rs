@@ -0,0 +1,390 @@
+use libc::{c_int, ETIMEDOUT};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::{Duration, Instant};
+
+use super::ad::{self, AdStructure};
+use super::adapter::Adapter;
+use super::addr::{AddressType, BdAddr};
+use super::class::ClassOfDevice;
+use super::connection::Connection;
+use super::error::StatusError;
+use super::filter::HciFilter;
+use super::host_ctl::IacLap;
+use super::inquiry::{EVT_EXTENDED_INQUIRY_RESULT, EVT_INQUIRY_COMPLETE, EVT_INQUIRY_RESULT, EVT_INQUIRY_RESULT_WITH_RSSI, InquiryMode, InquiryResult, parse_inquiry_results};
+use super::le::{AdvertisingReport, ConnectionParams, EVT_LE_ADVERTISING_REPORT, EVT_LE_META_EVENT, parse_advertising_reports, ScanOptions, ScanType};
+use super::link_ctl::CreateConnParams;
+use super::uuid::Uuid;
+use super::socket::{HCI_EVENT_PKT, Socket};
+
+/// Transports a device was discovered on
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Technology {
+    BrEdr,
+    Le,
+    /// Seen both in inquiry and in LE scanning with the same address
+    DualMode,
+}
+
+impl Technology {
+    fn merge(self, other: Technology) -> Technology {
+        if self == other { self } else { Technology::DualMode }
+    }
+}
+
+/// A device found by a discovery session, with everything learned about it so far
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    pub address: BdAddr,
+    /// Address type, if the device was seen over LE
+    pub address_type: Option<AddressType>,
+    pub technology: Technology,
+    /// Complete or shortened local name
+    pub name: Option<String>,
+    /// Class of device, if the device responded to an inquiry
+    pub class: Option<ClassOfDevice>,
+    /// Page scan repetition mode from the last inquiry response
+    pub page_scan_repetition_mode: Option<u8>,
+    /// Clock offset from the last inquiry response, which speeds up paging the device
+    pub clock_offset: Option<u16>,
+    /// Most recent signal strength in dBm
+    pub rssi: Option<i8>,
+    /// Advertising and extended inquiry response structures, keeping the latest of each type
+    pub ad: Vec<AdStructure>,
+    pub last_seen: Instant,
+}
+
+impl DiscoveredDevice {
+    fn new(address: BdAddr, technology: Technology, now: Instant) -> Self {
+        DiscoveredDevice {
+            address,
+            address_type: None,
+            technology,
+            name: None,
+            class: None,
+            page_scan_repetition_mode: None,
+            clock_offset: None,
+            rssi: None,
+            ad: Vec::new(),
+            last_seen: now,
+        }
+    }
+
+    /// Return the service UUIDs listed in the advertising data or extended inquiry response
+    pub fn service_uuids(&self) -> Vec<Uuid> {
+        ad::service_uuids(&self.ad)
+    }
+
+    /// Replace structures with newly received ones of the same type.
+    fn merge_ad(&mut self, structures: &[AdStructure]) {
+        for structure in structures {
+            self.ad.retain(|old| old.ad_type() != structure.ad_type());
+            self.ad.push(structure.clone());
+        }
+        if let Some(name) = ad::local_name(&self.ad) {
+            self.name = Some(name.into());
+        }
+    }
+
+    /// Return the address type to connect with over LE, or `None` if the device should be
+    /// paged over BR/EDR.
+    ///
+    /// Dual-mode devices with a public address are paged, since BR/EDR cannot reach other
+    /// addresses.
+    fn le_address_type(&self) -> Option<AddressType> {
+        match (self.technology, self.address_type) {
+            (Technology::Le, address_type) => address_type,
+            (_, Some(AddressType::Public)) | (_, None) => None,
+            (_, address_type) => address_type,
+        }
+    }
+}
+
+/// How inquiry and LE scanning share the radio
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DiscoveryMode {
+    /// Inquire and scan at the same time, leaving the scheduling to the controller
+    #[default]
+    Parallel,
+    /// Alternate between an inquiry and an LE scan
+    Interleaved,
+}
+
+/// Options of a discovery session
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveryOptions {
+    /// Whether to run BR/EDR inquiries
+    pub bredr: bool,
+    /// Whether to scan for LE advertisers
+    pub le: bool,
+    pub mode: DiscoveryMode,
+    /// Inquiry access code to inquire on
+    pub lap: IacLap,
+    /// Length of each inquiry, rounded down to units of 1.28 s
+    pub inquiry_length: Duration,
+    /// Format of inquiry results to set before the first inquiry, or `None` to keep the
+    /// current one. Names and other extended inquiry response data need `Extended`.
+    pub inquiry_mode: Option<InquiryMode>,
+    /// Length of each LE scan between inquiries in interleaved mode
+    pub le_scan_length: Duration,
+    pub scan: ScanOptions,
+}
+
+impl Default for DiscoveryOptions {
+    /// General discovery on both transports, with active scanning to collect names
+    fn default() -> Self {
+        DiscoveryOptions {
+            bredr: true,
+            le: true,
+            mode: DiscoveryMode::default(),
+            lap: IacLap::GIAC,
+            inquiry_length: Duration::from_millis(10240),
+            inquiry_mode: Some(InquiryMode::Extended),
+            le_scan_length: Duration::from_millis(10240),
+            scan: ScanOptions { scan_type: ScanType::Active, ..ScanOptions::default() },
+        }
+    }
+}
+
+/// A running discovery session over BR/EDR and LE.
+///
+/// Results of both transports are merged by address. While the session runs, the socket filter
+/// only passes inquiry and LE Meta events. Inquiry and scanning stop and the previous filter is
+/// restored when the session is dropped.
+pub struct Discovery<'a> {
+    socket: &'a mut Socket,
+    /// Devices known to the adapter, updated with the results when the session ends
+    known: &'a mut HashMap<BdAddr, DiscoveredDevice>,
+    old_filter: HciFilter,
+    timeout: c_int,
+    options: DiscoveryOptions,
+    inquiring: bool,
+    scanning: bool,
+    /// End of the current LE scan in interleaved mode
+    scan_until: Option<Instant>,
+    devices: HashMap<BdAddr, DiscoveredDevice>,
+    /// Devices updated since they were last returned
+    pending: VecDeque<BdAddr>,
+}
+
+impl Discovery<'_> {
+    fn start_inquiry(&mut self) -> Result<()> {
+        self.socket.inquiry(self.options.lap, self.options.inquiry_length, 0, self.timeout)?;
+        self.inquiring = true;
+        Ok(())
+    }
+
+    fn set_scan_enable(&mut self, enable: bool) -> Result<()> {
+        self.socket.le_set_scan_enable(enable, self.options.scan.filter_duplicates, self.timeout)?;
+        self.scanning = enable;
+        Ok(())
+    }
+
+    /// Start the next inquiry, or the LE scan between inquiries when interleaving.
+    fn inquiry_complete(&mut self) -> Result<()> {
+        self.inquiring = false;
+        if self.options.mode == DiscoveryMode::Interleaved && self.options.le {
+            self.set_scan_enable(true)?;
+            self.scan_until = Some(Instant::now() + self.options.le_scan_length);
+            Ok(())
+        } else {
+            self.start_inquiry()
+        }
+    }
+
+    /// Stop the LE scan between inquiries and start the next inquiry.
+    fn scan_complete(&mut self) -> Result<()> {
+        self.scan_until = None;
+        self.set_scan_enable(false)?;
+        self.start_inquiry()
+    }
+
+    fn update(&mut self, address: BdAddr, technology: Technology, now: Instant) -> &mut DiscoveredDevice {
+        if !self.pending.contains(&address) {
+            self.pending.push_back(address);
+        }
+        let device = self.devices.entry(address)
+            .or_insert_with(|| DiscoveredDevice::new(address, technology, now));
+        device.technology = device.technology.merge(technology);
+        device.last_seen = now;
+        device
+    }
+
+    fn inquiry_result(&mut self, result: InquiryResult, now: Instant) {
+        let device = self.update(result.address, Technology::BrEdr, now);
+        device.class = Some(result.class);
+        device.page_scan_repetition_mode = Some(result.page_scan_repetition_mode);
+        device.clock_offset = Some(result.clock_offset);
+        device.rssi = result.rssi.or(device.rssi);
+        device.merge_ad(&result.eir);
+    }
+
+    fn advertising_report(&mut self, report: AdvertisingReport, now: Instant) {
+        let device = self.update(report.address, Technology::Le, now);
+        device.address_type = Some(report.address_type);
+        device.rssi = report.rssi.or(device.rssi);
+        device.merge_ad(&report.ad);
+    }
+
+    /// Return every device found so far
+    pub fn devices(&self) -> impl Iterator<Item = &DiscoveredDevice> {
+        self.devices.values()
+    }
+
+    /// Wait until a device is found or updated, and return everything known about it.
+    ///
+    /// Blocks until a result arrives, switching between inquiry and LE scanning as needed.
+    pub fn next_device(&mut self) -> Result<DiscoveredDevice> {
+        loop {
+            if let Some(address) = self.pending.pop_front() {
+                return Ok(self.devices[&address].clone());
+            }
+
+            // Wait no longer than the end of the current LE scan when interleaving.
+            let wait = match self.scan_until {
+                Some(until) => {
+                    let left = until.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        self.scan_complete()?;
+                        continue;
+                    }
+                    c_int::try_from(left.as_millis()).unwrap_or(c_int::MAX).max(1)
+                }
+                None => 0,
+            };
+            let (event, params) = match self.socket.recv_event(wait) {
+                Err(e) if e.raw_os_error() == Some(ETIMEDOUT) && self.scan_until.is_some() => continue,
+                result => result?,
+            };
+
+            let now = Instant::now();
+            match event {
+                EVT_INQUIRY_COMPLETE => {
+                    if let Some(&status) = params.first().filter(|&&status| status != 0) {
+                        self.inquiring = false;
+                        return Err(StatusError(status).into());
+                    }
+                    self.inquiry_complete()?;
+                }
+                EVT_INQUIRY_RESULT | EVT_INQUIRY_RESULT_WITH_RSSI | EVT_EXTENDED_INQUIRY_RESULT => {
+                    for result in parse_inquiry_results(event, &params)? {
+                        self.inquiry_result(result, now);
+                    }
+                }
+                EVT_LE_META_EVENT => {
+                    if let Some((&EVT_LE_ADVERTISING_REPORT, params)) = params.split_first() {
+                        for report in parse_advertising_reports(params)? {
+                            self.advertising_report(report, now);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Iterate over discovered devices until an error occurs.
+impl Iterator for Discovery<'_> {
+    type Item = DiscoveredDevice;
+
+    fn next(&mut self) -> Option<DiscoveredDevice> {
+        self.next_device().ok()
+    }
+}
+
+impl Drop for Discovery<'_> {
+    fn drop(&mut self) {
+        if self.inquiring {
+            let _ = self.socket.inquiry_cancel(self.timeout);
+        }
+        if self.scanning {
+            let _ = self.socket.le_set_scan_enable(false, false, self.timeout);
+        }
+        let _ = self.socket.set_filter(&self.old_filter);
+        self.known.extend(self.devices.drain());
+    }
+}
+
+impl Adapter {
+    /// Start discovering devices with inquiries, LE scanning, or both.
+    pub fn discover(&mut self, options: &DiscoveryOptions) -> Result<Discovery<'_>> {
+        if !options.bredr && !options.le {
+            return Err(Error::new(InvalidInput, "No transport to discover on"));
+        }
+        let timeout = self.timeout;
+        let socket = &mut self.socket;
+        let known = &mut self.discovered;
+
+        if options.bredr {
+            if let Some(mode) = options.inquiry_mode {
+                socket.write_inquiry_mode(mode, timeout)?;
+            }
+        }
+        if options.le {
+            socket.le_set_scan_parameters(&options.scan, timeout)?;
+        }
+
+        let old_filter = socket.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        for event in [EVT_INQUIRY_COMPLETE, EVT_INQUIRY_RESULT, EVT_INQUIRY_RESULT_WITH_RSSI, EVT_EXTENDED_INQUIRY_RESULT, EVT_LE_META_EVENT] {
+            filter.set_event(event)?;
+        }
+        socket.set_filter(&filter)?;
+
+        let mut discovery = Discovery {
+            socket,
+            known,
+            old_filter,
+            timeout,
+            options: *options,
+            inquiring: false,
+            scanning: false,
+            scan_until: None,
+            devices: HashMap::new(),
+            pending: VecDeque::new(),
+        };
+        if options.bredr {
+            discovery.start_inquiry()?;
+        }
+        if options.le && (!options.bredr || options.mode == DiscoveryMode::Parallel) {
+            discovery.set_scan_enable(true)?;
+        }
+        Ok(discovery)
+    }
+
+    /// Return what earlier discovery sessions found about a device
+    pub fn discovered_device(&self, addr: BdAddr) -> Option<&DiscoveredDevice> {
+        self.discovered.get(&addr)
+    }
+
+    /// Forget the devices found by earlier discovery sessions.
+    pub fn clear_discovered(&mut self) {
+        self.discovered.clear();
+    }
+
+    /// Connect to a device over BR/EDR or LE, picking the transport from what earlier
+    /// discovery sessions found about it.
+    ///
+    /// Devices seen only in LE scans, or with an address that is not public, are connected
+    /// over LE with default connection parameters. Other devices are paged over BR/EDR, with the
+    /// page scan repetition mode and clock offset of their last inquiry response. Devices that
+    /// were never discovered are paged too, since an LE connection needs the address type; use
+    /// `le_connect` to reach them over LE.
+    pub fn connect_any(&mut self, addr: BdAddr) -> Result<Connection<'_>> {
+        let device = self.discovered.get(&addr);
+        if let Some(address_type) = device.and_then(DiscoveredDevice::le_address_type) {
+            return self.le_connect(addr, address_type, &ConnectionParams::default());
+        }
+        let defaults = CreateConnParams::default();
+        let params = CreateConnParams {
+            page_scan_repetition_mode: device.and_then(|device| device.page_scan_repetition_mode)
+                .unwrap_or(defaults.page_scan_repetition_mode),
+            clock_offset: device.and_then(|device| device.clock_offset),
+            ..defaults
+        };
+        self.connect(addr, &params)
+    }
+}
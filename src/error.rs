@@ -0,0 +1,230 @@
+use std::fmt::{Display, Formatter};
+use std::io::Error;
+use std::io::ErrorKind::{InvalidData, PermissionDenied as PermissionDeniedKind};
+use libc::{EACCES, EPERM};
+
+use super::codec;
+
+/// Error returned when the controller reports a non-zero status for a command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StatusError(pub u8);
+
+impl StatusError {
+    /// Return the HCI status code
+    pub fn status(&self) -> u8 {
+        self.0
+    }
+}
+
+impl Display for StatusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HCI command failed with status {:#04x}", self.0)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+impl From<StatusError> for Error {
+    fn from(e: StatusError) -> Error {
+        Error::other(e)
+    }
+}
+
+/// Error returned when the kernel reports a non-zero status for a management command.
+#[cfg(feature = "mgmt")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MgmtStatusError(pub u8);
+
+#[cfg(feature = "mgmt")]
+impl MgmtStatusError {
+    /// Return the management status code
+    pub fn status(&self) -> u8 {
+        self.0
+    }
+}
+
+#[cfg(feature = "mgmt")]
+impl Display for MgmtStatusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Management command failed with status {:#04x}", self.0)
+    }
+}
+
+#[cfg(feature = "mgmt")]
+impl std::error::Error for MgmtStatusError {}
+
+#[cfg(feature = "mgmt")]
+impl From<MgmtStatusError> for Error {
+    fn from(e: MgmtStatusError) -> Error {
+        Error::other(e)
+    }
+}
+
+/// Error returned when a packet from the controller is truncated or its lengths are inconsistent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MalformedPacket;
+
+impl Display for MalformedPacket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Malformed HCI packet")
+    }
+}
+
+impl std::error::Error for MalformedPacket {}
+
+impl From<MalformedPacket> for Error {
+    fn from(e: MalformedPacket) -> Error {
+        Error::new(InvalidData, e)
+    }
+}
+
+/// Linux capability needed by an operation
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Needed to bring devices up or down and to change their settings
+    CapNetAdmin,
+    /// Needed to open HCI sockets and send commands through them
+    CapNetRaw,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Capability::CapNetAdmin => "CAP_NET_ADMIN",
+            Capability::CapNetRaw => "CAP_NET_RAW",
+        })
+    }
+}
+
+/// Error returned when the kernel refused an operation for lack of a capability.
+///
+/// Converted to an `io::Error` of kind `PermissionDenied`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PermissionDenied {
+    pub needs: Capability,
+}
+
+impl PermissionDenied {
+    /// Return a function replacing `EPERM` and `EACCES` errors with a `PermissionDenied`
+    /// naming the needed capability, leaving other errors unchanged.
+    pub(crate) fn check(needs: Capability) -> impl Fn(Error) -> Error + Copy {
+        move |error| match error.raw_os_error() {
+            Some(EPERM | EACCES) => PermissionDenied { needs }.into(),
+            _ => error,
+        }
+    }
+}
+
+impl Display for PermissionDenied {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Permission denied, {} is needed (run as root or grant it with setcap)", self.needs)
+    }
+}
+
+impl std::error::Error for PermissionDenied {}
+
+impl From<PermissionDenied> for Error {
+    fn from(e: PermissionDenied) -> Error {
+        Error::new(PermissionDeniedKind, e)
+    }
+}
+
+/// Step of a command request at which it failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CommandPhase {
+    /// Setting the socket filter or sending the command
+    Send,
+    /// Waiting for or receiving the response
+    Poll,
+    /// Decoding the response
+    Parse,
+    /// The controller reported a failure status
+    Status,
+    /// Restoring the socket filter after the command succeeded
+    Restore,
+}
+
+impl Display for CommandPhase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CommandPhase::Send => "send",
+            CommandPhase::Poll => "poll",
+            CommandPhase::Parse => "parse",
+            CommandPhase::Status => "status",
+            CommandPhase::Restore => "restore",
+        })
+    }
+}
+
+/// Error of a command request, with the opcode of the command and the step that failed.
+///
+/// Converted to an `io::Error` of the same kind as the underlying error, which is returned by
+/// `source`.
+#[derive(Debug)]
+pub struct CommandError {
+    opcode: u16,
+    phase: CommandPhase,
+    error: Error,
+}
+
+impl CommandError {
+    pub fn new(opcode: u16, phase: CommandPhase, error: Error) -> Self {
+        CommandError { opcode, phase, error }
+    }
+
+    /// Return the opcode of the failed command
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    /// Return the step of the request that failed
+    pub fn phase(&self) -> CommandPhase {
+        self.phase
+    }
+
+    /// Return the underlying error
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// Return the status reported by the controller, if the command failed with one
+    pub fn status(&self) -> Option<u8> {
+        self.error.get_ref()
+            .and_then(|e| e.downcast_ref::<StatusError>())
+            .map(StatusError::status)
+    }
+
+    /// Return a function adding the context of a command to an error, leaving errors that
+    /// already have one unchanged.
+    pub(crate) fn wrap(opcode: u16, phase: CommandPhase) -> impl Fn(Error) -> Error + Copy {
+        move |error| {
+            if error.get_ref().is_some_and(|e| e.is::<CommandError>()) {
+                error
+            } else {
+                CommandError::new(opcode, phase, error).into()
+            }
+        }
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match codec::opcode_name(self.opcode) {
+            Some(name) => write!(f, "{} ({:#06x})", name, self.opcode)?,
+            None => write!(f, "Command {:#06x}", self.opcode)?,
+        }
+        write!(f, " failed at {}: {}", self.phase, self.error)
+    }
+}
+
+impl std::error::Error for CommandError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<CommandError> for Error {
+    fn from(e: CommandError) -> Error {
+        Error::new(e.error.kind(), e)
+    }
+}
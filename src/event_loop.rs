@@ -0,0 +1,201 @@
+use libc::{c_int, EINTR, EPOLL_CLOEXEC, EPOLL_CTL_ADD, EPOLL_CTL_DEL, epoll_event, EPOLLIN};
+use std::io::{Error, ErrorKind::NotFound, Read, Result};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr::{null_mut};
+
+use super::codec::Packet;
+use super::socket::{ShutdownHandle, Socket};
+
+/// Largest packet a source can deliver, an ISO data packet with its header
+const MAX_PACKET_SIZE: usize = 16384 + 5;
+/// Most readiness events collected by one call to `epoll_wait`
+const MAX_EVENTS: usize = 16;
+/// Bit of the epoll data marking the shutdown of a source rather than a packet
+const SHUTDOWN_TAG: u64 = 1 << 63;
+
+/// A socket whose packets can be dispatched by an `EventLoop`
+pub trait PacketSource: AsRawFd {
+    /// Receive one packet, with its packet type indicator, returning its length.
+    fn recv_packet(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Return the handle that shuts the source down, if it has one. The event loop drops the
+    /// source once it is shut down.
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        None
+    }
+}
+
+impl PacketSource for Socket {
+    fn recv_packet(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (&mut *self).read(buf)
+    }
+
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        Some(Socket::shutdown_handle(self))
+    }
+}
+
+impl PacketSource for &mut Socket {
+    fn recv_packet(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.read(buf)
+    }
+
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        Some(Socket::shutdown_handle(self))
+    }
+}
+
+/// Identifier of a source registered with an `EventLoop`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Token(usize);
+
+/// A source with its handler, with the source type erased.
+trait Dispatch {
+    fn fd(&self) -> RawFd;
+    fn shutdown_fd(&self) -> Option<RawFd>;
+    fn dispatch(&mut self, buf: &mut [u8]) -> Result<bool>;
+}
+
+struct Entry<S, F> {
+    source: S,
+    handler: F,
+    shutdown: Option<ShutdownHandle>,
+}
+
+impl<S, F> Dispatch for Entry<S, F>
+where S: PacketSource, F: FnMut(&mut S, Packet<'_>) -> Result<()> {
+    fn fd(&self) -> RawFd {
+        self.source.as_raw_fd()
+    }
+
+    fn shutdown_fd(&self) -> Option<RawFd> {
+        self.shutdown.as_ref().map(AsRawFd::as_raw_fd)
+    }
+
+    /// Receive one packet and pass it to the handler, returning whether it was parsed.
+    fn dispatch(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let size = self.source.recv_packet(buf)?;
+        match Packet::parse(&buf[..size]) {
+            Ok(packet) => (self.handler)(&mut self.source, packet).map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// Dispatches packets from several sockets, such as a `Socket` per adapter and a
+/// `MonitorSocket`, on one thread.
+///
+/// Each registered source is watched with epoll. When it becomes readable, one packet is
+/// received and passed to the handler of the source, together with the source itself, so the
+/// handler can send commands in response. Packets that cannot be parsed are dropped. Sockets
+/// shut down through a `ShutdownHandle` are dropped from the loop.
+pub struct EventLoop<'a> {
+    epoll: OwnedFd,
+    entries: Vec<Option<Box<dyn Dispatch + 'a>>>,
+    buf: Vec<u8>,
+}
+
+impl<'a> EventLoop<'a> {
+    pub fn new() -> Result<Self> {
+        let fd = syscall!(epoll_create1(EPOLL_CLOEXEC))?;
+        // The descriptor was just created and is owned by nothing else.
+        let epoll = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok(EventLoop { epoll, entries: Vec::new(), buf: vec![0; MAX_PACKET_SIZE] })
+    }
+
+    /// Watch a source, passing each packet it receives to `handler`.
+    ///
+    /// Sources can be registered by value or as mutable references. The socket filter of the
+    /// source decides which packets reach the handler.
+    pub fn register<S, F>(&mut self, source: S, handler: F) -> Result<Token>
+    where S: PacketSource + 'a, F: FnMut(&mut S, Packet<'_>) -> Result<()> + 'a {
+        let index = self.entries.iter().position(Option::is_none)
+            .unwrap_or(self.entries.len());
+        let mut event = epoll_event { events: EPOLLIN as u32, u64: index as u64 };
+        syscall!(epoll_ctl(self.epoll.as_raw_fd(), EPOLL_CTL_ADD, source.as_raw_fd(), &mut event))?;
+
+        // Watch for the shutdown of the source, to drop it instead of reading from it.
+        let shutdown = source.shutdown_handle();
+        if let Some(shutdown) = &shutdown {
+            let mut event = epoll_event { events: EPOLLIN as u32, u64: index as u64 | SHUTDOWN_TAG };
+            let added = syscall!(epoll_ctl(self.epoll.as_raw_fd(), EPOLL_CTL_ADD, shutdown.as_raw_fd(), &mut event));
+            if let Err(e) = added {
+                let _ = syscall!(epoll_ctl(self.epoll.as_raw_fd(), EPOLL_CTL_DEL, source.as_raw_fd(), null_mut()));
+                return Err(e);
+            }
+        }
+
+        let entry = Some(Box::new(Entry { source, handler, shutdown }) as Box<dyn Dispatch + 'a>);
+        if index == self.entries.len() {
+            self.entries.push(entry);
+        } else {
+            self.entries[index] = entry;
+        }
+        Ok(Token(index))
+    }
+
+    /// Stop watching a source, dropping it and its handler.
+    pub fn unregister(&mut self, token: Token) -> Result<()> {
+        let entry = self.entries.get_mut(token.0)
+            .and_then(Option::take)
+            .ok_or_else(|| Error::new(NotFound, "Unknown event loop token"))?;
+        if let Some(fd) = entry.shutdown_fd() {
+            syscall!(epoll_ctl(self.epoll.as_raw_fd(), EPOLL_CTL_DEL, fd, null_mut()))?;
+        }
+        syscall!(epoll_ctl(self.epoll.as_raw_fd(), EPOLL_CTL_DEL, entry.fd(), null_mut()))
+            .map(|_| ())
+    }
+
+    /// Return the number of registered sources
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.is_some()).count()
+    }
+
+    /// Return whether no sources are registered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Wait up to `timeout` milliseconds for packets and dispatch them, returning the number of
+    /// packets passed to handlers.
+    ///
+    /// A negative timeout waits indefinitely and a timeout of zero returns immediately. The
+    /// first error from a source or handler is returned.
+    pub fn poll_once(&mut self, timeout: c_int) -> Result<usize> {
+        let mut events = [epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let ready = loop {
+            match syscall!(epoll_wait(self.epoll.as_raw_fd(), events.as_mut_ptr(), MAX_EVENTS as c_int, timeout)) {
+                Ok(n) => break n as usize,
+                Err(e) if e.raw_os_error() == Some(EINTR) => continue,
+                Err(e) => return Err(e),
+            }
+        };
+
+        let mut dispatched = 0;
+        for event in &events[..ready] {
+            let index = (event.u64 & !SHUTDOWN_TAG) as usize;
+            if event.u64 & SHUTDOWN_TAG != 0 {
+                // The source was shut down and may not be readable anymore.
+                if matches!(self.entries.get(index), Some(Some(_))) {
+                    self.unregister(Token(index))?;
+                }
+                continue;
+            }
+            if let Some(Some(entry)) = self.entries.get_mut(index) {
+                if entry.dispatch(&mut self.buf)? {
+                    dispatched += 1;
+                }
+            }
+        }
+        Ok(dispatched)
+    }
+
+    /// Dispatch packets until a source or handler fails, or until no sources are left, such as
+    /// after all of them were shut down.
+    pub fn run(&mut self) -> Result<()> {
+        while !self.is_empty() {
+            self.poll_once(-1)?;
+        }
+        Ok(())
+    }
+}
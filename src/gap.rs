@@ -0,0 +1,211 @@
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::{Duration, Instant};
+
+use super::ad::{AdBuilder, AdFlags, AdStructure};
+use super::adapter::Adapter;
+use super::class::{ClassOfDevice, ServiceClasses};
+use super::host_ctl::{EIR_LEN, IacLap, ScanEnable};
+use super::le::{AdvertisingParams, AdvertisingType};
+
+/// Longest time the adapter may stay limited discoverable
+const MAX_LIMITED_DISCOVERABLE: Duration = Duration::from_secs(180);
+
+/// How the adapter answers devices looking for it
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DiscoverableMode {
+    #[default]
+    NonDiscoverable,
+    /// Discoverable for a limited time, for devices looking for recently activated devices as
+    /// well as general discovery
+    Limited,
+    General,
+}
+
+/// What the adapter tells devices that discover or connect to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GapConfig {
+    /// Local name, sent in the extended inquiry response and in LE scan responses
+    pub name: String,
+    /// Class of device. The limited discoverable service class is set by the discoverable mode.
+    pub class: ClassOfDevice,
+    /// Structures added to the extended inquiry response and the advertising data, in order,
+    /// skipping those that do not fit
+    pub ad: Vec<AdStructure>,
+    /// Parameters of LE advertising. The advertising type is set by the connectable mode.
+    pub advertising: AdvertisingParams,
+}
+
+impl Default for GapConfig {
+    fn default() -> Self {
+        GapConfig {
+            name: String::new(),
+            class: ClassOfDevice::from_raw(0),
+            ad: Vec::new(),
+            advertising: AdvertisingParams::default(),
+        }
+    }
+}
+
+/// Configuration and modes applied to the adapter by the GAP helpers
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GapState {
+    config: GapConfig,
+    discoverable: DiscoverableMode,
+    connectable: bool,
+    /// When the adapter stops being discoverable
+    discoverable_until: Option<Instant>,
+}
+
+/// Append the structures that fit, skipping the others.
+fn push_fitting(builder: &mut AdBuilder, structures: &[AdStructure]) {
+    for structure in structures {
+        let _ = builder.push(structure);
+    }
+}
+
+impl Adapter {
+    /// Return what the adapter tells devices that discover or connect to it
+    pub fn gap_config(&self) -> &GapConfig {
+        &self.gap.config
+    }
+
+    /// Change what the adapter tells devices that discover or connect to it, and apply it with
+    /// the current discoverable and connectable modes.
+    pub fn set_gap_config(&mut self, config: GapConfig) -> Result<()> {
+        self.gap.config = config;
+        self.apply_gap()
+    }
+
+    /// Return the discoverable mode set with `set_discoverable`
+    pub fn discoverable_mode(&self) -> DiscoverableMode {
+        self.gap.discoverable
+    }
+
+    /// Return whether the adapter was made connectable with `set_connectable`
+    pub fn is_connectable(&self) -> bool {
+        self.gap.connectable
+    }
+
+    /// Make the adapter discoverable over BR/EDR and LE, or stop it from being discoverable.
+    ///
+    /// Sets the inquiry access codes, class of device, inquiry scan, extended inquiry response
+    /// and advertising to match the mode. If `timeout` is set, the adapter becomes
+    /// non-discoverable the first time `update_discoverable` is called after it passes. Limited
+    /// discoverable mode needs a timeout of at most three minutes.
+    pub fn set_discoverable(&mut self, mode: DiscoverableMode, timeout: Option<Duration>) -> Result<()> {
+        if mode == DiscoverableMode::Limited
+            && timeout.map_or(true, |timeout| timeout > MAX_LIMITED_DISCOVERABLE) {
+            return Err(Error::new(InvalidInput, "Limited discoverable mode needs a timeout of at most 180 s"));
+        }
+        self.gap.discoverable = mode;
+        self.gap.discoverable_until = match mode {
+            DiscoverableMode::NonDiscoverable => None,
+            _ => timeout.map(|timeout| Instant::now() + timeout),
+        };
+        self.apply_gap()
+    }
+
+    /// Make the adapter connectable over BR/EDR and LE, or stop accepting connections.
+    ///
+    /// Sets page scan and the type of advertising to match.
+    pub fn set_connectable(&mut self, connectable: bool) -> Result<()> {
+        self.gap.connectable = connectable;
+        self.apply_gap()
+    }
+
+    /// Make the adapter non-discoverable if the timeout of `set_discoverable` has passed, and
+    /// return the current discoverable mode.
+    pub fn update_discoverable(&mut self) -> Result<DiscoverableMode> {
+        if self.gap.discoverable_until.is_some_and(|until| until <= Instant::now()) {
+            self.set_discoverable(DiscoverableMode::NonDiscoverable, None)?;
+        }
+        Ok(self.gap.discoverable)
+    }
+
+    fn apply_gap(&mut self) -> Result<()> {
+        self.apply_gap_bredr()?;
+        self.apply_gap_le()
+    }
+
+    fn apply_gap_bredr(&mut self) -> Result<()> {
+        let timeout = self.timeout;
+        let gap = &self.gap;
+        let socket = &mut self.socket;
+
+        socket.write_local_name(&gap.config.name, timeout)?;
+
+        let class = gap.config.class;
+        let mut services = class.service_classes() - ServiceClasses::LIMITED_DISCOVERABLE;
+        if gap.discoverable == DiscoverableMode::Limited {
+            services |= ServiceClasses::LIMITED_DISCOVERABLE;
+        }
+        socket.write_class_of_device(
+            ClassOfDevice::new(services, class.major_device_class(), class.minor_device_class()),
+            timeout)?;
+
+        let mut eir = AdBuilder::with_limit(EIR_LEN);
+        if !gap.config.name.is_empty() {
+            eir.local_name(&gap.config.name)?;
+        }
+        push_fitting(&mut eir, &gap.config.ad);
+        socket.write_extended_inquiry_response(false, eir.as_bytes(), timeout)?;
+
+        let mut scan_enable = ScanEnable::empty();
+        match gap.discoverable {
+            DiscoverableMode::NonDiscoverable => (),
+            DiscoverableMode::Limited => {
+                socket.write_current_iac_lap(&[IacLap::GIAC, IacLap::LIAC], timeout)?;
+                scan_enable |= ScanEnable::INQUIRY;
+            }
+            DiscoverableMode::General => {
+                socket.write_current_iac_lap(&[IacLap::GIAC], timeout)?;
+                scan_enable |= ScanEnable::INQUIRY;
+            }
+        }
+        if gap.connectable {
+            scan_enable |= ScanEnable::PAGE;
+        }
+        socket.write_scan_enable(scan_enable, timeout)
+    }
+
+    fn apply_gap_le(&mut self) -> Result<()> {
+        let timeout = self.timeout;
+        let gap = &self.gap;
+        let socket = &mut self.socket;
+
+        // Parameters and data cannot be changed while advertising.
+        socket.le_set_advertise_enable(false, timeout)?;
+        let flags = match gap.discoverable {
+            DiscoverableMode::NonDiscoverable if !gap.connectable => return Ok(()),
+            DiscoverableMode::NonDiscoverable => AdFlags::empty(),
+            DiscoverableMode::Limited => AdFlags::LE_LIMITED_DISCOVERABLE,
+            DiscoverableMode::General => AdFlags::LE_GENERAL_DISCOVERABLE,
+        };
+
+        let params = AdvertisingParams {
+            advertising_type: if gap.connectable {
+                AdvertisingType::ConnectableUndirected
+            } else {
+                AdvertisingType::ScannableUndirected
+            },
+            ..gap.config.advertising
+        };
+        socket.le_set_advertising_parameters(&params, timeout)?;
+
+        let mut data = AdBuilder::new();
+        if !flags.is_empty() {
+            data.flags(flags)?;
+        }
+        push_fitting(&mut data, &gap.config.ad);
+        socket.le_set_advertising_data(data.as_bytes(), timeout)?;
+
+        let mut scan_response = AdBuilder::new();
+        if !gap.config.name.is_empty() {
+            scan_response.local_name(&gap.config.name)?;
+        }
+        socket.le_set_scan_response_data(scan_response.as_bytes(), timeout)?;
+
+        socket.le_set_advertise_enable(true, timeout)
+    }
+}
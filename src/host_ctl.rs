@@ -0,0 +1,916 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::ops::{RangeInclusive};
+use std::time::Duration;
+
+use super::audio::{DataPath, DataPathDirection};
+use super::class::ClassOfDevice;
+use super::connection::Connection;
+use super::error::MalformedPacket;
+use super::info::DataBlockSize;
+use super::io::{Counted, NulPadded, ReadAs, ReadFrom, WriteAs, WriteTo};
+use super::socket::Socket;
+use super::units::{from_units, to_units, SLOT_US};
+
+const OGF_HOST_CTL: u16 = 0x03;
+const OCF_READ_PIN_TYPE: u16 = 0x0009;
+const OCF_WRITE_PIN_TYPE: u16 = 0x000A;
+const OCF_FLUSH: u16 = 0x0008;
+const OCF_WRITE_LOCAL_NAME: u16 = 0x0013;
+const OCF_READ_LOCAL_NAME: u16 = 0x0014;
+const OCF_READ_SCAN_ENABLE: u16 = 0x0019;
+const OCF_WRITE_SCAN_ENABLE: u16 = 0x001A;
+const OCF_READ_AUTH_ENABLE: u16 = 0x001F;
+const OCF_WRITE_AUTH_ENABLE: u16 = 0x0020;
+const OCF_READ_CLASS_OF_DEV: u16 = 0x0023;
+const OCF_WRITE_CLASS_OF_DEV: u16 = 0x0024;
+const OCF_READ_VOICE_SETTING: u16 = 0x0025;
+const OCF_WRITE_VOICE_SETTING: u16 = 0x0026;
+const OCF_SET_CONTROLLER_TO_HOST_FC: u16 = 0x0031;
+const OCF_HOST_BUFFER_SIZE: u16 = 0x0033;
+const OCF_HOST_NUM_COMP_PKTS: u16 = 0x0035;
+const OCF_READ_LINK_SUPERVISION_TIMEOUT: u16 = 0x0036;
+const OCF_WRITE_LINK_SUPERVISION_TIMEOUT: u16 = 0x0037;
+const OCF_READ_NUM_SUPPORTED_IAC: u16 = 0x0038;
+const OCF_READ_CURRENT_IAC_LAP: u16 = 0x0039;
+const OCF_WRITE_CURRENT_IAC_LAP: u16 = 0x003A;
+const OCF_SET_AFH_CLASSIFICATION: u16 = 0x003F;
+const OCF_READ_EXT_INQUIRY_RESPONSE: u16 = 0x0051;
+const OCF_WRITE_EXT_INQUIRY_RESPONSE: u16 = 0x0052;
+const OCF_READ_DEFAULT_ERRONEOUS_DATA_REPORTING: u16 = 0x005A;
+const OCF_WRITE_DEFAULT_ERRONEOUS_DATA_REPORTING: u16 = 0x005B;
+const OCF_ENHANCED_FLUSH: u16 = 0x005F;
+const OCF_SET_EVENT_MASK_PAGE_2: u16 = 0x0063;
+const OCF_READ_FLOW_CONTROL_MODE: u16 = 0x0066;
+const OCF_WRITE_FLOW_CONTROL_MODE: u16 = 0x0067;
+const OCF_CONFIGURE_DATA_PATH: u16 = 0x0083;
+
+const EVT_ENHANCED_FLUSH_COMPLETE: u8 = 0x39;
+
+/// Packet type of Enhanced Flush for automatically flushable packets, the only type defined
+const FLUSH_AUTOMATICALLY_FLUSHABLE: u8 = 0x00;
+
+/// Length of the local name field, including the terminating zero if it is shorter
+const LOCAL_NAME_LEN: usize = 248;
+/// Length of extended inquiry response data
+pub(crate) const EIR_LEN: usize = 240;
+
+impl Socket {
+    /// Read the class of device of the local adapter.
+    pub fn read_class_of_device(&mut self, timeout: c_int) -> Result<ClassOfDevice> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_CLASS_OF_DEV, &[], timeout)?;
+        let (class, _) = (&*data).read_as::<ClassOfDevice>()?;
+        Ok(class)
+    }
+
+    /// Set the class of device of the local adapter.
+    pub fn write_class_of_device(&mut self, class: ClassOfDevice, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_CLASS_OF_DEV, &class.bytes()?, timeout)
+            .map(|_| ())
+    }
+
+    /// Set the class of device of the local adapter from its 24-bit value.
+    #[deprecated(note = "use `write_class_of_device` with a `ClassOfDevice`")]
+    pub fn write_class_of_dev(&mut self, class: u32, timeout: c_int) -> Result<()> {
+        self.write_class_of_device(ClassOfDevice::from_raw(class), timeout)
+    }
+}
+
+// Discoverability
+
+bitflags! {
+    /// Scans the controller runs to answer other BR/EDR devices
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct ScanEnable: u8 {
+        /// Answer inquiries, making the device discoverable
+        const INQUIRY = 0x01;
+        /// Answer pages, making the device connectable
+        const PAGE = 0x02;
+    }
+}
+
+impl Socket {
+    /// Read which scans the controller runs.
+    pub fn read_scan_enable(&mut self, timeout: c_int) -> Result<ScanEnable> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_SCAN_ENABLE, &[], timeout)?;
+        let (scan_enable, _) = (&*data).read_as::<u8>()?;
+        Ok(ScanEnable::from_bits_truncate(scan_enable))
+    }
+
+    /// Set which scans the controller runs.
+    pub fn write_scan_enable(&mut self, scan_enable: ScanEnable, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_SCAN_ENABLE, &[scan_enable.bits()], timeout)
+            .map(|_| ())
+    }
+
+    /// Read the name of the local device.
+    pub fn read_local_name(&mut self, timeout: c_int) -> Result<String> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_LOCAL_NAME, &[], timeout)?;
+        let (NulPadded(name), _) = (&*data).read_as::<NulPadded<LOCAL_NAME_LEN>>()?;
+        Ok(name)
+    }
+
+    /// Write the name of the local device, sent to devices that request it.
+    ///
+    /// The name may be up to 248 bytes of UTF-8.
+    pub fn write_local_name(&mut self, name: &str, timeout: c_int) -> Result<()> {
+        if name.len() > LOCAL_NAME_LEN {
+            return Err(Error::new(InvalidInput, "Local name too long"));
+        }
+        let mut param = [0u8; LOCAL_NAME_LEN];
+        param[..name.len()].copy_from_slice(name.as_bytes());
+        self.execute(OGF_HOST_CTL, OCF_WRITE_LOCAL_NAME, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Read the extended inquiry response data and whether it is sent with FEC.
+    ///
+    /// The data is returned padded with zeros to 240 bytes.
+    pub fn read_extended_inquiry_response(&mut self, timeout: c_int) -> Result<(bool, Box<[u8]>)> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_EXT_INQUIRY_RESPONSE, &[], timeout)?;
+        let (&fec_required, eir) = data.split_first().ok_or(MalformedPacket)?;
+        Ok((fec_required != 0, eir.into()))
+    }
+
+    /// Write the extended inquiry response data sent in answers to inquiries.
+    ///
+    /// The data may be up to 240 bytes, as built by an `AdBuilder` with that limit.
+    pub fn write_extended_inquiry_response(&mut self, fec_required: bool, data: &[u8], timeout: c_int) -> Result<()> {
+        if data.len() > EIR_LEN {
+            return Err(Error::new(InvalidInput, "Extended inquiry response data too long"));
+        }
+        let mut param = [0u8; EIR_LEN + 1];
+        param[0] = fec_required as u8;
+        param[1..=data.len()].copy_from_slice(data);
+        self.execute(OGF_HOST_CTL, OCF_WRITE_EXT_INQUIRY_RESPONSE, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+// Adaptive frequency hopping
+
+/// Bitmap of the 79 BR/EDR channels used for adaptive frequency hopping.
+///
+/// In a host classification, a set bit marks a channel as unknown and a cleared bit marks it as
+/// bad. In a map read from the controller, a set bit marks a channel as used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelMap([u8; 10]);
+
+impl ChannelMap {
+    /// Number of channels in the map
+    pub const CHANNELS: u8 = 79;
+
+    /// Return a map with every channel set
+    pub fn all() -> Self {
+        let mut map = [0xFFu8; 10];
+        // Bit 79 is reserved.
+        map[9] = 0x7F;
+        ChannelMap(map)
+    }
+
+    /// Return a map with every channel cleared
+    pub fn none() -> Self {
+        ChannelMap([0u8; 10])
+    }
+
+    /// Create a map from its raw bytes, with channel 0 in the lowest bit of the first byte
+    pub fn from_bytes(mut bytes: [u8; 10]) -> Self {
+        bytes[9] &= 0x7F;
+        ChannelMap(bytes)
+    }
+
+    /// Return the raw bytes of the map
+    pub fn as_bytes(&self) -> &[u8; 10] {
+        &self.0
+    }
+
+    /// Return whether a channel is set
+    pub fn is_set(&self, channel: u8) -> bool {
+        channel < Self::CHANNELS && self.0[(channel / 8) as usize] & (1 << (channel % 8)) != 0
+    }
+
+    /// Set a channel in the map
+    pub fn set(&mut self, channel: u8) -> Result<()> {
+        if channel < Self::CHANNELS {
+            self.0[(channel / 8) as usize] |= 1 << (channel % 8);
+            Ok(())
+        } else {
+            Err(Error::new(InvalidInput, "Channel out of range"))
+        }
+    }
+
+    /// Clear a channel in the map
+    pub fn clear(&mut self, channel: u8) -> Result<()> {
+        if channel < Self::CHANNELS {
+            self.0[(channel / 8) as usize] &= !(1 << (channel % 8));
+            Ok(())
+        } else {
+            Err(Error::new(InvalidInput, "Channel out of range"))
+        }
+    }
+
+    /// Set every channel in a range
+    pub fn set_range(&mut self, channels: RangeInclusive<u8>) -> Result<()> {
+        channels.into_iter().try_for_each(|channel| self.set(channel))
+    }
+
+    /// Clear every channel in a range, such as the channels overlapping a Wi-Fi channel
+    pub fn clear_range(&mut self, channels: RangeInclusive<u8>) -> Result<()> {
+        channels.into_iter().try_for_each(|channel| self.clear(channel))
+    }
+
+    /// Return the number of channels set
+    pub fn count(&self) -> u32 {
+        self.0.iter().map(|b| b.count_ones()).sum()
+    }
+}
+
+impl ReadFrom for ChannelMap {
+    fn read_from<R: Read>(r: R) -> Result<(Self, usize)> {
+        let (bytes, size) = <[u8; 10]>::read_from(r)?;
+        Ok((ChannelMap::from_bytes(bytes), size))
+    }
+}
+
+impl WriteTo for &ChannelMap {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        self.0.write_to(w)
+    }
+}
+
+impl Socket {
+    /// Set which events of the second page of the event mask the controller reports, with
+    /// bit `n` standing for event code `0x40 + n`.
+    ///
+    /// All are masked after a reset.
+    pub fn set_event_mask_page_2(&mut self, mask: u64, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_SET_EVENT_MASK_PAGE_2, &mask.to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+
+    /// Tell the controller which channels the host knows to be bad.
+    pub fn set_afh_host_channel_classification(&mut self, map: &ChannelMap, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_SET_AFH_CLASSIFICATION, &map.bytes()?, timeout)
+            .map(|_| ())
+    }
+}
+
+// Link supervision
+
+impl Connection<'_> {
+    /// Read the time after which the controller considers this link lost.
+    pub fn link_supervision_timeout(&mut self, timeout: c_int) -> Result<Duration> {
+        let handle = self.handle();
+        let data = self.socket.execute(OGF_HOST_CTL, OCF_READ_LINK_SUPERVISION_TIMEOUT, &handle.to_le_bytes(), timeout)?;
+        let mut r: &[u8] = &data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (supervision_timeout, _) = r.read_as::<u16>()?;
+        Ok(from_units(supervision_timeout.into(), SLOT_US))
+    }
+
+    /// Write the time after which the controller considers this link lost.
+    ///
+    /// A zero duration disables the supervision timeout.
+    pub fn set_link_supervision_timeout(&mut self, supervision_timeout: Duration, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&to_units(supervision_timeout, SLOT_US)?.to_le_bytes());
+        self.socket.execute(OGF_HOST_CTL, OCF_WRITE_LINK_SUPERVISION_TIMEOUT, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Discard all data of this connection waiting to be sent by the controller, including the
+    /// rest of a packet being sent.
+    pub fn flush(&mut self, timeout: c_int) -> Result<()> {
+        let handle = self.handle();
+        self.socket.execute(OGF_HOST_CTL, OCF_FLUSH, &handle.to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+
+    /// Discard the automatically flushable data of this connection waiting to be sent, and
+    /// wait for the Enhanced Flush Complete event.
+    ///
+    /// Non-flushable packets stay queued. Data sent after the event is not flushed.
+    pub fn enhanced_flush(&mut self, timeout: c_int) -> Result<()> {
+        let handle = self.handle().to_le_bytes();
+        let mut param = handle.to_vec();
+        param.push(FLUSH_AUTOMATICALLY_FLUSHABLE);
+        self.socket.send_req_matching(OGF_HOST_CTL, OCF_ENHANCED_FLUSH,
+            EVT_ENHANCED_FLUSH_COMPLETE as c_int,
+            &param,
+            timeout,
+            |_, data| data.get(0..2) == Some(&handle),
+        ).map(|_| ())
+    }
+}
+
+// Voice settings
+
+/// Coding of audio data exchanged with the host
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputCoding {
+    Linear = 0x00,
+    MuLaw = 0x01,
+    ALaw = 0x02,
+}
+
+/// Format of linear audio samples exchanged with the host
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputDataFormat {
+    OnesComplement = 0x00,
+    TwosComplement = 0x01,
+    SignMagnitude = 0x02,
+    Unsigned = 0x03,
+}
+
+/// Size of linear audio samples exchanged with the host
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleSize {
+    Bits8 = 0x00,
+    Bits16 = 0x01,
+}
+
+/// Coding of audio data sent over the air
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AirCoding {
+    Cvsd = 0x00,
+    MuLaw = 0x01,
+    ALaw = 0x02,
+    Transparent = 0x03,
+}
+
+/// Voice setting used by synchronous connections
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VoiceSetting {
+    pub input_coding: InputCoding,
+    pub data_format: InputDataFormat,
+    pub sample_size: SampleSize,
+    /// Bit position of the MSB of a linear PCM sample
+    pub pcm_bit_position: u8,
+    pub air_coding: AirCoding,
+}
+
+impl VoiceSetting {
+    /// 16-bit two's complement linear input with CVSD air coding, the default for SCO audio
+    pub const CVSD: VoiceSetting = VoiceSetting {
+        input_coding: InputCoding::Linear,
+        data_format: InputDataFormat::TwosComplement,
+        sample_size: SampleSize::Bits16,
+        pcm_bit_position: 0,
+        air_coding: AirCoding::Cvsd,
+    };
+
+    /// 16-bit two's complement linear input with transparent air coding, as used by mSBC
+    pub const TRANSPARENT: VoiceSetting = VoiceSetting {
+        air_coding: AirCoding::Transparent,
+        ..VoiceSetting::CVSD
+    };
+}
+
+impl TryFrom<u16> for VoiceSetting {
+    type Error = Error;
+
+    fn try_from(setting: u16) -> Result<Self> {
+        let input_coding = match (setting >> 8) & 0x03 {
+            0x00 => InputCoding::Linear,
+            0x01 => InputCoding::MuLaw,
+            0x02 => InputCoding::ALaw,
+            _ => return Err(Error::new(InvalidData, "Unknown input coding")),
+        };
+        let data_format = match (setting >> 6) & 0x03 {
+            0x00 => InputDataFormat::OnesComplement,
+            0x01 => InputDataFormat::TwosComplement,
+            0x02 => InputDataFormat::SignMagnitude,
+            _ => InputDataFormat::Unsigned,
+        };
+        let sample_size = match (setting >> 5) & 0x01 {
+            0x00 => SampleSize::Bits8,
+            _ => SampleSize::Bits16,
+        };
+        let air_coding = match setting & 0x03 {
+            0x00 => AirCoding::Cvsd,
+            0x01 => AirCoding::MuLaw,
+            0x02 => AirCoding::ALaw,
+            _ => AirCoding::Transparent,
+        };
+        Ok(VoiceSetting {
+            input_coding,
+            data_format,
+            sample_size,
+            pcm_bit_position: ((setting >> 2) & 0x07) as u8,
+            air_coding,
+        })
+    }
+}
+
+impl From<VoiceSetting> for u16 {
+    fn from(setting: VoiceSetting) -> u16 {
+        ((setting.input_coding as u16) << 8)
+            | ((setting.data_format as u16) << 6)
+            | ((setting.sample_size as u16) << 5)
+            | ((setting.pcm_bit_position as u16 & 0x07) << 2)
+            | setting.air_coding as u16
+    }
+}
+
+impl Socket {
+    /// Read the voice setting used by synchronous connections.
+    pub fn read_voice_setting(&mut self, timeout: c_int) -> Result<VoiceSetting> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_VOICE_SETTING, &[], timeout)?;
+        let (setting, _) = (&*data).read_as::<u16>()?;
+        VoiceSetting::try_from(setting)
+    }
+
+    /// Write the voice setting used by synchronous connections.
+    pub fn write_voice_setting(&mut self, setting: VoiceSetting, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_VOICE_SETTING, &u16::from(setting).to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+}
+
+// Audio data paths
+
+impl Socket {
+    /// Configure a vendor-specific audio data path, such as an I2S or PCM interface.
+    ///
+    /// `vendor_config` holds the vendor-specific configuration of the transport.
+    pub fn configure_data_path(&mut self, direction: DataPathDirection, path: DataPath, vendor_config: &[u8], timeout: c_int) -> Result<()> {
+        if !matches!(path, DataPath::Vendor(_)) {
+            return Err(Error::new(InvalidInput, "Only vendor-specific data paths can be configured"));
+        }
+        let config_len: u8 = vendor_config.len().try_into()
+            .map_err(|_| Error::new(InvalidInput, "Vendor configuration too long"))?;
+
+        let mut param = vec![direction as u8, path.into(), config_len];
+        param.extend_from_slice(vendor_config);
+        self.execute(OGF_HOST_CTL, OCF_CONFIGURE_DATA_PATH, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+// Host flow control
+
+/// Packet types for which the controller applies host flow control
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HostFlowControl {
+    Off = 0x00,
+    Acl = 0x01,
+    Sync = 0x02,
+    AclAndSync = 0x03,
+}
+
+/// Size and number of the host's receive buffers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HostBufferSize {
+    pub acl_data_packet_length: u16,
+    pub sync_data_packet_length: u8,
+    pub total_num_acl_data_packets: u16,
+    pub total_num_sync_data_packets: u16,
+}
+
+impl Socket {
+    /// Turn on flow control from the controller to the host.
+    ///
+    /// While ACL flow control is on, packets returned by `recv_acl` are acknowledged to the
+    /// controller automatically.
+    pub fn set_controller_to_host_flow_control(&mut self, mode: HostFlowControl, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_SET_CONTROLLER_TO_HOST_FC, &[mode as u8], timeout)?;
+        self.host_flow_control = matches!(mode, HostFlowControl::Acl | HostFlowControl::AclAndSync);
+        Ok(())
+    }
+
+    /// Tell the controller the size and number of the host's receive buffers.
+    pub fn host_buffer_size(&mut self, size: &HostBufferSize, timeout: c_int) -> Result<()> {
+        let mut param = size.acl_data_packet_length.to_le_bytes().to_vec();
+        param.push(size.sync_data_packet_length);
+        param.extend_from_slice(&size.total_num_acl_data_packets.to_le_bytes());
+        param.extend_from_slice(&size.total_num_sync_data_packets.to_le_bytes());
+        self.execute(OGF_HOST_CTL, OCF_HOST_BUFFER_SIZE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Report packets consumed by the host, given as pairs of connection handle and count.
+    ///
+    /// The controller does not respond to this command unless it fails.
+    pub fn host_number_of_completed_packets(&self, completed: &[(u16, u16)]) -> Result<()> {
+        let num_handles: u8 = completed.len().try_into()
+            .map_err(|_| Error::new(InvalidInput, "Too many handles"))?;
+
+        let mut param = vec![num_handles];
+        (&mut param).write_all_as(completed.iter().flat_map(|&(handle, count)| [handle, count]))?;
+        self.send_cmd(OGF_HOST_CTL, OCF_HOST_NUM_COMP_PKTS, &param)
+            .map(|_| ())
+    }
+}
+
+// Flow control mode
+
+/// How the host keeps track of the ACL data buffers of the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlowControlMode {
+    /// Count packets, acknowledged with Number Of Completed Packets events
+    PacketBased = 0x00,
+    /// Count data blocks, acknowledged with Number Of Completed Data Blocks events
+    DataBlockBased = 0x01,
+}
+
+impl TryFrom<u8> for FlowControlMode {
+    type Error = Error;
+
+    fn try_from(mode: u8) -> Result<Self> {
+        match mode {
+            0x00 => Ok(FlowControlMode::PacketBased),
+            0x01 => Ok(FlowControlMode::DataBlockBased),
+            _ => Err(Error::new(InvalidData, "Unknown flow control mode")),
+        }
+    }
+}
+
+/// Buffers of one connection freed by the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompletedBlocks {
+    pub handle: u16,
+    /// Packets sent or flushed since the last report
+    pub packets: u16,
+    /// Data blocks freed since the last report
+    pub blocks: u16,
+}
+
+/// Parameters of a Number Of Completed Data Blocks event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompletedDataBlocks {
+    /// Data blocks the controller now has in total, or `None` if the host should read the
+    /// data block size again because the buffer pool changed
+    pub total_num_data_blocks: Option<u16>,
+    pub completed: Vec<CompletedBlocks>,
+}
+
+impl CompletedDataBlocks {
+    /// Parse the parameters of a Number Of Completed Data Blocks event.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (total_num_data_blocks, _) = r.read_as::<u16>()?;
+        let (Counted(entries), _) = r.read_as::<Counted<(u16, u16, u16)>>()?;
+        let completed = entries.into_iter()
+            .map(|(handle, packets, blocks)| CompletedBlocks { handle: handle & 0x0FFF, packets, blocks })
+            .collect();
+        Ok(CompletedDataBlocks {
+            total_num_data_blocks: (total_num_data_blocks != 0).then_some(total_num_data_blocks),
+            completed,
+        })
+    }
+}
+
+/// Data blocks of the controller free for ACL data, for data-block-based flow control.
+///
+/// Blocks are taken for each ACL data packet before it is sent, and given back by Number Of
+/// Completed Data Blocks events.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataBlockCredits {
+    size: DataBlockSize,
+    free: u16,
+}
+
+impl DataBlockCredits {
+    /// Start with every data block of the controller free.
+    pub fn new(size: DataBlockSize) -> Self {
+        DataBlockCredits { size, free: size.total_num_data_blocks }
+    }
+
+    /// Return the data buffers the credits were counted for
+    pub fn size(&self) -> DataBlockSize {
+        self.size
+    }
+
+    /// Return the number of data blocks free for sending
+    pub fn free_blocks(&self) -> u16 {
+        self.free
+    }
+
+    /// Return the number of data blocks a packet with `len` bytes of ACL data takes.
+    pub fn blocks_for(&self, len: usize) -> Result<u16> {
+        if len > self.size.max_acl_data_packet_length.into() {
+            return Err(Error::new(InvalidInput, "ACL data is longer than the controller accepts"));
+        }
+        if self.size.data_block_length == 0 {
+            return Err(Error::new(InvalidData, "Data block length is 0"));
+        }
+        // At most 65535 blocks, since the length is at most the maximum packet length.
+        Ok(len.div_ceil(self.size.data_block_length.into()) as u16)
+    }
+
+    /// Take the data blocks for a packet with `len` bytes of ACL data, returning whether
+    /// enough were free. No blocks are taken if there were not.
+    pub fn take(&mut self, len: usize) -> Result<bool> {
+        let blocks = self.blocks_for(len)?;
+        match self.free.checked_sub(blocks) {
+            Some(free) => {
+                self.free = free;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Give back the data blocks freed by a Number Of Completed Data Blocks event.
+    ///
+    /// Returns `false` if the controller changed its buffer pool, in which case the host
+    /// should read the data block size again and `resize` the credits.
+    pub fn complete(&mut self, completed: &CompletedDataBlocks) -> bool {
+        let freed = completed.completed.iter().fold(0u16, |freed, entry| freed.saturating_add(entry.blocks));
+        self.free = self.free.saturating_add(freed).min(self.size.total_num_data_blocks);
+        match completed.total_num_data_blocks {
+            Some(total) if total != self.size.total_num_data_blocks => {
+                // Blocks in use stay in use with the new total.
+                let in_use = self.size.total_num_data_blocks - self.free;
+                self.size.total_num_data_blocks = total;
+                self.free = total.saturating_sub(in_use);
+                true
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    /// Count the credits again for a newly read data block size, keeping blocks in use.
+    pub fn resize(&mut self, size: DataBlockSize) {
+        let in_use = self.size.total_num_data_blocks - self.free;
+        self.size = size;
+        self.free = size.total_num_data_blocks.saturating_sub(in_use);
+    }
+}
+
+impl Socket {
+    /// Read whether the controller uses packet-based or data-block-based flow control.
+    pub fn read_flow_control_mode(&mut self, timeout: c_int) -> Result<FlowControlMode> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_FLOW_CONTROL_MODE, &[], timeout)?;
+        let (mode, _) = (&*data).read_as::<u8>()?;
+        FlowControlMode::try_from(mode)
+    }
+
+    /// Choose packet-based or data-block-based flow control. Only allowed while there are no
+    /// connections.
+    pub fn write_flow_control_mode(&mut self, mode: FlowControlMode, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_FLOW_CONTROL_MODE, &[mode as u8], timeout)
+            .map(|_| ())
+    }
+}
+
+// Inquiry access codes
+
+/// Lower address part of an inquiry access code
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IacLap(pub u32);
+
+impl IacLap {
+    /// General Inquiry Access Code
+    pub const GIAC: IacLap = IacLap(0x9E8B33);
+    /// Limited Inquiry Access Code
+    pub const LIAC: IacLap = IacLap(0x9E8B00);
+}
+
+impl ReadFrom for IacLap {
+    fn read_from<R: Read>(r: R) -> Result<(Self, usize)> {
+        let ([b0, b1, b2], size) = <[u8; 3]>::read_from(r)?;
+        Ok((IacLap(u32::from_le_bytes([b0, b1, b2, 0])), size))
+    }
+}
+
+impl WriteTo for IacLap {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        w.write(&self.0.to_le_bytes()[..3])
+    }
+}
+
+impl Socket {
+    /// Read the number of inquiry access codes the controller can listen for at once.
+    pub fn read_num_supported_iac(&mut self, timeout: c_int) -> Result<u8> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_NUM_SUPPORTED_IAC, &[], timeout)?;
+        let (num_iac, _) = (&*data).read_as::<u8>()?;
+        Ok(num_iac)
+    }
+
+    /// Read the inquiry access codes the controller is listening for.
+    pub fn read_current_iac_lap(&mut self, timeout: c_int) -> Result<Vec<IacLap>> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_CURRENT_IAC_LAP, &[], timeout)?;
+        let (Counted(laps), _) = (&*data).read_as::<Counted<IacLap>>()?;
+        Ok(laps)
+    }
+
+    /// Write the inquiry access codes the controller listens for.
+    ///
+    /// Listening for both `IacLap::LIAC` and `IacLap::GIAC` makes the device limited
+    /// discoverable.
+    pub fn write_current_iac_lap(&mut self, laps: &[IacLap], timeout: c_int) -> Result<()> {
+        let num_iac: u8 = laps.len().try_into()
+            .map_err(|_| Error::new(InvalidInput, "Too many inquiry access codes"))?;
+
+        let mut param = vec![num_iac];
+        (&mut param).write_all_as(laps.iter().copied())?;
+        self.execute(OGF_HOST_CTL, OCF_WRITE_CURRENT_IAC_LAP, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+// Security
+
+/// Whether the host uses a variable or a fixed PIN for legacy pairing
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PinType {
+    Variable = 0x00,
+    Fixed = 0x01,
+}
+
+impl TryFrom<u8> for PinType {
+    type Error = Error;
+
+    fn try_from(pin_type: u8) -> Result<Self> {
+        match pin_type {
+            0x00 => Ok(PinType::Variable),
+            0x01 => Ok(PinType::Fixed),
+            _ => Err(Error::new(InvalidData, "Unknown PIN type")),
+        }
+    }
+}
+
+impl Socket {
+    /// Read the PIN type used for legacy pairing.
+    pub fn read_pin_type(&mut self, timeout: c_int) -> Result<PinType> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_PIN_TYPE, &[], timeout)?;
+        let (pin_type, _) = (&*data).read_as::<u8>()?;
+        PinType::try_from(pin_type)
+    }
+
+    /// Write the PIN type used for legacy pairing.
+    pub fn write_pin_type(&mut self, pin_type: PinType, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_PIN_TYPE, &[pin_type as u8], timeout)
+            .map(|_| ())
+    }
+
+    /// Read whether authentication is required on every new connection.
+    pub fn read_auth_enable(&mut self, timeout: c_int) -> Result<bool> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_AUTH_ENABLE, &[], timeout)?;
+        let (enable, _) = (&*data).read_as::<u8>()?;
+        Ok(enable != 0)
+    }
+
+    /// Write whether authentication is required on every new connection.
+    pub fn write_auth_enable(&mut self, enable: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_AUTH_ENABLE, &[enable as u8], timeout)
+            .map(|_| ())
+    }
+
+    /// Read whether erroneous data reporting is enabled on new synchronous connections.
+    pub fn read_default_erroneous_data_reporting(&mut self, timeout: c_int) -> Result<bool> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_DEFAULT_ERRONEOUS_DATA_REPORTING, &[], timeout)?;
+        let (enable, _) = (&*data).read_as::<u8>()?;
+        Ok(enable != 0)
+    }
+
+    /// Write whether erroneous data reporting is enabled on new synchronous connections.
+    ///
+    /// When enabled, the controller passes on SCO packets received with errors or lost,
+    /// marked by the packet status flag, instead of dropping them, so packet loss
+    /// concealment can repair the audio.
+    pub fn write_default_erroneous_data_reporting(&mut self, enable: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_DEFAULT_ERRONEOUS_DATA_REPORTING, &[enable as u8], timeout)
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: DataBlockSize = DataBlockSize {
+        max_acl_data_packet_length: 1021,
+        data_block_length: 100,
+        total_num_data_blocks: 10,
+    };
+
+    fn completed(total_num_data_blocks: Option<u16>, blocks: u16) -> CompletedDataBlocks {
+        CompletedDataBlocks {
+            total_num_data_blocks,
+            completed: vec![CompletedBlocks { handle: 0x0001, packets: 1, blocks }],
+        }
+    }
+
+    #[test]
+    fn packets_take_whole_blocks() {
+        let mut credits = DataBlockCredits::new(SIZE);
+        assert_eq!(credits.blocks_for(0).unwrap(), 0);
+        assert_eq!(credits.blocks_for(100).unwrap(), 1);
+        assert_eq!(credits.blocks_for(101).unwrap(), 2);
+        assert!(credits.blocks_for(1022).is_err());
+        assert!(credits.take(950).unwrap());
+        assert_eq!(credits.free_blocks(), 0);
+        assert!(!credits.take(1).unwrap());
+        assert_eq!(credits.free_blocks(), 0);
+    }
+
+    #[test]
+    fn completed_blocks_are_given_back() {
+        let mut credits = DataBlockCredits::new(SIZE);
+        assert!(credits.take(1000).unwrap());
+        assert!(credits.complete(&completed(Some(10), 4)));
+        assert_eq!(credits.free_blocks(), 4);
+        // More blocks than were taken never exceed the total.
+        assert!(credits.complete(&completed(Some(10), 20)));
+        assert_eq!(credits.free_blocks(), 10);
+    }
+
+    #[test]
+    fn changed_buffer_pools_keep_blocks_in_use() {
+        let mut credits = DataBlockCredits::new(SIZE);
+        assert!(credits.take(600).unwrap());
+        assert!(credits.complete(&completed(Some(20), 2)));
+        assert_eq!(credits.free_blocks(), 16);
+        assert!(!credits.complete(&completed(None, 0)));
+        credits.resize(DataBlockSize { total_num_data_blocks: 5, ..SIZE });
+        assert_eq!(credits.free_blocks(), 1);
+    }
+
+    #[test]
+    fn channel_map_ignores_bit_79() {
+        let map = ChannelMap::from_bytes([0xFF; 10]);
+        assert_eq!(map, ChannelMap::all());
+        assert_eq!(map.as_bytes()[9], 0x7F);
+        assert_eq!(map.count(), 79);
+        assert!(!map.is_set(79));
+
+        let (map, size) = (&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80][..]).read_as::<ChannelMap>().unwrap();
+        assert_eq!(map, ChannelMap::none());
+        assert_eq!(size, 10);
+
+        let mut map = ChannelMap::none();
+        assert!(map.set(79).is_err());
+        assert!(map.set_range(78..=79).is_err());
+        assert_eq!(map.count(), 1);
+    }
+
+    #[test]
+    fn channel_map_is_little_endian() {
+        let map = ChannelMap::from_bytes([0x01, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40]);
+        let set: Vec<u8> = (0..ChannelMap::CHANNELS).filter(|&channel| map.is_set(channel)).collect();
+        assert_eq!(set, [0, 15, 78]);
+
+        let mut map = ChannelMap::none();
+        map.set(9).unwrap();
+        map.set_range(16..=23).unwrap();
+        assert_eq!(map.as_bytes(), &[0x00, 0x02, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        map.clear(20).unwrap();
+        assert_eq!(map.as_bytes()[2], 0xEF);
+        assert_eq!(&*(&map).bytes().unwrap(), map.as_bytes());
+    }
+
+    #[test]
+    fn voice_setting_codes_air_coding() {
+        assert_eq!(u16::from(VoiceSetting::CVSD), 0x0060);
+        assert_eq!(u16::from(VoiceSetting::TRANSPARENT), 0x0063);
+        assert_eq!(u16::from(VoiceSetting { air_coding: AirCoding::MuLaw, ..VoiceSetting::CVSD }), 0x0061);
+        assert_eq!(u16::from(VoiceSetting { air_coding: AirCoding::ALaw, ..VoiceSetting::CVSD }), 0x0062);
+        assert_eq!(VoiceSetting::try_from(0x0060).unwrap(), VoiceSetting::CVSD);
+        assert_eq!(VoiceSetting::try_from(0x0062).unwrap().air_coding, AirCoding::ALaw);
+    }
+
+    #[test]
+    fn voice_setting_codes_input() {
+        let mu_law = VoiceSetting { input_coding: InputCoding::MuLaw, air_coding: AirCoding::MuLaw, ..VoiceSetting::CVSD };
+        assert_eq!(u16::from(mu_law), 0x0161);
+        assert_eq!(VoiceSetting::try_from(0x0161).unwrap(), mu_law);
+
+        let a_law = VoiceSetting { input_coding: InputCoding::ALaw, air_coding: AirCoding::ALaw, ..VoiceSetting::CVSD };
+        assert_eq!(u16::from(a_law), 0x0262);
+        assert_eq!(VoiceSetting::try_from(0x0262).unwrap(), a_law);
+
+        assert!(VoiceSetting::try_from(0x0360).is_err());
+    }
+
+    #[test]
+    fn voice_setting_codes_linear_samples() {
+        let setting = VoiceSetting {
+            data_format: InputDataFormat::SignMagnitude,
+            sample_size: SampleSize::Bits8,
+            ..VoiceSetting::CVSD
+        };
+        assert_eq!(u16::from(setting), 0x0080);
+        assert_eq!(VoiceSetting::try_from(0x0080).unwrap(), setting);
+
+        let setting = VoiceSetting::try_from(0x00C0).unwrap();
+        assert_eq!(setting.data_format, InputDataFormat::Unsigned);
+        assert_eq!(setting.sample_size, SampleSize::Bits8);
+        assert_eq!(VoiceSetting::try_from(0x0000).unwrap().data_format, InputDataFormat::OnesComplement);
+    }
+
+    #[test]
+    fn voice_setting_codes_pcm_bit_position() {
+        for position in 0..8 {
+            let setting = VoiceSetting { pcm_bit_position: position, ..VoiceSetting::CVSD };
+            assert_eq!(u16::from(setting), 0x0060 | u16::from(position) << 2);
+            assert_eq!(VoiceSetting::try_from(u16::from(setting)).unwrap().pcm_bit_position, position);
+        }
+        // Only three bits are sent.
+        assert_eq!(u16::from(VoiceSetting { pcm_bit_position: 0x0F, ..VoiceSetting::CVSD }), 0x007C);
+    }
+}
@@ -0,0 +1,215 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use super::audio::{CodecTransports, CodingFormat, CodingId, DataPathDirection, LogicalTransport, SupportedCodec};
+use super::company::CompanyId;
+use super::error::MalformedPacket;
+use super::io::{read_bytes, ReadAs, WriteTo};
+#[cfg(feature = "bredr")]
+use super::remote::LmpFeatures;
+use super::socket::Socket;
+use super::units::read_interval_u24;
+use super::version::{HciVersion, LmpVersion};
+
+const OGF_INFO_PARAM: u16 = 0x04;
+const OCF_READ_LOCAL_VERSION: u16 = 0x0001;
+const OCF_READ_LOCAL_COMMANDS: u16 = 0x0002;
+#[cfg(feature = "bredr")]
+const OCF_READ_LOCAL_FEATURES: u16 = 0x0003;
+const OCF_READ_DATA_BLOCK_SIZE: u16 = 0x000A;
+const OCF_READ_LOCAL_CODECS: u16 = 0x000B;
+const OCF_READ_LOCAL_CODECS_V2: u16 = 0x000D;
+const OCF_READ_LOCAL_CODEC_CAPABILITIES: u16 = 0x000E;
+const OCF_READ_LOCAL_CONTROLLER_DELAY: u16 = 0x000F;
+
+/// Build the coding format, transport and direction parameters shared by codec queries.
+fn codec_query(format: &CodingFormat, transport: LogicalTransport, direction: DataPathDirection) -> Result<Vec<u8>> {
+    let mut param = format.bytes()?.into_vec();
+    param.push(transport as u8);
+    param.push(direction as u8);
+    Ok(param)
+}
+
+/// Versions and manufacturer of the local controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LocalVersion {
+    pub hci_version: HciVersion,
+    /// Revision of the HCI implementation, defined by the manufacturer
+    pub hci_subversion: u16,
+    pub lmp_version: LmpVersion,
+    pub company: CompanyId,
+    /// Revision of the link manager or link layer implementation, defined by the manufacturer
+    pub lmp_subversion: u16,
+}
+
+/// Commands supported by the local controller, as a bit mask of 64 octets
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SupportedCommands(pub [u8; 64]);
+
+impl SupportedCommands {
+    /// Return whether the command at the given octet and bit of the mask is supported, as
+    /// numbered in the Supported Commands table of the Core specification.
+    pub fn contains(&self, octet: usize, bit: u8) -> bool {
+        self.0.get(octet).is_some_and(|&mask| mask & (1 << bit) != 0)
+    }
+}
+
+impl Default for SupportedCommands {
+    fn default() -> Self {
+        SupportedCommands([0; 64])
+    }
+}
+
+/// Data buffers of a controller using data-block-based flow control
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataBlockSize {
+    /// Longest ACL data packet the controller accepts
+    pub max_acl_data_packet_length: u16,
+    /// Length of each data block
+    pub data_block_length: u16,
+    pub total_num_data_blocks: u16,
+}
+
+impl Socket {
+    /// Read the size and number of the data blocks of a controller using data-block-based
+    /// flow control.
+    pub fn read_data_block_size(&mut self, timeout: c_int) -> Result<DataBlockSize> {
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_DATA_BLOCK_SIZE, &[], timeout)?;
+        let mut r: &[u8] = &data;
+        let (max_acl_data_packet_length, _) = r.read_as::<u16>()?;
+        let (data_block_length, _) = r.read_as::<u16>()?;
+        let (total_num_data_blocks, _) = r.read_as::<u16>()?;
+        Ok(DataBlockSize { max_acl_data_packet_length, data_block_length, total_num_data_blocks })
+    }
+
+    /// Read the commands supported by the local controller.
+    pub fn read_local_supported_commands(&mut self, timeout: c_int) -> Result<SupportedCommands> {
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_LOCAL_COMMANDS, &[], timeout)?;
+        let mut commands = SupportedCommands::default();
+        read_bytes(&mut &*data, &mut commands.0)?;
+        Ok(commands)
+    }
+
+    /// Read page 0 of the LMP features supported by the local controller.
+    #[cfg(feature = "bredr")]
+    pub fn read_local_supported_features(&mut self, timeout: c_int) -> Result<LmpFeatures> {
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_LOCAL_FEATURES, &[], timeout)?;
+        let (features, _) = (&*data).read_as::<u64>()?;
+        Ok(LmpFeatures::from_bits_retain(features))
+    }
+
+    /// Read the versions and manufacturer of the local controller.
+    pub fn read_local_version(&mut self, timeout: c_int) -> Result<LocalVersion> {
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_LOCAL_VERSION, &[], timeout)?;
+        let mut r: &[u8] = &data;
+        let (hci_version, _) = r.read_as::<u8>()?;
+        let (hci_subversion, _) = r.read_as::<u16>()?;
+        let (lmp_version, _) = r.read_as::<u8>()?;
+        let (company, _) = r.read_as::<u16>()?;
+        let (lmp_subversion, _) = r.read_as::<u16>()?;
+        Ok(LocalVersion {
+            hci_version: hci_version.into(),
+            hci_subversion,
+            lmp_version: lmp_version.into(),
+            company: CompanyId(company),
+            lmp_subversion,
+        })
+    }
+}
+
+impl Socket {
+    /// Read the codecs supported by the local controller.
+    pub fn read_local_supported_codecs(&mut self, timeout: c_int) -> Result<Vec<CodingFormat>> {
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_LOCAL_CODECS, &[], timeout)?;
+        let mut r: &[u8] = &data;
+        let mut codecs = Vec::new();
+
+        let (num_standard, _) = r.read_as::<u8>()?;
+        for _ in 0..num_standard {
+            let (id, _) = r.read_as::<u8>()?;
+            codecs.push(CodingFormat::new(CodingId::from(id)));
+        }
+
+        let (num_vendor, _) = r.read_as::<u8>()?;
+        for _ in 0..num_vendor {
+            let (company_id, _) = r.read_as::<u16>()?;
+            let (vendor_codec_id, _) = r.read_as::<u16>()?;
+            codecs.push(CodingFormat::vendor(company_id, vendor_codec_id));
+        }
+
+        Ok(codecs)
+    }
+
+    /// Read the codecs supported by the local controller along with the transports each one is
+    /// supported on.
+    pub fn read_local_supported_codecs_v2(&mut self, timeout: c_int) -> Result<Vec<SupportedCodec>> {
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_LOCAL_CODECS_V2, &[], timeout)?;
+        let mut r: &[u8] = &data;
+        let mut codecs = Vec::new();
+
+        let (num_standard, _) = r.read_as::<u8>()?;
+        for _ in 0..num_standard {
+            let (id, _) = r.read_as::<u8>()?;
+            let (transports, _) = r.read_as::<u8>()?;
+            codecs.push(SupportedCodec {
+                format: CodingFormat::new(CodingId::from(id)),
+                transports: CodecTransports::from_bits_retain(transports),
+            });
+        }
+
+        let (num_vendor, _) = r.read_as::<u8>()?;
+        for _ in 0..num_vendor {
+            let (company_id, _) = r.read_as::<u16>()?;
+            let (vendor_codec_id, _) = r.read_as::<u16>()?;
+            let (transports, _) = r.read_as::<u8>()?;
+            codecs.push(SupportedCodec {
+                format: CodingFormat::vendor(company_id, vendor_codec_id),
+                transports: CodecTransports::from_bits_retain(transports),
+            });
+        }
+
+        Ok(codecs)
+    }
+
+    /// Read the capabilities of a codec on a transport and direction.
+    ///
+    /// Each capability is returned as its raw codec-specific bytes.
+    pub fn read_local_supported_codec_capabilities(&mut self, format: &CodingFormat, transport: LogicalTransport, direction: DataPathDirection, timeout: c_int) -> Result<Vec<Box<[u8]>>> {
+        let param = codec_query(format, transport, direction)?;
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_LOCAL_CODEC_CAPABILITIES, &param, timeout)?;
+        let mut r: &[u8] = &data;
+        let mut capabilities = Vec::new();
+
+        let (num_capabilities, _) = r.read_as::<u8>()?;
+        for _ in 0..num_capabilities {
+            let (length, _) = r.read_as::<u8>()?;
+            let length = length as usize;
+            if r.len() < length {
+                return Err(MalformedPacket.into());
+            }
+            let (capability, rest) = r.split_at(length);
+            capabilities.push(capability.into());
+            r = rest;
+        }
+
+        Ok(capabilities)
+    }
+
+    /// Read the range of controller delays supported for a codec configuration.
+    ///
+    /// Returns the minimum and maximum delay.
+    pub fn read_local_supported_controller_delay(&mut self, format: &CodingFormat, transport: LogicalTransport, direction: DataPathDirection, configuration: &[u8], timeout: c_int) -> Result<(Duration, Duration)> {
+        let mut param = codec_query(format, transport, direction)?;
+        param.push(configuration.len().try_into()
+            .map_err(|_| Error::new(InvalidInput, "Codec configuration too long"))?);
+        param.extend_from_slice(configuration);
+
+        let data = self.execute(OGF_INFO_PARAM, OCF_READ_LOCAL_CONTROLLER_DELAY, &param, timeout)?;
+        let mut r: &[u8] = &data;
+        let min_delay = read_interval_u24(&mut r, 1)?;
+        let max_delay = read_interval_u24(&mut r, 1)?;
+        Ok((min_delay, max_delay))
+    }
+}
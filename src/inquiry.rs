@@ -0,0 +1,154 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::time::Duration;
+
+use super::ad::{self, AdStructure};
+use super::addr::BdAddr;
+use super::class::ClassOfDevice;
+use super::error::MalformedPacket;
+use super::host_ctl::{EIR_LEN, IacLap};
+use super::io::{ReadAs, WriteTo};
+use super::socket::{EVT_CMD_STATUS, Socket};
+use super::units::{to_units};
+
+const OGF_LINK_CTL: u16 = 0x01;
+const OCF_INQUIRY: u16 = 0x0001;
+const OCF_INQUIRY_CANCEL: u16 = 0x0002;
+
+const OGF_HOST_CTL: u16 = 0x03;
+const OCF_READ_INQUIRY_MODE: u16 = 0x0044;
+const OCF_WRITE_INQUIRY_MODE: u16 = 0x0045;
+
+#[cfg(feature = "le")]
+pub(crate) const EVT_INQUIRY_COMPLETE: u8 = 0x01;
+pub(crate) const EVT_INQUIRY_RESULT: u8 = 0x02;
+pub(crate) const EVT_INQUIRY_RESULT_WITH_RSSI: u8 = 0x22;
+pub(crate) const EVT_EXTENDED_INQUIRY_RESULT: u8 = 0x2F;
+
+/// Unit of the inquiry length in microseconds
+const INQUIRY_LENGTH_US: u64 = 1_280_000;
+/// Longest inquiry, in units of 1.28 s
+const MAX_INQUIRY_LENGTH: u16 = 0x30;
+
+/// Format of the results reported during an inquiry
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum InquiryMode {
+    /// Inquiry Result events
+    #[default]
+    Standard = 0x00,
+    /// Inquiry Result with RSSI events
+    Rssi = 0x01,
+    /// Inquiry Result with RSSI events, or Extended Inquiry Result events when the device sent
+    /// extended inquiry response data
+    Extended = 0x02,
+}
+
+impl TryFrom<u8> for InquiryMode {
+    type Error = Error;
+
+    fn try_from(mode: u8) -> Result<Self> {
+        match mode {
+            0x00 => Ok(InquiryMode::Standard),
+            0x01 => Ok(InquiryMode::Rssi),
+            0x02 => Ok(InquiryMode::Extended),
+            _ => Err(Error::new(InvalidData, "Unknown inquiry mode")),
+        }
+    }
+}
+
+/// A device that responded to an inquiry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InquiryResult {
+    pub address: BdAddr,
+    pub page_scan_repetition_mode: u8,
+    pub class: ClassOfDevice,
+    /// Offset between the clocks of the devices, as sent in connection requests
+    pub clock_offset: u16,
+    /// Signal strength in dBm, if the inquiry mode reports it
+    pub rssi: Option<i8>,
+    /// Extended inquiry response structures, up to the first malformed structure
+    pub eir: Vec<AdStructure>,
+}
+
+/// Parse an Inquiry Result, Inquiry Result with RSSI or Extended Inquiry Result event.
+///
+/// Responses are read one after another, in the layout used by BlueZ and existing controllers.
+pub fn parse_inquiry_results(event: u8, params: &[u8]) -> Result<Vec<InquiryResult>> {
+    let mut r = params;
+    let (num_responses, _) = r.read_as::<u8>()?;
+    // Some controllers send Inquiry Result with RSSI events with the page scan mode included.
+    let with_scan_mode = event == EVT_INQUIRY_RESULT_WITH_RSSI
+        && r.len() == num_responses as usize * 15;
+
+    (0..num_responses).map(|_| {
+        let (address, _) = r.read_as::<BdAddr>()?;
+        let (page_scan_repetition_mode, _) = r.read_as::<u8>()?;
+        // Reserved page scan period mode and page scan mode
+        let reserved = match event {
+            EVT_INQUIRY_RESULT => 2,
+            EVT_INQUIRY_RESULT_WITH_RSSI if with_scan_mode => 2,
+            _ => 1,
+        };
+        r = r.get(reserved..).ok_or(MalformedPacket)?;
+        let (class, _) = r.read_as::<ClassOfDevice>()?;
+        let (clock_offset, _) = r.read_as::<u16>()?;
+
+        let rssi = match event {
+            EVT_INQUIRY_RESULT => None,
+            _ => Some(r.read_as::<u8>()?.0 as i8),
+        };
+        let eir = match event {
+            EVT_EXTENDED_INQUIRY_RESULT => {
+                let data = r.get(..EIR_LEN).ok_or(MalformedPacket)?;
+                r = &r[EIR_LEN..];
+                // The data is padded with zeros, which end the structures.
+                ad::structures(data)
+                    .map_while(|structure| structure.ok())
+                    .filter_map(|(ad_type, data)| AdStructure::decode(ad_type, data).ok())
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(InquiryResult { address, page_scan_repetition_mode, class, clock_offset, rssi, eir })
+    }).collect()
+}
+
+impl Socket {
+    /// Start an inquiry for devices listening on `lap`.
+    ///
+    /// Returns once the controller has started the inquiry. Results arrive as inquiry result
+    /// events, in the format set with `write_inquiry_mode`, until an Inquiry Complete event.
+    /// The length is rounded down to units of 1.28 s. If `num_responses` is zero, the number of
+    /// responses is unlimited.
+    pub fn inquiry(&mut self, lap: IacLap, length: Duration, num_responses: u8, timeout: c_int) -> Result<()> {
+        let length = to_units(length, INQUIRY_LENGTH_US)?;
+        if length == 0 || length > MAX_INQUIRY_LENGTH {
+            return Err(Error::new(InvalidInput, "Inquiry length out of range"));
+        }
+        let mut param = lap.bytes()?.into_vec();
+        param.extend_from_slice(&[length as u8, num_responses]);
+        self.send_req(OGF_LINK_CTL, OCF_INQUIRY, EVT_CMD_STATUS as c_int, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Stop a running inquiry.
+    pub fn inquiry_cancel(&mut self, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LINK_CTL, OCF_INQUIRY_CANCEL, &[], timeout)
+            .map(|_| ())
+    }
+
+    /// Read the format of inquiry results.
+    pub fn read_inquiry_mode(&mut self, timeout: c_int) -> Result<InquiryMode> {
+        let data = self.execute(OGF_HOST_CTL, OCF_READ_INQUIRY_MODE, &[], timeout)?;
+        let (mode, _) = (&*data).read_as::<u8>()?;
+        InquiryMode::try_from(mode)
+    }
+
+    /// Set the format of inquiry results.
+    pub fn write_inquiry_mode(&mut self, mode: InquiryMode, timeout: c_int) -> Result<()> {
+        self.execute(OGF_HOST_CTL, OCF_WRITE_INQUIRY_MODE, &[mode as u8], timeout)
+            .map(|_| ())
+    }
+}
@@ -1,8 +1,20 @@
 use libc::{c_int};
-use std::io::{Read, Result, Write};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidData, UnexpectedEof};
+
+use super::codec::{Be, Le};
+use super::error::MalformedPacket;
 
 // Traits for reading events
 
+/// Fill `buf` from `r`, failing with `MalformedPacket` if the data ends first.
+pub(crate) fn read_bytes<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<()> {
+    r.read_exact(buf).map_err(|e| match e.kind() {
+        UnexpectedEof => MalformedPacket.into(),
+        _ => e,
+    })
+}
+
 pub trait ReadFrom: Sized {
     fn read_from<R: Read>(r: R) -> Result<(Self, usize)>;
 }
@@ -18,24 +30,113 @@ impl <R: Read> ReadAs for R {
     }
 }
 
+macro_rules! impl_read_from_endian {
+    ($($t:ty),+) => {
+        $(
+            impl ReadFrom for Le<$t> {
+                fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+                    let mut bytes = [0u8; size_of::<$t>()];
+                    read_bytes(&mut r, &mut bytes)?;
+                    Ok((Le(<$t>::from_le_bytes(bytes)), bytes.len()))
+                }
+            }
+
+            impl ReadFrom for Be<$t> {
+                fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+                    let mut bytes = [0u8; size_of::<$t>()];
+                    read_bytes(&mut r, &mut bytes)?;
+                    Ok((Be(<$t>::from_be_bytes(bytes)), bytes.len()))
+                }
+            }
+        )+
+    }
+}
+
+impl_read_from_endian!(u16, i16, u32, i32, u64, u128);
+
+/// Read single bytes, or little-endian integers, the order of HCI fields.
 macro_rules! impl_read_from_int {
     ($t:ty) => {
         impl ReadFrom for $t {
-            fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
-                let mut bytes = [0u8; size_of::<$t>()];
-                let size = r.read(&mut bytes)?;
-                Ok((<$t>::from_le_bytes(bytes), size))
+            fn read_from<R: Read>(r: R) -> Result<(Self, usize)> {
+                Le::<$t>::read_from(r).map(|(Le(value), size)| (value, size))
             }
         }
     }
 }
 
-impl_read_from_int!(u8);
+impl ReadFrom for u8 {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let mut bytes = [0u8; 1];
+        read_bytes(&mut r, &mut bytes)?;
+        Ok((bytes[0], 1))
+    }
+}
+
+impl ReadFrom for i8 {
+    fn read_from<R: Read>(r: R) -> Result<(Self, usize)> {
+        u8::read_from(r).map(|(value, size)| (value as i8, size))
+    }
+}
+
 impl_read_from_int!(u16);
+impl_read_from_int!(i16);
 impl_read_from_int!(u32);
 impl_read_from_int!(u64);
 
+impl <const N: usize> ReadFrom for [u8; N] {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let mut bytes = [0u8; N];
+        read_bytes(&mut r, &mut bytes)?;
+        Ok((bytes, N))
+    }
+}
+
+macro_rules! impl_read_from_tuple {
+    ($($t:ident $value:ident $size:ident),+) => {
+        impl <$($t: ReadFrom),+> ReadFrom for ($($t,)+) {
+            fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+                $(let ($value, $size) = r.read_as::<$t>()?;)+
+                Ok((($($value,)+), 0 $(+ $size)+))
+            }
+        }
+    }
+}
+
+impl_read_from_tuple!(A a a_size, B b b_size);
+impl_read_from_tuple!(A a a_size, B b b_size, C c c_size);
+
+/// Items preceded by their number in one byte, as in the handle lists of Number Of Completed
+/// Packets events
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Counted<T>(pub Vec<T>);
+
+impl <T: ReadFrom> ReadFrom for Counted<T> {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (count, mut size) = r.read_as::<u8>()?;
+        let items = (0..count).map(|_| {
+            let (item, item_size) = r.read_as::<T>()?;
+            size += item_size;
+            Ok(item)
+        }).collect::<Result<_>>()?;
+        Ok((Counted(items), size))
+    }
+}
 
+/// UTF-8 text in a field of `N` bytes, ending at the first null byte or at the end of the
+/// field, as in local names
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct NulPadded<const N: usize>(pub String);
+
+impl <const N: usize> ReadFrom for NulPadded<N> {
+    fn read_from<R: Read>(r: R) -> Result<(Self, usize)> {
+        let (bytes, size) = <[u8; N]>::read_from(r)?;
+        let text = bytes.split(|&b| b == 0).next().unwrap_or_default();
+        let text = String::from_utf8(text.to_vec())
+            .map_err(|_| Error::new(InvalidData, "Text is not valid UTF-8"))?;
+        Ok((NulPadded(text), size))
+    }
+}
 
 
 pub trait WriteTo: Sized {
@@ -50,6 +151,9 @@ pub trait WriteTo: Sized {
 
 pub trait WriteAs {
     fn write_as<T: WriteTo>(self, value: T) -> Result<usize>;
+
+    /// Write each of `values` in turn, returning the total size written.
+    fn write_all_as<T: WriteTo, I: IntoIterator<Item = T>>(self, values: I) -> Result<usize>;
 }
 
 // Automatic implementation of WriteAs for any Write.
@@ -57,22 +161,148 @@ impl <W: Write> WriteAs for W {
     fn write_as<T: WriteTo>(mut self, value: T) -> Result<usize> {
         value.write_to(&mut self)
     }
+
+    fn write_all_as<T: WriteTo, I: IntoIterator<Item = T>>(mut self, values: I) -> Result<usize> {
+        values.into_iter().try_fold(0, |size, value| Ok(size + value.write_to(&mut self)?))
+    }
 }
 
+macro_rules! impl_write_to_endian {
+    ($($t:ty),+) => {
+        $(
+            impl WriteTo for Le<$t> {
+                fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+                    w.write(&self.to_bytes())
+                }
+            }
+
+            impl WriteTo for Be<$t> {
+                fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+                    w.write(&self.to_bytes())
+                }
+            }
+        )+
+    }
+}
+
+impl_write_to_endian!(u16, i16, u32, i32, u64, u128);
+
+/// Write single bytes, or little-endian integers, the order of HCI fields.
 macro_rules! impl_write_as_int {
     ($t:ty) => {
         impl WriteTo for $t {
             fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
-                w.write(&self.to_le_bytes())
+                Le(self).write_to(w)
             }
         }
     }
 }
 
-impl_write_as_int!(u8);
+impl WriteTo for u8 {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        w.write(&[self])
+    }
+}
+
+impl WriteTo for i8 {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        (self as u8).write_to(w)
+    }
+}
+
 impl_write_as_int!(u16);
 impl_write_as_int!(u32);
 impl_write_as_int!(u64);
 impl_write_as_int!(u128);
 impl_write_as_int!(c_int);
 
+/// Write the bytes unchanged, for parameters that embed variable payloads.
+impl WriteTo for &[u8] {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        w.write_all(self)?;
+        Ok(self.len())
+    }
+}
+
+impl WriteTo for &Vec<u8> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        self.as_slice().write_to(w)
+    }
+}
+
+impl WriteTo for Vec<u8> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        self.as_slice().write_to(w)
+    }
+}
+
+impl <const N: usize> WriteTo for &[u8; N] {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        self.as_slice().write_to(w)
+    }
+}
+
+impl <const N: usize> WriteTo for [u8; N] {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        self.as_slice().write_to(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::{AddressType, BdAddr};
+
+    /// Return whether reading `T` from `data` fails as a malformed packet
+    fn is_malformed<T: ReadFrom>(mut data: &[u8]) -> bool {
+        data.read_as::<T>().is_err_and(|e| e.get_ref().is_some_and(|e| e.is::<MalformedPacket>()))
+    }
+
+    #[test]
+    fn truncated_integers_are_malformed() {
+        assert!(is_malformed::<u8>(&[]));
+        assert!(is_malformed::<u16>(&[0x01]));
+        assert!(is_malformed::<i16>(&[0x01]));
+        assert!(is_malformed::<u32>(&[0x01, 0x02, 0x03]));
+        assert!(is_malformed::<u64>(&[0x01; 7]));
+        assert!(is_malformed::<Le<u128>>(&[0x01; 15]));
+        assert!(is_malformed::<Be<u16>>(&[0x01]));
+    }
+
+    #[test]
+    fn integers_read_only_their_size() {
+        let data = [0x01, 0x02, 0x03, 0x04, 0x05];
+        assert_eq!((&data[..]).read_as::<u16>().unwrap(), (0x0201, 2));
+        assert_eq!((&data[..]).read_as::<Be<u16>>().unwrap(), (Be(0x0102), 2));
+        assert_eq!((&data[..]).read_as::<u32>().unwrap(), (0x04030201, 4));
+    }
+
+    #[test]
+    fn truncated_arrays_and_addresses_are_malformed() {
+        assert!(is_malformed::<[u8; 4]>(&[0x01, 0x02, 0x03]));
+        assert!(is_malformed::<BdAddr>(&[0x01; 5]));
+        assert!(is_malformed::<AddressType>(&[]));
+    }
+
+    #[test]
+    fn truncated_tuples_are_malformed() {
+        assert!(is_malformed::<(u8, u16)>(&[0x01, 0x02]));
+        assert!(is_malformed::<(u8, u8, u8)>(&[0x01, 0x02]));
+        assert_eq!((&[0x01, 0x02, 0x03, 0xFF][..]).read_as::<(u8, u16)>().unwrap(), ((0x01, 0x0302), 3));
+    }
+
+    #[test]
+    fn counted_items_stop_at_the_data() {
+        assert!(is_malformed::<Counted<u16>>(&[]));
+        assert!(is_malformed::<Counted<u16>>(&[0x02, 0x01, 0x00, 0x02]));
+        assert_eq!((&[0x01, 0x34, 0x12, 0xFF][..]).read_as::<Counted<u16>>().unwrap(), (Counted(vec![0x1234]), 3));
+    }
+
+    #[test]
+    fn nul_padded_text_needs_the_whole_field() {
+        assert!(is_malformed::<NulPadded<4>>(b"abc"));
+        assert_eq!((&b"ab\0d"[..]).read_as::<NulPadded<4>>().unwrap(), (NulPadded("ab".into()), 4));
+        assert_eq!((&b"abcd"[..]).read_as::<NulPadded<4>>().unwrap(), (NulPadded("abcd".into()), 4));
+        assert!((&[0xFF, 0x00][..]).read_as::<NulPadded<2>>().is_err());
+    }
+}
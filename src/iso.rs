@@ -0,0 +1,100 @@
+use libc::EIO;
+use std::io::{Error, IoSlice, Result};
+use std::io::ErrorKind::{InvalidInput};
+
+use super::codec::{self, Packet};
+use super::socket::{Socket, Timestamp};
+
+const HCI_MAX_ISO_SIZE: usize = 16384;
+
+/// Packet boundary flag of an ISO data packet
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IsoBoundary {
+    /// First fragment of a fragmented SDU
+    First = 0x00,
+    /// Continuation fragment of a fragmented SDU
+    Continuation = 0x01,
+    /// A complete SDU
+    Complete = 0x02,
+    /// Last fragment of a fragmented SDU
+    Last = 0x03,
+}
+
+impl From<u8> for IsoBoundary {
+    fn from(flag: u8) -> Self {
+        match flag & 0x03 {
+            0x00 => IsoBoundary::First,
+            0x01 => IsoBoundary::Continuation,
+            0x02 => IsoBoundary::Complete,
+            _ => IsoBoundary::Last,
+        }
+    }
+}
+
+impl IsoBoundary {
+    /// Return whether packets with this flag start an SDU and carry its header
+    pub fn starts_sdu(&self) -> bool {
+        matches!(self, IsoBoundary::First | IsoBoundary::Complete)
+    }
+}
+
+/// ISO data packet exchanged with the controller
+#[derive(Clone, Debug)]
+pub struct IsoPacket {
+    pub handle: u16,
+    pub boundary: IsoBoundary,
+    /// Whether the data load starts with a time stamp
+    pub has_timestamp: bool,
+    /// Data load, including the time stamp and SDU header if present
+    pub data: Box<[u8]>,
+}
+
+impl Socket {
+    /// Receive the next ISO data packet, discarding packets of other types.
+    ///
+    /// The socket filter must pass ISO data packets. A packet with less data than its header
+    /// announces fails with `MalformedPacket` instead of being returned short.
+    pub fn recv_iso(&mut self) -> Result<IsoPacket> {
+        let mut buf = self.pool.take(HCI_MAX_ISO_SIZE + 5);
+        let result = (|s: &mut Socket| loop {
+            let size = s.recv_timestamped(&mut buf)?;
+            match Packet::parse(&buf[..size]) {
+                Ok(Packet::Iso { handle, packet_boundary, has_timestamp, data }) => return Ok(IsoPacket {
+                    handle,
+                    boundary: IsoBoundary::from(packet_boundary),
+                    has_timestamp,
+                    data: data.into(),
+                }),
+                Ok(_) | Err(codec::Error::UnknownPacketType(_)) => continue,
+                Err(err) => {
+                    s.stats.malformed();
+                    return Err(err.into());
+                }
+            }
+        })(self);
+        self.pool.give(buf);
+        result
+    }
+
+    /// Receive the next ISO data packet, like `recv_iso`, and return it with the time it was
+    /// received.
+    pub fn recv_iso_timestamped(&mut self) -> Result<(IsoPacket, Timestamp)> {
+        let packet = self.recv_iso()?;
+        let timestamp = self.last_event_timestamp().ok_or_else(|| Error::from_raw_os_error(EIO))?;
+        Ok((packet, timestamp))
+    }
+
+    /// Send an ISO data packet to the controller.
+    pub fn send_iso(&self, packet: &IsoPacket) -> Result<()> {
+        if packet.handle > 0x0EFF {
+            return Err(Error::new(InvalidInput, "Invalid connection handle"));
+        }
+        let packet = Packet::Iso {
+            handle: packet.handle,
+            packet_boundary: packet.boundary as u8,
+            has_timestamp: packet.has_timestamp,
+            data: &packet.data,
+        }.encode()?;
+        self.send_all_vectored(&[IoSlice::new(&packet)]).map(|_| ())
+    }
+}
@@ -0,0 +1,136 @@
+use libc::{c_int};
+use std::io::{Result};
+
+use crate::addr::BdAddr;
+use crate::io::ReadAs;
+use crate::socket::Socket;
+use super::OGF_LE_CTL;
+
+const OCF_LE_READ_FILTER_ACCEPT_LIST_SIZE: u16 = 0x000F;
+const OCF_LE_CLEAR_FILTER_ACCEPT_LIST: u16 = 0x0010;
+const OCF_LE_ADD_DEVICE_TO_FILTER_ACCEPT_LIST: u16 = 0x0011;
+const OCF_LE_REMOVE_DEVICE_FROM_FILTER_ACCEPT_LIST: u16 = 0x0012;
+
+/// Device in the LE Filter Accept List
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AcceptListEntry {
+    Public(BdAddr),
+    Random(BdAddr),
+    /// Advertisements sent without an advertiser address
+    Anonymous,
+}
+
+impl AcceptListEntry {
+    /// Encode the entry as an address type followed by an address
+    fn bytes(&self) -> [u8; 7] {
+        let (address_type, addr) = match self {
+            AcceptListEntry::Public(addr) => (0x00, *addr),
+            AcceptListEntry::Random(addr) => (0x01, *addr),
+            AcceptListEntry::Anonymous => (0xFF, BdAddr::ANY),
+        };
+        let mut bytes = [address_type, 0, 0, 0, 0, 0, 0];
+        bytes[1..].copy_from_slice(addr.as_bytes());
+        bytes
+    }
+}
+
+impl Socket {
+    /// Read the number of entries the Filter Accept List can hold.
+    pub fn le_read_filter_accept_list_size(&mut self, timeout: c_int) -> Result<usize> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_FILTER_ACCEPT_LIST_SIZE, &[], timeout)?;
+        let (size, _) = (&*data).read_as::<u8>()?;
+        Ok(size.into())
+    }
+
+    /// Remove all devices from the Filter Accept List.
+    pub fn le_clear_filter_accept_list(&mut self, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_CLEAR_FILTER_ACCEPT_LIST, &[], timeout)
+            .map(|_| ())
+    }
+
+    /// Add a device to the Filter Accept List.
+    pub fn le_add_device_to_filter_accept_list(&mut self, entry: AcceptListEntry, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_ADD_DEVICE_TO_FILTER_ACCEPT_LIST, &entry.bytes(), timeout)
+            .map(|_| ())
+    }
+
+    /// Remove a device from the Filter Accept List.
+    pub fn le_remove_device_from_filter_accept_list(&mut self, entry: AcceptListEntry, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_REMOVE_DEVICE_FROM_FILTER_ACCEPT_LIST, &entry.bytes(), timeout)
+            .map(|_| ())
+    }
+}
+
+/// Copy of the controller's Filter Accept List.
+///
+/// Changes are sent to the controller and only recorded once the controller accepts them, so
+/// the copy stays in sync as long as the list is not changed through other means.
+#[derive(Clone, Debug)]
+pub struct FilterAcceptList {
+    capacity: usize,
+    entries: Vec<AcceptListEntry>,
+}
+
+impl FilterAcceptList {
+    /// Clear the controller's list and read its capacity.
+    pub fn new(socket: &mut Socket, timeout: c_int) -> Result<Self> {
+        let capacity = socket.le_read_filter_accept_list_size(timeout)?;
+        socket.le_clear_filter_accept_list(timeout)?;
+        Ok(FilterAcceptList {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+        })
+    }
+
+    /// Number of entries the controller can hold
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of entries in the list
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the list is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries in the list, in the order they were added
+    pub fn entries(&self) -> &[AcceptListEntry] {
+        &self.entries
+    }
+
+    /// Return whether the list contains an entry.
+    pub fn contains(&self, entry: &AcceptListEntry) -> bool {
+        self.entries.contains(entry)
+    }
+
+    /// Add an entry to the list. Entries already in the list are not sent again.
+    pub fn add(&mut self, socket: &mut Socket, entry: AcceptListEntry, timeout: c_int) -> Result<()> {
+        if self.contains(&entry) {
+            return Ok(());
+        }
+        socket.le_add_device_to_filter_accept_list(entry, timeout)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Remove an entry from the list. Entries not in the list are ignored.
+    pub fn remove(&mut self, socket: &mut Socket, entry: AcceptListEntry, timeout: c_int) -> Result<()> {
+        let Some(index) = self.entries.iter().position(|e| *e == entry) else {
+            return Ok(());
+        };
+        socket.le_remove_device_from_filter_accept_list(entry, timeout)?;
+        self.entries.remove(index);
+        Ok(())
+    }
+
+    /// Remove all entries from the list.
+    pub fn clear(&mut self, socket: &mut Socket, timeout: c_int) -> Result<()> {
+        socket.le_clear_filter_accept_list(timeout)?;
+        self.entries.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Error, Result, Write};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::addr::{AddressType, BdAddr, OwnAddressType};
+use crate::io::{WriteAs, WriteTo};
+use crate::socket::Socket;
+use crate::units::{to_units, SLOT_US};
+use super::OGF_LE_CTL;
+
+pub use crate::ad::{MAX_ADV_DATA_LEN, MAX_EXT_ADV_DATA_LEN};
+
+const OCF_LE_SET_ADVERTISING_PARAMETERS: u16 = 0x0006;
+const OCF_LE_SET_ADVERTISING_DATA: u16 = 0x0008;
+const OCF_LE_SET_SCAN_RESPONSE_DATA: u16 = 0x0009;
+const OCF_LE_SET_ADVERTISE_ENABLE: u16 = 0x000A;
+
+/// Type of legacy advertising
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AdvertisingType {
+    /// Connectable and scannable undirected advertising (ADV_IND)
+    #[default]
+    ConnectableUndirected = 0x00,
+    /// Connectable high duty cycle directed advertising (ADV_DIRECT_IND)
+    ConnectableHighDutyDirected = 0x01,
+    /// Scannable undirected advertising (ADV_SCAN_IND)
+    ScannableUndirected = 0x02,
+    /// Non-connectable undirected advertising (ADV_NONCONN_IND)
+    NonConnectableUndirected = 0x03,
+    /// Connectable low duty cycle directed advertising (ADV_DIRECT_IND)
+    ConnectableLowDutyDirected = 0x04,
+}
+
+bitflags! {
+    /// Primary advertising channels
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct AdvertisingChannels: u8 {
+        const CHANNEL_37 = 0x01;
+        const CHANNEL_38 = 0x02;
+        const CHANNEL_39 = 0x04;
+    }
+}
+
+impl Default for AdvertisingChannels {
+    fn default() -> Self {
+        AdvertisingChannels::all()
+    }
+}
+
+/// Which devices are allowed to scan or connect to the advertiser
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AdvertisingFilterPolicy {
+    /// Accept scan and connection requests from any device
+    #[default]
+    None = 0x00,
+    /// Only accept scan requests from devices in the filter accept list
+    FilterScan = 0x01,
+    /// Only accept connection requests from devices in the filter accept list
+    FilterConnect = 0x02,
+    /// Only accept scan and connection requests from devices in the filter accept list
+    FilterBoth = 0x03,
+}
+
+/// Parameters of legacy advertising
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AdvertisingParams {
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    pub advertising_type: AdvertisingType,
+    pub own_address_type: OwnAddressType,
+    /// Type of the peer address, used by directed advertising and privacy
+    pub peer_address_type: AddressType,
+    /// Address of the peer, used by directed advertising and privacy
+    pub peer_address: BdAddr,
+    pub channel_map: AdvertisingChannels,
+    pub filter_policy: AdvertisingFilterPolicy,
+}
+
+impl Default for AdvertisingParams {
+    /// The parameters used by the controller after a reset
+    fn default() -> Self {
+        AdvertisingParams {
+            interval_min: Duration::from_millis(1280),
+            interval_max: Duration::from_millis(1280),
+            advertising_type: AdvertisingType::default(),
+            own_address_type: OwnAddressType::default(),
+            peer_address_type: AddressType::default(),
+            peer_address: BdAddr::ANY,
+            channel_map: AdvertisingChannels::default(),
+            filter_policy: AdvertisingFilterPolicy::default(),
+        }
+    }
+}
+
+impl WriteTo for &AdvertisingParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(to_units(self.interval_min, SLOT_US)?)?
+            + w.write_as(to_units(self.interval_max, SLOT_US)?)?
+            + w.write_as(self.advertising_type as u8)?
+            + w.write_as(self.own_address_type as u8)?
+            + w.write_as(self.peer_address_type as u8)?
+            + w.write_as(&self.peer_address)?
+            + w.write_as(self.channel_map.bits())?
+            + w.write_as(self.filter_policy as u8)?)
+    }
+}
+
+/// Pad advertising data to the fixed 31-byte field, prefixed with its length.
+fn adv_data_param(data: &[u8]) -> Result<[u8; MAX_ADV_DATA_LEN + 1]> {
+    if data.len() > MAX_ADV_DATA_LEN {
+        return Err(Error::new(InvalidInput, "Advertising data too long"));
+    }
+    let mut param = [0u8; MAX_ADV_DATA_LEN + 1];
+    param[0] = data.len() as u8;
+    param[1..=data.len()].copy_from_slice(data);
+    Ok(param)
+}
+
+impl Socket {
+    /// Set the parameters of legacy advertising.
+    pub fn le_set_advertising_parameters(&mut self, params: &AdvertisingParams, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_ADVERTISING_PARAMETERS, &params.bytes()?, timeout)
+            .map(|_| ())
+    }
+
+    /// Set the data sent in legacy advertising packets.
+    pub fn le_set_advertising_data(&mut self, data: &[u8], timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_ADVERTISING_DATA, &adv_data_param(data)?, timeout)
+            .map(|_| ())
+    }
+
+    /// Set the data sent in legacy scan responses.
+    pub fn le_set_scan_response_data(&mut self, data: &[u8], timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_SCAN_RESPONSE_DATA, &adv_data_param(data)?, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop legacy advertising.
+    pub fn le_set_advertise_enable(&mut self, enable: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_ADVERTISE_ENABLE, &[enable as u8], timeout)
+            .map(|_| ())
+    }
+}
+
+/// Legacy advertiser on a controller.
+///
+/// Commands are sent through the borrowed socket, each waiting up to `timeout` milliseconds.
+pub struct Advertiser<'a> {
+    socket: &'a mut Socket,
+    timeout: c_int,
+}
+
+impl <'a> Advertiser<'a> {
+    /// Create an advertiser using a socket.
+    pub fn new(socket: &'a mut Socket, timeout: c_int) -> Self {
+        Advertiser { socket, timeout }
+    }
+
+    /// Set the advertising parameters. Advertising must be disabled.
+    pub fn set_parameters(&mut self, params: &AdvertisingParams) -> Result<()> {
+        self.socket.le_set_advertising_parameters(params, self.timeout)
+    }
+
+    /// Set the advertising interval range, keeping the other parameters at their defaults.
+    pub fn set_interval(&mut self, min: Duration, max: Duration) -> Result<()> {
+        self.set_parameters(&AdvertisingParams {
+            interval_min: min,
+            interval_max: max,
+            ..AdvertisingParams::default()
+        })
+    }
+
+    /// Set the advertising data.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<()> {
+        self.socket.le_set_advertising_data(data, self.timeout)
+    }
+
+    /// Set the scan response data.
+    pub fn set_scan_response(&mut self, data: &[u8]) -> Result<()> {
+        self.socket.le_set_scan_response_data(data, self.timeout)
+    }
+
+    /// Start advertising.
+    pub fn start(&mut self) -> Result<()> {
+        self.socket.le_set_advertise_enable(true, self.timeout)
+    }
+
+    /// Stop advertising.
+    pub fn stop(&mut self) -> Result<()> {
+        self.socket.le_set_advertise_enable(false, self.timeout)
+    }
+}
@@ -0,0 +1,365 @@
+use libc::{c_int};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::adapter::Adapter;
+use crate::error::StatusError;
+use crate::io::{ReadAs, WriteAs, WriteTo};
+use crate::socket::Socket;
+use crate::units::{from_units, read_interval_u24, to_units, write_interval_u24};
+use super::{OGF_LE_CTL, Packing, Phy, PhyMask};
+use super::conn::CONN_INTERVAL_US;
+
+const OCF_LE_CREATE_BIG: u16 = 0x0068;
+const OCF_LE_TERMINATE_BIG: u16 = 0x006A;
+const OCF_LE_BIG_CREATE_SYNC: u16 = 0x006B;
+const OCF_LE_BIG_TERMINATE_SYNC: u16 = 0x006C;
+
+pub(crate) const EVT_LE_CREATE_BIG_COMPLETE: u8 = 0x1B;
+pub(crate) const EVT_LE_TERMINATE_BIG_COMPLETE: u8 = 0x1C;
+pub(crate) const EVT_LE_BIG_SYNC_ESTABLISHED: u8 = 0x1D;
+pub(crate) const EVT_LE_BIG_SYNC_LOST: u8 = 0x1E;
+
+/// Unit of BIG sync timeouts, in microseconds
+const BIG_SYNC_TIMEOUT_US: u64 = 10_000;
+
+/// Reason given when the host ends a BIG
+const REASON_LOCAL_HOST_TERMINATED: u8 = 0x16;
+
+/// Parameters of a broadcast isochronous group
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BigParams {
+    /// Number of BISes in the group
+    pub num_bis: u8,
+    pub sdu_interval: Duration,
+    pub max_sdu: u16,
+    pub max_transport_latency: Duration,
+    /// Number of times each packet is retransmitted
+    pub rtn: u8,
+    pub phy: PhyMask,
+    pub packing: Packing,
+    /// Whether SDUs are framed
+    pub framed: bool,
+    /// Code used to encrypt the BISes, or `None` for unencrypted broadcast
+    pub broadcast_code: Option<[u8; 16]>,
+}
+
+impl Default for BigParams {
+    /// One unencrypted BIS for 10 ms unframed SDUs on LE 2M
+    fn default() -> Self {
+        BigParams {
+            num_bis: 1,
+            sdu_interval: Duration::from_millis(10),
+            max_sdu: 100,
+            max_transport_latency: Duration::from_millis(20),
+            rtn: 2,
+            phy: PhyMask::LE_2M,
+            packing: Packing::default(),
+            framed: false,
+            broadcast_code: None,
+        }
+    }
+}
+
+/// Parameters of LE Create BIG.
+struct CreateBig<'a> {
+    big_handle: u8,
+    adv_handle: u8,
+    params: &'a BigParams,
+}
+
+impl WriteTo for CreateBig<'_> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let params = self.params;
+        Ok(w.write_as(self.big_handle)?
+            + w.write_as(self.adv_handle)?
+            + w.write_as(params.num_bis)?
+            + write_interval_u24(w, params.sdu_interval, 1)?
+            + w.write_as(params.max_sdu)?
+            + w.write_as(to_units(params.max_transport_latency, 1000)?)?
+            + w.write_as(params.rtn)?
+            + w.write_as(params.phy.bits())?
+            + w.write_as(params.packing as u8)?
+            + w.write_as(params.framed as u8)?
+            + w.write_as(params.broadcast_code.is_some() as u8)?
+            + w.write(&params.broadcast_code.unwrap_or_default())?)
+    }
+}
+
+/// Read a BIS count followed by that many connection handles.
+fn read_handles<R: Read>(mut r: R) -> Result<Vec<u16>> {
+    let (num_bis, _) = r.read_as::<u8>()?;
+    (0..num_bis).map(|_| r.read_as::<u16>().map(|(handle, _)| handle)).collect()
+}
+
+/// Parameters of an LE Create BIG Complete subevent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigComplete {
+    pub status: u8,
+    pub big_handle: u8,
+    pub big_sync_delay: Duration,
+    pub transport_latency: Duration,
+    pub phy: Phy,
+    /// Number of subevents in each BIS event
+    pub nse: u8,
+    /// Burst number
+    pub bn: u8,
+    /// Pre-transmission offset
+    pub pto: u8,
+    /// Number of times each packet is repeated
+    pub irc: u8,
+    pub max_pdu: u16,
+    pub iso_interval: Duration,
+    /// Connection handles of the BISes
+    pub handles: Vec<u16>,
+}
+
+impl BigComplete {
+    /// Parse the parameters of an LE Create BIG Complete subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (big_handle, _) = r.read_as::<u8>()?;
+        let big_sync_delay = read_interval_u24(&mut r, 1)?;
+        let transport_latency = read_interval_u24(&mut r, 1)?;
+        let (phy, _) = r.read_as::<u8>()?;
+        let (nse, _) = r.read_as::<u8>()?;
+        let (bn, _) = r.read_as::<u8>()?;
+        let (pto, _) = r.read_as::<u8>()?;
+        let (irc, _) = r.read_as::<u8>()?;
+        let (max_pdu, _) = r.read_as::<u16>()?;
+        let (iso_interval, _) = r.read_as::<u16>()?;
+        let handles = read_handles(&mut r)?;
+        Ok(BigComplete {
+            status,
+            big_handle,
+            big_sync_delay,
+            transport_latency,
+            // The PHY is not meaningful when the BIG failed.
+            phy: Phy::try_from(phy).unwrap_or_default(),
+            nse,
+            bn,
+            pto,
+            irc,
+            max_pdu,
+            iso_interval: from_units(iso_interval.into(), CONN_INTERVAL_US),
+            handles,
+        })
+    }
+}
+
+/// Options for synchronizing to a broadcast isochronous group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigSyncOptions {
+    /// Code used to decrypt the BISes, or `None` if they are not encrypted
+    pub broadcast_code: Option<[u8; 16]>,
+    /// Largest number of subevents to receive in each BIS event, or zero to let the controller
+    /// choose
+    pub mse: u8,
+    /// Time without receiving a packet after which the sync is lost
+    pub sync_timeout: Duration,
+    /// Indices of the BISes to receive, starting at 1
+    pub bis: Vec<u8>,
+}
+
+impl Default for BigSyncOptions {
+    /// Receive the first BIS without encryption
+    fn default() -> Self {
+        BigSyncOptions {
+            broadcast_code: None,
+            mse: 0,
+            sync_timeout: Duration::from_secs(2),
+            bis: vec![1],
+        }
+    }
+}
+
+/// Parameters of an LE BIG Sync Established subevent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigSyncEstablished {
+    pub status: u8,
+    pub big_handle: u8,
+    pub transport_latency: Duration,
+    /// Number of subevents in each BIS event
+    pub nse: u8,
+    /// Burst number
+    pub bn: u8,
+    /// Pre-transmission offset
+    pub pto: u8,
+    /// Number of times each packet is repeated
+    pub irc: u8,
+    pub max_pdu: u16,
+    pub iso_interval: Duration,
+    /// Connection handles of the BISes, in the order they were requested
+    pub handles: Vec<u16>,
+}
+
+impl BigSyncEstablished {
+    /// Parse the parameters of an LE BIG Sync Established subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (big_handle, _) = r.read_as::<u8>()?;
+        let transport_latency = read_interval_u24(&mut r, 1)?;
+        let (nse, _) = r.read_as::<u8>()?;
+        let (bn, _) = r.read_as::<u8>()?;
+        let (pto, _) = r.read_as::<u8>()?;
+        let (irc, _) = r.read_as::<u8>()?;
+        let (max_pdu, _) = r.read_as::<u16>()?;
+        let (iso_interval, _) = r.read_as::<u16>()?;
+        let handles = read_handles(&mut r)?;
+        Ok(BigSyncEstablished {
+            status,
+            big_handle,
+            transport_latency,
+            nse,
+            bn,
+            pto,
+            irc,
+            max_pdu,
+            iso_interval: from_units(iso_interval.into(), CONN_INTERVAL_US),
+            handles,
+        })
+    }
+}
+
+impl Socket {
+    /// Create a broadcast isochronous group on the periodic advertising of an advertising set
+    /// and wait for the LE Create BIG Complete event.
+    pub fn le_create_big(&mut self, big_handle: u8, adv_handle: u8, params: &BigParams, timeout: c_int) -> Result<BigComplete> {
+        let param = CreateBig { big_handle, adv_handle, params }.bytes()?;
+        let (_, data) = self.le_request(OCF_LE_CREATE_BIG, &param, timeout,
+            |subevent, params| subevent == EVT_LE_CREATE_BIG_COMPLETE && params.get(1) == Some(&big_handle))?;
+        let complete = BigComplete::parse(&data)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(complete)
+    }
+
+    /// End a broadcast isochronous group and wait for the LE Terminate BIG Complete event.
+    pub fn le_terminate_big(&mut self, big_handle: u8, reason: u8, timeout: c_int) -> Result<()> {
+        self.le_request(OCF_LE_TERMINATE_BIG, &[big_handle, reason], timeout,
+            |subevent, params| subevent == EVT_LE_TERMINATE_BIG_COMPLETE && params.first() == Some(&big_handle))
+            .map(|_| ())
+    }
+
+    /// Synchronize to a broadcast isochronous group found on a periodic advertising sync and
+    /// wait for the LE BIG Sync Established event.
+    pub fn le_big_create_sync(&mut self, big_handle: u8, sync_handle: u16, options: &BigSyncOptions, timeout: c_int) -> Result<BigSyncEstablished> {
+        let num_bis = u8::try_from(options.bis.len())
+            .map_err(|_| Error::new(InvalidInput, "Too many BISes"))?;
+        let mut param = vec![big_handle];
+        param.extend_from_slice(&sync_handle.to_le_bytes());
+        param.push(options.broadcast_code.is_some() as u8);
+        param.extend_from_slice(&options.broadcast_code.unwrap_or_default());
+        param.push(options.mse);
+        param.extend_from_slice(&to_units(options.sync_timeout, BIG_SYNC_TIMEOUT_US)?.to_le_bytes());
+        param.push(num_bis);
+        param.extend_from_slice(&options.bis);
+
+        let (_, data) = self.le_request(OCF_LE_BIG_CREATE_SYNC, &param, timeout,
+            |subevent, params| subevent == EVT_LE_BIG_SYNC_ESTABLISHED && params.get(1) == Some(&big_handle))?;
+        let established = BigSyncEstablished::parse(&data)?;
+        if established.status != 0 {
+            return Err(StatusError(established.status).into());
+        }
+        Ok(established)
+    }
+
+    /// Stop receiving a broadcast isochronous group, or cancel a sync in progress.
+    pub fn le_big_terminate_sync(&mut self, big_handle: u8, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_BIG_TERMINATE_SYNC, &[big_handle], timeout)
+            .map(|_| ())
+    }
+}
+
+/// A broadcast isochronous group sent by the local controller.
+///
+/// The group is terminated when it is dropped.
+pub struct Big<'a> {
+    socket: &'a mut Socket,
+    pub(super) timeout: c_int,
+    info: BigComplete,
+}
+
+impl Big<'_> {
+    /// Return the parameters the group was created with
+    pub fn info(&self) -> &BigComplete {
+        &self.info
+    }
+
+    /// Return the connection handles of the BISes
+    pub fn bis_handles(&self) -> &[u16] {
+        &self.info.handles
+    }
+
+    /// Return the socket used by the group
+    pub fn socket(&mut self) -> &mut Socket {
+        self.socket
+    }
+}
+
+impl Drop for Big<'_> {
+    fn drop(&mut self) {
+        let _ = self.socket.le_terminate_big(self.info.big_handle, REASON_LOCAL_HOST_TERMINATED, self.timeout);
+    }
+}
+
+/// A broadcast isochronous group received by the local controller.
+///
+/// The sync is terminated when it is dropped.
+pub struct BigSync<'a> {
+    socket: &'a mut Socket,
+    pub(super) timeout: c_int,
+    info: BigSyncEstablished,
+}
+
+impl BigSync<'_> {
+    /// Return the parameters the sync was established with
+    pub fn info(&self) -> &BigSyncEstablished {
+        &self.info
+    }
+
+    /// Return the connection handles of the BISes, in the order they were requested
+    pub fn bis_handles(&self) -> &[u16] {
+        &self.info.handles
+    }
+
+    /// Return the socket used by the sync
+    pub fn socket(&mut self) -> &mut Socket {
+        self.socket
+    }
+
+    /// Wait for the sync to be lost, returning the reason.
+    pub fn wait_lost(&mut self, timeout: c_int) -> Result<u8> {
+        let big_handle = self.info.big_handle;
+        let (_, data) = self.socket.wait_le_event(timeout,
+            |subevent, params| subevent == EVT_LE_BIG_SYNC_LOST && params.first() == Some(&big_handle))?;
+        let (reason, _) = (&data[1..]).read_as::<u8>()?;
+        Ok(reason)
+    }
+}
+
+impl Drop for BigSync<'_> {
+    fn drop(&mut self) {
+        let _ = self.socket.le_big_terminate_sync(self.info.big_handle, self.timeout);
+    }
+}
+
+impl Adapter {
+    /// Broadcast a new isochronous group on the periodic advertising of an advertising set.
+    ///
+    /// The advertising set must already be sending periodic advertising.
+    pub fn le_create_big(&mut self, big_handle: u8, adv_handle: u8, params: &BigParams) -> Result<Big<'_>> {
+        let info = self.socket.le_create_big(big_handle, adv_handle, params, self.timeout)?;
+        Ok(Big { socket: &mut self.socket, timeout: self.timeout, info })
+    }
+
+    /// Receive a broadcast isochronous group advertised on a periodic advertising sync.
+    pub fn le_big_sync(&mut self, big_handle: u8, sync_handle: u16, options: &BigSyncOptions) -> Result<BigSync<'_>> {
+        let info = self.socket.le_big_create_sync(big_handle, sync_handle, options, self.timeout)?;
+        Ok(BigSync { socket: &mut self.socket, timeout: self.timeout, info })
+    }
+}
@@ -0,0 +1,411 @@
+use libc::{c_int};
+use std::io::{Error, Result, Write};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::adapter::Adapter;
+use crate::error::StatusError;
+use crate::filter::HciFilter;
+use crate::io::{ReadAs, WriteAs, WriteTo};
+use crate::socket::{HCI_EVENT_PKT, Socket};
+use crate::units::{from_units, read_interval_u24, to_units, write_interval_u24};
+use super::{EVT_LE_META_EVENT, OGF_LE_CTL, Phy, PhyMask};
+use super::conn::CONN_INTERVAL_US;
+
+const OCF_LE_SET_CIG_PARAMETERS: u16 = 0x0062;
+const OCF_LE_CREATE_CIS: u16 = 0x0064;
+const OCF_LE_REMOVE_CIG: u16 = 0x0065;
+const OCF_LE_ACCEPT_CIS_REQUEST: u16 = 0x0066;
+const OCF_LE_REJECT_CIS_REQUEST: u16 = 0x0067;
+
+pub(crate) const EVT_LE_CIS_ESTABLISHED: u8 = 0x19;
+pub(crate) const EVT_LE_CIS_REQUEST: u8 = 0x1A;
+
+/// How the CISes of a group are scheduled
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Packing {
+    /// Send the subevents of each CIS one after another
+    #[default]
+    Sequential = 0x00,
+    /// Interleave the subevents of the CISes
+    Interleaved = 0x01,
+}
+
+/// Parameters of one CIS in a connected isochronous group
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CisParams {
+    pub cis_id: u8,
+    /// Largest SDU sent from the central to the peripheral, or zero for none
+    pub max_sdu_c_to_p: u16,
+    /// Largest SDU sent from the peripheral to the central, or zero for none
+    pub max_sdu_p_to_c: u16,
+    pub phy_c_to_p: PhyMask,
+    pub phy_p_to_c: PhyMask,
+    /// Number of retransmissions of each packet from the central
+    pub rtn_c_to_p: u8,
+    /// Number of retransmissions of each packet from the peripheral
+    pub rtn_p_to_c: u8,
+}
+
+impl Default for CisParams {
+    fn default() -> Self {
+        CisParams {
+            cis_id: 0,
+            max_sdu_c_to_p: 0,
+            max_sdu_p_to_c: 0,
+            phy_c_to_p: PhyMask::LE_2M,
+            phy_p_to_c: PhyMask::LE_2M,
+            rtn_c_to_p: 2,
+            rtn_p_to_c: 2,
+        }
+    }
+}
+
+impl WriteTo for &CisParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(self.cis_id)?
+            + w.write_as(self.max_sdu_c_to_p)?
+            + w.write_as(self.max_sdu_p_to_c)?
+            + w.write_as(self.phy_c_to_p.bits())?
+            + w.write_as(self.phy_p_to_c.bits())?
+            + w.write_as(self.rtn_c_to_p)?
+            + w.write_as(self.rtn_p_to_c)?)
+    }
+}
+
+/// Parameters of a connected isochronous group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CigParams {
+    pub cig_id: u8,
+    pub sdu_interval_c_to_p: Duration,
+    pub sdu_interval_p_to_c: Duration,
+    /// Worst sleep clock accuracy of the peripherals
+    pub worst_case_sca: u8,
+    pub packing: Packing,
+    /// Whether SDUs are framed
+    pub framed: bool,
+    pub max_transport_latency_c_to_p: Duration,
+    pub max_transport_latency_p_to_c: Duration,
+    pub cis: Vec<CisParams>,
+}
+
+impl Default for CigParams {
+    /// A group for 10 ms unframed SDUs with no CISes
+    fn default() -> Self {
+        CigParams {
+            cig_id: 0,
+            sdu_interval_c_to_p: Duration::from_millis(10),
+            sdu_interval_p_to_c: Duration::from_millis(10),
+            worst_case_sca: 0,
+            packing: Packing::default(),
+            framed: false,
+            max_transport_latency_c_to_p: Duration::from_millis(20),
+            max_transport_latency_p_to_c: Duration::from_millis(20),
+            cis: Vec::new(),
+        }
+    }
+}
+
+impl WriteTo for &CigParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let cis_count = u8::try_from(self.cis.len())
+            .map_err(|_| Error::new(InvalidInput, "Too many CISes"))?;
+        let mut size = w.write_as(self.cig_id)?
+            + write_interval_u24(w, self.sdu_interval_c_to_p, 1)?
+            + write_interval_u24(w, self.sdu_interval_p_to_c, 1)?
+            + w.write_as(self.worst_case_sca)?
+            + w.write_as(self.packing as u8)?
+            + w.write_as(self.framed as u8)?
+            + w.write_as(to_units(self.max_transport_latency_c_to_p, 1000)?)?
+            + w.write_as(to_units(self.max_transport_latency_p_to_c, 1000)?)?
+            + w.write_as(cis_count)?;
+        for cis in &self.cis {
+            size += w.write_as(cis)?;
+        }
+        Ok(size)
+    }
+}
+
+/// Parameters of an LE CIS Established subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CisEstablished {
+    pub status: u8,
+    pub handle: u16,
+    pub cig_sync_delay: Duration,
+    pub cis_sync_delay: Duration,
+    pub transport_latency_c_to_p: Duration,
+    pub transport_latency_p_to_c: Duration,
+    pub phy_c_to_p: Phy,
+    pub phy_p_to_c: Phy,
+    /// Number of subevents in each isochronous interval
+    pub nse: u8,
+    /// Burst number from the central
+    pub bn_c_to_p: u8,
+    /// Burst number from the peripheral
+    pub bn_p_to_c: u8,
+    /// Flush timeout from the central, in isochronous intervals
+    pub ft_c_to_p: u8,
+    /// Flush timeout from the peripheral, in isochronous intervals
+    pub ft_p_to_c: u8,
+    pub max_pdu_c_to_p: u16,
+    pub max_pdu_p_to_c: u16,
+    pub iso_interval: Duration,
+}
+
+impl CisEstablished {
+    /// Parse the parameters of an LE CIS Established subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (handle, _) = r.read_as::<u16>()?;
+        let cig_sync_delay = read_interval_u24(&mut r, 1)?;
+        let cis_sync_delay = read_interval_u24(&mut r, 1)?;
+        let transport_latency_c_to_p = read_interval_u24(&mut r, 1)?;
+        let transport_latency_p_to_c = read_interval_u24(&mut r, 1)?;
+        let (phy_c_to_p, _) = r.read_as::<u8>()?;
+        let (phy_p_to_c, _) = r.read_as::<u8>()?;
+        let (nse, _) = r.read_as::<u8>()?;
+        let (bn_c_to_p, _) = r.read_as::<u8>()?;
+        let (bn_p_to_c, _) = r.read_as::<u8>()?;
+        let (ft_c_to_p, _) = r.read_as::<u8>()?;
+        let (ft_p_to_c, _) = r.read_as::<u8>()?;
+        let (max_pdu_c_to_p, _) = r.read_as::<u16>()?;
+        let (max_pdu_p_to_c, _) = r.read_as::<u16>()?;
+        let (iso_interval, _) = r.read_as::<u16>()?;
+        Ok(CisEstablished {
+            status,
+            handle,
+            cig_sync_delay,
+            cis_sync_delay,
+            transport_latency_c_to_p,
+            transport_latency_p_to_c,
+            // The PHYs are not meaningful when the CIS failed.
+            phy_c_to_p: Phy::try_from(phy_c_to_p).unwrap_or_default(),
+            phy_p_to_c: Phy::try_from(phy_p_to_c).unwrap_or_default(),
+            nse,
+            bn_c_to_p,
+            bn_p_to_c,
+            ft_c_to_p,
+            ft_p_to_c,
+            max_pdu_c_to_p,
+            max_pdu_p_to_c,
+            iso_interval: from_units(iso_interval.into(), CONN_INTERVAL_US),
+        })
+    }
+}
+
+/// Parameters of an LE CIS Request subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CisRequest {
+    /// Handle of the ACL connection the CIS is requested on
+    pub acl_handle: u16,
+    /// Handle the CIS will have if accepted
+    pub cis_handle: u16,
+    pub cig_id: u8,
+    pub cis_id: u8,
+}
+
+impl CisRequest {
+    /// Parse the parameters of an LE CIS Request subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (acl_handle, _) = r.read_as::<u16>()?;
+        let (cis_handle, _) = r.read_as::<u16>()?;
+        let (cig_id, _) = r.read_as::<u8>()?;
+        let (cis_id, _) = r.read_as::<u8>()?;
+        Ok(CisRequest { acl_handle, cis_handle, cig_id, cis_id })
+    }
+}
+
+/// Return whether a subevent is the CIS Established subevent of a CIS in `handles`.
+fn is_cis_established(subevent: u8, params: &[u8], handles: &[u16]) -> bool {
+    subevent == EVT_LE_CIS_ESTABLISHED
+        && params.get(1..3)
+            .is_some_and(|handle| handles.contains(&u16::from_le_bytes([handle[0], handle[1]])))
+}
+
+impl Socket {
+    /// Create or change a connected isochronous group.
+    ///
+    /// Returns the connection handles of the CISes, in the order of `params.cis`.
+    pub fn le_set_cig_parameters(&mut self, params: &CigParams, timeout: c_int) -> Result<Vec<u16>> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_SET_CIG_PARAMETERS, &params.bytes()?, timeout)?;
+        let mut r = &*data;
+        let (_cig_id, _) = r.read_as::<u8>()?;
+        let (cis_count, _) = r.read_as::<u8>()?;
+        (0..cis_count).map(|_| r.read_as::<u16>().map(|(handle, _)| handle)).collect()
+    }
+
+    /// Remove a connected isochronous group. None of its CISes may be connected.
+    pub fn le_remove_cig(&mut self, cig_id: u8, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_REMOVE_CIG, &[cig_id], timeout)
+            .map(|_| ())
+    }
+
+    /// Connect CISes, each given as a CIS handle and the handle of the ACL connection to the
+    /// peripheral, and wait for the LE CIS Established event of each one.
+    ///
+    /// Returns the events in the order they arrive. CISes that failed to connect have a
+    /// nonzero status.
+    pub fn le_create_cis(&mut self, cises: &[(u16, u16)], timeout: c_int) -> Result<Vec<CisEstablished>> {
+        let count = u8::try_from(cises.len())
+            .map_err(|_| Error::new(InvalidInput, "Too many CISes"))?;
+        let mut param = vec![count];
+        for (cis_handle, acl_handle) in cises {
+            param.extend_from_slice(&cis_handle.to_le_bytes());
+            param.extend_from_slice(&acl_handle.to_le_bytes());
+        }
+        let handles: Vec<u16> = cises.iter().map(|&(cis_handle, _)| cis_handle).collect();
+
+        // Keep LE Meta events in the filter between the first CIS Established event and the
+        // rest, so none are dropped.
+        let old_filter = self.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_LE_META_EVENT)?;
+        self.set_filter(&filter)?;
+
+        let result = (|s: &mut Socket| {
+            let mut established = Vec::new();
+            let (_, data) = s.le_request(OCF_LE_CREATE_CIS, &param, timeout,
+                |subevent, params| is_cis_established(subevent, params, &handles))?;
+            established.push(CisEstablished::parse(&data)?);
+
+            while established.len() < handles.len() {
+                let (event, data) = s.recv_event(timeout)?;
+                if event != EVT_LE_META_EVENT {
+                    continue;
+                }
+                if let Some((&subevent, params)) = data.split_first() {
+                    if is_cis_established(subevent, params, &handles) {
+                        established.push(CisEstablished::parse(params)?);
+                    }
+                }
+            }
+            Ok(established)
+        })(self);
+
+        self.set_filter(&old_filter)?;
+        result
+    }
+
+    /// Accept a CIS request from the central and wait for the LE CIS Established event.
+    pub fn le_accept_cis_request(&mut self, cis_handle: u16, timeout: c_int) -> Result<CisEstablished> {
+        let (_, data) = self.le_request(OCF_LE_ACCEPT_CIS_REQUEST, &cis_handle.to_le_bytes(), timeout,
+            |subevent, params| is_cis_established(subevent, params, &[cis_handle]))?;
+        let established = CisEstablished::parse(&data)?;
+        if established.status != 0 {
+            return Err(StatusError(established.status).into());
+        }
+        Ok(established)
+    }
+
+    /// Reject a CIS request from the central with a reason code.
+    pub fn le_reject_cis_request(&mut self, cis_handle: u16, reason: u8, timeout: c_int) -> Result<()> {
+        let mut param = cis_handle.to_le_bytes().to_vec();
+        param.push(reason);
+        self.execute(OGF_LE_CTL, OCF_LE_REJECT_CIS_REQUEST, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+/// A connected isochronous group configured in the controller.
+///
+/// The group is removed when it is dropped, which fails if any of its CISes are still connected.
+pub struct Cig<'a> {
+    socket: &'a mut Socket,
+    timeout: c_int,
+    cig_id: u8,
+    handles: Vec<u16>,
+}
+
+impl Cig<'_> {
+    /// Return the identifier of the group
+    pub fn cig_id(&self) -> u8 {
+        self.cig_id
+    }
+
+    /// Return the connection handles of the CISes, in the order they were configured
+    pub fn cis_handles(&self) -> &[u16] {
+        &self.handles
+    }
+
+    /// Connect several CISes at once, each given as a CIS handle and the handle of the ACL
+    /// connection to the peripheral.
+    ///
+    /// Returns the CIS Established event of each CIS, including those that failed.
+    pub fn create_cis(&mut self, cises: &[(u16, u16)]) -> Result<Vec<CisEstablished>> {
+        if let Some(&(cis_handle, _)) = cises.iter().find(|(cis_handle, _)| !self.handles.contains(cis_handle)) {
+            return Err(Error::new(InvalidInput, format!("CIS handle {cis_handle} is not in the group")));
+        }
+        self.socket.le_create_cis(cises, self.timeout)
+    }
+
+    /// Connect one CIS of the group to the peripheral on an ACL connection.
+    pub fn connect(&mut self, cis_handle: u16, acl_handle: u16) -> Result<CisStream<'_>> {
+        let established = self.create_cis(&[(cis_handle, acl_handle)])?;
+        let info = established[0];
+        if info.status != 0 {
+            return Err(StatusError(info.status).into());
+        }
+        Ok(CisStream { socket: self.socket, timeout: self.timeout, info })
+    }
+}
+
+impl Drop for Cig<'_> {
+    fn drop(&mut self) {
+        let _ = self.socket.le_remove_cig(self.cig_id, self.timeout);
+    }
+}
+
+/// An established connected isochronous stream.
+///
+/// Commands on the stream are sent through the borrowed socket.
+pub struct CisStream<'a> {
+    socket: &'a mut Socket,
+    pub(super) timeout: c_int,
+    info: CisEstablished,
+}
+
+impl CisStream<'_> {
+    /// Return the connection handle of the CIS
+    pub fn handle(&self) -> u16 {
+        self.info.handle
+    }
+
+    /// Return the parameters the CIS was established with
+    pub fn info(&self) -> &CisEstablished {
+        &self.info
+    }
+
+    /// Return the socket used by the stream
+    pub fn socket(&mut self) -> &mut Socket {
+        self.socket
+    }
+}
+
+impl Adapter {
+    /// Configure a connected isochronous group as a central.
+    pub fn le_set_cig(&mut self, params: &CigParams) -> Result<Cig<'_>> {
+        let handles = self.socket.le_set_cig_parameters(params, self.timeout)?;
+        Ok(Cig { socket: &mut self.socket, timeout: self.timeout, cig_id: params.cig_id, handles })
+    }
+
+    /// Wait for a central to request a CIS, as a peripheral.
+    pub fn le_wait_cis_request(&mut self) -> Result<CisRequest> {
+        let (_, data) = self.socket.wait_le_event(self.timeout,
+            |subevent, _| subevent == EVT_LE_CIS_REQUEST)?;
+        CisRequest::parse(&data)
+    }
+
+    /// Accept a CIS request and wait for the CIS to be established.
+    pub fn le_accept_cis(&mut self, request: &CisRequest) -> Result<CisStream<'_>> {
+        let info = self.socket.le_accept_cis_request(request.cis_handle, self.timeout)?;
+        Ok(CisStream { socket: &mut self.socket, timeout: self.timeout, info })
+    }
+
+    /// Reject a CIS request with a reason code.
+    pub fn le_reject_cis(&mut self, request: &CisRequest, reason: u8) -> Result<()> {
+        self.socket.le_reject_cis_request(request.cis_handle, reason, self.timeout)
+    }
+}
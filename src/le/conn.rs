@@ -0,0 +1,587 @@
+use libc::{c_int};
+use std::io::{Error, Result, Write};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::adapter::Adapter;
+use crate::addr::{AddressType, BdAddr, OwnAddressType};
+use crate::connection::{Connection, Role};
+use crate::error::{MalformedPacket, StatusError};
+use crate::filter::HciFilter;
+use crate::io::{ReadAs, WriteAs, WriteTo};
+use crate::socket::{HCI_EVENT_PKT, Socket};
+use crate::units::{from_units, to_units, SLOT_US};
+use super::{EVT_LE_META_EVENT, OGF_LE_CTL};
+
+const OCF_LE_CREATE_CONN: u16 = 0x000D;
+const OCF_LE_CREATE_CONN_CANCEL: u16 = 0x000E;
+const OCF_LE_CONN_UPDATE: u16 = 0x0013;
+const OCF_LE_REMOTE_CONN_PARAM_REQ_REPLY: u16 = 0x0020;
+const OCF_LE_REMOTE_CONN_PARAM_REQ_NEG_REPLY: u16 = 0x0021;
+const OCF_LE_EXTENDED_CREATE_CONN: u16 = 0x0043;
+const OCF_LE_SET_DEFAULT_SUBRATE: u16 = 0x007D;
+const OCF_LE_SUBRATE_REQUEST: u16 = 0x007E;
+
+/// Status of a connection attempt that was cancelled
+const STATUS_UNKNOWN_CONN_ID: u8 = 0x02;
+
+pub(crate) const EVT_LE_CONN_COMPLETE: u8 = 0x01;
+pub(crate) const EVT_LE_CONN_UPDATE_COMPLETE: u8 = 0x03;
+pub(crate) const EVT_LE_REMOTE_CONN_PARAM_REQ: u8 = 0x06;
+pub(crate) const EVT_LE_ENHANCED_CONN_COMPLETE: u8 = 0x0A;
+pub(crate) const EVT_LE_SUBRATE_CHANGE: u8 = 0x23;
+pub(crate) const EVT_LE_ENHANCED_CONN_COMPLETE_V2: u8 = 0x29;
+
+/// Unit of connection intervals, in microseconds
+pub(crate) const CONN_INTERVAL_US: u64 = 1250;
+/// Unit of supervision timeouts, in microseconds
+pub(crate) const SUPERVISION_TIMEOUT_US: u64 = 10_000;
+
+/// Parameters of a new LE connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionParams {
+    pub scan_interval: Duration,
+    pub scan_window: Duration,
+    /// Connect to any device in the filter accept list instead of the given peer
+    pub use_filter_accept_list: bool,
+    pub own_address_type: OwnAddressType,
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    /// Number of connection events the peripheral may skip
+    pub max_latency: u16,
+    pub supervision_timeout: Duration,
+    pub min_ce_length: Duration,
+    pub max_ce_length: Duration,
+}
+
+impl Default for ConnectionParams {
+    fn default() -> Self {
+        ConnectionParams {
+            scan_interval: Duration::from_millis(60),
+            scan_window: Duration::from_millis(60),
+            use_filter_accept_list: false,
+            own_address_type: OwnAddressType::default(),
+            interval_min: Duration::from_millis(30),
+            interval_max: Duration::from_millis(50),
+            max_latency: 0,
+            supervision_timeout: Duration::from_millis(420),
+            min_ce_length: Duration::ZERO,
+            max_ce_length: Duration::ZERO,
+        }
+    }
+}
+
+/// Parameters of a connection that follow the scanning and addressing parameters of LE
+/// Create Connection.
+pub(crate) struct ConnectionIntervals<'a>(pub(crate) &'a ConnectionParams);
+
+impl WriteTo for ConnectionIntervals<'_> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let params = self.0;
+        Ok(w.write_as(to_units(params.interval_min, CONN_INTERVAL_US)?)?
+            + w.write_as(to_units(params.interval_max, CONN_INTERVAL_US)?)?
+            + w.write_as(params.max_latency)?
+            + w.write_as(to_units(params.supervision_timeout, SUPERVISION_TIMEOUT_US)?)?
+            + w.write_as(to_units(params.min_ce_length, SLOT_US)?)?
+            + w.write_as(to_units(params.max_ce_length, SLOT_US)?)?)
+    }
+}
+
+/// Parameters of LE Create Connection.
+struct CreateConnection<'a> {
+    addr: BdAddr,
+    address_type: AddressType,
+    params: &'a ConnectionParams,
+}
+
+impl WriteTo for CreateConnection<'_> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let params = self.params;
+        Ok(w.write_as(to_units(params.scan_interval, SLOT_US)?)?
+            + w.write_as(to_units(params.scan_window, SLOT_US)?)?
+            + w.write_as(params.use_filter_accept_list as u8)?
+            + w.write_as(self.address_type as u8)?
+            + w.write_as(&self.addr)?
+            + w.write_as(params.own_address_type as u8)?
+            + w.write_as(ConnectionIntervals(params))?)
+    }
+}
+
+/// Parameters of an LE Connection Complete or LE Enhanced Connection Complete subevent, of
+/// either version
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LeConnectionComplete {
+    pub status: u8,
+    pub handle: u16,
+    /// Role of the local device
+    pub role: Role,
+    pub peer_address_type: AddressType,
+    pub peer_address: BdAddr,
+    /// Resolvable private address used by the local device, if reported
+    pub local_rpa: Option<BdAddr>,
+    /// Resolvable private address used by the peer, if reported
+    pub peer_rpa: Option<BdAddr>,
+    pub interval: Duration,
+    /// Number of connection events the peripheral may skip
+    pub latency: u16,
+    pub supervision_timeout: Duration,
+    /// Sleep clock accuracy of the central
+    pub central_clock_accuracy: u8,
+    /// Advertising set the connection was made through, if reported by version 2 of the
+    /// enhanced subevent
+    pub advertising_handle: Option<u8>,
+    /// Periodic advertising train the connection was made through, if reported by version 2
+    /// of the enhanced subevent
+    pub sync_handle: Option<u16>,
+}
+
+impl LeConnectionComplete {
+    /// Parse the parameters of a connection complete subevent.
+    pub fn parse(subevent: u8, params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (role, _) = r.read_as::<u8>()?;
+        let (peer_address_type, _) = r.read_as::<AddressType>()?;
+        let (peer_address, _) = r.read_as::<BdAddr>()?;
+        let enhanced = subevent == EVT_LE_ENHANCED_CONN_COMPLETE || subevent == EVT_LE_ENHANCED_CONN_COMPLETE_V2;
+        let (local_rpa, peer_rpa) = if enhanced {
+            let (local_rpa, _) = r.read_as::<BdAddr>()?;
+            let (peer_rpa, _) = r.read_as::<BdAddr>()?;
+            (Some(local_rpa).filter(|addr| *addr != BdAddr::ANY), Some(peer_rpa).filter(|addr| *addr != BdAddr::ANY))
+        } else {
+            (None, None)
+        };
+        let (interval, _) = r.read_as::<u16>()?;
+        let (latency, _) = r.read_as::<u16>()?;
+        let (supervision_timeout, _) = r.read_as::<u16>()?;
+        let (central_clock_accuracy, _) = r.read_as::<u8>()?;
+        let (advertising_handle, sync_handle) = if subevent == EVT_LE_ENHANCED_CONN_COMPLETE_V2 {
+            let (advertising_handle, _) = r.read_as::<u8>()?;
+            let (sync_handle, _) = r.read_as::<u16>()?;
+            (Some(advertising_handle).filter(|&handle| handle != 0xFF), Some(sync_handle).filter(|&handle| handle != 0xFFFF))
+        } else {
+            (None, None)
+        };
+
+        Ok(LeConnectionComplete {
+            status,
+            handle,
+            // The role is meaningless when the connection failed.
+            role: Role::try_from(role).unwrap_or(Role::Central),
+            peer_address_type,
+            peer_address,
+            local_rpa,
+            peer_rpa,
+            interval: from_units(interval.into(), CONN_INTERVAL_US),
+            latency,
+            supervision_timeout: from_units(supervision_timeout.into(), SUPERVISION_TIMEOUT_US),
+            central_clock_accuracy,
+            advertising_handle,
+            sync_handle,
+        })
+    }
+}
+
+/// Return whether a subevent is a connection complete subevent.
+pub(crate) fn is_conn_complete(subevent: u8) -> bool {
+    matches!(subevent, EVT_LE_CONN_COMPLETE | EVT_LE_ENHANCED_CONN_COMPLETE | EVT_LE_ENHANCED_CONN_COMPLETE_V2)
+}
+
+/// Return whether a subevent ends a connection attempt to `addr`.
+///
+/// Failures are accepted whatever their address, since only one attempt can be in progress.
+/// Resolved identity addresses match the type they were resolved from. With the filter accept
+/// list, any peer may be connected to.
+fn completes_connect(subevent: u8, params: &[u8], addr: BdAddr, address_type: AddressType, use_filter_accept_list: bool) -> bool {
+    is_conn_complete(subevent) && LeConnectionComplete::parse(subevent, params).is_ok_and(|complete| {
+        complete.status != 0
+            || use_filter_accept_list
+            || (complete.peer_address == addr && complete.peer_address_type as u8 & 0x01 == address_type as u8 & 0x01)
+    })
+}
+
+impl Adapter {
+    /// Connect to an LE device.
+    ///
+    /// Waits up to the adapter timeout for the connection to be established and returns the
+    /// new connection.
+    pub fn le_connect(&mut self, addr: BdAddr, address_type: AddressType, params: &ConnectionParams) -> Result<Connection<'_>> {
+        let param = CreateConnection { addr, address_type, params }.bytes()?;
+        let (subevent, data) = self.socket.le_request(OCF_LE_CREATE_CONN, &param, self.timeout,
+            |subevent, data| completes_connect(subevent, data, addr, address_type, params.use_filter_accept_list))?;
+        let complete = LeConnectionComplete::parse(subevent, &data)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(Connection::from_le(&mut self.socket, complete))
+    }
+}
+
+impl Socket {
+    /// Cancel an LE connection attempt in progress.
+    ///
+    /// Waits for the LE Connection Complete event that ends the attempt. Returns `None` if the
+    /// attempt was cancelled, or the new connection if it was established before the cancel
+    /// took effect. If no attempt was in progress, the controller answers with Command
+    /// Disallowed, which is returned at once as a `StatusError`.
+    pub fn le_create_connection_cancel(&mut self, timeout: c_int) -> Result<Option<LeConnectionComplete>> {
+        // Keep LE Meta events in the filter from sending the command until the Connection
+        // Complete event is received, so it is not dropped between the two waits.
+        let old_filter = self.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_LE_META_EVENT)?;
+        self.set_filter(&filter)?;
+
+        let result = (|s: &mut Socket| {
+            // The connection may complete before the Command Complete of the cancel.
+            let (code, data) = s.send_req_event(OGF_LE_CTL, OCF_LE_CREATE_CONN_CANCEL,
+                EVT_LE_META_EVENT as c_int,
+                &[],
+                timeout,
+                |_, data| data.first().is_some_and(|&subevent| is_conn_complete(subevent)),
+            )?;
+            let (subevent, params) = if code == EVT_LE_META_EVENT {
+                let (&subevent, params) = data.split_first().ok_or(MalformedPacket)?;
+                (subevent, params.into())
+            } else {
+                // Command Disallowed without a Connection Complete before it means no attempt
+                // was in progress, so none will end.
+                match data.first().copied() {
+                    Some(0) => (),
+                    Some(status) => return Err(StatusError(status).into()),
+                    None => return Err(MalformedPacket.into()),
+                }
+                s.wait_le_event(timeout, |subevent, _| is_conn_complete(subevent))?
+            };
+
+            let complete = LeConnectionComplete::parse(subevent, &params)?;
+            match complete.status {
+                0 => Ok(Some(complete)),
+                STATUS_UNKNOWN_CONN_ID => Ok(None),
+                status => Err(StatusError(status).into()),
+            }
+        })(self);
+
+        self.set_filter(&old_filter)?;
+        result
+    }
+}
+
+impl Adapter {
+    /// Cancel an LE connection attempt in progress, such as one that timed out in
+    /// `le_connect`.
+    ///
+    /// Returns the connection if it was established before the cancel took effect.
+    pub fn le_cancel_connect(&mut self) -> Result<Option<Connection<'_>>> {
+        let complete = self.socket.le_create_connection_cancel(self.timeout)?;
+        Ok(complete.map(|complete| Connection::from_le(&mut self.socket, complete)))
+    }
+}
+
+// Connection parameter updates
+
+/// Parameters of an LE Connection Update Complete subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LeConnectionUpdate {
+    pub status: u8,
+    pub handle: u16,
+    pub interval: Duration,
+    /// Number of connection events the peripheral may skip
+    pub latency: u16,
+    pub supervision_timeout: Duration,
+}
+
+impl LeConnectionUpdate {
+    /// Parse the parameters of an LE Connection Update Complete subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (interval, _) = r.read_as::<u16>()?;
+        let (latency, _) = r.read_as::<u16>()?;
+        let (supervision_timeout, _) = r.read_as::<u16>()?;
+        Ok(LeConnectionUpdate {
+            status,
+            handle,
+            interval: from_units(interval.into(), CONN_INTERVAL_US),
+            latency,
+            supervision_timeout: from_units(supervision_timeout.into(), SUPERVISION_TIMEOUT_US),
+        })
+    }
+}
+
+/// Parameters of an LE Remote Connection Parameter Request subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RemoteConnParamRequest {
+    pub handle: u16,
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    /// Number of connection events the peripheral may skip
+    pub max_latency: u16,
+    pub supervision_timeout: Duration,
+}
+
+impl RemoteConnParamRequest {
+    /// Parse the parameters of an LE Remote Connection Parameter Request subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (interval_min, _) = r.read_as::<u16>()?;
+        let (interval_max, _) = r.read_as::<u16>()?;
+        let (max_latency, _) = r.read_as::<u16>()?;
+        let (supervision_timeout, _) = r.read_as::<u16>()?;
+        Ok(RemoteConnParamRequest {
+            handle,
+            interval_min: from_units(interval_min.into(), CONN_INTERVAL_US),
+            interval_max: from_units(interval_max.into(), CONN_INTERVAL_US),
+            max_latency,
+            supervision_timeout: from_units(supervision_timeout.into(), SUPERVISION_TIMEOUT_US),
+        })
+    }
+
+    /// Return connection parameters matching the request, to accept it unchanged
+    pub fn params(&self) -> ConnectionParams {
+        ConnectionParams {
+            interval_min: self.interval_min,
+            interval_max: self.interval_max,
+            max_latency: self.max_latency,
+            supervision_timeout: self.supervision_timeout,
+            ..ConnectionParams::default()
+        }
+    }
+}
+
+impl Connection<'_> {
+    /// Change the parameters of this LE connection and wait for the LE Connection Update
+    /// Complete event.
+    ///
+    /// Only the interval, latency, supervision timeout and connection event length fields of
+    /// `params` are used.
+    pub fn le_update(&mut self, params: &ConnectionParams, timeout: c_int) -> Result<LeConnectionUpdate> {
+        let handle = self.handle().to_le_bytes();
+        let mut param = handle.to_vec();
+        param.extend_from_slice(&ConnectionIntervals(params).bytes()?);
+
+        let (_, data) = self.socket.le_request(OCF_LE_CONN_UPDATE, &param, timeout,
+            |subevent, params| subevent == EVT_LE_CONN_UPDATE_COMPLETE && params.get(1..3) == Some(&handle))?;
+        let update = LeConnectionUpdate::parse(&data)?;
+        if update.status != 0 {
+            return Err(StatusError(update.status).into());
+        }
+        self.le_updated(&update);
+        Ok(update)
+    }
+
+    /// Wait for the peer to request new connection parameters.
+    pub fn le_wait_param_request(&mut self, timeout: c_int) -> Result<RemoteConnParamRequest> {
+        let handle = self.handle().to_le_bytes();
+        let (_, data) = self.socket.wait_le_event(timeout,
+            |subevent, params| subevent == EVT_LE_REMOTE_CONN_PARAM_REQ && params.get(0..2) == Some(&handle))?;
+        RemoteConnParamRequest::parse(&data)
+    }
+
+    /// Accept a connection parameter request from the peer with the given parameters.
+    ///
+    /// Only the interval, latency, supervision timeout and connection event length fields of
+    /// `params` are used.
+    pub fn le_accept_param_request(&mut self, params: &ConnectionParams, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&ConnectionIntervals(params).bytes()?);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_REMOTE_CONN_PARAM_REQ_REPLY, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Reject a connection parameter request from the peer with a reason code.
+    pub fn le_reject_param_request(&mut self, reason: u8, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.push(reason);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_REMOTE_CONN_PARAM_REQ_NEG_REPLY, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+// Extended connections
+
+/// Parameters of a new LE connection initiated on one or more PHYs
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExtConnectionParams {
+    /// Connect to any device in the filter accept list instead of the given peer
+    pub use_filter_accept_list: bool,
+    pub own_address_type: OwnAddressType,
+    /// Parameters for initiating on LE 1M, or `None` to not initiate on LE 1M
+    pub le_1m: Option<ConnectionParams>,
+    /// Parameters of a connection on LE 2M, or `None` to not connect on LE 2M
+    ///
+    /// LE 2M is not used for scanning, so the scan interval and window are ignored.
+    pub le_2m: Option<ConnectionParams>,
+    /// Parameters for initiating on LE Coded, or `None` to not initiate on LE Coded
+    pub le_coded: Option<ConnectionParams>,
+}
+
+impl Default for ExtConnectionParams {
+    /// Initiate on LE 1M only
+    fn default() -> Self {
+        ExtConnectionParams {
+            use_filter_accept_list: false,
+            own_address_type: OwnAddressType::default(),
+            le_1m: Some(ConnectionParams::default()),
+            le_2m: None,
+            le_coded: None,
+        }
+    }
+}
+
+/// Parameters of LE Extended Create Connection.
+struct ExtCreateConnection<'a> {
+    addr: BdAddr,
+    address_type: AddressType,
+    params: &'a ExtConnectionParams,
+}
+
+impl WriteTo for ExtCreateConnection<'_> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let params = self.params;
+        let phys = [params.le_1m, params.le_2m, params.le_coded];
+        let initiating_phys = phys.iter()
+            .enumerate()
+            .filter(|(_, phy)| phy.is_some())
+            .fold(0u8, |mask, (bit, _)| mask | 1 << bit);
+        if params.le_1m.is_none() && params.le_coded.is_none() {
+            return Err(Error::new(InvalidInput, "No initiating PHYs"));
+        }
+
+        let mut size = w.write_as(params.use_filter_accept_list as u8)?
+            + w.write_as(params.own_address_type as u8)?
+            + w.write_as(self.address_type as u8)?
+            + w.write_as(&self.addr)?
+            + w.write_as(initiating_phys)?;
+        for phy in phys.iter().flatten() {
+            size += w.write_as(to_units(phy.scan_interval, SLOT_US)?)?
+                + w.write_as(to_units(phy.scan_window, SLOT_US)?)?
+                + w.write_as(ConnectionIntervals(phy))?;
+        }
+        Ok(size)
+    }
+}
+
+impl Adapter {
+    /// Connect to an LE device, initiating on the PHYs given in `params`.
+    ///
+    /// This can connect to extended advertisers and over LE 2M or LE Coded. Waits up to the
+    /// adapter timeout for the connection to be established and returns the new connection.
+    pub fn le_ext_connect(&mut self, addr: BdAddr, address_type: AddressType, params: &ExtConnectionParams) -> Result<Connection<'_>> {
+        let param = ExtCreateConnection { addr, address_type, params }.bytes()?;
+        let (subevent, data) = self.socket.le_request(OCF_LE_EXTENDED_CREATE_CONN, &param, self.timeout,
+            |subevent, data| completes_connect(subevent, data, addr, address_type, params.use_filter_accept_list))?;
+        let complete = LeConnectionComplete::parse(subevent, &data)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(Connection::from_le(&mut self.socket, complete))
+    }
+}
+
+// Connection subrating
+
+/// Range of subrate factors and related parameters requested for a connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SubrateParams {
+    pub subrate_min: u16,
+    pub subrate_max: u16,
+    /// Number of subrated connection events the peripheral may skip
+    pub max_latency: u16,
+    /// Number of underlying connection events to stay active for after receiving a packet
+    pub continuation_number: u16,
+    pub supervision_timeout: Duration,
+}
+
+impl Default for SubrateParams {
+    /// No subrating
+    fn default() -> Self {
+        SubrateParams {
+            subrate_min: 1,
+            subrate_max: 1,
+            max_latency: 0,
+            continuation_number: 0,
+            supervision_timeout: Duration::from_millis(420),
+        }
+    }
+}
+
+impl WriteTo for &SubrateParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(self.subrate_min)?
+            + w.write_as(self.subrate_max)?
+            + w.write_as(self.max_latency)?
+            + w.write_as(self.continuation_number)?
+            + w.write_as(to_units(self.supervision_timeout, SUPERVISION_TIMEOUT_US)?)?)
+    }
+}
+
+/// Parameters of an LE Subrate Change subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SubrateChange {
+    pub status: u8,
+    pub handle: u16,
+    pub subrate_factor: u16,
+    /// Number of subrated connection events the peripheral may skip
+    pub peripheral_latency: u16,
+    pub continuation_number: u16,
+    pub supervision_timeout: Duration,
+}
+
+impl SubrateChange {
+    /// Parse the parameters of an LE Subrate Change subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (subrate_factor, _) = r.read_as::<u16>()?;
+        let (peripheral_latency, _) = r.read_as::<u16>()?;
+        let (continuation_number, _) = r.read_as::<u16>()?;
+        let (supervision_timeout, _) = r.read_as::<u16>()?;
+        Ok(SubrateChange {
+            status,
+            handle,
+            subrate_factor,
+            peripheral_latency,
+            continuation_number,
+            supervision_timeout: from_units(supervision_timeout.into(), SUPERVISION_TIMEOUT_US),
+        })
+    }
+}
+
+impl Socket {
+    /// Set the subrating parameters the controller accepts for new connections as a central.
+    pub fn le_set_default_subrate(&mut self, params: &SubrateParams, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_DEFAULT_SUBRATE, &params.bytes()?, timeout)
+            .map(|_| ())
+    }
+}
+
+impl Connection<'_> {
+    /// Request a change of subrating on this link and wait for the LE Subrate Change event.
+    pub fn le_subrate_request(&mut self, params: &SubrateParams, timeout: c_int) -> Result<SubrateChange> {
+        let handle = self.handle().to_le_bytes();
+        let mut param = handle.to_vec();
+        param.extend_from_slice(&params.bytes()?);
+
+        let (_, data) = self.socket.le_request(OCF_LE_SUBRATE_REQUEST, &param, timeout,
+            |subevent, params| subevent == EVT_LE_SUBRATE_CHANGE && params.get(1..3) == Some(&handle))?;
+        let change = SubrateChange::parse(&data)?;
+        if change.status != 0 {
+            return Err(StatusError(change.status).into());
+        }
+        Ok(change)
+    }
+
+    /// Wait for the subrating of this link to change, such as at the request of the peer.
+    pub fn le_wait_subrate_change(&mut self, timeout: c_int) -> Result<SubrateChange> {
+        let handle = self.handle().to_le_bytes();
+        let (_, data) = self.socket.wait_le_event(timeout,
+            |subevent, params| subevent == EVT_LE_SUBRATE_CHANGE && params.get(1..3) == Some(&handle))?;
+        SubrateChange::parse(&data)
+    }
+}
@@ -0,0 +1,419 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidData, InvalidInput, NotConnected};
+use std::time::Duration;
+
+use crate::connection::Connection;
+use crate::error::StatusError;
+use crate::io::ReadAs;
+use crate::socket::Socket;
+use crate::units::{from_units, to_units};
+use super::{EVT_LE_META_EVENT, OGF_LE_CTL, Phy, PeriodicSync};
+use super::ext_adv::ExtendedAdvertiser;
+use super::periodic_sync::EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST;
+
+const OCF_LE_SET_CONNECTIONLESS_CTE_TRANSMIT_PARAMETERS: u16 = 0x0051;
+const OCF_LE_SET_CONNECTIONLESS_CTE_TRANSMIT_ENABLE: u16 = 0x0052;
+const OCF_LE_SET_CONNECTIONLESS_IQ_SAMPLING_ENABLE: u16 = 0x0053;
+const OCF_LE_SET_CONNECTION_CTE_RECEIVE_PARAMETERS: u16 = 0x0054;
+const OCF_LE_SET_CONNECTION_CTE_TRANSMIT_PARAMETERS: u16 = 0x0055;
+const OCF_LE_CONNECTION_CTE_REQUEST_ENABLE: u16 = 0x0056;
+const OCF_LE_CONNECTION_CTE_RESPONSE_ENABLE: u16 = 0x0057;
+const OCF_LE_READ_ANTENNA_INFORMATION: u16 = 0x0058;
+
+pub(crate) const EVT_LE_CONNECTIONLESS_IQ_REPORT: u8 = 0x15;
+pub(crate) const EVT_LE_CONNECTION_IQ_REPORT: u8 = 0x16;
+pub(crate) const EVT_LE_CTE_REQUEST_FAILED: u8 = 0x17;
+
+/// Unit of constant tone extension lengths, in microseconds
+pub(super) const CTE_LENGTH_US: u64 = 8;
+
+/// Type of a constant tone extension
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CteType {
+    /// Angle of arrival
+    AoA = 0x00,
+    /// Angle of departure with 1 µs antenna switching slots
+    AoD1Us = 0x01,
+    /// Angle of departure with 2 µs antenna switching slots
+    AoD2Us = 0x02,
+}
+
+impl TryFrom<u8> for CteType {
+    type Error = Error;
+
+    fn try_from(cte_type: u8) -> Result<Self> {
+        match cte_type {
+            0x00 => Ok(CteType::AoA),
+            0x01 => Ok(CteType::AoD1Us),
+            0x02 => Ok(CteType::AoD2Us),
+            _ => Err(Error::new(InvalidData, "Unknown CTE type")),
+        }
+    }
+}
+
+bitflags! {
+    /// Set of constant tone extension types
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct CteTypes: u8 {
+        const AOA = 1 << 0;
+        const AOD_1US = 1 << 1;
+        const AOD_2US = 1 << 2;
+    }
+}
+
+/// Length of the switching and sampling slots of a constant tone extension
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SlotDuration {
+    #[default]
+    Us1 = 0x01,
+    Us2 = 0x02,
+}
+
+impl TryFrom<u8> for SlotDuration {
+    type Error = Error;
+
+    fn try_from(slot_durations: u8) -> Result<Self> {
+        match slot_durations {
+            0x01 => Ok(SlotDuration::Us1),
+            0x02 => Ok(SlotDuration::Us2),
+            _ => Err(Error::new(InvalidData, "Unknown slot duration")),
+        }
+    }
+}
+
+/// In-phase and quadrature components of one sample of a constant tone extension
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct IqSample {
+    pub i: i8,
+    pub q: i8,
+}
+
+impl IqSample {
+    /// Value of both components when the controller could not take the sample
+    pub const INVALID: i8 = -128;
+
+    /// Return whether the controller took this sample
+    pub fn is_valid(&self) -> bool {
+        self.i != IqSample::INVALID || self.q != IqSample::INVALID
+    }
+}
+
+/// Fields shared by connectionless and connection IQ reports, after the handle.
+struct IqFields {
+    channel_index: u8,
+    rssi: i16,
+    rssi_antenna_id: u8,
+    cte_type: CteType,
+    slot_durations: SlotDuration,
+    packet_status: u8,
+    event_counter: u16,
+    samples: Vec<IqSample>,
+}
+
+impl IqFields {
+    fn read(r: &mut &[u8]) -> Result<Self> {
+        let (channel_index, _) = r.read_as::<u8>()?;
+        let (rssi, _) = r.read_as::<i16>()?;
+        let (rssi_antenna_id, _) = r.read_as::<u8>()?;
+        let (cte_type, _) = r.read_as::<u8>()?;
+        let (slot_durations, _) = r.read_as::<u8>()?;
+        let (packet_status, _) = r.read_as::<u8>()?;
+        let (event_counter, _) = r.read_as::<u16>()?;
+        let (sample_count, _) = r.read_as::<u8>()?;
+        let samples = (0..sample_count)
+            .map(|_| {
+                let (i, _) = r.read_as::<i8>()?;
+                let (q, _) = r.read_as::<i8>()?;
+                Ok(IqSample { i, q })
+            })
+            .collect::<Result<_>>()?;
+        Ok(IqFields {
+            channel_index,
+            rssi,
+            rssi_antenna_id,
+            cte_type: CteType::try_from(cte_type)?,
+            slot_durations: SlotDuration::try_from(slot_durations)?,
+            packet_status,
+            event_counter,
+            samples,
+        })
+    }
+}
+
+/// IQ samples of a constant tone extension in a periodic advertisement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionlessIqReport {
+    pub sync_handle: u16,
+    pub channel_index: u8,
+    /// Signal strength in units of 0.1 dBm
+    pub rssi: i16,
+    /// Antenna the signal strength was measured on
+    pub rssi_antenna_id: u8,
+    pub cte_type: CteType,
+    pub slot_durations: SlotDuration,
+    /// Zero if the packet CRC was correct
+    pub packet_status: u8,
+    pub periodic_event_counter: u16,
+    pub samples: Vec<IqSample>,
+}
+
+impl ConnectionlessIqReport {
+    /// Parse the parameters of an LE Connectionless IQ Report subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (sync_handle, _) = r.read_as::<u16>()?;
+        let fields = IqFields::read(&mut r)?;
+        Ok(ConnectionlessIqReport {
+            sync_handle,
+            channel_index: fields.channel_index,
+            rssi: fields.rssi,
+            rssi_antenna_id: fields.rssi_antenna_id,
+            cte_type: fields.cte_type,
+            slot_durations: fields.slot_durations,
+            packet_status: fields.packet_status,
+            periodic_event_counter: fields.event_counter,
+            samples: fields.samples,
+        })
+    }
+}
+
+/// IQ samples of a constant tone extension received on a connection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionIqReport {
+    pub handle: u16,
+    pub rx_phy: Phy,
+    pub data_channel_index: u8,
+    /// Signal strength in units of 0.1 dBm
+    pub rssi: i16,
+    /// Antenna the signal strength was measured on
+    pub rssi_antenna_id: u8,
+    pub cte_type: CteType,
+    pub slot_durations: SlotDuration,
+    /// Zero if the packet CRC was correct
+    pub packet_status: u8,
+    pub connection_event_counter: u16,
+    pub samples: Vec<IqSample>,
+}
+
+impl ConnectionIqReport {
+    /// Parse the parameters of an LE Connection IQ Report subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (rx_phy, _) = r.read_as::<u8>()?;
+        let fields = IqFields::read(&mut r)?;
+        Ok(ConnectionIqReport {
+            handle,
+            rx_phy: Phy::try_from(rx_phy)?,
+            data_channel_index: fields.channel_index,
+            rssi: fields.rssi,
+            rssi_antenna_id: fields.rssi_antenna_id,
+            cte_type: fields.cte_type,
+            slot_durations: fields.slot_durations,
+            packet_status: fields.packet_status,
+            connection_event_counter: fields.event_counter,
+            samples: fields.samples,
+        })
+    }
+}
+
+/// Append an antenna switching pattern, preceded by its length.
+pub(super) fn push_antenna_ids(param: &mut Vec<u8>, antenna_ids: &[u8]) -> Result<()> {
+    let len = u8::try_from(antenna_ids.len())
+        .map_err(|_| Error::new(InvalidInput, "Switching pattern too long"))?;
+    param.push(len);
+    param.extend_from_slice(antenna_ids);
+    Ok(())
+}
+
+impl Socket {
+    /// Set the constant tone extensions sent in the periodic advertising of an advertising set.
+    ///
+    /// `cte_count` extensions are sent in each periodic advertising event. `antenna_ids` is
+    /// the antenna switching pattern used for angle of departure.
+    pub fn le_set_connectionless_cte_transmit_parameters(&mut self, handle: u8, cte_length: Duration, cte_type: CteType, cte_count: u8, antenna_ids: &[u8], timeout: c_int) -> Result<()> {
+        let cte_length = u8::try_from(to_units(cte_length, CTE_LENGTH_US)?)
+            .map_err(|_| Error::new(InvalidInput, "Duration out of range"))?;
+        let mut param = vec![handle, cte_length, cte_type as u8, cte_count];
+        push_antenna_ids(&mut param, antenna_ids)?;
+        self.execute(OGF_LE_CTL, OCF_LE_SET_CONNECTIONLESS_CTE_TRANSMIT_PARAMETERS, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop sending constant tone extensions in the periodic advertising of a set.
+    pub fn le_set_connectionless_cte_transmit_enable(&mut self, handle: u8, enable: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_CONNECTIONLESS_CTE_TRANSMIT_ENABLE, &[handle, enable as u8], timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop sampling the constant tone extensions of a periodic advertising sync.
+    ///
+    /// At most `max_sampled_ctes` extensions are sampled in each event, or every extension if
+    /// zero. `antenna_ids` is the antenna switching pattern used for angle of arrival.
+    pub fn le_set_connectionless_iq_sampling_enable(&mut self, sync_handle: u16, enable: bool, slot_durations: SlotDuration, max_sampled_ctes: u8, antenna_ids: &[u8], timeout: c_int) -> Result<()> {
+        let mut param = sync_handle.to_le_bytes().to_vec();
+        param.extend_from_slice(&[enable as u8, slot_durations as u8, max_sampled_ctes]);
+        push_antenna_ids(&mut param, antenna_ids)?;
+        self.execute(OGF_LE_CTL, OCF_LE_SET_CONNECTIONLESS_IQ_SAMPLING_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+impl ExtendedAdvertiser<'_> {
+    /// Set the constant tone extensions sent in the periodic advertising of a set.
+    pub fn set_cte_transmit_parameters(&mut self, handle: u8, cte_length: Duration, cte_type: CteType, cte_count: u8, antenna_ids: &[u8]) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_set_connectionless_cte_transmit_parameters(handle, cte_length, cte_type, cte_count, antenna_ids, self.timeout)
+    }
+
+    /// Start or stop sending constant tone extensions in the periodic advertising of a set.
+    pub fn set_cte_transmit_enable(&mut self, handle: u8, enable: bool) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_set_connectionless_cte_transmit_enable(handle, enable, self.timeout)
+    }
+}
+
+impl PeriodicSync<'_> {
+    /// Start or stop sampling the constant tone extensions of the periodic advertising.
+    pub fn set_iq_sampling(&mut self, enable: bool, slot_durations: SlotDuration, max_sampled_ctes: u8, antenna_ids: &[u8]) -> Result<()> {
+        let handle = self.handle();
+        self.socket.le_set_connectionless_iq_sampling_enable(handle, enable, slot_durations, max_sampled_ctes, antenna_ids, self.timeout)
+    }
+
+    /// Wait for the next IQ report of the sync.
+    ///
+    /// Periodic advertising reports received while waiting are dropped. Fails with
+    /// `NotConnected` once the sync is lost.
+    pub fn next_iq_report(&mut self) -> Result<ConnectionlessIqReport> {
+        let handle = self.handle().to_le_bytes();
+        loop {
+            if self.lost {
+                return Err(Error::new(NotConnected, "Periodic advertising sync lost"));
+            }
+            let (event, data) = self.socket.recv_event(self.timeout)?;
+            if event != EVT_LE_META_EVENT {
+                continue;
+            }
+            let Some((&subevent, params)) = data.split_first() else {
+                continue;
+            };
+            if params.get(0..2) != Some(&handle) {
+                continue;
+            }
+            match subevent {
+                EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST => self.lost = true,
+                EVT_LE_CONNECTIONLESS_IQ_REPORT => return ConnectionlessIqReport::parse(params),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Connection<'_> {
+    /// Set how constant tone extensions received on this link are sampled.
+    ///
+    /// `antenna_ids` is the antenna switching pattern used for angle of arrival.
+    pub fn le_set_cte_receive_parameters(&mut self, sampling_enable: bool, slot_durations: SlotDuration, antenna_ids: &[u8], timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&[sampling_enable as u8, slot_durations as u8]);
+        push_antenna_ids(&mut param, antenna_ids)?;
+        self.socket.execute(OGF_LE_CTL, OCF_LE_SET_CONNECTION_CTE_RECEIVE_PARAMETERS, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Set the types of constant tone extensions sent on this link in response to requests.
+    ///
+    /// `antenna_ids` is the antenna switching pattern used for angle of departure.
+    pub fn le_set_cte_transmit_parameters(&mut self, cte_types: CteTypes, antenna_ids: &[u8], timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.push(cte_types.bits());
+        push_antenna_ids(&mut param, antenna_ids)?;
+        self.socket.execute(OGF_LE_CTL, OCF_LE_SET_CONNECTION_CTE_TRANSMIT_PARAMETERS, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop requesting constant tone extensions from the peer.
+    ///
+    /// Requests are sent every `interval` connection events, or only once if zero.
+    pub fn le_cte_request_enable(&mut self, enable: bool, interval: u16, cte_length: Duration, cte_type: CteType, timeout: c_int) -> Result<()> {
+        let cte_length = u8::try_from(to_units(cte_length, CTE_LENGTH_US)?)
+            .map_err(|_| Error::new(InvalidInput, "Duration out of range"))?;
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.push(enable as u8);
+        param.extend_from_slice(&interval.to_le_bytes());
+        param.extend_from_slice(&[cte_length, cte_type as u8]);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_CONNECTION_CTE_REQUEST_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop responding to constant tone extension requests from the peer.
+    pub fn le_cte_response_enable(&mut self, enable: bool, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.push(enable as u8);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_CONNECTION_CTE_RESPONSE_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Wait for the next IQ report of this link.
+    ///
+    /// Fails with a `StatusError` if the peer did not respond to a request for a constant tone
+    /// extension.
+    pub fn le_wait_iq_report(&mut self, timeout: c_int) -> Result<ConnectionIqReport> {
+        let handle = self.handle().to_le_bytes();
+        let (subevent, data) = self.socket.wait_le_event(timeout, |subevent, params| match subevent {
+            EVT_LE_CONNECTION_IQ_REPORT => params.get(0..2) == Some(&handle),
+            EVT_LE_CTE_REQUEST_FAILED => params.get(1..3) == Some(&handle),
+            _ => false,
+        })?;
+        if subevent == EVT_LE_CTE_REQUEST_FAILED {
+            return Err(StatusError(data[0]).into());
+        }
+        ConnectionIqReport::parse(&data)
+    }
+}
+
+// Antenna information
+
+bitflags! {
+    /// Antenna switching and sampling rates supported by a controller
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct SwitchingSamplingRates: u8 {
+        /// 1 µs switching for angle of departure transmission
+        const AOD_TX_1US = 1 << 0;
+        /// 1 µs sampling for angle of departure reception
+        const AOD_RX_1US = 1 << 1;
+        /// 1 µs switching and sampling for angle of arrival reception
+        const AOA_RX_1US = 1 << 2;
+    }
+}
+
+/// Direction finding capabilities of a controller's antenna array
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AntennaInfo {
+    pub switching_sampling_rates: SwitchingSamplingRates,
+    pub num_antennae: u8,
+    /// Longest antenna switching pattern
+    pub max_switching_pattern_len: u8,
+    /// Longest constant tone extension
+    pub max_cte_length: Duration,
+}
+
+impl Socket {
+    /// Read the direction finding capabilities of the local controller's antennae.
+    pub fn le_read_antenna_information(&mut self, timeout: c_int) -> Result<AntennaInfo> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_ANTENNA_INFORMATION, &[], timeout)?;
+        let mut r = &*data;
+        let (switching_sampling_rates, _) = r.read_as::<u8>()?;
+        let (num_antennae, _) = r.read_as::<u8>()?;
+        let (max_switching_pattern_len, _) = r.read_as::<u8>()?;
+        let (max_cte_length, _) = r.read_as::<u8>()?;
+        Ok(AntennaInfo {
+            switching_sampling_rates: SwitchingSamplingRates::from_bits_retain(switching_sampling_rates),
+            num_antennae,
+            max_switching_pattern_len,
+            max_cte_length: from_units(max_cte_length.into(), CTE_LENGTH_US),
+        })
+    }
+}
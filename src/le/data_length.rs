@@ -0,0 +1,135 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::connection::Connection;
+use crate::io::ReadAs;
+use crate::socket::Socket;
+use crate::units::{from_units, to_units};
+use super::OGF_LE_CTL;
+
+const OCF_LE_SET_DATA_LENGTH: u16 = 0x0022;
+const OCF_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH: u16 = 0x0023;
+const OCF_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH: u16 = 0x0024;
+const OCF_LE_READ_MAXIMUM_DATA_LENGTH: u16 = 0x002F;
+
+const EVT_LE_DATA_LENGTH_CHANGE: u8 = 0x07;
+
+/// Payload sizes of LE link layer data PDUs, in octets
+const OCTETS_RANGE: std::ops::RangeInclusive<u16> = 27..=251;
+/// Transmission times of LE link layer data PDUs, in microseconds
+const TIME_RANGE: std::ops::RangeInclusive<u16> = 328..=17040;
+
+/// Size and transmission time of LE link layer data PDUs
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DataLength {
+    /// Payload size in octets
+    pub octets: u16,
+    pub time: Duration,
+}
+
+impl DataLength {
+    /// The length every controller supports
+    pub const MIN: DataLength = DataLength { octets: 27, time: Duration::from_micros(328) };
+    /// The longest length allowed by the specification
+    pub const MAX: DataLength = DataLength { octets: 251, time: Duration::from_micros(17040) };
+
+    /// Return this length limited to `max` in both size and time.
+    pub fn clamp_to(&self, max: DataLength) -> DataLength {
+        DataLength {
+            octets: self.octets.min(max.octets),
+            time: self.time.min(max.time),
+        }
+    }
+
+    /// Read a length as octets followed by microseconds.
+    fn read(r: &mut &[u8]) -> Result<Self> {
+        let (octets, _) = r.read_as::<u16>()?;
+        let (time, _) = r.read_as::<u16>()?;
+        Ok(DataLength { octets, time: from_units(time.into(), 1) })
+    }
+
+    /// Encode the length as octets followed by microseconds, checking that it is in range.
+    fn bytes(&self) -> Result<[u8; 4]> {
+        let time = to_units(self.time, 1)?;
+        if !OCTETS_RANGE.contains(&self.octets) || !TIME_RANGE.contains(&time) {
+            return Err(Error::new(InvalidInput, "Data length out of range"));
+        }
+        let [o0, o1] = self.octets.to_le_bytes();
+        let [t0, t1] = time.to_le_bytes();
+        Ok([o0, o1, t0, t1])
+    }
+}
+
+/// Parameters of an LE Data Length Change subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataLengthChange {
+    pub handle: u16,
+    /// Longest PDUs the local controller will send
+    pub max_tx: DataLength,
+    /// Longest PDUs the local controller expects to receive
+    pub max_rx: DataLength,
+}
+
+impl DataLengthChange {
+    /// Parse the parameters of an LE Data Length Change subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (handle, _) = r.read_as::<u16>()?;
+        let max_tx = DataLength::read(&mut r)?;
+        let max_rx = DataLength::read(&mut r)?;
+        Ok(DataLengthChange { handle, max_tx, max_rx })
+    }
+}
+
+/// Longest data PDUs supported by a controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaxDataLength {
+    pub tx: DataLength,
+    pub rx: DataLength,
+}
+
+impl Socket {
+    /// Read the longest data PDUs the local controller supports.
+    pub fn le_read_maximum_data_length(&mut self, timeout: c_int) -> Result<MaxDataLength> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_MAXIMUM_DATA_LENGTH, &[], timeout)?;
+        let mut r = &*data;
+        let tx = DataLength::read(&mut r)?;
+        let rx = DataLength::read(&mut r)?;
+        Ok(MaxDataLength { tx, rx })
+    }
+
+    /// Read the data length the controller suggests for new connections.
+    pub fn le_read_suggested_default_data_length(&mut self, timeout: c_int) -> Result<DataLength> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH, &[], timeout)?;
+        DataLength::read(&mut &*data)
+    }
+
+    /// Set the data length the controller suggests for new connections.
+    pub fn le_write_suggested_default_data_length(&mut self, length: DataLength, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH, &length.bytes()?, timeout)
+            .map(|_| ())
+    }
+}
+
+impl Connection<'_> {
+    /// Suggest the longest PDUs the local controller should send on this link.
+    ///
+    /// The controller negotiates the length with the peer and reports the result in an LE
+    /// Data Length Change event if it changes.
+    pub fn le_set_data_length(&mut self, length: DataLength, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&length.bytes()?);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_SET_DATA_LENGTH, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Wait for the data length of this link to change.
+    pub fn le_wait_data_length_change(&mut self, timeout: c_int) -> Result<DataLengthChange> {
+        let handle = self.handle().to_le_bytes();
+        let (_, data) = self.socket.wait_le_event(timeout,
+            |subevent, params| subevent == EVT_LE_DATA_LENGTH_CHANGE && params.get(0..2) == Some(&handle))?;
+        DataLengthChange::parse(&data)
+    }
+}
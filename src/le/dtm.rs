@@ -0,0 +1,168 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::io::ReadAs;
+use crate::socket::Socket;
+use crate::units::{to_units};
+use super::{CteType, OGF_LE_CTL, Phy, SlotDuration};
+use super::cte::{CTE_LENGTH_US, push_antenna_ids};
+
+const OCF_LE_RECEIVER_TEST: u16 = 0x001D;
+const OCF_LE_TRANSMITTER_TEST: u16 = 0x001E;
+const OCF_LE_TEST_END: u16 = 0x001F;
+const OCF_LE_RECEIVER_TEST_V2: u16 = 0x0033;
+const OCF_LE_TRANSMITTER_TEST_V2: u16 = 0x0034;
+const OCF_LE_RECEIVER_TEST_V3: u16 = 0x004F;
+const OCF_LE_TRANSMITTER_TEST_V3: u16 = 0x0050;
+
+/// Highest RF channel used in direct test mode
+const MAX_TEST_CHANNEL: u8 = 39;
+
+/// Bit pattern of the payloads sent in a transmitter test
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TestPayload {
+    /// Pseudo-random sequence of period 511
+    #[default]
+    Prbs9 = 0x00,
+    /// Repeated 11110000
+    Pattern11110000 = 0x01,
+    /// Repeated 10101010
+    Pattern10101010 = 0x02,
+    /// Pseudo-random sequence of period 32767
+    Prbs15 = 0x03,
+    AllOnes = 0x04,
+    AllZeros = 0x05,
+    /// Repeated 00001111
+    Pattern00001111 = 0x06,
+    /// Repeated 01010101
+    Pattern01010101 = 0x07,
+}
+
+/// PHY and coding used by a transmitter test
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TestPhy {
+    #[default]
+    Le1M = 0x01,
+    Le2M = 0x02,
+    /// LE Coded with eight symbols per bit
+    LeCodedS8 = 0x03,
+    /// LE Coded with two symbols per bit
+    LeCodedS2 = 0x04,
+}
+
+/// Modulation index assumed by a receiver test
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ModulationIndex {
+    #[default]
+    Standard = 0x00,
+    Stable = 0x01,
+}
+
+/// Constant tone extension sent or expected on test packets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestCte {
+    pub length: Duration,
+    pub cte_type: CteType,
+    /// Length of the switching and sampling slots, only used when receiving
+    pub slot_durations: SlotDuration,
+    /// Antenna switching pattern
+    pub antenna_ids: Vec<u8>,
+}
+
+/// Parameters of an LE receiver test
+///
+/// The oldest version of the command that supports the parameters is used, so controllers
+/// without the 2M or coded PHYs can still run tests on the 1M PHY.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReceiverTestParams {
+    /// RF channel, from 0 at 2402 MHz to 39 at 2480 MHz
+    pub channel: u8,
+    pub phy: Phy,
+    pub modulation_index: ModulationIndex,
+    pub cte: Option<TestCte>,
+}
+
+/// Parameters of an LE transmitter test
+///
+/// The oldest version of the command that supports the parameters is used.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransmitterTestParams {
+    /// RF channel, from 0 at 2402 MHz to 39 at 2480 MHz
+    pub channel: u8,
+    /// Length of the payload of each packet
+    pub data_len: u8,
+    pub payload: TestPayload,
+    pub phy: TestPhy,
+    pub cte: Option<TestCte>,
+}
+
+/// Append the length and type of a constant tone extension.
+fn push_cte(param: &mut Vec<u8>, cte: &TestCte) -> Result<()> {
+    let length = u8::try_from(to_units(cte.length, CTE_LENGTH_US)?)
+        .map_err(|_| Error::new(InvalidInput, "Duration out of range"))?;
+    param.extend_from_slice(&[length, cte.cte_type as u8]);
+    Ok(())
+}
+
+fn check_channel(channel: u8) -> Result<()> {
+    if channel > MAX_TEST_CHANNEL {
+        return Err(Error::new(InvalidInput, "Invalid test channel"));
+    }
+    Ok(())
+}
+
+impl Socket {
+    /// Start receiving test packets in direct test mode.
+    ///
+    /// The test runs until it is ended with `le_test_end`.
+    pub fn le_receiver_test(&mut self, params: &ReceiverTestParams, timeout: c_int) -> Result<()> {
+        check_channel(params.channel)?;
+        let mut param = vec![params.channel];
+        let ocf = if let Some(cte) = &params.cte {
+            param.extend_from_slice(&[params.phy as u8, params.modulation_index as u8]);
+            push_cte(&mut param, cte)?;
+            param.push(cte.slot_durations as u8);
+            push_antenna_ids(&mut param, &cte.antenna_ids)?;
+            OCF_LE_RECEIVER_TEST_V3
+        } else if params.phy != Phy::Le1M || params.modulation_index != ModulationIndex::Standard {
+            param.extend_from_slice(&[params.phy as u8, params.modulation_index as u8]);
+            OCF_LE_RECEIVER_TEST_V2
+        } else {
+            OCF_LE_RECEIVER_TEST
+        };
+        self.execute(OGF_LE_CTL, ocf, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Start sending test packets in direct test mode.
+    ///
+    /// The test runs until it is ended with `le_test_end`.
+    pub fn le_transmitter_test(&mut self, params: &TransmitterTestParams, timeout: c_int) -> Result<()> {
+        check_channel(params.channel)?;
+        let mut param = vec![params.channel, params.data_len, params.payload as u8];
+        let ocf = if let Some(cte) = &params.cte {
+            param.push(params.phy as u8);
+            push_cte(&mut param, cte)?;
+            push_antenna_ids(&mut param, &cte.antenna_ids)?;
+            OCF_LE_TRANSMITTER_TEST_V3
+        } else if params.phy != TestPhy::Le1M {
+            param.push(params.phy as u8);
+            OCF_LE_TRANSMITTER_TEST_V2
+        } else {
+            OCF_LE_TRANSMITTER_TEST
+        };
+        self.execute(OGF_LE_CTL, ocf, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// End a receiver or transmitter test, returning the number of packets received.
+    ///
+    /// The count is zero after a transmitter test.
+    pub fn le_test_end(&mut self, timeout: c_int) -> Result<u16> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_TEST_END, &[], timeout)?;
+        let (num_packets, _) = (&*data).read_as::<u16>()?;
+        Ok(num_packets)
+    }
+}
@@ -0,0 +1,380 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Error, Result, Write};
+use std::io::ErrorKind::{InvalidInput, OutOfMemory};
+use std::time::Duration;
+
+use crate::addr::{AddressType, BdAddr, OwnAddressType};
+use crate::io::{ReadAs, WriteAs, WriteTo};
+use crate::socket::Socket;
+use crate::units::{to_units, write_interval_u24, SLOT_US};
+use super::{AdvertisingChannels, AdvertisingFilterPolicy, MAX_EXT_ADV_DATA_LEN, OGF_LE_CTL, Phy};
+
+const OCF_LE_SET_ADVERTISING_SET_RANDOM_ADDRESS: u16 = 0x0035;
+const OCF_LE_SET_EXTENDED_ADVERTISING_PARAMETERS: u16 = 0x0036;
+const OCF_LE_SET_EXTENDED_ADVERTISING_DATA: u16 = 0x0037;
+const OCF_LE_SET_EXTENDED_SCAN_RESPONSE_DATA: u16 = 0x0038;
+const OCF_LE_SET_EXTENDED_ADVERTISING_ENABLE: u16 = 0x0039;
+const OCF_LE_READ_MAXIMUM_ADVERTISING_DATA_LENGTH: u16 = 0x003A;
+const OCF_LE_READ_NUMBER_OF_SUPPORTED_ADVERTISING_SETS: u16 = 0x003B;
+const OCF_LE_REMOVE_ADVERTISING_SET: u16 = 0x003C;
+const OCF_LE_CLEAR_ADVERTISING_SETS: u16 = 0x003D;
+
+/// Longest fragment of advertising data sent in one command
+const MAX_ADV_FRAGMENT_LEN: usize = 251;
+/// Largest advertising set handle
+const MAX_ADV_HANDLE: u8 = 0xEF;
+/// Unit of advertising set durations, in microseconds
+const ADV_DURATION_US: u64 = 10_000;
+
+bitflags! {
+    /// Properties of the advertising events of an advertising set
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct AdvertisingEventProperties: u16 {
+        const CONNECTABLE = 1 << 0;
+        const SCANNABLE = 1 << 1;
+        const DIRECTED = 1 << 2;
+        const HIGH_DUTY_CYCLE_DIRECTED = 1 << 3;
+        /// Use legacy advertising PDUs
+        const LEGACY = 1 << 4;
+        /// Omit the advertiser's address
+        const ANONYMOUS = 1 << 5;
+        /// Include the transmit power in the extended header
+        const INCLUDE_TX_POWER = 1 << 6;
+    }
+}
+
+/// Parameters of an extended advertising set
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExtAdvertisingParams {
+    pub event_properties: AdvertisingEventProperties,
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    pub channel_map: AdvertisingChannels,
+    pub own_address_type: OwnAddressType,
+    /// Type of the peer address, used by directed advertising and privacy
+    pub peer_address_type: AddressType,
+    /// Address of the peer, used by directed advertising and privacy
+    pub peer_address: BdAddr,
+    pub filter_policy: AdvertisingFilterPolicy,
+    /// Highest transmit power in dBm, or `None` to let the controller choose
+    pub tx_power: Option<i8>,
+    /// PHY of the primary advertising channels, either LE 1M or LE Coded
+    pub primary_phy: Phy,
+    /// Number of advertising events that may be skipped on the secondary channels
+    pub secondary_max_skip: u8,
+    pub secondary_phy: Phy,
+    /// Advertising set identifier sent in the advertising data info
+    pub sid: u8,
+    /// Report scan requests with LE Scan Request Received events
+    pub scan_request_notification: bool,
+}
+
+impl Default for ExtAdvertisingParams {
+    /// Connectable extended advertising at 1.28 s intervals on LE 1M
+    fn default() -> Self {
+        ExtAdvertisingParams {
+            event_properties: AdvertisingEventProperties::CONNECTABLE,
+            interval_min: Duration::from_millis(1280),
+            interval_max: Duration::from_millis(1280),
+            channel_map: AdvertisingChannels::default(),
+            own_address_type: OwnAddressType::default(),
+            peer_address_type: AddressType::default(),
+            peer_address: BdAddr::ANY,
+            filter_policy: AdvertisingFilterPolicy::default(),
+            tx_power: None,
+            primary_phy: Phy::Le1M,
+            secondary_max_skip: 0,
+            secondary_phy: Phy::Le1M,
+            sid: 0,
+            scan_request_notification: false,
+        }
+    }
+}
+
+/// Extended advertising parameters for one set
+struct SetParams<'a>(u8, &'a ExtAdvertisingParams);
+
+impl WriteTo for &SetParams<'_> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let SetParams(handle, params) = *self;
+        Ok(w.write_as(handle)?
+            + w.write_as(params.event_properties.bits())?
+            + write_interval_u24(w, params.interval_min, SLOT_US)?
+            + write_interval_u24(w, params.interval_max, SLOT_US)?
+            + w.write_as(params.channel_map.bits())?
+            + w.write_as(params.own_address_type as u8)?
+            + w.write_as(params.peer_address_type as u8)?
+            + w.write_as(&params.peer_address)?
+            + w.write_as(params.filter_policy as u8)?
+            + w.write_as(params.tx_power.unwrap_or(0x7F))?
+            + w.write_as(params.primary_phy as u8)?
+            + w.write_as(params.secondary_max_skip)?
+            + w.write_as(params.secondary_phy as u8)?
+            + w.write_as(params.sid)?
+            + w.write_as(params.scan_request_notification as u8)?)
+    }
+}
+
+/// Part of the advertising data carried by one command
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataOperation {
+    Intermediate = 0x00,
+    First = 0x01,
+    Last = 0x02,
+    /// All of the data in one fragment
+    Complete = 0x03,
+    /// Keep the data but change the advertising data ID
+    Unchanged = 0x04,
+}
+
+/// Advertising set to enable or disable, with limits on how long it advertises
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AdvertisingSetEnable {
+    pub handle: u8,
+    /// Time to advertise for, or zero to advertise until disabled
+    pub duration: Duration,
+    /// Number of extended advertising events to send, or zero for no limit
+    pub max_events: u8,
+}
+
+impl AdvertisingSetEnable {
+    /// Advertise with a set until it is disabled.
+    pub fn new(handle: u8) -> Self {
+        AdvertisingSetEnable { handle, duration: Duration::ZERO, max_events: 0 }
+    }
+}
+
+/// Split data into fragments of up to `max_len` bytes and the operation used to send each one.
+pub(super) fn fragments(data: &[u8], max_len: usize) -> impl Iterator<Item = (DataOperation, &[u8])> {
+    let count = data.len().div_ceil(max_len).max(1);
+    (0..count).map(move |i| {
+        let operation = match i {
+            _ if count == 1 => DataOperation::Complete,
+            0 => DataOperation::First,
+            _ if i == count - 1 => DataOperation::Last,
+            _ => DataOperation::Intermediate,
+        };
+        let end = data.len().min((i + 1) * max_len);
+        (operation, &data[i * max_len..end])
+    })
+}
+
+/// Encode one fragment of data for an advertising set.
+fn data_param(handle: u8, operation: DataOperation, fragment: &[u8]) -> Result<Vec<u8>> {
+    if fragment.len() > MAX_ADV_FRAGMENT_LEN {
+        return Err(Error::new(InvalidInput, "Advertising data fragment too long"));
+    }
+    // Allow the controller to fragment the data over the air.
+    let mut param = vec![handle, operation as u8, 0x00, fragment.len() as u8];
+    param.extend_from_slice(fragment);
+    Ok(param)
+}
+
+impl Socket {
+    /// Set the random address used by an advertising set.
+    pub fn le_set_advertising_set_random_address(&mut self, handle: u8, addr: BdAddr, timeout: c_int) -> Result<()> {
+        let mut param = vec![handle];
+        param.extend_from_slice(addr.as_bytes());
+        self.execute(OGF_LE_CTL, OCF_LE_SET_ADVERTISING_SET_RANDOM_ADDRESS, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Set the parameters of an advertising set, creating it if needed.
+    ///
+    /// Returns the transmit power selected by the controller, in dBm.
+    pub fn le_set_extended_advertising_parameters(&mut self, handle: u8, params: &ExtAdvertisingParams, timeout: c_int) -> Result<i8> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_SET_EXTENDED_ADVERTISING_PARAMETERS, &SetParams(handle, params).bytes()?, timeout)?;
+        let (tx_power, _) = (&*data).read_as::<i8>()?;
+        Ok(tx_power)
+    }
+
+    /// Send one fragment of the advertising data of an advertising set.
+    pub fn le_set_extended_advertising_data(&mut self, handle: u8, operation: DataOperation, fragment: &[u8], timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_EXTENDED_ADVERTISING_DATA, &data_param(handle, operation, fragment)?, timeout)
+            .map(|_| ())
+    }
+
+    /// Send one fragment of the scan response data of an advertising set.
+    pub fn le_set_extended_scan_response_data(&mut self, handle: u8, operation: DataOperation, fragment: &[u8], timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_EXTENDED_SCAN_RESPONSE_DATA, &data_param(handle, operation, fragment)?, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop advertising with a number of advertising sets.
+    ///
+    /// Disabling with no sets disables every set.
+    pub fn le_set_extended_advertising_enable(&mut self, enable: bool, sets: &[AdvertisingSetEnable], timeout: c_int) -> Result<()> {
+        let mut param = vec![enable as u8, sets.len() as u8];
+        for set in sets {
+            param.push(set.handle);
+            param.extend_from_slice(&to_units(set.duration, ADV_DURATION_US)?.to_le_bytes());
+            param.push(set.max_events);
+        }
+        self.execute(OGF_LE_CTL, OCF_LE_SET_EXTENDED_ADVERTISING_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Read the longest advertising or scan response data the controller supports.
+    pub fn le_read_maximum_advertising_data_length(&mut self, timeout: c_int) -> Result<usize> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_MAXIMUM_ADVERTISING_DATA_LENGTH, &[], timeout)?;
+        let (length, _) = (&*data).read_as::<u16>()?;
+        Ok(length.into())
+    }
+
+    /// Read the number of advertising sets the controller supports at the same time.
+    pub fn le_read_number_of_supported_advertising_sets(&mut self, timeout: c_int) -> Result<u8> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_NUMBER_OF_SUPPORTED_ADVERTISING_SETS, &[], timeout)?;
+        let (count, _) = (&*data).read_as::<u8>()?;
+        Ok(count)
+    }
+
+    /// Remove an advertising set from the controller.
+    pub fn le_remove_advertising_set(&mut self, handle: u8, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_REMOVE_ADVERTISING_SET, &[handle], timeout)
+            .map(|_| ())
+    }
+
+    /// Remove every advertising set from the controller.
+    pub fn le_clear_advertising_sets(&mut self, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_CLEAR_ADVERTISING_SETS, &[], timeout)
+            .map(|_| ())
+    }
+}
+
+/// Extended advertiser managing the advertising sets of a controller.
+///
+/// Commands are sent through the borrowed socket, each waiting up to `timeout` milliseconds.
+pub struct ExtendedAdvertiser<'a> {
+    pub(super) socket: &'a mut Socket,
+    pub(super) timeout: c_int,
+    max_sets: u8,
+    max_data_len: usize,
+    sets: Vec<u8>,
+}
+
+impl <'a> ExtendedAdvertiser<'a> {
+    /// Create an advertiser using a socket, removing any existing advertising sets.
+    pub fn new(socket: &'a mut Socket, timeout: c_int) -> Result<Self> {
+        let max_sets = socket.le_read_number_of_supported_advertising_sets(timeout)?;
+        let max_data_len = socket.le_read_maximum_advertising_data_length(timeout)?
+            .min(MAX_EXT_ADV_DATA_LEN);
+        socket.le_clear_advertising_sets(timeout)?;
+        Ok(ExtendedAdvertiser { socket, timeout, max_sets, max_data_len, sets: Vec::new() })
+    }
+
+    /// Number of advertising sets the controller supports
+    pub fn max_sets(&self) -> u8 {
+        self.max_sets
+    }
+
+    /// Longest advertising or scan response data the controller supports
+    pub fn max_data_len(&self) -> usize {
+        self.max_data_len
+    }
+
+    /// Handles of the advertising sets created by this advertiser
+    pub fn sets(&self) -> &[u8] {
+        &self.sets
+    }
+
+    /// Check that a handle belongs to a set created by this advertiser.
+    pub(super) fn check_set(&self, handle: u8) -> Result<()> {
+        if !self.sets.contains(&handle) {
+            return Err(Error::new(InvalidInput, "Unknown advertising set"));
+        }
+        Ok(())
+    }
+
+    /// Create an advertising set with the lowest free handle.
+    ///
+    /// Returns the handle of the set and the transmit power selected by the controller.
+    pub fn add_set(&mut self, params: &ExtAdvertisingParams) -> Result<(u8, i8)> {
+        if self.sets.len() >= self.max_sets as usize {
+            return Err(Error::new(OutOfMemory, "No free advertising sets"));
+        }
+        let handle = (0..=MAX_ADV_HANDLE)
+            .find(|handle| !self.sets.contains(handle))
+            .ok_or(Error::new(OutOfMemory, "No free advertising sets"))?;
+        let tx_power = self.socket.le_set_extended_advertising_parameters(handle, params, self.timeout)?;
+        self.sets.push(handle);
+        Ok((handle, tx_power))
+    }
+
+    /// Change the parameters of an advertising set. The set must not be advertising.
+    ///
+    /// Returns the transmit power selected by the controller.
+    pub fn set_parameters(&mut self, handle: u8, params: &ExtAdvertisingParams) -> Result<i8> {
+        self.check_set(handle)?;
+        self.socket.le_set_extended_advertising_parameters(handle, params, self.timeout)
+    }
+
+    /// Set the random address used by an advertising set.
+    pub fn set_random_address(&mut self, handle: u8, addr: BdAddr) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_set_advertising_set_random_address(handle, addr, self.timeout)
+    }
+
+    /// Set the advertising data of a set, split into as many commands as needed.
+    pub fn set_data(&mut self, handle: u8, data: &[u8]) -> Result<()> {
+        self.check_set(handle)?;
+        if data.len() > self.max_data_len {
+            return Err(Error::new(InvalidInput, "Advertising data too long"));
+        }
+        for (operation, fragment) in fragments(data, MAX_ADV_FRAGMENT_LEN) {
+            self.socket.le_set_extended_advertising_data(handle, operation, fragment, self.timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Set the scan response data of a set, split into as many commands as needed.
+    pub fn set_scan_response(&mut self, handle: u8, data: &[u8]) -> Result<()> {
+        self.check_set(handle)?;
+        if data.len() > self.max_data_len {
+            return Err(Error::new(InvalidInput, "Scan response data too long"));
+        }
+        for (operation, fragment) in fragments(data, MAX_ADV_FRAGMENT_LEN) {
+            self.socket.le_set_extended_scan_response_data(handle, operation, fragment, self.timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Start advertising with a set until it is stopped.
+    pub fn start(&mut self, handle: u8) -> Result<()> {
+        self.start_sets(&[AdvertisingSetEnable::new(handle)])
+    }
+
+    /// Start advertising with a number of sets, each with its own limits.
+    pub fn start_sets(&mut self, sets: &[AdvertisingSetEnable]) -> Result<()> {
+        for set in sets {
+            self.check_set(set.handle)?;
+        }
+        self.socket.le_set_extended_advertising_enable(true, sets, self.timeout)
+    }
+
+    /// Stop advertising with a set.
+    pub fn stop(&mut self, handle: u8) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_set_extended_advertising_enable(false, &[AdvertisingSetEnable::new(handle)], self.timeout)
+    }
+
+    /// Stop advertising with every set.
+    pub fn stop_all(&mut self) -> Result<()> {
+        self.socket.le_set_extended_advertising_enable(false, &[], self.timeout)
+    }
+
+    /// Remove an advertising set. The set must not be advertising.
+    pub fn remove_set(&mut self, handle: u8) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_remove_advertising_set(handle, self.timeout)?;
+        self.sets.retain(|&set| set != handle);
+        Ok(())
+    }
+
+    /// Remove every advertising set. No set may be advertising.
+    pub fn clear(&mut self) -> Result<()> {
+        self.socket.le_clear_advertising_sets(self.timeout)?;
+        self.sets.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidInput, TimedOut};
+use std::time::Duration;
+
+use crate::ad::{self, AdStructure};
+use crate::adapter::Adapter;
+use crate::addr::{AddressType, BdAddr, OwnAddressType};
+use crate::filter::HciFilter;
+use crate::io::{read_bytes, ReadAs, ReadFrom, WriteAs, WriteTo};
+use crate::socket::{HCI_EVENT_PKT, Socket};
+use crate::units::{from_units, to_units, SLOT_US};
+use super::{EVT_LE_META_EVENT, OGF_LE_CTL, Phy, ScanFilterPolicy, ScanType};
+use super::conn::CONN_INTERVAL_US;
+
+const OCF_LE_SET_EXTENDED_SCAN_PARAMETERS: u16 = 0x0041;
+const OCF_LE_SET_EXTENDED_SCAN_ENABLE: u16 = 0x0042;
+
+pub(crate) const EVT_LE_EXTENDED_ADVERTISING_REPORT: u8 = 0x0D;
+const EVT_LE_SCAN_TIMEOUT: u8 = 0x11;
+
+/// Unit of scan durations, in microseconds
+const SCAN_DURATION_US: u64 = 10_000;
+/// Unit of scan periods, in microseconds
+const SCAN_PERIOD_US: u64 = 1_280_000;
+
+/// Address type reported for anonymous advertisements
+const ADDRESS_TYPE_ANONYMOUS: u8 = 0xFF;
+/// Direct address type reported for a resolvable private address the controller could not resolve
+const ADDRESS_TYPE_UNRESOLVED: u8 = 0xFE;
+/// Advertising set identifier reported when the advertisement carries none
+const SID_UNAVAILABLE: u8 = 0xFF;
+/// Transmit power or RSSI value reported when it is not available
+const POWER_UNAVAILABLE: i8 = 127;
+
+/// Scan parameters for one primary advertising PHY
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExtScanPhyParams {
+    pub scan_type: ScanType,
+    pub interval: Duration,
+    pub window: Duration,
+}
+
+impl Default for ExtScanPhyParams {
+    fn default() -> Self {
+        ExtScanPhyParams {
+            scan_type: ScanType::default(),
+            interval: Duration::from_millis(10),
+            window: Duration::from_millis(10),
+        }
+    }
+}
+
+impl WriteTo for &ExtScanPhyParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(self.scan_type as u8)?
+            + w.write_as(to_units(self.interval, SLOT_US)?)?
+            + w.write_as(to_units(self.window, SLOT_US)?)?)
+    }
+}
+
+/// How the controller filters duplicate advertising reports
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateFilter {
+    #[default]
+    Disabled = 0x00,
+    Enabled = 0x01,
+    /// Filter duplicates, forgetting the reports seen at the start of each scan period
+    ResetEachPeriod = 0x02,
+}
+
+/// Options of an extended LE scan
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExtScanOptions {
+    pub own_address_type: OwnAddressType,
+    pub filter_policy: ScanFilterPolicy,
+    /// Parameters for scanning on LE 1M, or `None` to not scan on LE 1M
+    pub le_1m: Option<ExtScanPhyParams>,
+    /// Parameters for scanning on LE Coded, or `None` to not scan on LE Coded
+    pub le_coded: Option<ExtScanPhyParams>,
+    pub filter_duplicates: DuplicateFilter,
+    /// Time to scan for in each period, or zero to scan until disabled
+    pub duration: Duration,
+    /// Time from the start of one scan duration to the next, or zero to scan only once
+    pub period: Duration,
+}
+
+impl Default for ExtScanOptions {
+    /// Passive scanning on LE 1M until disabled
+    fn default() -> Self {
+        ExtScanOptions {
+            own_address_type: OwnAddressType::default(),
+            filter_policy: ScanFilterPolicy::default(),
+            le_1m: Some(ExtScanPhyParams::default()),
+            le_coded: None,
+            filter_duplicates: DuplicateFilter::default(),
+            duration: Duration::ZERO,
+            period: Duration::ZERO,
+        }
+    }
+}
+
+impl WriteTo for &ExtScanOptions {
+    /// Write the parameters of LE Set Extended Scan Parameters.
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let phys = [self.le_1m, self.le_coded];
+        let mut scanning_phys = 0u8;
+        if self.le_1m.is_some() {
+            scanning_phys |= 1 << 0;
+        }
+        if self.le_coded.is_some() {
+            scanning_phys |= 1 << 2;
+        }
+        if scanning_phys == 0 {
+            return Err(Error::new(InvalidInput, "No scanning PHYs"));
+        }
+
+        let mut size = w.write_as(self.own_address_type as u8)?
+            + w.write_as(self.filter_policy as u8)?
+            + w.write_as(scanning_phys)?;
+        for params in phys.iter().flatten() {
+            size += w.write_as(params)?;
+        }
+        Ok(size)
+    }
+}
+
+impl Socket {
+    /// Set the parameters of extended LE scanning.
+    pub fn le_set_extended_scan_parameters(&mut self, options: &ExtScanOptions, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_EXTENDED_SCAN_PARAMETERS, &options.bytes()?, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop extended LE scanning.
+    ///
+    /// A zero `duration` scans until disabled, and a zero `period` scans only once.
+    pub fn le_set_extended_scan_enable(&mut self, enable: bool, filter_duplicates: DuplicateFilter, duration: Duration, period: Duration, timeout: c_int) -> Result<()> {
+        let mut param = vec![enable as u8, filter_duplicates as u8];
+        param.extend_from_slice(&to_units(duration, SCAN_DURATION_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(period, SCAN_PERIOD_US)?.to_le_bytes());
+        self.execute(OGF_LE_CTL, OCF_LE_SET_EXTENDED_SCAN_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+// Extended advertising reports
+
+bitflags! {
+    /// Properties of an extended advertising report
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct ExtAdvertisingEventType: u16 {
+        const CONNECTABLE = 1 << 0;
+        const SCANNABLE = 1 << 1;
+        const DIRECTED = 1 << 2;
+        const SCAN_RESPONSE = 1 << 3;
+        /// Received as a legacy advertising PDU
+        const LEGACY = 1 << 4;
+    }
+}
+
+/// Completeness of the data in an extended advertising report
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DataStatus {
+    #[default]
+    Complete = 0x00,
+    /// More data follows in later reports
+    Incomplete = 0x01,
+    /// The controller could not receive the rest of the data
+    Truncated = 0x02,
+}
+
+impl From<u8> for DataStatus {
+    /// Convert a reported data status, treating unknown values as truncated.
+    fn from(status: u8) -> Self {
+        match status {
+            0x00 => DataStatus::Complete,
+            0x01 => DataStatus::Incomplete,
+            _ => DataStatus::Truncated,
+        }
+    }
+}
+
+/// Extended advertising packet received while scanning
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtAdvertisingReport {
+    pub event_type: ExtAdvertisingEventType,
+    pub data_status: DataStatus,
+    /// Type and address of the advertiser, or `None` for anonymous advertisements
+    pub address: Option<(AddressType, BdAddr)>,
+    pub primary_phy: Phy,
+    /// PHY of the auxiliary packets, or `None` if there were none
+    pub secondary_phy: Option<Phy>,
+    /// Advertising set identifier, if the advertisement carries one
+    pub sid: Option<u8>,
+    /// Transmit power in dBm, if available
+    pub tx_power: Option<i8>,
+    /// Signal strength in dBm, if available
+    pub rssi: Option<i8>,
+    /// Interval of the periodic advertising of the set, if it has any
+    pub periodic_interval: Option<Duration>,
+    /// Type and address the advertisement was directed to, for directed advertisements
+    ///
+    /// A resolvable private address the controller could not resolve is reported as random.
+    pub direct_address: Option<(AddressType, BdAddr)>,
+    /// Raw advertising data
+    pub data: Box<[u8]>,
+    /// Advertising data structures, up to the first malformed structure
+    pub ad: Vec<AdStructure>,
+}
+
+impl ExtAdvertisingReport {
+    /// Return whether this report was directed to another device
+    pub fn is_directed(&self) -> bool {
+        self.event_type.contains(ExtAdvertisingEventType::DIRECTED)
+    }
+
+    /// Return whether this report has no advertiser address
+    pub fn is_anonymous(&self) -> bool {
+        self.address.is_none()
+    }
+
+    /// Append the data of a later fragment of the same advertisement.
+    fn append(&mut self, fragment: ExtAdvertisingReport) {
+        let mut data = std::mem::take(&mut self.data).into_vec();
+        data.extend_from_slice(&fragment.data);
+        self.data = data.into_boxed_slice();
+        self.data_status = fragment.data_status;
+        self.rssi = fragment.rssi.or(self.rssi);
+        self.ad = decode_ad(&self.data);
+    }
+}
+
+/// Decode advertising data structures up to the first malformed structure.
+pub(super) fn decode_ad(data: &[u8]) -> Vec<AdStructure> {
+    ad::structures(data)
+        .map_while(|structure| structure.ok())
+        .filter_map(|(ad_type, data)| AdStructure::decode(ad_type, data).ok())
+        .collect()
+}
+
+impl ReadFrom for ExtAdvertisingReport {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (event_type, _) = r.read_as::<u16>()?;
+        let (address_type, _) = r.read_as::<u8>()?;
+        let (address, _) = r.read_as::<BdAddr>()?;
+        let (primary_phy, _) = r.read_as::<u8>()?;
+        let (secondary_phy, _) = r.read_as::<u8>()?;
+        let (sid, _) = r.read_as::<u8>()?;
+        let (tx_power, _) = r.read_as::<i8>()?;
+        let (rssi, _) = r.read_as::<i8>()?;
+        let (periodic_interval, _) = r.read_as::<u16>()?;
+        let (direct_address_type, _) = r.read_as::<u8>()?;
+        let (direct_address, _) = r.read_as::<BdAddr>()?;
+        let (data_len, _) = r.read_as::<u8>()?;
+        let mut data = vec![0u8; data_len as usize];
+        read_bytes(&mut r, &mut data)?;
+
+        let address = match address_type {
+            ADDRESS_TYPE_ANONYMOUS => None,
+            address_type => Some((AddressType::try_from(address_type)?, address)),
+        };
+        let direct_address = if event_type & ExtAdvertisingEventType::DIRECTED.bits() != 0 {
+            let direct_address_type = match direct_address_type {
+                ADDRESS_TYPE_UNRESOLVED => AddressType::Random,
+                direct_address_type => AddressType::try_from(direct_address_type)?,
+            };
+            Some((direct_address_type, direct_address))
+        } else {
+            None
+        };
+        let data_status = DataStatus::from(((event_type >> 5) & 0x03) as u8);
+
+        Ok((ExtAdvertisingReport {
+            event_type: ExtAdvertisingEventType::from_bits_truncate(event_type),
+            data_status,
+            address,
+            primary_phy: Phy::try_from(primary_phy)?,
+            secondary_phy: (secondary_phy != 0).then(|| Phy::try_from(secondary_phy)).transpose()?,
+            sid: (sid != SID_UNAVAILABLE).then_some(sid),
+            tx_power: (tx_power != POWER_UNAVAILABLE).then_some(tx_power),
+            rssi: (rssi != POWER_UNAVAILABLE).then_some(rssi),
+            periodic_interval: (periodic_interval != 0)
+                .then(|| from_units(periodic_interval.into(), CONN_INTERVAL_US)),
+            direct_address,
+            ad: decode_ad(&data),
+            data: data.into_boxed_slice(),
+        }, 24 + data_len as usize))
+    }
+}
+
+/// Parse the parameters of an LE Extended Advertising Report subevent.
+///
+/// Each report holds at most one fragment of an advertisement. Reports with an incomplete data
+/// status are followed by more fragments of the same advertisement.
+pub fn parse_extended_advertising_reports(params: &[u8]) -> Result<Vec<ExtAdvertisingReport>> {
+    let mut r = params;
+    let (num_reports, _) = r.read_as::<u8>()?;
+    (0..num_reports).map(|_| r.read_as::<ExtAdvertisingReport>().map(|(report, _)| report)).collect()
+}
+
+/// Advertiser and set of a fragmented advertisement
+type FragmentKey = (Option<(AddressType, BdAddr)>, Option<u8>, bool);
+
+/// A running extended LE scan.
+///
+/// While the scan runs, the socket filter only passes LE Meta events. Scanning stops and the
+/// previous filter is restored when the scan is dropped.
+pub struct ExtLeScan<'a> {
+    socket: &'a mut Socket,
+    old_filter: HciFilter,
+    timeout: c_int,
+    pending: VecDeque<ExtAdvertisingReport>,
+    /// Advertisements waiting for more fragments
+    partial: HashMap<FragmentKey, ExtAdvertisingReport>,
+}
+
+impl ExtLeScan<'_> {
+    /// Wait for the next LE Meta event, returning its subevent code and parameters.
+    pub fn next_event(&mut self) -> Result<(u8, Box<[u8]>)> {
+        loop {
+            let (event, data) = self.socket.recv_event(self.timeout)?;
+            if event != EVT_LE_META_EVENT {
+                continue;
+            }
+            if let Some((&subevent, params)) = data.split_first() {
+                return Ok((subevent, params.into()));
+            }
+        }
+    }
+
+    /// Wait for the next complete or truncated advertisement, joining fragmented data.
+    ///
+    /// Fails with `TimedOut` once a scan with a duration and no period has ended.
+    pub fn next_report(&mut self) -> Result<ExtAdvertisingReport> {
+        loop {
+            if let Some(report) = self.pending.pop_front() {
+                return Ok(report);
+            }
+
+            let (subevent, params) = self.next_event()?;
+            if subevent == EVT_LE_SCAN_TIMEOUT {
+                return Err(Error::new(TimedOut, "Scan duration elapsed"));
+            }
+            if subevent != EVT_LE_EXTENDED_ADVERTISING_REPORT {
+                continue;
+            }
+            for fragment in parse_extended_advertising_reports(&params)? {
+                let scan_response = fragment.event_type.contains(ExtAdvertisingEventType::SCAN_RESPONSE);
+                let key = (fragment.address, fragment.sid, scan_response);
+                let report = match self.partial.remove(&key) {
+                    Some(mut report) => {
+                        report.append(fragment);
+                        report
+                    },
+                    None => fragment,
+                };
+                if report.data_status == DataStatus::Incomplete {
+                    self.partial.insert(key, report);
+                } else {
+                    self.pending.push_back(report);
+                }
+            }
+        }
+    }
+}
+
+/// Iterate over advertising reports until an error occurs, such as a timeout.
+impl Iterator for ExtLeScan<'_> {
+    type Item = ExtAdvertisingReport;
+
+    fn next(&mut self) -> Option<ExtAdvertisingReport> {
+        self.next_report().ok()
+    }
+}
+
+impl Drop for ExtLeScan<'_> {
+    fn drop(&mut self) {
+        let _ = self.socket.le_set_extended_scan_enable(false, DuplicateFilter::Disabled, Duration::ZERO, Duration::ZERO, self.timeout);
+        let _ = self.socket.set_filter(&self.old_filter);
+    }
+}
+
+impl Adapter {
+    /// Start an extended LE scan.
+    pub fn le_ext_scan(&mut self, options: &ExtScanOptions) -> Result<ExtLeScan<'_>> {
+        let timeout = self.timeout;
+        let socket = &mut self.socket;
+
+        socket.le_set_extended_scan_parameters(options, timeout)?;
+
+        let old_filter = socket.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_LE_META_EVENT)?;
+        socket.set_filter(&filter)?;
+
+        let scan = ExtLeScan {
+            socket,
+            old_filter,
+            timeout,
+            pending: VecDeque::new(),
+            partial: HashMap::new(),
+        };
+        scan.socket.le_set_extended_scan_enable(true, options.filter_duplicates, options.duration, options.period, timeout)?;
+        Ok(scan)
+    }
+}
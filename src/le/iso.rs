@@ -0,0 +1,390 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::time::Duration;
+
+use crate::audio::{CodingFormat, CodingId, DataPath, DataPathDirection};
+use crate::error::MalformedPacket;
+use crate::filter::HciFilter;
+use crate::io::{ReadAs, WriteTo};
+use crate::iso::{IsoBoundary, IsoPacket};
+use crate::socket::{HCI_ISODATA_PKT, Socket};
+use crate::units::{write_interval_u24};
+use super::{Big, BigSync, CisStream, OGF_LE_CTL};
+
+const OCF_LE_READ_BUFFER_SIZE_V2: u16 = 0x0060;
+const OCF_LE_SETUP_ISO_DATA_PATH: u16 = 0x006E;
+const OCF_LE_REMOVE_ISO_DATA_PATH: u16 = 0x006F;
+const OCF_LE_ISO_TRANSMIT_TEST: u16 = 0x0070;
+const OCF_LE_ISO_RECEIVE_TEST: u16 = 0x0071;
+const OCF_LE_ISO_READ_TEST_COUNTERS: u16 = 0x0072;
+const OCF_LE_ISO_TEST_END: u16 = 0x0073;
+
+/// Longest SDU described by an ISO SDU header
+const MAX_SDU_LEN: usize = 0x0FFF;
+/// Mask of the SDU length in the length and status field of an SDU header
+const SDU_LENGTH_MASK: u16 = 0x0FFF;
+
+/// Sizes of the LE data buffers of a controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LeBufferSize {
+    /// Longest LE ACL data packet, or zero if LE shares the BR/EDR buffers
+    pub acl_packet_len: u16,
+    pub acl_packets: u8,
+    /// Longest ISO data packet
+    pub iso_packet_len: u16,
+    pub iso_packets: u8,
+}
+
+/// Path and codec of isochronous data in one direction of a CIS or BIS
+///
+/// The codec configuration and controller delay are only used by codecs in the controller.
+/// The default passes transparent data over HCI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IsoDataPathParams {
+    pub path: DataPath,
+    pub codec: CodingFormat,
+    pub controller_delay: Duration,
+    pub codec_config: Vec<u8>,
+}
+
+impl Default for IsoDataPathParams {
+    fn default() -> Self {
+        IsoDataPathParams {
+            path: DataPath::Hci,
+            codec: CodingFormat::new(CodingId::Transparent),
+            controller_delay: Duration::ZERO,
+            codec_config: Vec::new(),
+        }
+    }
+}
+
+/// Status of the data of a received SDU
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SduStatus {
+    #[default]
+    Valid = 0x00,
+    /// The data may contain errors
+    PossiblyInvalid = 0x01,
+    /// Part of the data was lost
+    Lost = 0x02,
+}
+
+impl TryFrom<u8> for SduStatus {
+    type Error = Error;
+
+    fn try_from(status: u8) -> Result<Self> {
+        match status {
+            0x00 => Ok(SduStatus::Valid),
+            0x01 => Ok(SduStatus::PossiblyInvalid),
+            0x02 => Ok(SduStatus::Lost),
+            _ => Err(Error::new(InvalidData, "Unknown SDU status")),
+        }
+    }
+}
+
+/// Service data unit received on an isochronous stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IsoSdu {
+    /// Time stamp of the SDU in microseconds of the controller clock, if reported
+    pub timestamp: Option<u32>,
+    pub sequence_number: u16,
+    pub status: SduStatus,
+    pub data: Box<[u8]>,
+}
+
+impl Socket {
+    /// Read the sizes of the LE ACL and ISO data buffers of the local controller.
+    pub fn le_read_buffer_size_v2(&mut self, timeout: c_int) -> Result<LeBufferSize> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_BUFFER_SIZE_V2, &[], timeout)?;
+        let mut r = &*data;
+        let (acl_packet_len, _) = r.read_as::<u16>()?;
+        let (acl_packets, _) = r.read_as::<u8>()?;
+        let (iso_packet_len, _) = r.read_as::<u16>()?;
+        let (iso_packets, _) = r.read_as::<u8>()?;
+        Ok(LeBufferSize { acl_packet_len, acl_packets, iso_packet_len, iso_packets })
+    }
+
+    /// Set up the path of isochronous data in one direction of a CIS or BIS.
+    pub fn le_setup_iso_data_path(&mut self, handle: u16, direction: DataPathDirection, params: &IsoDataPathParams, timeout: c_int) -> Result<()> {
+        let config_len = u8::try_from(params.codec_config.len())
+            .map_err(|_| Error::new(InvalidInput, "Codec configuration too long"))?;
+        let mut param = handle.to_le_bytes().to_vec();
+        param.extend_from_slice(&[direction as u8, params.path.into()]);
+        param.extend_from_slice(&params.codec.bytes()?);
+        write_interval_u24(&mut param, params.controller_delay, 1)?;
+        param.push(config_len);
+        param.extend_from_slice(&params.codec_config);
+        self.execute(OGF_LE_CTL, OCF_LE_SETUP_ISO_DATA_PATH, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Remove the paths of isochronous data in the given directions of a CIS or BIS.
+    pub fn le_remove_iso_data_path(&mut self, handle: u16, directions: &[DataPathDirection], timeout: c_int) -> Result<()> {
+        let mask = directions.iter().fold(0u8, |mask, &direction| mask | 1 << direction as u8);
+        let mut param = handle.to_le_bytes().to_vec();
+        param.push(mask);
+        self.execute(OGF_LE_CTL, OCF_LE_REMOVE_ISO_DATA_PATH, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+/// A stream of SDUs sent and received over HCI on a CIS or BIS.
+///
+/// The crate splits outgoing SDUs into ISO data packets, numbers them and joins incoming
+/// fragments. While the stream exists, the socket filter only passes ISO data packets. The
+/// data paths are removed and the previous filter is restored when the stream is dropped.
+pub struct IsoStream<'a> {
+    socket: &'a mut Socket,
+    old_filter: HciFilter,
+    timeout: c_int,
+    handle: u16,
+    directions: Vec<DataPathDirection>,
+    /// Longest data load of an ISO data packet sent to the controller
+    max_packet_len: usize,
+    sequence_number: u16,
+}
+
+impl <'a> IsoStream<'a> {
+    /// Set up HCI data paths in the given directions of a CIS or BIS and start a stream.
+    pub fn new(socket: &'a mut Socket, handle: u16, directions: &[DataPathDirection], timeout: c_int) -> Result<Self> {
+        let max_packet_len = socket.le_read_buffer_size_v2(timeout)?.iso_packet_len.into();
+        if max_packet_len == 0 {
+            return Err(Error::new(InvalidData, "Controller has no ISO buffers"));
+        }
+
+        // Set the filter first, so the data paths never need removing for a filter error.
+        let old_filter = socket.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_ISODATA_PKT)?;
+        socket.set_filter(&filter)?;
+
+        let params = IsoDataPathParams::default();
+        for (i, &direction) in directions.iter().enumerate() {
+            let result = socket.le_setup_iso_data_path(handle, direction, &params, timeout);
+            if let Err(err) = result {
+                if i > 0 {
+                    let _ = socket.le_remove_iso_data_path(handle, &directions[..i], timeout);
+                }
+                let _ = socket.set_filter(&old_filter);
+                return Err(err);
+            }
+        }
+
+        Ok(IsoStream {
+            socket,
+            old_filter,
+            timeout,
+            handle,
+            directions: directions.to_vec(),
+            max_packet_len,
+            sequence_number: 0,
+        })
+    }
+}
+
+impl IsoStream<'_> {
+    /// Return the connection handle of the CIS or BIS
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// Send an SDU, with a time stamp in microseconds of the controller clock if given.
+    ///
+    /// Returns the sequence number given to the SDU.
+    pub fn write_sdu(&mut self, sdu: &[u8], timestamp: Option<u32>) -> Result<u16> {
+        if sdu.len() > MAX_SDU_LEN {
+            return Err(Error::new(InvalidInput, "SDU too long"));
+        }
+        let sequence_number = self.sequence_number;
+
+        // The first packet carries the time stamp and SDU header before the data.
+        let mut header = Vec::new();
+        if let Some(timestamp) = timestamp {
+            header.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        header.extend_from_slice(&sequence_number.to_le_bytes());
+        header.extend_from_slice(&(sdu.len() as u16).to_le_bytes());
+        if header.len() >= self.max_packet_len {
+            return Err(Error::new(InvalidInput, "ISO data packets too short"));
+        }
+
+        let first_len = sdu.len().min(self.max_packet_len - header.len());
+        let (first, mut rest) = sdu.split_at(first_len);
+        let mut data = header;
+        data.extend_from_slice(first);
+        self.socket.send_iso(&IsoPacket {
+            handle: self.handle,
+            boundary: if rest.is_empty() { IsoBoundary::Complete } else { IsoBoundary::First },
+            has_timestamp: timestamp.is_some(),
+            data: data.into_boxed_slice(),
+        })?;
+
+        while !rest.is_empty() {
+            let (fragment, remaining) = rest.split_at(rest.len().min(self.max_packet_len));
+            rest = remaining;
+            self.socket.send_iso(&IsoPacket {
+                handle: self.handle,
+                boundary: if rest.is_empty() { IsoBoundary::Last } else { IsoBoundary::Continuation },
+                has_timestamp: false,
+                data: fragment.into(),
+            })?;
+        }
+
+        self.sequence_number = sequence_number.wrapping_add(1);
+        Ok(sequence_number)
+    }
+
+    /// Wait for the next SDU, joining fragmented data.
+    ///
+    /// Fragments received without the start of their SDU are dropped.
+    pub fn read_sdu(&mut self) -> Result<IsoSdu> {
+        let mut partial: Option<IsoSdu> = None;
+        // Length of the SDU given in its header
+        let mut sdu_len = 0;
+        loop {
+            let packet = self.socket.recv_iso()?;
+            if packet.handle != self.handle {
+                continue;
+            }
+
+            if packet.boundary.starts_sdu() {
+                let mut r = &*packet.data;
+                let timestamp = if packet.has_timestamp {
+                    Some(r.read_as::<u32>()?.0)
+                } else {
+                    None
+                };
+                let (sequence_number, _) = r.read_as::<u16>()?;
+                let (length_status, _) = r.read_as::<u16>()?;
+                sdu_len = (length_status & SDU_LENGTH_MASK) as usize;
+                partial = Some(IsoSdu {
+                    timestamp,
+                    sequence_number,
+                    status: SduStatus::try_from((length_status >> 14) as u8)?,
+                    data: r.into(),
+                });
+            } else if let Some(sdu) = partial.as_mut() {
+                let mut data = std::mem::take(&mut sdu.data).into_vec();
+                data.extend_from_slice(&packet.data);
+                sdu.data = data.into_boxed_slice();
+            } else {
+                continue;
+            }
+
+            if matches!(packet.boundary, IsoBoundary::Complete | IsoBoundary::Last) {
+                if let Some(sdu) = partial.take() {
+                    if sdu.data.len() != sdu_len {
+                        return Err(MalformedPacket.into());
+                    }
+                    return Ok(sdu);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for IsoStream<'_> {
+    fn drop(&mut self) {
+        let _ = self.socket.set_filter(&self.old_filter);
+        let _ = self.socket.le_remove_iso_data_path(self.handle, &self.directions, self.timeout);
+    }
+}
+
+// Test mode
+
+/// Length of the test payloads sent or expected on an ISO link
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IsoTestPayload {
+    /// Empty payloads
+    ZeroLength = 0x00,
+    /// Payloads of varying length up to the maximum SDU size
+    VariableLength = 0x01,
+    /// Payloads of the maximum SDU size
+    #[default]
+    MaximumLength = 0x02,
+}
+
+/// SDU counters of an ISO receive test
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct IsoTestCounters {
+    /// SDUs received with the expected payload
+    pub received: u32,
+    /// SDUs that were not received
+    pub missed: u32,
+    /// SDUs received with an unexpected payload
+    pub failed: u32,
+}
+
+impl IsoTestCounters {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let mut r = data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (received, _) = r.read_as::<u32>()?;
+        let (missed, _) = r.read_as::<u32>()?;
+        let (failed, _) = r.read_as::<u32>()?;
+        Ok(IsoTestCounters { received, missed, failed })
+    }
+}
+
+impl Socket {
+    /// Start sending test payloads on a CIS or BIS instead of data from the host.
+    pub fn le_iso_transmit_test(&mut self, handle: u16, payload: IsoTestPayload, timeout: c_int) -> Result<()> {
+        let mut param = handle.to_le_bytes().to_vec();
+        param.push(payload as u8);
+        self.execute(OGF_LE_CTL, OCF_LE_ISO_TRANSMIT_TEST, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Start checking test payloads received on a CIS or BIS instead of passing them to the host.
+    pub fn le_iso_receive_test(&mut self, handle: u16, payload: IsoTestPayload, timeout: c_int) -> Result<()> {
+        let mut param = handle.to_le_bytes().to_vec();
+        param.push(payload as u8);
+        self.execute(OGF_LE_CTL, OCF_LE_ISO_RECEIVE_TEST, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Read the SDU counters of a receive test on a CIS or BIS.
+    pub fn le_iso_read_test_counters(&mut self, handle: u16, timeout: c_int) -> Result<IsoTestCounters> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_ISO_READ_TEST_COUNTERS, &handle.to_le_bytes(), timeout)?;
+        IsoTestCounters::parse(&data)
+    }
+
+    /// End an ISO test on a CIS or BIS, returning the final counters.
+    ///
+    /// The counters are all zero if only a transmit test was running.
+    pub fn le_iso_test_end(&mut self, handle: u16, timeout: c_int) -> Result<IsoTestCounters> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_ISO_TEST_END, &handle.to_le_bytes(), timeout)?;
+        IsoTestCounters::parse(&data)
+    }
+}
+
+impl CisStream<'_> {
+    /// Send and receive SDUs over HCI on this CIS.
+    pub fn iso(&mut self) -> Result<IsoStream<'_>> {
+        let (handle, timeout) = (self.handle(), self.timeout);
+        IsoStream::new(self.socket(), handle, &[DataPathDirection::Input, DataPathDirection::Output], timeout)
+    }
+}
+
+impl Big<'_> {
+    /// Send SDUs over HCI on one BIS of the group.
+    pub fn iso(&mut self, bis_handle: u16) -> Result<IsoStream<'_>> {
+        if !self.bis_handles().contains(&bis_handle) {
+            return Err(Error::new(InvalidInput, "BIS handle is not in the group"));
+        }
+        let timeout = self.timeout;
+        IsoStream::new(self.socket(), bis_handle, &[DataPathDirection::Input], timeout)
+    }
+}
+
+impl BigSync<'_> {
+    /// Receive SDUs over HCI on one BIS of the group.
+    pub fn iso(&mut self, bis_handle: u16) -> Result<IsoStream<'_>> {
+        if !self.bis_handles().contains(&bis_handle) {
+            return Err(Error::new(InvalidInput, "BIS handle is not in the group"));
+        }
+        let timeout = self.timeout;
+        IsoStream::new(self.socket(), bis_handle, &[DataPathDirection::Output], timeout)
+    }
+}
@@ -0,0 +1,440 @@
+mod accept_list;
+mod adv;
+#[cfg(feature = "iso")]
+mod big;
+#[cfg(feature = "iso")]
+mod cis;
+mod conn;
+mod cte;
+mod data_length;
+mod dtm;
+mod ext_adv;
+mod ext_scan;
+#[cfg(feature = "iso")]
+mod iso;
+mod periodic_adv;
+mod periodic_sync;
+mod phy;
+mod power;
+mod privacy;
+mod report;
+mod scan;
+mod security;
+
+pub use accept_list::{AcceptListEntry, FilterAcceptList};
+pub use adv::{Advertiser, AdvertisingChannels, AdvertisingFilterPolicy, AdvertisingParams, AdvertisingType, MAX_ADV_DATA_LEN, MAX_EXT_ADV_DATA_LEN};
+#[cfg(feature = "iso")]
+pub use big::{Big, BigComplete, BigParams, BigSync, BigSyncEstablished, BigSyncOptions};
+#[cfg(feature = "iso")]
+pub use cis::{Cig, CigParams, CisEstablished, CisParams, CisRequest, CisStream, Packing};
+pub use conn::{ConnectionParams, ExtConnectionParams, LeConnectionComplete, LeConnectionUpdate, RemoteConnParamRequest, SubrateChange, SubrateParams};
+pub use cte::{AntennaInfo, ConnectionIqReport, ConnectionlessIqReport, CteType, CteTypes, IqSample, SlotDuration, SwitchingSamplingRates};
+pub use data_length::{DataLength, DataLengthChange, MaxDataLength};
+pub use dtm::{ModulationIndex, ReceiverTestParams, TestCte, TestPayload, TestPhy, TransmitterTestParams};
+pub use ext_adv::{AdvertisingEventProperties, AdvertisingSetEnable, DataOperation, ExtAdvertisingParams, ExtendedAdvertiser};
+pub use ext_scan::{DataStatus, DuplicateFilter, ExtAdvertisingEventType, ExtAdvertisingReport, ExtLeScan, ExtScanOptions, ExtScanPhyParams, parse_extended_advertising_reports};
+#[cfg(feature = "iso")]
+pub use iso::{IsoDataPathParams, IsoSdu, IsoStream, IsoTestCounters, IsoTestPayload, LeBufferSize, SduStatus};
+pub use periodic_adv::{PeriodicAdvertisingParams};
+pub use periodic_sync::{PeriodicAdvertisingReport, PeriodicSync, PeriodicSyncEstablished, PeriodicSyncOptions};
+pub use phy::{CodedPhyOptions, Phy, PhyMask, PhyUpdate};
+pub use power::{PathLossParams, PathLossThreshold, PathLossZone, PowerControlPhy, PowerReportReason, RfPathCompensation, TransmitPowerReport};
+pub use privacy::{IdentityAddress, PrivacyMode, ResolvingListEntry};
+#[cfg(feature = "bredr")]
+pub(crate) use report::EVT_LE_ADVERTISING_REPORT;
+pub use report::{AdvertisingEventType, AdvertisingReport, parse_advertising_reports, ScannedDevice};
+pub use scan::{LeScan, ScanFilterPolicy, ScanOptions, ScanType};
+pub use security::{EncryptionChange, LtkRequest};
+
+use bitflags::bitflags;
+use libc::{c_int, ETIMEDOUT};
+use std::io::{Error, Result};
+use std::time::{Duration, Instant};
+use std::io::ErrorKind::{InvalidInput};
+
+use super::addr::BdAddr;
+use super::connection::Connection;
+use super::error::{MalformedPacket, StatusError};
+use super::filter::HciFilter;
+use super::io::ReadAs;
+use super::socket::{EVT_CMD_COMPLETE, HCI_EVENT_PKT, Socket};
+
+pub(crate) const OGF_LE_CTL: u16 = 0x08;
+const OCF_LE_SET_EVENT_MASK: u16 = 0x0001;
+const OCF_LE_READ_LOCAL_SUPPORTED_FEATURES: u16 = 0x0003;
+const OCF_LE_SET_RANDOM_ADDRESS: u16 = 0x0005;
+const OCF_LE_READ_REMOTE_FEATURES: u16 = 0x0016;
+const OCF_LE_READ_SUPPORTED_STATES: u16 = 0x001C;
+const OCF_LE_SET_HOST_FEATURE: u16 = 0x0074;
+
+pub(crate) const EVT_LE_META_EVENT: u8 = 0x3E;
+const EVT_LE_READ_REMOTE_FEATURES_COMPLETE: u8 = 0x04;
+
+impl Socket {
+    /// Send an LE command and wait for an LE Meta event accepted by `matches`.
+    ///
+    /// `matches` receives the subevent code and the subevent parameters. Returns the subevent
+    /// code and parameters of the matching event. A command the controller answers with a
+    /// Command Complete instead, such as one it does not know, fails with a `StatusError`.
+    pub(crate) fn le_request<F>(&mut self, ocf: u16, param: &[u8], timeout: c_int, matches: F) -> Result<(u8, Box<[u8]>)>
+    where F: Fn(u8, &[u8]) -> bool {
+        let (code, data) = self.send_req_event(OGF_LE_CTL, ocf,
+            EVT_LE_META_EVENT as c_int,
+            param,
+            timeout,
+            |_, data| data.split_first()
+                .is_some_and(|(&subevent, params)| matches(subevent, params)),
+        )?;
+        match (code, data.split_first()) {
+            (EVT_LE_META_EVENT, Some((&subevent, params))) => Ok((subevent, params.into())),
+            (EVT_CMD_COMPLETE, Some((&status, _))) if status != 0 => Err(StatusError(status).into()),
+            _ => Err(MalformedPacket.into()),
+        }
+    }
+
+    /// Wait for an LE Meta event accepted by `matches`, without sending a command.
+    ///
+    /// `matches` receives the subevent code and the subevent parameters. Returns the subevent
+    /// code and parameters of the matching event. A positive `timeout` bounds the whole wait in
+    /// milliseconds, however many other LE Meta events arrive meanwhile.
+    pub fn wait_le_event<F>(&mut self, timeout: c_int, matches: F) -> Result<(u8, Box<[u8]>)>
+    where F: Fn(u8, &[u8]) -> bool {
+        let old_filter = self.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_LE_META_EVENT)?;
+        self.set_filter(&filter)?;
+
+        let deadline = Instant::now() + Duration::from_millis(timeout.max(0) as u64);
+        let result = (|s: &mut Socket| loop {
+            let wait = if timeout > 0 {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(Error::from_raw_os_error(ETIMEDOUT));
+                }
+                c_int::try_from(left.as_millis()).unwrap_or(c_int::MAX).max(1)
+            } else {
+                timeout
+            };
+            // Only copy the parameters of the matching subevent.
+            let found = s.recv_event_map(wait, |event, data| match data.split_first() {
+                Some((&subevent, params)) if event == EVT_LE_META_EVENT && matches(subevent, params) =>
+                    Some((subevent, params.into())),
+                _ => None,
+            })?;
+            if let Some(found) = found {
+                return Ok(found);
+            }
+        })(self);
+
+        self.set_filter(&old_filter)?;
+        result
+    }
+}
+
+// Event mask
+
+bitflags! {
+    /// LE Meta subevents the controller may report to the host
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct LeEventMask: u64 {
+        const CONNECTION_COMPLETE = 1 << 0;
+        const ADVERTISING_REPORT = 1 << 1;
+        const CONNECTION_UPDATE_COMPLETE = 1 << 2;
+        const READ_REMOTE_FEATURES_COMPLETE = 1 << 3;
+        const LONG_TERM_KEY_REQUEST = 1 << 4;
+        const REMOTE_CONNECTION_PARAMETER_REQUEST = 1 << 5;
+        const DATA_LENGTH_CHANGE = 1 << 6;
+        const READ_LOCAL_P256_PUBLIC_KEY_COMPLETE = 1 << 7;
+        const GENERATE_DHKEY_COMPLETE = 1 << 8;
+        const ENHANCED_CONNECTION_COMPLETE = 1 << 9;
+        const DIRECTED_ADVERTISING_REPORT = 1 << 10;
+        const PHY_UPDATE_COMPLETE = 1 << 11;
+        const EXTENDED_ADVERTISING_REPORT = 1 << 12;
+        const PERIODIC_ADVERTISING_SYNC_ESTABLISHED = 1 << 13;
+        const PERIODIC_ADVERTISING_REPORT = 1 << 14;
+        const PERIODIC_ADVERTISING_SYNC_LOST = 1 << 15;
+        const SCAN_TIMEOUT = 1 << 16;
+        const ADVERTISING_SET_TERMINATED = 1 << 17;
+        const SCAN_REQUEST_RECEIVED = 1 << 18;
+        const CHANNEL_SELECTION_ALGORITHM = 1 << 19;
+        const CONNECTIONLESS_IQ_REPORT = 1 << 20;
+        const CONNECTION_IQ_REPORT = 1 << 21;
+        const CTE_REQUEST_FAILED = 1 << 22;
+        const PERIODIC_ADVERTISING_SYNC_TRANSFER_RECEIVED = 1 << 23;
+        const CIS_ESTABLISHED = 1 << 24;
+        const CIS_REQUEST = 1 << 25;
+        const CREATE_BIG_COMPLETE = 1 << 26;
+        const TERMINATE_BIG_COMPLETE = 1 << 27;
+        const BIG_SYNC_ESTABLISHED = 1 << 28;
+        const BIG_SYNC_LOST = 1 << 29;
+        const REQUEST_PEER_SCA_COMPLETE = 1 << 30;
+        const PATH_LOSS_THRESHOLD = 1 << 31;
+        const TRANSMIT_POWER_REPORTING = 1 << 32;
+        const BIGINFO_ADVERTISING_REPORT = 1 << 33;
+        const SUBRATE_CHANGE = 1 << 34;
+        const PERIODIC_ADVERTISING_SYNC_ESTABLISHED_V2 = 1 << 35;
+        const PERIODIC_ADVERTISING_REPORT_V2 = 1 << 36;
+        const PERIODIC_ADVERTISING_SYNC_TRANSFER_RECEIVED_V2 = 1 << 37;
+        const PERIODIC_ADVERTISING_SUBEVENT_DATA_REQUEST = 1 << 38;
+        const PERIODIC_ADVERTISING_RESPONSE_REPORT = 1 << 39;
+        const ENHANCED_CONNECTION_COMPLETE_V2 = 1 << 40;
+    }
+}
+
+impl Default for LeEventMask {
+    /// The mask set by the controller after a reset
+    fn default() -> Self {
+        LeEventMask::from_bits_retain(0x1F)
+    }
+}
+
+impl Socket {
+    /// Set the LE Meta subevents the controller reports to the host.
+    ///
+    /// The LE Meta event itself must also be enabled in the controller's event mask.
+    pub fn le_set_event_mask(&mut self, mask: LeEventMask, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_EVENT_MASK, &mask.bits().to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+}
+
+// Features
+
+bitflags! {
+    /// LE features supported by a controller
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct LeFeatures: u64 {
+        const ENCRYPTION = 1 << 0;
+        const CONNECTION_PARAMETERS_REQUEST = 1 << 1;
+        const EXTENDED_REJECT_INDICATION = 1 << 2;
+        const PERIPHERAL_INITIATED_FEATURES_EXCHANGE = 1 << 3;
+        const PING = 1 << 4;
+        const DATA_PACKET_LENGTH_EXTENSION = 1 << 5;
+        const LL_PRIVACY = 1 << 6;
+        const EXTENDED_SCANNER_FILTER_POLICIES = 1 << 7;
+        const LE_2M_PHY = 1 << 8;
+        const STABLE_MODULATION_INDEX_TX = 1 << 9;
+        const STABLE_MODULATION_INDEX_RX = 1 << 10;
+        const LE_CODED_PHY = 1 << 11;
+        const EXTENDED_ADVERTISING = 1 << 12;
+        const PERIODIC_ADVERTISING = 1 << 13;
+        const CHANNEL_SELECTION_ALGORITHM_2 = 1 << 14;
+        const POWER_CLASS_1 = 1 << 15;
+        const MIN_USED_CHANNELS = 1 << 16;
+        const CONNECTION_CTE_REQUEST = 1 << 17;
+        const CONNECTION_CTE_RESPONSE = 1 << 18;
+        const CONNECTIONLESS_CTE_TRANSMITTER = 1 << 19;
+        const CONNECTIONLESS_CTE_RECEIVER = 1 << 20;
+        const ANTENNA_SWITCHING_AOD = 1 << 21;
+        const ANTENNA_SWITCHING_AOA = 1 << 22;
+        const RECEIVING_CTE = 1 << 23;
+        const PERIODIC_ADVERTISING_SYNC_TRANSFER_SENDER = 1 << 24;
+        const PERIODIC_ADVERTISING_SYNC_TRANSFER_RECIPIENT = 1 << 25;
+        const SLEEP_CLOCK_ACCURACY_UPDATES = 1 << 26;
+        const REMOTE_PUBLIC_KEY_VALIDATION = 1 << 27;
+        const CIS_CENTRAL = 1 << 28;
+        const CIS_PERIPHERAL = 1 << 29;
+        const ISOCHRONOUS_BROADCASTER = 1 << 30;
+        const SYNCHRONIZED_RECEIVER = 1 << 31;
+        const CIS_HOST_SUPPORT = 1 << 32;
+        const POWER_CONTROL_REQUEST = 1 << 33;
+        const POWER_CONTROL_REQUEST_2 = 1 << 34;
+        const PATH_LOSS_MONITORING = 1 << 35;
+        const PERIODIC_ADVERTISING_ADI = 1 << 36;
+        const CONNECTION_SUBRATING = 1 << 37;
+        const CONNECTION_SUBRATING_HOST_SUPPORT = 1 << 38;
+        const CHANNEL_CLASSIFICATION = 1 << 39;
+        const ADVERTISING_CODING_SELECTION = 1 << 40;
+        const ADVERTISING_CODING_SELECTION_HOST_SUPPORT = 1 << 41;
+        const PERIODIC_ADVERTISING_WITH_RESPONSES_ADVERTISER = 1 << 43;
+        const PERIODIC_ADVERTISING_WITH_RESPONSES_SCANNER = 1 << 44;
+    }
+}
+
+impl Socket {
+    /// Read the LE features supported by the local controller.
+    pub fn le_read_local_supported_features(&mut self, timeout: c_int) -> Result<LeFeatures> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_LOCAL_SUPPORTED_FEATURES, &[], timeout)?;
+        let (features, _) = (&*data).read_as::<u64>()?;
+        Ok(LeFeatures::from_bits_retain(features))
+    }
+
+    /// Set or clear a feature bit the host controls, such as `LeFeatures::CIS_HOST_SUPPORT`
+    /// (bit 32), in the local controller.
+    ///
+    /// Must be called before connections are made, as the features are exchanged with peers.
+    pub fn le_set_host_feature(&mut self, bit: u8, enabled: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_HOST_FEATURE, &[bit, enabled as u8], timeout)
+            .map(|_| ())
+    }
+}
+
+impl Connection<'_> {
+    /// Read the LE features supported by the remote device.
+    pub fn le_remote_features(&mut self, timeout: c_int) -> Result<LeFeatures> {
+        let handle = self.handle().to_le_bytes();
+        let (_, data) = self.socket.le_request(OCF_LE_READ_REMOTE_FEATURES, &handle, timeout,
+            |subevent, params| subevent == EVT_LE_READ_REMOTE_FEATURES_COMPLETE && params.get(1..3) == Some(&handle))?;
+        let mut r = &*data;
+        let (status, _) = r.read_as::<u8>()?;
+        if status != 0 {
+            return Err(StatusError(status).into());
+        }
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (features, _) = r.read_as::<u64>()?;
+        Ok(LeFeatures::from_bits_retain(features))
+    }
+}
+
+// Supported states
+
+/// State or role of the LE link layer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LeState {
+    NonConnectableAdvertising,
+    ScannableAdvertising,
+    ConnectableAdvertising,
+    HighDutyCycleDirectedAdvertising,
+    LowDutyCycleDirectedAdvertising,
+    PassiveScanning,
+    ActiveScanning,
+    Initiating,
+    /// Connection state in the central role
+    Central,
+    /// Connection state in the peripheral role
+    Peripheral,
+}
+
+/// State and role combinations in the order of the bits of the supported states mask.
+const STATE_COMBINATIONS: [(LeState, Option<LeState>); 42] = {
+    use LeState::*;
+    [
+        (NonConnectableAdvertising, None),
+        (ScannableAdvertising, None),
+        (ConnectableAdvertising, None),
+        (HighDutyCycleDirectedAdvertising, None),
+        (PassiveScanning, None),
+        (ActiveScanning, None),
+        (Initiating, None),
+        (Peripheral, None),
+        (NonConnectableAdvertising, Some(PassiveScanning)),
+        (ScannableAdvertising, Some(PassiveScanning)),
+        (ConnectableAdvertising, Some(PassiveScanning)),
+        (HighDutyCycleDirectedAdvertising, Some(PassiveScanning)),
+        (NonConnectableAdvertising, Some(ActiveScanning)),
+        (ScannableAdvertising, Some(ActiveScanning)),
+        (ConnectableAdvertising, Some(ActiveScanning)),
+        (HighDutyCycleDirectedAdvertising, Some(ActiveScanning)),
+        (NonConnectableAdvertising, Some(Initiating)),
+        (ScannableAdvertising, Some(Initiating)),
+        (NonConnectableAdvertising, Some(Central)),
+        (ScannableAdvertising, Some(Central)),
+        (NonConnectableAdvertising, Some(Peripheral)),
+        (ScannableAdvertising, Some(Peripheral)),
+        (PassiveScanning, Some(Initiating)),
+        (ActiveScanning, Some(Initiating)),
+        (PassiveScanning, Some(Central)),
+        (ActiveScanning, Some(Central)),
+        (PassiveScanning, Some(Peripheral)),
+        (ActiveScanning, Some(Peripheral)),
+        (Initiating, Some(Central)),
+        (LowDutyCycleDirectedAdvertising, None),
+        (LowDutyCycleDirectedAdvertising, Some(PassiveScanning)),
+        (LowDutyCycleDirectedAdvertising, Some(ActiveScanning)),
+        (ConnectableAdvertising, Some(Initiating)),
+        (HighDutyCycleDirectedAdvertising, Some(Initiating)),
+        (LowDutyCycleDirectedAdvertising, Some(Initiating)),
+        (ConnectableAdvertising, Some(Central)),
+        (HighDutyCycleDirectedAdvertising, Some(Central)),
+        (LowDutyCycleDirectedAdvertising, Some(Central)),
+        (ConnectableAdvertising, Some(Peripheral)),
+        (HighDutyCycleDirectedAdvertising, Some(Peripheral)),
+        (LowDutyCycleDirectedAdvertising, Some(Peripheral)),
+        (Initiating, Some(Peripheral)),
+    ]
+};
+
+/// Set of state and role combinations supported by an LE controller
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LeSupportedStates(pub u64);
+
+impl LeSupportedStates {
+    /// Return whether a single state is supported
+    pub fn supports_state(&self, state: LeState) -> bool {
+        self.combinations().any(|combination| combination == (state, None))
+    }
+
+    /// Return whether two states can be active at the same time, in either order
+    pub fn supports(&self, a: LeState, b: LeState) -> bool {
+        self.combinations()
+            .any(|combination| combination == (a, Some(b)) || combination == (b, Some(a)))
+    }
+
+    /// Iterate over the supported states and combinations of two states
+    pub fn combinations(&self) -> impl Iterator<Item = (LeState, Option<LeState>)> + '_ {
+        STATE_COMBINATIONS.iter()
+            .enumerate()
+            .filter(|(bit, _)| self.0 & (1 << bit) != 0)
+            .map(|(_, &combination)| combination)
+    }
+}
+
+impl Socket {
+    /// Read the state and role combinations supported by the local controller.
+    pub fn le_read_supported_states(&mut self, timeout: c_int) -> Result<LeSupportedStates> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_SUPPORTED_STATES, &[], timeout)?;
+        let (states, _) = (&*data).read_as::<u64>()?;
+        Ok(LeSupportedStates(states))
+    }
+}
+
+// Random address
+
+impl Socket {
+    /// Set the random address used by the local controller.
+    ///
+    /// The address must be a valid static, resolvable private or non-resolvable private
+    /// address.
+    pub fn le_set_random_address(&mut self, addr: BdAddr, timeout: c_int) -> Result<()> {
+        if addr.random_kind().is_none() {
+            return Err(Error::new(InvalidInput, "Invalid random address"));
+        }
+        self.execute(OGF_LE_CTL, OCF_LE_SET_RANDOM_ADDRESS, addr.as_bytes(), timeout)
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LeState::*;
+
+    #[test]
+    fn supported_states_decode_in_bit_order() {
+        let mask = [0x13, 0x01, 0x40, 0x00, 0x20, 0x00, 0x00, 0x00];
+        let (states, _) = (&mask[..]).read_as::<u64>().unwrap();
+        let states = LeSupportedStates(states);
+        assert_eq!(states.combinations().collect::<Vec<_>>(), [
+            (NonConnectableAdvertising, None),
+            (ScannableAdvertising, None),
+            (PassiveScanning, None),
+            (NonConnectableAdvertising, Some(PassiveScanning)),
+            (PassiveScanning, Some(Initiating)),
+            (LowDutyCycleDirectedAdvertising, Some(Central)),
+        ]);
+
+        assert!(states.supports_state(PassiveScanning));
+        assert!(!states.supports_state(Initiating));
+        assert!(states.supports(Initiating, PassiveScanning));
+        assert!(states.supports(Central, LowDutyCycleDirectedAdvertising));
+        assert!(!states.supports(ActiveScanning, Initiating));
+    }
+
+    #[test]
+    fn reserved_state_bits_are_ignored() {
+        let states = LeSupportedStates(u64::MAX);
+        assert_eq!(states.combinations().count(), STATE_COMBINATIONS.len());
+        assert_eq!(LeSupportedStates(!0x3FF_FFFF_FFFF).combinations().count(), 0);
+    }
+}
@@ -0,0 +1,120 @@
+use libc::{c_int};
+use std::io::{Error, Result, Write};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::io::{WriteAs, WriteTo};
+use crate::socket::Socket;
+use crate::units::{to_units};
+use super::OGF_LE_CTL;
+use super::conn::CONN_INTERVAL_US;
+use super::ext_adv::{fragments, DataOperation, ExtendedAdvertiser};
+
+const OCF_LE_SET_PERIODIC_ADVERTISING_PARAMETERS: u16 = 0x003E;
+const OCF_LE_SET_PERIODIC_ADVERTISING_DATA: u16 = 0x003F;
+const OCF_LE_SET_PERIODIC_ADVERTISING_ENABLE: u16 = 0x0040;
+
+/// Longest fragment of periodic advertising data sent in one command
+const MAX_PERIODIC_FRAGMENT_LEN: usize = 252;
+
+/// Periodic advertising property to include the transmit power in the extended header
+const PROPERTY_INCLUDE_TX_POWER: u16 = 1 << 6;
+
+/// Parameters of the periodic advertising of an advertising set
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeriodicAdvertisingParams {
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    /// Include the transmit power in the extended header
+    pub include_tx_power: bool,
+}
+
+impl Default for PeriodicAdvertisingParams {
+    /// Periodic advertising at 1 s intervals
+    fn default() -> Self {
+        PeriodicAdvertisingParams {
+            interval_min: Duration::from_secs(1),
+            interval_max: Duration::from_secs(1),
+            include_tx_power: false,
+        }
+    }
+}
+
+/// Periodic advertising parameters for one set
+struct SetPeriodicParams<'a>(u8, &'a PeriodicAdvertisingParams);
+
+impl WriteTo for &SetPeriodicParams<'_> {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let SetPeriodicParams(handle, params) = *self;
+        let properties = if params.include_tx_power { PROPERTY_INCLUDE_TX_POWER } else { 0 };
+        Ok(w.write_as(handle)?
+            + w.write_as(to_units(params.interval_min, CONN_INTERVAL_US)?)?
+            + w.write_as(to_units(params.interval_max, CONN_INTERVAL_US)?)?
+            + w.write_as(properties)?)
+    }
+}
+
+impl Socket {
+    /// Set the periodic advertising parameters of an advertising set.
+    ///
+    /// The set must already exist and must not use legacy, connectable or scannable
+    /// advertising.
+    pub fn le_set_periodic_advertising_parameters(&mut self, handle: u8, params: &PeriodicAdvertisingParams, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_PERIODIC_ADVERTISING_PARAMETERS, &SetPeriodicParams(handle, params).bytes()?, timeout)
+            .map(|_| ())
+    }
+
+    /// Send one fragment of the periodic advertising data of an advertising set.
+    pub fn le_set_periodic_advertising_data(&mut self, handle: u8, operation: DataOperation, fragment: &[u8], timeout: c_int) -> Result<()> {
+        if fragment.len() > MAX_PERIODIC_FRAGMENT_LEN {
+            return Err(Error::new(InvalidInput, "Periodic advertising data fragment too long"));
+        }
+        let mut param = vec![handle, operation as u8, fragment.len() as u8];
+        param.extend_from_slice(fragment);
+        self.execute(OGF_LE_CTL, OCF_LE_SET_PERIODIC_ADVERTISING_DATA, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop the periodic advertising of an advertising set.
+    ///
+    /// `include_adi` adds the advertising data info to the periodic advertising packets.
+    pub fn le_set_periodic_advertising_enable(&mut self, handle: u8, enable: bool, include_adi: bool, timeout: c_int) -> Result<()> {
+        let enable = enable as u8 | (include_adi as u8) << 1;
+        self.execute(OGF_LE_CTL, OCF_LE_SET_PERIODIC_ADVERTISING_ENABLE, &[enable, handle], timeout)
+            .map(|_| ())
+    }
+}
+
+impl ExtendedAdvertiser<'_> {
+    /// Set the periodic advertising parameters of a set.
+    pub fn set_periodic_parameters(&mut self, handle: u8, params: &PeriodicAdvertisingParams) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_set_periodic_advertising_parameters(handle, params, self.timeout)
+    }
+
+    /// Set the periodic advertising data of a set, split into as many commands as needed.
+    pub fn set_periodic_data(&mut self, handle: u8, data: &[u8]) -> Result<()> {
+        self.check_set(handle)?;
+        if data.len() > self.max_data_len() {
+            return Err(Error::new(InvalidInput, "Periodic advertising data too long"));
+        }
+        for (operation, fragment) in fragments(data, MAX_PERIODIC_FRAGMENT_LEN) {
+            self.socket.le_set_periodic_advertising_data(handle, operation, fragment, self.timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Start the periodic advertising of a set.
+    ///
+    /// Periodic advertisements are only sent once the set is also advertising.
+    pub fn start_periodic(&mut self, handle: u8) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_set_periodic_advertising_enable(handle, true, false, self.timeout)
+    }
+
+    /// Stop the periodic advertising of a set.
+    pub fn stop_periodic(&mut self, handle: u8) -> Result<()> {
+        self.check_set(handle)?;
+        self.socket.le_set_periodic_advertising_enable(handle, false, false, self.timeout)
+    }
+}
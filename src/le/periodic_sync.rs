@@ -0,0 +1,343 @@
+use libc::{c_int};
+use std::collections::{VecDeque};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{NotConnected};
+use std::time::Duration;
+
+use crate::ad::{AdStructure};
+use crate::adapter::Adapter;
+use crate::addr::{AddressType, BdAddr};
+use crate::error::{MalformedPacket, StatusError};
+use crate::filter::HciFilter;
+use crate::io::ReadAs;
+use crate::socket::{EVT_CMD_STATUS, HCI_EVENT_PKT, Socket};
+use crate::units::{from_units, to_units};
+use super::{CteType, DataStatus, EVT_LE_META_EVENT, OGF_LE_CTL, Phy};
+use super::conn::CONN_INTERVAL_US;
+use super::ext_scan::decode_ad;
+
+const OCF_LE_PERIODIC_ADVERTISING_CREATE_SYNC: u16 = 0x0044;
+const OCF_LE_PERIODIC_ADVERTISING_CREATE_SYNC_CANCEL: u16 = 0x0045;
+const OCF_LE_PERIODIC_ADVERTISING_TERMINATE_SYNC: u16 = 0x0046;
+const OCF_LE_SET_PERIODIC_ADVERTISING_RECEIVE_ENABLE: u16 = 0x0059;
+
+pub(crate) const EVT_LE_PERIODIC_ADVERTISING_SYNC_ESTABLISHED: u8 = 0x0E;
+pub(crate) const EVT_LE_PERIODIC_ADVERTISING_REPORT: u8 = 0x0F;
+pub(crate) const EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST: u8 = 0x10;
+
+/// Unit of sync timeouts, in microseconds
+const SYNC_TIMEOUT_US: u64 = 10_000;
+
+/// Transmit power or RSSI value reported when it is not available
+const POWER_UNAVAILABLE: i8 = 127;
+
+/// Options for synchronizing to periodic advertising
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeriodicSyncOptions {
+    /// Synchronize to any advertiser in the periodic advertiser list instead of the given one
+    pub use_periodic_advertiser_list: bool,
+    /// Report periodic advertisements once synchronized
+    pub reporting_enabled: bool,
+    /// Drop reports with the same advertising data ID as the previous one
+    pub filter_duplicates: bool,
+    /// Number of periodic advertisements that may be skipped after a successful receive
+    pub skip: u16,
+    /// Time without receiving an advertisement after which the sync is lost
+    pub sync_timeout: Duration,
+}
+
+impl Default for PeriodicSyncOptions {
+    fn default() -> Self {
+        PeriodicSyncOptions {
+            use_periodic_advertiser_list: false,
+            reporting_enabled: true,
+            filter_duplicates: false,
+            skip: 0,
+            sync_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Parameters of an LE Periodic Advertising Sync Established subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeriodicSyncEstablished {
+    pub status: u8,
+    pub sync_handle: u16,
+    /// Advertising set identifier of the periodic advertising
+    pub sid: u8,
+    pub address_type: AddressType,
+    pub address: BdAddr,
+    pub phy: Phy,
+    pub interval: Duration,
+    /// Clock accuracy of the advertiser
+    pub clock_accuracy: u8,
+}
+
+impl PeriodicSyncEstablished {
+    /// Parse the parameters of an LE Periodic Advertising Sync Established subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (sync_handle, _) = r.read_as::<u16>()?;
+        let (sid, _) = r.read_as::<u8>()?;
+        let (address_type, _) = r.read_as::<u8>()?;
+        let (address, _) = r.read_as::<BdAddr>()?;
+        let (phy, _) = r.read_as::<u8>()?;
+        let (interval, _) = r.read_as::<u16>()?;
+        let (clock_accuracy, _) = r.read_as::<u8>()?;
+        Ok(PeriodicSyncEstablished {
+            status,
+            sync_handle,
+            sid,
+            // The advertiser is not meaningful when the sync failed.
+            address_type: AddressType::try_from(address_type).unwrap_or_default(),
+            address,
+            phy: Phy::try_from(phy).unwrap_or_default(),
+            interval: from_units(interval.into(), CONN_INTERVAL_US),
+            clock_accuracy,
+        })
+    }
+}
+
+/// Periodic advertisement received on a sync
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeriodicAdvertisingReport {
+    pub sync_handle: u16,
+    /// Transmit power in dBm, if available
+    pub tx_power: Option<i8>,
+    /// Signal strength in dBm, if available
+    pub rssi: Option<i8>,
+    /// Type of the constant tone extension, if the packet has one
+    pub cte_type: Option<CteType>,
+    pub data_status: DataStatus,
+    /// Raw advertising data
+    pub data: Box<[u8]>,
+    /// Advertising data structures, up to the first malformed structure
+    pub ad: Vec<AdStructure>,
+}
+
+impl PeriodicAdvertisingReport {
+    /// Parse the parameters of an LE Periodic Advertising Report subevent.
+    ///
+    /// Each report holds at most one fragment of an advertisement. Reports with an incomplete
+    /// data status are followed by more fragments of the same advertisement.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (sync_handle, _) = r.read_as::<u16>()?;
+        let (tx_power, _) = r.read_as::<i8>()?;
+        let (rssi, _) = r.read_as::<i8>()?;
+        let (cte_type, _) = r.read_as::<u8>()?;
+        let (data_status, _) = r.read_as::<u8>()?;
+        let (data_len, _) = r.read_as::<u8>()?;
+        let data = r.get(..data_len as usize)
+            .ok_or(MalformedPacket)?;
+        Ok(PeriodicAdvertisingReport {
+            sync_handle,
+            tx_power: (tx_power != POWER_UNAVAILABLE).then_some(tx_power),
+            rssi: (rssi != POWER_UNAVAILABLE).then_some(rssi),
+            cte_type: CteType::try_from(cte_type).ok(),
+            data_status: DataStatus::from(data_status),
+            data: data.into(),
+            ad: decode_ad(data),
+        })
+    }
+
+    /// Append the data of a later fragment of the same advertisement.
+    fn append(&mut self, fragment: PeriodicAdvertisingReport) {
+        let mut data = std::mem::take(&mut self.data).into_vec();
+        data.extend_from_slice(&fragment.data);
+        self.data = data.into_boxed_slice();
+        self.data_status = fragment.data_status;
+        self.rssi = fragment.rssi.or(self.rssi);
+        self.ad = decode_ad(&self.data);
+    }
+}
+
+impl Socket {
+    /// Synchronize to the periodic advertising of an advertising set and wait for the LE
+    /// Periodic Advertising Sync Established event.
+    ///
+    /// The controller only finds the advertiser while extended scanning is enabled.
+    pub fn le_periodic_advertising_create_sync(&mut self, sid: u8, address_type: AddressType, address: BdAddr, options: &PeriodicSyncOptions, timeout: c_int) -> Result<PeriodicSyncEstablished> {
+        let flags = options.use_periodic_advertiser_list as u8
+            | (!options.reporting_enabled as u8) << 1
+            | (options.filter_duplicates as u8) << 2;
+        let mut param = vec![flags, sid, address_type as u8];
+        param.extend_from_slice(address.as_bytes());
+        param.extend_from_slice(&options.skip.to_le_bytes());
+        param.extend_from_slice(&to_units(options.sync_timeout, SYNC_TIMEOUT_US)?.to_le_bytes());
+        // Synchronize regardless of constant tone extensions.
+        param.push(0x00);
+
+        // The command is answered with a Command Status. Sync Established only follows once
+        // the advertiser is found, possibly after many advertising reports.
+        self.send_req(OGF_LE_CTL, OCF_LE_PERIODIC_ADVERTISING_CREATE_SYNC, EVT_CMD_STATUS as c_int, &param, timeout)?;
+        let any_advertiser = options.use_periodic_advertiser_list;
+        let (_, data) = self.wait_le_event(timeout, |subevent, params| {
+            subevent == EVT_LE_PERIODIC_ADVERTISING_SYNC_ESTABLISHED
+                // With the periodic advertiser list, the sync may be with any listed advertiser.
+                // The advertiser of a failed sync is not meaningful.
+                && (any_advertiser
+                    || params.first() != Some(&0)
+                    || (params.get(3) == Some(&sid) && params.get(5..11) == Some(&address.as_bytes()[..])))
+        })?;
+        let established = PeriodicSyncEstablished::parse(&data)?;
+        if established.status != 0 {
+            return Err(StatusError(established.status).into());
+        }
+        Ok(established)
+    }
+
+    /// Cancel a periodic advertising sync in progress.
+    pub fn le_periodic_advertising_create_sync_cancel(&mut self, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_PERIODIC_ADVERTISING_CREATE_SYNC_CANCEL, &[], timeout)
+            .map(|_| ())
+    }
+
+    /// Stop receiving the periodic advertising of a sync.
+    pub fn le_periodic_advertising_terminate_sync(&mut self, sync_handle: u16, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_PERIODIC_ADVERTISING_TERMINATE_SYNC, &sync_handle.to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+
+    /// Turn reporting of the periodic advertisements of a sync on or off.
+    pub fn le_set_periodic_advertising_receive_enable(&mut self, sync_handle: u16, reporting: bool, filter_duplicates: bool, timeout: c_int) -> Result<()> {
+        let mut param = sync_handle.to_le_bytes().to_vec();
+        param.push(reporting as u8 | (filter_duplicates as u8) << 1);
+        self.execute(OGF_LE_CTL, OCF_LE_SET_PERIODIC_ADVERTISING_RECEIVE_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+/// A stream of periodic advertisements from a synchronized advertiser.
+///
+/// While the sync exists, the socket filter only passes LE Meta events. The sync is terminated
+/// and the previous filter is restored when the stream is dropped.
+pub struct PeriodicSync<'a> {
+    pub(super) socket: &'a mut Socket,
+    old_filter: HciFilter,
+    pub(super) timeout: c_int,
+    info: PeriodicSyncEstablished,
+    pending: VecDeque<PeriodicAdvertisingReport>,
+    /// Advertisement waiting for more fragments
+    partial: Option<PeriodicAdvertisingReport>,
+    pub(super) lost: bool,
+}
+
+impl PeriodicSync<'_> {
+    /// Return the parameters the sync was established with
+    pub fn info(&self) -> &PeriodicSyncEstablished {
+        &self.info
+    }
+
+    /// Return the sync handle
+    pub fn handle(&self) -> u16 {
+        self.info.sync_handle
+    }
+
+    /// Turn reporting of periodic advertisements on or off.
+    pub fn set_receive_enable(&mut self, reporting: bool, filter_duplicates: bool) -> Result<()> {
+        self.socket.le_set_periodic_advertising_receive_enable(self.handle(), reporting, filter_duplicates, self.timeout)
+    }
+
+    /// Wait for the next complete or truncated periodic advertisement, joining fragmented data.
+    ///
+    /// Fails with `NotConnected` once the sync is lost.
+    pub fn next_report(&mut self) -> Result<PeriodicAdvertisingReport> {
+        let handle = self.handle().to_le_bytes();
+        loop {
+            if let Some(report) = self.pending.pop_front() {
+                return Ok(report);
+            }
+            if self.lost {
+                return Err(Error::new(NotConnected, "Periodic advertising sync lost"));
+            }
+
+            let (event, data) = self.socket.recv_event(self.timeout)?;
+            if event != EVT_LE_META_EVENT {
+                continue;
+            }
+            let Some((&subevent, params)) = data.split_first() else {
+                continue;
+            };
+            if params.get(0..2) != Some(&handle) {
+                continue;
+            }
+            match subevent {
+                EVT_LE_PERIODIC_ADVERTISING_SYNC_LOST => self.lost = true,
+                EVT_LE_PERIODIC_ADVERTISING_REPORT => {
+                    let fragment = PeriodicAdvertisingReport::parse(params)?;
+                    let report = match self.partial.take() {
+                        Some(mut report) => {
+                            report.append(fragment);
+                            report
+                        },
+                        None => fragment,
+                    };
+                    if report.data_status == DataStatus::Incomplete {
+                        self.partial = Some(report);
+                    } else {
+                        self.pending.push_back(report);
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Iterate over periodic advertisements until an error occurs, such as a timeout or the loss
+/// of the sync.
+impl Iterator for PeriodicSync<'_> {
+    type Item = PeriodicAdvertisingReport;
+
+    fn next(&mut self) -> Option<PeriodicAdvertisingReport> {
+        self.next_report().ok()
+    }
+}
+
+impl Drop for PeriodicSync<'_> {
+    fn drop(&mut self) {
+        if !self.lost {
+            let _ = self.socket.le_periodic_advertising_terminate_sync(self.handle(), self.timeout);
+        }
+        let _ = self.socket.set_filter(&self.old_filter);
+    }
+}
+
+impl Adapter {
+    /// Synchronize to the periodic advertising of an advertising set.
+    ///
+    /// Extended scanning must be enabled, such as with `Socket::le_set_extended_scan_enable`,
+    /// until the sync is established. Waits up to the adapter timeout for the sync.
+    pub fn le_periodic_sync(&mut self, sid: u8, address_type: AddressType, address: BdAddr, options: &PeriodicSyncOptions) -> Result<PeriodicSync<'_>> {
+        let timeout = self.timeout;
+        let socket = &mut self.socket;
+
+        // Set the filter first so that no report after the sync is established is dropped.
+        let old_filter = socket.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_LE_META_EVENT)?;
+        socket.set_filter(&filter)?;
+
+        let info = match socket.le_periodic_advertising_create_sync(sid, address_type, address, options, timeout) {
+            Ok(info) => info,
+            Err(err) => {
+                let _ = socket.le_periodic_advertising_create_sync_cancel(timeout);
+                socket.set_filter(&old_filter)?;
+                return Err(err);
+            },
+        };
+
+        Ok(PeriodicSync {
+            socket,
+            old_filter,
+            timeout,
+            info,
+            pending: VecDeque::new(),
+            partial: None,
+            lost: false,
+        })
+    }
+}
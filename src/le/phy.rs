@@ -0,0 +1,133 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidData};
+
+use crate::connection::Connection;
+use crate::error::StatusError;
+use crate::io::ReadAs;
+use crate::socket::Socket;
+use super::OGF_LE_CTL;
+
+const OCF_LE_READ_PHY: u16 = 0x0030;
+const OCF_LE_SET_DEFAULT_PHY: u16 = 0x0031;
+const OCF_LE_SET_PHY: u16 = 0x0032;
+
+const EVT_LE_PHY_UPDATE_COMPLETE: u8 = 0x0C;
+
+/// LE physical layer
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Phy {
+    #[default]
+    Le1M = 0x01,
+    Le2M = 0x02,
+    /// Long range PHY with forward error correction
+    LeCoded = 0x03,
+}
+
+impl TryFrom<u8> for Phy {
+    type Error = Error;
+
+    fn try_from(phy: u8) -> Result<Self> {
+        match phy {
+            0x01 => Ok(Phy::Le1M),
+            0x02 => Ok(Phy::Le2M),
+            0x03 => Ok(Phy::LeCoded),
+            _ => Err(Error::new(InvalidData, "Unknown PHY")),
+        }
+    }
+}
+
+bitflags! {
+    /// Set of LE physical layers
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct PhyMask: u8 {
+        const LE_1M = 1 << 0;
+        const LE_2M = 1 << 1;
+        const LE_CODED = 1 << 2;
+    }
+}
+
+/// Coding preferred when transmitting on the coded PHY
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CodedPhyOptions {
+    #[default]
+    NoPreference = 0x00,
+    /// Two symbols per bit
+    S2 = 0x01,
+    /// Eight symbols per bit, for the longest range
+    S8 = 0x02,
+}
+
+/// Encode PHY preferences, where `None` means no preference.
+fn phy_preferences(tx: Option<PhyMask>, rx: Option<PhyMask>) -> [u8; 3] {
+    let all_phys = tx.is_none() as u8 | (rx.is_none() as u8) << 1;
+    [all_phys, tx.map_or(0, |tx| tx.bits()), rx.map_or(0, |rx| rx.bits())]
+}
+
+/// Parameters of an LE PHY Update Complete subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PhyUpdate {
+    pub status: u8,
+    pub handle: u16,
+    pub tx: Phy,
+    pub rx: Phy,
+}
+
+impl PhyUpdate {
+    /// Parse the parameters of an LE PHY Update Complete subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (tx, _) = r.read_as::<u8>()?;
+        let (rx, _) = r.read_as::<u8>()?;
+        // The PHYs are not meaningful when the update failed.
+        let (tx, rx) = if status == 0 {
+            (Phy::try_from(tx)?, Phy::try_from(rx)?)
+        } else {
+            (Phy::default(), Phy::default())
+        };
+        Ok(PhyUpdate { status, handle, tx, rx })
+    }
+}
+
+impl Socket {
+    /// Set the PHYs preferred for new connections.
+    ///
+    /// `None` leaves the choice of PHY in that direction to the controller.
+    pub fn le_set_default_phy(&mut self, tx: Option<PhyMask>, rx: Option<PhyMask>, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_DEFAULT_PHY, &phy_preferences(tx, rx), timeout)
+            .map(|_| ())
+    }
+}
+
+impl Connection<'_> {
+    /// Read the transmitter and receiver PHYs of this link.
+    pub fn le_read_phy(&mut self, timeout: c_int) -> Result<(Phy, Phy)> {
+        let data = self.socket.execute(OGF_LE_CTL, OCF_LE_READ_PHY, &self.handle().to_le_bytes(), timeout)?;
+        let mut r = &*data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (tx, _) = r.read_as::<u8>()?;
+        let (rx, _) = r.read_as::<u8>()?;
+        Ok((Phy::try_from(tx)?, Phy::try_from(rx)?))
+    }
+
+    /// Request a change of PHYs on this link and wait for the LE PHY Update Complete event.
+    ///
+    /// `None` leaves the choice of PHY in that direction to the controller.
+    pub fn le_set_phy(&mut self, tx: Option<PhyMask>, rx: Option<PhyMask>, options: CodedPhyOptions, timeout: c_int) -> Result<PhyUpdate> {
+        let handle = self.handle().to_le_bytes();
+        let mut param = handle.to_vec();
+        param.extend_from_slice(&phy_preferences(tx, rx));
+        param.extend_from_slice(&(options as u16).to_le_bytes());
+
+        let (_, data) = self.socket.le_request(OCF_LE_SET_PHY, &param, timeout,
+            |subevent, params| subevent == EVT_LE_PHY_UPDATE_COMPLETE && params.get(1..3) == Some(&handle))?;
+        let update = PhyUpdate::parse(&data)?;
+        if update.status != 0 {
+            return Err(StatusError(update.status).into());
+        }
+        Ok(update)
+    }
+}
@@ -0,0 +1,293 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+
+use crate::connection::Connection;
+use crate::error::StatusError;
+use crate::io::ReadAs;
+use crate::socket::Socket;
+use super::OGF_LE_CTL;
+
+const OCF_LE_READ_RF_PATH_COMPENSATION: u16 = 0x004C;
+const OCF_LE_WRITE_RF_PATH_COMPENSATION: u16 = 0x004D;
+const OCF_LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL: u16 = 0x0076;
+const OCF_LE_READ_REMOTE_TRANSMIT_POWER_LEVEL: u16 = 0x0077;
+const OCF_LE_SET_PATH_LOSS_REPORTING_PARAMETERS: u16 = 0x0078;
+const OCF_LE_SET_PATH_LOSS_REPORTING_ENABLE: u16 = 0x0079;
+const OCF_LE_SET_TRANSMIT_POWER_REPORTING_ENABLE: u16 = 0x007A;
+
+/// RF path compensations the controller accepts, -128.0 to 128.0 dB in units of 0.1 dB
+const RF_PATH_COMPENSATION_RANGE: std::ops::RangeInclusive<i16> = -1280..=1280;
+
+pub(crate) const EVT_LE_PATH_LOSS_THRESHOLD: u8 = 0x20;
+pub(crate) const EVT_LE_TRANSMIT_POWER_REPORTING: u8 = 0x21;
+
+/// Transmit power or delta reported when it is not available
+const POWER_UNAVAILABLE: i8 = 127;
+/// Path loss reported when it is not available
+const PATH_LOSS_UNAVAILABLE: u8 = 0xFF;
+
+/// PHY and coding a transmit power level applies to
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PowerControlPhy {
+    #[default]
+    Le1M = 0x01,
+    Le2M = 0x02,
+    /// LE Coded with eight symbols per bit
+    LeCodedS8 = 0x03,
+    /// LE Coded with two symbols per bit
+    LeCodedS2 = 0x04,
+}
+
+impl TryFrom<u8> for PowerControlPhy {
+    type Error = Error;
+
+    fn try_from(phy: u8) -> Result<Self> {
+        match phy {
+            0x01 => Ok(PowerControlPhy::Le1M),
+            0x02 => Ok(PowerControlPhy::Le2M),
+            0x03 => Ok(PowerControlPhy::LeCodedS8),
+            0x04 => Ok(PowerControlPhy::LeCodedS2),
+            _ => Err(Error::new(InvalidData, "Unknown PHY")),
+        }
+    }
+}
+
+/// Why a transmit power level was reported
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PowerReportReason {
+    /// The local transmit power changed
+    LocalChange = 0x00,
+    /// The remote transmit power changed
+    RemoteChange = 0x01,
+    /// A read of the remote transmit power completed
+    ReadRemoteComplete = 0x02,
+}
+
+impl TryFrom<u8> for PowerReportReason {
+    type Error = Error;
+
+    fn try_from(reason: u8) -> Result<Self> {
+        match reason {
+            0x00 => Ok(PowerReportReason::LocalChange),
+            0x01 => Ok(PowerReportReason::RemoteChange),
+            0x02 => Ok(PowerReportReason::ReadRemoteComplete),
+            _ => Err(Error::new(InvalidData, "Unknown power report reason")),
+        }
+    }
+}
+
+/// Parameters of an LE Transmit Power Reporting subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TransmitPowerReport {
+    pub status: u8,
+    pub handle: u16,
+    pub reason: PowerReportReason,
+    pub phy: PowerControlPhy,
+    /// Transmit power in dBm, if available
+    ///
+    /// A value of 126 means the remote device is not managing its power levels on this PHY.
+    pub tx_power: Option<i8>,
+    /// Whether the transmit power is at the minimum level
+    pub at_min: bool,
+    /// Whether the transmit power is at the maximum level
+    pub at_max: bool,
+    /// Change in transmit power in dB, if available
+    pub delta: Option<i8>,
+}
+
+impl TransmitPowerReport {
+    /// Parse the parameters of an LE Transmit Power Reporting subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (reason, _) = r.read_as::<u8>()?;
+        let (phy, _) = r.read_as::<u8>()?;
+        let (tx_power, _) = r.read_as::<i8>()?;
+        let (flags, _) = r.read_as::<u8>()?;
+        let (delta, _) = r.read_as::<i8>()?;
+        Ok(TransmitPowerReport {
+            status,
+            handle,
+            reason: PowerReportReason::try_from(reason)?,
+            // The PHY is not meaningful when the read failed.
+            phy: PowerControlPhy::try_from(phy).unwrap_or_default(),
+            tx_power: (tx_power != POWER_UNAVAILABLE).then_some(tx_power),
+            at_min: flags & 0x01 != 0,
+            at_max: flags & 0x02 != 0,
+            delta: (delta != POWER_UNAVAILABLE).then_some(delta),
+        })
+    }
+}
+
+impl Connection<'_> {
+    /// Read the current and maximum transmit power of the local controller on this link, in
+    /// dBm.
+    pub fn le_enhanced_read_tx_power(&mut self, phy: PowerControlPhy, timeout: c_int) -> Result<(i8, i8)> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.push(phy as u8);
+        let data = self.socket.execute(OGF_LE_CTL, OCF_LE_ENHANCED_READ_TRANSMIT_POWER_LEVEL, &param, timeout)?;
+        let mut r = &*data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (_phy, _) = r.read_as::<u8>()?;
+        let (current, _) = r.read_as::<i8>()?;
+        let (max, _) = r.read_as::<i8>()?;
+        Ok((current, max))
+    }
+
+    /// Read the transmit power of the remote device on this link and wait for the report.
+    pub fn le_read_remote_tx_power(&mut self, phy: PowerControlPhy, timeout: c_int) -> Result<TransmitPowerReport> {
+        let handle = self.handle().to_le_bytes();
+        let mut param = handle.to_vec();
+        param.push(phy as u8);
+
+        let (_, data) = self.socket.le_request(OCF_LE_READ_REMOTE_TRANSMIT_POWER_LEVEL, &param, timeout,
+            |subevent, params| subevent == EVT_LE_TRANSMIT_POWER_REPORTING
+                && params.get(1..3) == Some(&handle)
+                && params.get(3) == Some(&(PowerReportReason::ReadRemoteComplete as u8)))?;
+        let report = TransmitPowerReport::parse(&data)?;
+        if report.status != 0 {
+            return Err(StatusError(report.status).into());
+        }
+        Ok(report)
+    }
+
+    /// Turn reporting of changes in the local and remote transmit power on this link on or off.
+    pub fn le_set_tx_power_reporting(&mut self, local: bool, remote: bool, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&[local as u8, remote as u8]);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_SET_TRANSMIT_POWER_REPORTING_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Wait for a change in the local or remote transmit power on this link.
+    pub fn le_wait_tx_power_report(&mut self, timeout: c_int) -> Result<TransmitPowerReport> {
+        let handle = self.handle().to_le_bytes();
+        let (_, data) = self.socket.wait_le_event(timeout,
+            |subevent, params| subevent == EVT_LE_TRANSMIT_POWER_REPORTING && params.get(1..3) == Some(&handle))?;
+        TransmitPowerReport::parse(&data)
+    }
+}
+
+// Path loss monitoring
+
+/// Thresholds dividing path loss into low, middle and high zones, in dB
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathLossParams {
+    pub high_threshold: u8,
+    pub high_hysteresis: u8,
+    pub low_threshold: u8,
+    pub low_hysteresis: u8,
+    /// Number of connection events the path loss must stay in a new zone before it is reported
+    pub min_time_spent: u16,
+}
+
+/// Range of path loss on a link
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathLossZone {
+    Low = 0x00,
+    Middle = 0x01,
+    High = 0x02,
+}
+
+impl TryFrom<u8> for PathLossZone {
+    type Error = Error;
+
+    fn try_from(zone: u8) -> Result<Self> {
+        match zone {
+            0x00 => Ok(PathLossZone::Low),
+            0x01 => Ok(PathLossZone::Middle),
+            0x02 => Ok(PathLossZone::High),
+            _ => Err(Error::new(InvalidData, "Unknown path loss zone")),
+        }
+    }
+}
+
+/// Parameters of an LE Path Loss Threshold subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PathLossThreshold {
+    pub handle: u16,
+    /// Path loss in dB, if available
+    pub path_loss: Option<u8>,
+    /// Zone the path loss entered
+    pub zone: PathLossZone,
+}
+
+impl PathLossThreshold {
+    /// Parse the parameters of an LE Path Loss Threshold subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (path_loss, _) = r.read_as::<u8>()?;
+        let (zone, _) = r.read_as::<u8>()?;
+        Ok(PathLossThreshold {
+            handle,
+            path_loss: (path_loss != PATH_LOSS_UNAVAILABLE).then_some(path_loss),
+            zone: PathLossZone::try_from(zone)?,
+        })
+    }
+}
+
+impl Connection<'_> {
+    /// Set the path loss zones of this link.
+    pub fn le_set_path_loss_reporting_parameters(&mut self, params: &PathLossParams, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&[params.high_threshold, params.high_hysteresis, params.low_threshold, params.low_hysteresis]);
+        param.extend_from_slice(&params.min_time_spent.to_le_bytes());
+        self.socket.execute(OGF_LE_CTL, OCF_LE_SET_PATH_LOSS_REPORTING_PARAMETERS, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Turn reporting of path loss zone changes on this link on or off.
+    pub fn le_set_path_loss_reporting_enable(&mut self, enable: bool, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.push(enable as u8);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_SET_PATH_LOSS_REPORTING_ENABLE, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Wait for the path loss of this link to enter a new zone.
+    pub fn le_wait_path_loss_threshold(&mut self, timeout: c_int) -> Result<PathLossThreshold> {
+        let handle = self.handle().to_le_bytes();
+        let (_, data) = self.socket.wait_le_event(timeout,
+            |subevent, params| subevent == EVT_LE_PATH_LOSS_THRESHOLD && params.get(0..2) == Some(&handle))?;
+        PathLossThreshold::parse(&data)
+    }
+}
+
+// RF path compensation
+
+/// Gain or loss between the controller and the antenna, in units of 0.1 dB
+///
+/// Positive values are gains. The controller adds the compensation to the power levels it
+/// reports.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RfPathCompensation {
+    pub tx: i16,
+    pub rx: i16,
+}
+
+impl Socket {
+    /// Read the RF path compensation of the local controller.
+    pub fn le_read_rf_path_compensation(&mut self, timeout: c_int) -> Result<RfPathCompensation> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_RF_PATH_COMPENSATION, &[], timeout)?;
+        let mut r = &*data;
+        let (tx, _) = r.read_as::<i16>()?;
+        let (rx, _) = r.read_as::<i16>()?;
+        Ok(RfPathCompensation { tx, rx })
+    }
+
+    /// Set the RF path compensation of the local controller.
+    ///
+    /// Each value must be between -128.0 and 128.0 dB, or the call fails with `InvalidInput`.
+    pub fn le_write_rf_path_compensation(&mut self, compensation: RfPathCompensation, timeout: c_int) -> Result<()> {
+        if !RF_PATH_COMPENSATION_RANGE.contains(&compensation.tx) || !RF_PATH_COMPENSATION_RANGE.contains(&compensation.rx) {
+            return Err(Error::new(InvalidInput, "RF path compensation out of range"));
+        }
+        let mut param = compensation.tx.to_le_bytes().to_vec();
+        param.extend_from_slice(&compensation.rx.to_le_bytes());
+        self.execute(OGF_LE_CTL, OCF_LE_WRITE_RF_PATH_COMPENSATION, &param, timeout)
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,145 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use crate::addr::BdAddr;
+use crate::io::ReadAs;
+use crate::socket::Socket;
+use super::OGF_LE_CTL;
+
+const OCF_LE_ADD_DEVICE_TO_RESOLVING_LIST: u16 = 0x0027;
+const OCF_LE_REMOVE_DEVICE_FROM_RESOLVING_LIST: u16 = 0x0028;
+const OCF_LE_CLEAR_RESOLVING_LIST: u16 = 0x0029;
+const OCF_LE_READ_RESOLVING_LIST_SIZE: u16 = 0x002A;
+const OCF_LE_READ_PEER_RESOLVABLE_ADDRESS: u16 = 0x002B;
+const OCF_LE_READ_LOCAL_RESOLVABLE_ADDRESS: u16 = 0x002C;
+const OCF_LE_SET_ADDRESS_RESOLUTION_ENABLE: u16 = 0x002D;
+const OCF_LE_SET_RESOLVABLE_PRIVATE_ADDRESS_TIMEOUT: u16 = 0x002E;
+const OCF_LE_SET_PRIVACY_MODE: u16 = 0x004E;
+
+/// Longest resolvable private address timeout, in seconds
+const MAX_RPA_TIMEOUT_S: u64 = 0xA1B8;
+
+/// Identity address of a device using resolvable private addresses
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IdentityAddress {
+    Public(BdAddr),
+    /// Random static address
+    Random(BdAddr),
+}
+
+impl IdentityAddress {
+    /// Encode the identity as an address type followed by an address
+    fn bytes(&self) -> [u8; 7] {
+        let (address_type, addr) = match self {
+            IdentityAddress::Public(addr) => (0x00, addr),
+            IdentityAddress::Random(addr) => (0x01, addr),
+        };
+        let mut bytes = [address_type, 0, 0, 0, 0, 0, 0];
+        bytes[1..].copy_from_slice(addr.as_bytes());
+        bytes
+    }
+
+    /// Address of the identity
+    pub fn address(&self) -> BdAddr {
+        match self {
+            IdentityAddress::Public(addr) | IdentityAddress::Random(addr) => *addr,
+        }
+    }
+}
+
+/// Device in the resolving list
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResolvingListEntry {
+    pub identity: IdentityAddress,
+    /// Identity resolving key of the peer, or all zeros if the peer uses its identity address
+    pub peer_irk: [u8; 16],
+    /// Identity resolving key used to generate local addresses for this peer
+    pub local_irk: [u8; 16],
+}
+
+impl Socket {
+    /// Add a device to the resolving list.
+    pub fn le_add_device_to_resolving_list(&mut self, entry: &ResolvingListEntry, timeout: c_int) -> Result<()> {
+        let mut param = entry.identity.bytes().to_vec();
+        param.extend_from_slice(&entry.peer_irk);
+        param.extend_from_slice(&entry.local_irk);
+        self.execute(OGF_LE_CTL, OCF_LE_ADD_DEVICE_TO_RESOLVING_LIST, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Remove a device from the resolving list.
+    pub fn le_remove_device_from_resolving_list(&mut self, identity: IdentityAddress, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_REMOVE_DEVICE_FROM_RESOLVING_LIST, &identity.bytes(), timeout)
+            .map(|_| ())
+    }
+
+    /// Remove all devices from the resolving list.
+    pub fn le_clear_resolving_list(&mut self, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_CLEAR_RESOLVING_LIST, &[], timeout)
+            .map(|_| ())
+    }
+
+    /// Read the number of entries the resolving list can hold.
+    pub fn le_read_resolving_list_size(&mut self, timeout: c_int) -> Result<usize> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_RESOLVING_LIST_SIZE, &[], timeout)?;
+        let (size, _) = (&*data).read_as::<u8>()?;
+        Ok(size.into())
+    }
+
+    /// Read the resolvable private address a peer in the resolving list currently uses.
+    pub fn le_read_peer_resolvable_address(&mut self, identity: IdentityAddress, timeout: c_int) -> Result<BdAddr> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_PEER_RESOLVABLE_ADDRESS, &identity.bytes(), timeout)?;
+        let (addr, _) = (&*data).read_as::<BdAddr>()?;
+        Ok(addr)
+    }
+
+    /// Read the resolvable private address the local controller currently uses for a peer in
+    /// the resolving list.
+    pub fn le_read_local_resolvable_address(&mut self, identity: IdentityAddress, timeout: c_int) -> Result<BdAddr> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_READ_LOCAL_RESOLVABLE_ADDRESS, &identity.bytes(), timeout)?;
+        let (addr, _) = (&*data).read_as::<BdAddr>()?;
+        Ok(addr)
+    }
+
+    /// Enable or disable resolution of private addresses in the controller.
+    pub fn le_set_address_resolution_enable(&mut self, enable: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_ADDRESS_RESOLUTION_ENABLE, &[enable.into()], timeout)
+            .map(|_| ())
+    }
+
+    /// Set how long the controller uses a resolvable private address before generating a new
+    /// one.
+    ///
+    /// The timeout is rounded down to whole seconds and must be between 1 second and about
+    /// 11.5 hours.
+    pub fn le_set_resolvable_private_address_timeout(&mut self, rpa_timeout: Duration, timeout: c_int) -> Result<()> {
+        let seconds = rpa_timeout.as_secs();
+        if !(1..=MAX_RPA_TIMEOUT_S).contains(&seconds) {
+            return Err(Error::new(InvalidInput, "Resolvable private address timeout out of range"));
+        }
+        self.execute(OGF_LE_CTL, OCF_LE_SET_RESOLVABLE_PRIVATE_ADDRESS_TIMEOUT, &(seconds as u16).to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+}
+
+/// How strictly the controller enforces privacy for a peer in the resolving list
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PrivacyMode {
+    /// Only accept resolvable private addresses from the peer
+    #[default]
+    Network = 0x00,
+    /// Also accept the peer's identity address
+    Device = 0x01,
+}
+
+impl Socket {
+    /// Set the privacy mode used for a peer in the resolving list.
+    pub fn le_set_privacy_mode(&mut self, peer: IdentityAddress, mode: PrivacyMode, timeout: c_int) -> Result<()> {
+        let mut param = peer.bytes().to_vec();
+        param.push(mode as u8);
+        self.execute(OGF_LE_CTL, OCF_LE_SET_PRIVACY_MODE, &param, timeout)
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, Read, Result};
+use std::io::ErrorKind::{InvalidData};
+use std::time::{Duration, Instant};
+
+use crate::ad::{self, AdStructure};
+use crate::addr::{AddressType, BdAddr};
+use crate::io::{read_bytes, ReadAs, ReadFrom};
+use super::scan::LeScan;
+
+pub(crate) const EVT_LE_ADVERTISING_REPORT: u8 = 0x02;
+
+/// RSSI value reported when the controller could not measure the signal strength
+const RSSI_UNAVAILABLE: i8 = 127;
+
+/// Type of a legacy advertising packet
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AdvertisingEventType {
+    /// Connectable and scannable undirected advertising (ADV_IND)
+    ConnectableUndirected = 0x00,
+    /// Connectable directed advertising (ADV_DIRECT_IND)
+    ConnectableDirected = 0x01,
+    /// Scannable undirected advertising (ADV_SCAN_IND)
+    ScannableUndirected = 0x02,
+    /// Non-connectable undirected advertising (ADV_NONCONN_IND)
+    NonConnectableUndirected = 0x03,
+    /// Scan response (SCAN_RSP)
+    ScanResponse = 0x04,
+}
+
+impl TryFrom<u8> for AdvertisingEventType {
+    type Error = Error;
+
+    fn try_from(event_type: u8) -> Result<Self> {
+        match event_type {
+            0x00 => Ok(AdvertisingEventType::ConnectableUndirected),
+            0x01 => Ok(AdvertisingEventType::ConnectableDirected),
+            0x02 => Ok(AdvertisingEventType::ScannableUndirected),
+            0x03 => Ok(AdvertisingEventType::NonConnectableUndirected),
+            0x04 => Ok(AdvertisingEventType::ScanResponse),
+            _ => Err(Error::new(InvalidData, "Unknown advertising event type")),
+        }
+    }
+}
+
+/// Advertising packet received while scanning
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdvertisingReport {
+    pub event_type: AdvertisingEventType,
+    pub address_type: AddressType,
+    pub address: BdAddr,
+    /// Signal strength in dBm, if available
+    pub rssi: Option<i8>,
+    /// Raw advertising data
+    pub data: Box<[u8]>,
+    /// Advertising data structures, up to the first malformed structure
+    pub ad: Vec<AdStructure>,
+}
+
+impl ReadFrom for AdvertisingReport {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (event_type, _) = r.read_as::<u8>()?;
+        let (address_type, _) = r.read_as::<AddressType>()?;
+        let (address, _) = r.read_as::<BdAddr>()?;
+        let (data_len, _) = r.read_as::<u8>()?;
+        let mut data = vec![0u8; data_len as usize];
+        read_bytes(&mut r, &mut data)?;
+        let (rssi, _) = r.read_as::<u8>()?;
+
+        let ad = ad::structures(&data)
+            .map_while(|structure| structure.ok())
+            .filter_map(|(ad_type, data)| AdStructure::decode(ad_type, data).ok())
+            .collect();
+        let rssi = rssi as i8;
+
+        Ok((AdvertisingReport {
+            event_type: AdvertisingEventType::try_from(event_type)?,
+            address_type,
+            address,
+            rssi: (rssi != RSSI_UNAVAILABLE).then_some(rssi),
+            data: data.into_boxed_slice(),
+            ad,
+        }, 11 + data_len as usize))
+    }
+}
+
+/// Parse the parameters of an LE Advertising Report subevent.
+pub fn parse_advertising_reports(params: &[u8]) -> Result<Vec<AdvertisingReport>> {
+    let mut r = params;
+    let (num_reports, _) = r.read_as::<u8>()?;
+    (0..num_reports).map(|_| r.read_as::<AdvertisingReport>().map(|(report, _)| report)).collect()
+}
+
+/// Everything learned about one advertiser during a scan
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScannedDevice {
+    pub address_type: AddressType,
+    pub address: BdAddr,
+    /// Most recent signal strength in dBm
+    pub rssi: Option<i8>,
+    /// Most recent advertising data
+    pub advertising_data: Vec<AdStructure>,
+    /// Most recent scan response data
+    pub scan_response: Vec<AdStructure>,
+    /// Number of reports received from the device
+    pub reports: usize,
+    pub last_seen: Instant,
+}
+
+impl ScannedDevice {
+    /// Iterate over advertising and scan response structures together
+    pub fn ad(&self) -> impl Iterator<Item = &AdStructure> {
+        self.advertising_data.iter().chain(self.scan_response.iter())
+    }
+}
+
+/// Advertiser and report kind, with scan responses tracked separately from advertisements
+type ReportKey = (BdAddr, AddressType, bool);
+
+/// Deduplication and aggregation of advertising reports
+#[derive(Default)]
+pub(crate) struct ReportCache {
+    /// Window in which identical reports are dropped, if deduplication is on
+    pub(crate) dedup: Option<Duration>,
+    pub(crate) aggregate: bool,
+    /// Data and arrival time of the last report of each type per advertiser
+    seen: HashMap<ReportKey, (Box<[u8]>, Instant)>,
+    pub(crate) devices: HashMap<(BdAddr, AddressType), ScannedDevice>,
+}
+
+impl ReportCache {
+    /// Record a report, returning whether it should be delivered.
+    pub(crate) fn accept(&mut self, report: &AdvertisingReport, now: Instant) -> bool {
+        let scan_response = report.event_type == AdvertisingEventType::ScanResponse;
+
+        if self.aggregate {
+            let device = self.devices.entry((report.address, report.address_type))
+                .or_insert_with(|| ScannedDevice {
+                    address_type: report.address_type,
+                    address: report.address,
+                    rssi: None,
+                    advertising_data: Vec::new(),
+                    scan_response: Vec::new(),
+                    reports: 0,
+                    last_seen: now,
+                });
+            device.rssi = report.rssi.or(device.rssi);
+            if scan_response {
+                device.scan_response = report.ad.clone();
+            } else {
+                device.advertising_data = report.ad.clone();
+            }
+            device.reports += 1;
+            device.last_seen = now;
+        }
+
+        let Some(window) = self.dedup else {
+            return true;
+        };
+        let key = (report.address, report.address_type, scan_response);
+        let duplicate = self.seen.get(&key)
+            .is_some_and(|(data, time)| *data == report.data && now.duration_since(*time) < window);
+        if !duplicate {
+            self.seen.insert(key, (report.data.clone(), now));
+        }
+        !duplicate
+    }
+}
+
+impl LeScan<'_> {
+    /// Drop reports identical to one received from the same advertiser within `window`.
+    pub fn dedup(&mut self, window: Duration) -> &mut Self {
+        self.cache.dedup = Some(window);
+        self
+    }
+
+    /// Keep track of every advertiser seen during the scan, retrievable with `devices`.
+    pub fn aggregate(&mut self) -> &mut Self {
+        self.cache.aggregate = true;
+        self
+    }
+
+    /// Return the advertisers seen so far, if aggregation is on
+    pub fn devices(&self) -> impl Iterator<Item = &ScannedDevice> {
+        self.cache.devices.values()
+    }
+
+    /// Wait for the next advertising report that passes deduplication.
+    pub fn next_report(&mut self) -> Result<AdvertisingReport> {
+        loop {
+            if let Some(report) = self.pending.pop_front() {
+                return Ok(report);
+            }
+
+            let (subevent, params) = self.next_event()?;
+            if subevent != EVT_LE_ADVERTISING_REPORT {
+                continue;
+            }
+            let now = Instant::now();
+            let reports = parse_advertising_reports(&params)?;
+            let cache = &mut self.cache;
+            self.pending.extend(reports.into_iter().filter(|report| cache.accept(report, now)));
+        }
+    }
+}
+
+/// Iterate over advertising reports until an error occurs, such as a timeout.
+impl Iterator for LeScan<'_> {
+    type Item = AdvertisingReport;
+
+    fn next(&mut self) -> Option<AdvertisingReport> {
+        self.next_report().ok()
+    }
+}
+
+/// Reports waiting to be returned by a scan
+pub(crate) type PendingReports = VecDeque<AdvertisingReport>;
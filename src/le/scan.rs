@@ -0,0 +1,149 @@
+use libc::{c_int};
+use std::io::{Result, Write};
+use std::time::Duration;
+
+use crate::adapter::Adapter;
+use crate::addr::OwnAddressType;
+use crate::filter::HciFilter;
+use crate::io::{WriteAs, WriteTo};
+use crate::socket::{HCI_EVENT_PKT, Socket};
+use crate::units::{to_units, SLOT_US};
+use super::{EVT_LE_META_EVENT, OGF_LE_CTL};
+use super::report::{PendingReports, ReportCache};
+
+const OCF_LE_SET_SCAN_PARAMETERS: u16 = 0x000B;
+const OCF_LE_SET_SCAN_ENABLE: u16 = 0x000C;
+
+/// Whether the scanner sends scan requests
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ScanType {
+    #[default]
+    Passive = 0x00,
+    Active = 0x01,
+}
+
+/// Which advertising packets the scanner reports
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ScanFilterPolicy {
+    /// Report all advertising packets
+    #[default]
+    Basic = 0x00,
+    /// Only report packets from devices in the filter accept list
+    FilterAcceptList = 0x01,
+    /// Report all packets, including directed packets to a resolvable private address
+    BasicExtended = 0x02,
+    /// Only report packets from devices in the filter accept list, including directed packets
+    /// to a resolvable private address
+    FilterAcceptListExtended = 0x03,
+}
+
+/// Options of an LE scan
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub scan_type: ScanType,
+    pub interval: Duration,
+    pub window: Duration,
+    pub own_address_type: OwnAddressType,
+    pub filter_policy: ScanFilterPolicy,
+    /// Whether the controller drops duplicate reports
+    pub filter_duplicates: bool,
+}
+
+impl Default for ScanOptions {
+    /// The parameters used by the controller after a reset
+    fn default() -> Self {
+        ScanOptions {
+            scan_type: ScanType::default(),
+            interval: Duration::from_millis(10),
+            window: Duration::from_millis(10),
+            own_address_type: OwnAddressType::default(),
+            filter_policy: ScanFilterPolicy::default(),
+            filter_duplicates: false,
+        }
+    }
+}
+
+impl WriteTo for &ScanOptions {
+    /// Write the parameters of LE Set Scan Parameters.
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(self.scan_type as u8)?
+            + w.write_as(to_units(self.interval, SLOT_US)?)?
+            + w.write_as(to_units(self.window, SLOT_US)?)?
+            + w.write_as(self.own_address_type as u8)?
+            + w.write_as(self.filter_policy as u8)?)
+    }
+}
+
+impl Socket {
+    /// Set the parameters of LE scanning.
+    pub fn le_set_scan_parameters(&mut self, options: &ScanOptions, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_SCAN_PARAMETERS, &options.bytes()?, timeout)
+            .map(|_| ())
+    }
+
+    /// Start or stop LE scanning.
+    pub fn le_set_scan_enable(&mut self, enable: bool, filter_duplicates: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LE_CTL, OCF_LE_SET_SCAN_ENABLE, &[enable as u8, filter_duplicates as u8], timeout)
+            .map(|_| ())
+    }
+}
+
+/// A running LE scan.
+///
+/// While the scan runs, the socket filter only passes LE Meta events. Scanning stops and the
+/// previous filter is restored when the scan is dropped.
+pub struct LeScan<'a> {
+    socket: &'a mut Socket,
+    old_filter: HciFilter,
+    timeout: c_int,
+    pub(super) pending: PendingReports,
+    pub(super) cache: ReportCache,
+}
+
+impl LeScan<'_> {
+    /// Wait for the next LE Meta event, returning its subevent code and parameters.
+    pub fn next_event(&mut self) -> Result<(u8, Box<[u8]>)> {
+        loop {
+            let (event, data) = self.socket.recv_event(self.timeout)?;
+            if event != EVT_LE_META_EVENT {
+                continue;
+            }
+            if let Some((&subevent, params)) = data.split_first() {
+                return Ok((subevent, params.into()));
+            }
+        }
+    }
+}
+
+impl Drop for LeScan<'_> {
+    fn drop(&mut self) {
+        let _ = self.socket.le_set_scan_enable(false, false, self.timeout);
+        let _ = self.socket.set_filter(&self.old_filter);
+    }
+}
+
+impl Adapter {
+    /// Start an LE scan.
+    pub fn le_scan(&mut self, options: &ScanOptions) -> Result<LeScan<'_>> {
+        let timeout = self.timeout;
+        let socket = &mut self.socket;
+
+        socket.le_set_scan_parameters(options, timeout)?;
+
+        let old_filter = socket.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_LE_META_EVENT)?;
+        socket.set_filter(&filter)?;
+
+        let scan = LeScan {
+            socket,
+            old_filter,
+            timeout,
+            pending: PendingReports::new(),
+            cache: ReportCache::default(),
+        };
+        scan.socket.le_set_scan_enable(true, options.filter_duplicates, timeout)?;
+        Ok(scan)
+    }
+}
@@ -0,0 +1,168 @@
+use libc::{c_int};
+use std::io::{Error, Result};
+use std::io::ErrorKind::{InvalidData};
+
+use crate::codec::{self, EventBody};
+use crate::connection::Connection;
+use crate::error::StatusError;
+use crate::filter::HciFilter;
+use crate::io::ReadAs;
+use crate::socket::{EVT_CMD_STATUS, HCI_EVENT_PKT, Socket};
+use super::OGF_LE_CTL;
+
+const OCF_LE_ENCRYPT: u16 = 0x0017;
+const OCF_LE_RAND: u16 = 0x0018;
+const OCF_LE_ENABLE_ENCRYPTION: u16 = 0x0019;
+const OCF_LE_LTK_REQ_REPLY: u16 = 0x001A;
+const OCF_LE_LTK_REQ_NEG_REPLY: u16 = 0x001B;
+
+pub(crate) const EVT_ENCRYPT_CHANGE: u8 = 0x08;
+pub(crate) const EVT_ENCRYPT_KEY_REFRESH_COMPLETE: u8 = 0x30;
+const EVT_LE_LTK_REQUEST: u8 = 0x05;
+
+// Crypto primitives
+
+impl Socket {
+    /// Encrypt a single block with AES-128 in the controller.
+    ///
+    /// The key, plaintext and result are in HCI order, least significant octet first.
+    pub fn le_encrypt(&mut self, key: &[u8; 16], plaintext: &[u8; 16], timeout: c_int) -> Result<[u8; 16]> {
+        let mut param = [0u8; 32];
+        param[..16].copy_from_slice(key);
+        param[16..].copy_from_slice(plaintext);
+        let data = self.execute(OGF_LE_CTL, OCF_LE_ENCRYPT, &param, timeout)?;
+        let (encrypted, _) = (&*data).read_as::<[u8; 16]>()?;
+        Ok(encrypted)
+    }
+
+    /// Generate 8 random octets in the controller.
+    pub fn le_rand(&mut self, timeout: c_int) -> Result<[u8; 8]> {
+        let data = self.execute(OGF_LE_CTL, OCF_LE_RAND, &[], timeout)?;
+        let (random, _) = (&*data).read_as::<[u8; 8]>()?;
+        Ok(random)
+    }
+}
+
+// Link encryption
+
+/// Result of an Encryption Change or Encryption Key Refresh Complete event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EncryptionChange {
+    pub status: u8,
+    pub handle: u16,
+    /// Whether the link is encrypted after the change
+    pub enabled: bool,
+}
+
+impl EncryptionChange {
+    /// Parse the parameters of an Encryption Change or Encryption Key Refresh Complete event.
+    pub fn parse(event: u8, params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (status, _) = r.read_as::<u8>()?;
+        let (handle, _) = r.read_as::<u16>()?;
+        let enabled = match event {
+            EVT_ENCRYPT_CHANGE => r.read_as::<u8>()?.0 != 0,
+            // A key refresh only happens on an encrypted link.
+            EVT_ENCRYPT_KEY_REFRESH_COMPLETE => true,
+            _ => return Err(Error::new(InvalidData, "Not an encryption change event")),
+        };
+        Ok(EncryptionChange { status, handle, enabled })
+    }
+}
+
+/// Parameters of an LE Long Term Key Request subevent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LtkRequest {
+    pub handle: u16,
+    pub random_number: [u8; 8],
+    /// Encrypted diversifier
+    pub ediv: u16,
+}
+
+impl LtkRequest {
+    /// Parse the parameters of an LE Long Term Key Request subevent.
+    pub fn parse(params: &[u8]) -> Result<Self> {
+        let mut r = params;
+        let (handle, _) = r.read_as::<u16>()?;
+        let (random_number, _) = r.read_as::<[u8; 8]>()?;
+        let (ediv, _) = r.read_as::<u16>()?;
+        Ok(LtkRequest { handle, random_number, ediv })
+    }
+}
+
+impl Connection<'_> {
+    /// Start or refresh encryption of this link as the central, and wait for the result.
+    ///
+    /// `random_number` and `ediv` identify the long term key `ltk` to the peripheral. Both
+    /// are zero for keys generated with LE Secure Connections.
+    pub fn le_enable_encryption(&mut self, random_number: &[u8; 8], ediv: u16, ltk: &[u8; 16], timeout: c_int) -> Result<EncryptionChange> {
+        let handle = self.handle();
+        let opcode = codec::opcode(OGF_LE_CTL, OCF_LE_ENABLE_ENCRYPTION);
+        let mut param = handle.to_le_bytes().to_vec();
+        param.extend_from_slice(random_number);
+        param.extend_from_slice(&ediv.to_le_bytes());
+        param.extend_from_slice(ltk);
+
+        let socket = &mut *self.socket;
+        let old_filter = socket.get_filter()?;
+        // A single filter catches the Command Status and the events that follow it, so none
+        // are dropped between sending the command and waiting for the result.
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_CMD_STATUS)?;
+        filter.set_event(EVT_ENCRYPT_CHANGE)?;
+        filter.set_event(EVT_ENCRYPT_KEY_REFRESH_COMPLETE)?;
+        filter.set_opcode(opcode);
+        socket.set_filter(&filter)?;
+
+        let result = (|s: &mut Socket| {
+            s.send_cmd(OGF_LE_CTL, OCF_LE_ENABLE_ENCRYPTION, &param)?;
+            loop {
+                let found = s.recv_event_body(timeout, |event| match *event {
+                    EventBody::CommandStatus { status, opcode: r_opcode, .. } if r_opcode == opcode && status != 0 =>
+                        Some(Err(StatusError(status).into())),
+                    EventBody::Other { code, params }
+                    if code == EVT_ENCRYPT_CHANGE || code == EVT_ENCRYPT_KEY_REFRESH_COMPLETE =>
+                        Some(EncryptionChange::parse(code, params)),
+                    _ => None,
+                })?;
+                let change = match found {
+                    Some(change) => change?,
+                    None => continue,
+                };
+                if change.handle != handle {
+                    continue;
+                }
+                if change.status != 0 {
+                    return Err(StatusError(change.status).into());
+                }
+                return Ok(change);
+            }
+        })(socket);
+
+        socket.set_filter(&old_filter)?;
+        result
+    }
+
+    /// Wait for the controller to request the long term key for this link as the peripheral.
+    pub fn le_wait_ltk_request(&mut self, timeout: c_int) -> Result<LtkRequest> {
+        let handle = self.handle().to_le_bytes();
+        let (_, data) = self.socket.wait_le_event(timeout,
+            |subevent, params| subevent == EVT_LE_LTK_REQUEST && params.get(0..2) == Some(&handle))?;
+        LtkRequest::parse(&data)
+    }
+
+    /// Reply to a long term key request with the key for this link.
+    pub fn le_ltk_reply(&mut self, ltk: &[u8; 16], timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(ltk);
+        self.socket.execute(OGF_LE_CTL, OCF_LE_LTK_REQ_REPLY, &param, timeout)
+            .map(|_| ())
+    }
+
+    /// Reply to a long term key request when no key is available for this link.
+    pub fn le_ltk_negative_reply(&mut self, timeout: c_int) -> Result<()> {
+        self.socket.execute(OGF_LE_CTL, OCF_LE_LTK_REQ_NEG_REPLY, &self.handle().to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+}
@@ -1,5 +1,117 @@
-mod filter;
-mod io;
-mod socket;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-pub use socket::{Socket};
+extern crate alloc;
+
+#[macro_use]
+mod macros;
+
+pub mod codec;
+
+with_std! {
+    #[cfg(any(feature = "bredr", feature = "le"))]
+    pub mod ad;
+    mod acl;
+    mod adapter;
+    mod addr;
+    mod audio;
+    mod capabilities;
+    #[cfg(feature = "le")]
+    pub mod beacon;
+    mod class;
+    mod command;
+    mod company;
+    mod connection;
+    mod device;
+    #[cfg(feature = "bredr")]
+    mod csb;
+    #[cfg(all(feature = "bredr", feature = "le"))]
+    mod discovery;
+    mod error;
+    mod event_loop;
+    mod filter;
+    #[cfg(all(feature = "bredr", feature = "le"))]
+    mod gap;
+    mod host_ctl;
+    mod info;
+    #[cfg(feature = "bredr")]
+    mod inquiry;
+    mod io;
+    #[cfg(feature = "iso")]
+    mod iso;
+    #[cfg(feature = "le")]
+    mod le;
+    #[cfg(feature = "bredr")]
+    mod link_ctl;
+    #[cfg(feature = "bredr")]
+    mod link_policy;
+    #[cfg(feature = "mgmt")]
+    mod mgmt;
+    #[cfg(feature = "monitor")]
+    mod monitor;
+    #[cfg(feature = "bredr")]
+    mod pairing;
+    mod pool;
+    mod remote;
+    mod rssi;
+    mod socket;
+    mod stats;
+    #[cfg(feature = "bredr")]
+    mod status;
+    mod testing;
+    mod trace;
+    mod units;
+    mod uuid;
+    mod version;
+    #[cfg(feature = "vendor")]
+    pub mod vendor;
+
+    pub use acl::{AclPacket};
+    pub use adapter::{Adapter};
+    pub use addr::{AddressType, BdAddr, OwnAddressType, RandomAddressKind};
+    pub use audio::{CodecTransports, CodingFormat, CodingId, DataPath, DataPathDirection, LogicalTransport, PcmDataFormat, SupportedCodec};
+    pub use capabilities::{Capabilities};
+    pub use class::{AudioVideoClass, ClassOfDevice, ComputerClass, MajorDeviceClass, MinorDeviceClass, PhoneClass, ServiceClasses, ToyClass, WearableClass};
+    pub use command::{BatchResponse, Command, RawCommand};
+    pub use company::{CompanyId};
+    pub use connection::{Connection, Role};
+    pub use device::{DeviceId, UpGuard};
+    #[cfg(feature = "bredr")]
+    pub use csb::{CsbEvent, CsbFragment, CsbParams, CsbReceive, CsbReceiveParams, SyncScanParams, SyncTrainParams, SyncTrainReceived, SyncTrainSettings};
+    #[cfg(all(feature = "bredr", feature = "le"))]
+    pub use discovery::{DiscoveredDevice, Discovery, DiscoveryMode, DiscoveryOptions, Technology};
+    pub use error::{Capability, CommandError, CommandPhase, MalformedPacket, PermissionDenied, StatusError};
+    pub use event_loop::{EventLoop, PacketSource, Token};
+    #[cfg(all(feature = "bredr", feature = "le"))]
+    pub use gap::{DiscoverableMode, GapConfig};
+    pub use host_ctl::{AirCoding, ChannelMap, CompletedBlocks, CompletedDataBlocks, DataBlockCredits, FlowControlMode, HostBufferSize, HostFlowControl, IacLap, PinType, InputCoding, InputDataFormat, SampleSize, ScanEnable, VoiceSetting};
+    pub use info::{DataBlockSize, LocalVersion, SupportedCommands};
+    #[cfg(feature = "le")]
+    pub use le::{AcceptListEntry, Advertiser, AntennaInfo, AdvertisingChannels, AdvertisingEventProperties, AdvertisingFilterPolicy, AdvertisingParams, AdvertisingEventType, AdvertisingReport, AdvertisingSetEnable, AdvertisingType, CodedPhyOptions, ConnectionIqReport, ConnectionlessIqReport, ConnectionParams, CteType, CteTypes, DataLength, DataLengthChange, DataOperation, DataStatus, DuplicateFilter, EncryptionChange, ExtAdvertisingEventType, ExtAdvertisingParams, ExtAdvertisingReport, ExtConnectionParams, ExtendedAdvertiser, ExtLeScan, ExtScanOptions, ExtScanPhyParams, FilterAcceptList, IdentityAddress, IqSample, LeConnectionComplete, LeConnectionUpdate, LeEventMask, LeFeatures, LeScan, LeState, LeSupportedStates, LtkRequest, MAX_ADV_DATA_LEN, MAX_EXT_ADV_DATA_LEN, MaxDataLength, ModulationIndex, parse_advertising_reports, parse_extended_advertising_reports, PathLossParams, PathLossThreshold, PathLossZone, PeriodicAdvertisingParams, PeriodicAdvertisingReport, PeriodicSync, PeriodicSyncEstablished, PeriodicSyncOptions, Phy, PhyMask, PhyUpdate, PowerControlPhy, PowerReportReason, PrivacyMode, ReceiverTestParams, RemoteConnParamRequest, ResolvingListEntry, RfPathCompensation, ScanFilterPolicy, ScannedDevice, ScanOptions, ScanType, SlotDuration, SubrateChange, SubrateParams, SwitchingSamplingRates, TestCte, TestPayload, TestPhy, TransmitPowerReport, TransmitterTestParams};
+    #[cfg(feature = "iso")]
+    pub use le::{Big, BigComplete, BigParams, BigSync, BigSyncEstablished, BigSyncOptions, Cig, CigParams, CisEstablished, CisParams, CisRequest, CisStream, IsoDataPathParams, IsoSdu, IsoStream, IsoTestCounters, IsoTestPayload, LeBufferSize, Packing, SduStatus};
+    #[cfg(feature = "bredr")]
+    pub use inquiry::{InquiryMode, InquiryResult, parse_inquiry_results};
+    #[cfg(feature = "iso")]
+    pub use iso::{IsoBoundary, IsoPacket};
+    #[cfg(feature = "bredr")]
+    pub use link_ctl::{AclPacketTypes, AirMode, ConnComplete, ConnRequest, CreateConnParams, EnhancedSyncConnParams, LinkType, RetransmissionEffort, SyncConnComplete, SyncConnParams, SyncPacketTypes, SyncRejectReason};
+    #[cfg(feature = "bredr")]
+    pub use link_policy::{FlowDirection, FlowSpec, LinkPolicy, Mode, ModeChange, Qos, RoleChange, ServiceType};
+    #[cfg(feature = "mgmt")]
+    pub use error::{MgmtStatusError};
+    #[cfg(feature = "mgmt")]
+    pub use mgmt::{MGMT_INDEX_NONE, MgmtEvent, MgmtHeader, MgmtSocket, MgmtVersion};
+    #[cfg(feature = "monitor")]
+    pub use monitor::{MonitorFrame, MonitorHeader, MonitorSocket};
+    #[cfg(feature = "bredr")]
+    pub use pairing::{AuthenticationRequirements, IoCapability, LinkKey, LinkKeyType, Pairing, PairingAgent, PairingParams, PairingState, RemoteIoCapability};
+    pub use remote::{RemoteVersion};
+    #[cfg(feature = "bredr")]
+    pub use remote::{LmpFeatures, RemoteFeaturesPage};
+    pub use rssi::{RssiMonitor, RssiSample};
+    pub use socket::{HandleEvents, ShutdownHandle, Socket, Timestamp};
+    pub use stats::{Stats};
+    pub use testing::{LoopbackCommand, LoopbackMode};
+    pub use uuid::{Uuid};
+    pub use version::{HciVersion, LmpVersion};
+}
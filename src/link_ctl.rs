@@ -0,0 +1,544 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidData};
+use std::time::Duration;
+
+use super::adapter::Adapter;
+use super::addr::BdAddr;
+use super::audio::{CodingFormat, DataPath, PcmDataFormat};
+use super::class::ClassOfDevice;
+use super::connection::Connection;
+use super::error::StatusError;
+use super::filter::HciFilter;
+use super::host_ctl::VoiceSetting;
+use super::io::{ReadAs, ReadFrom, WriteAs, WriteTo};
+use super::socket::{HCI_EVENT_PKT, Socket};
+use super::units::{from_units, to_units, SLOT_US};
+
+const OGF_LINK_CTL: u16 = 0x01;
+const OCF_CREATE_CONN: u16 = 0x0005;
+const OCF_CREATE_CONN_CANCEL: u16 = 0x0008;
+const OCF_SETUP_SYNC_CONN: u16 = 0x0028;
+const OCF_ACCEPT_SYNC_CONN_REQ: u16 = 0x0029;
+const OCF_REJECT_SYNC_CONN_REQ: u16 = 0x002A;
+const OCF_ENHANCED_SETUP_SYNC_CONN: u16 = 0x003D;
+const OCF_ENHANCED_ACCEPT_SYNC_CONN_REQ: u16 = 0x003E;
+
+const EVT_CONN_COMPLETE: u8 = 0x03;
+const EVT_CONN_REQUEST: u8 = 0x04;
+const EVT_SYNC_CONN_COMPLETE: u8 = 0x2C;
+
+/// Page scan repetition mode R2, assumed for devices that were not found by an inquiry
+const DEFAULT_PAGE_SCAN_REPETITION_MODE: u8 = 0x02;
+/// Flag marking the clock offset of a Create Connection command as valid
+const CLOCK_OFFSET_VALID: u16 = 0x8000;
+
+/// Type of a link between two devices
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkType {
+    Sco = 0x00,
+    Acl = 0x01,
+    Esco = 0x02,
+}
+
+impl TryFrom<u8> for LinkType {
+    type Error = Error;
+
+    fn try_from(link_type: u8) -> Result<Self> {
+        match link_type {
+            0x00 => Ok(LinkType::Sco),
+            0x01 => Ok(LinkType::Acl),
+            0x02 => Ok(LinkType::Esco),
+            _ => Err(Error::new(InvalidData, "Unknown link type")),
+        }
+    }
+}
+
+// ACL connections
+
+bitflags! {
+    /// Packet types allowed on an ACL connection
+    ///
+    /// The EDR flags are inverted: setting them forbids the packet type.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct AclPacketTypes: u16 {
+        const NO_2DH1 = 0x0002;
+        const NO_3DH1 = 0x0004;
+        const DM1 = 0x0008;
+        const DH1 = 0x0010;
+        const NO_2DH3 = 0x0100;
+        const NO_3DH3 = 0x0200;
+        const DM3 = 0x0400;
+        const DH3 = 0x0800;
+        const NO_2DH5 = 0x1000;
+        const NO_3DH5 = 0x2000;
+        const DM5 = 0x4000;
+        const DH5 = 0x8000;
+    }
+}
+
+/// Parameters for paging a BR/EDR device
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CreateConnParams {
+    pub packet_type: AclPacketTypes,
+    /// Page scan repetition mode of the device, as reported by an inquiry
+    pub page_scan_repetition_mode: u8,
+    /// Clock offset of the device as reported by an inquiry, or `None` if unknown
+    pub clock_offset: Option<u16>,
+    /// Whether the remote device may become central of the connection
+    pub allow_role_switch: bool,
+}
+
+impl Default for CreateConnParams {
+    /// All basic rate packet types and no knowledge of the device from an inquiry
+    fn default() -> Self {
+        CreateConnParams {
+            packet_type: AclPacketTypes::DM1 | AclPacketTypes::DH1 | AclPacketTypes::DM3
+                | AclPacketTypes::DH3 | AclPacketTypes::DM5 | AclPacketTypes::DH5,
+            page_scan_repetition_mode: DEFAULT_PAGE_SCAN_REPETITION_MODE,
+            clock_offset: None,
+            allow_role_switch: true,
+        }
+    }
+}
+
+impl WriteTo for &CreateConnParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let clock_offset = match self.clock_offset {
+            Some(offset) => offset | CLOCK_OFFSET_VALID,
+            None => 0,
+        };
+        Ok(w.write_as(self.packet_type.bits())?
+            + w.write_as(self.page_scan_repetition_mode)?
+            // Reserved
+            + w.write_as(0u8)?
+            + w.write_as(clock_offset)?
+            + w.write_as(self.allow_role_switch as u8)?)
+    }
+}
+
+/// Parameters of a Connection Complete event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ConnComplete {
+    pub status: u8,
+    pub handle: u16,
+    pub bdaddr: BdAddr,
+    pub link_type: LinkType,
+    pub encryption_enabled: bool,
+}
+
+impl ReadFrom for ConnComplete {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (status, status_size) = r.read_as::<u8>()?;
+        let (handle, handle_size) = r.read_as::<u16>()?;
+        let (bdaddr, bdaddr_size) = r.read_as::<BdAddr>()?;
+        let (link_type, link_type_size) = r.read_as::<u8>()?;
+        let (encryption_enabled, encryption_enabled_size) = r.read_as::<u8>()?;
+        Ok((ConnComplete {
+            status,
+            handle,
+            bdaddr,
+            link_type: LinkType::try_from(link_type)?,
+            encryption_enabled: encryption_enabled != 0,
+        }, status_size + handle_size + bdaddr_size + link_type_size + encryption_enabled_size))
+    }
+}
+
+impl Socket {
+    /// Page a BR/EDR device and wait for the Connection Complete event of the new connection.
+    ///
+    /// The Connection Complete event is returned even if paging failed, so the caller can
+    /// check its status.
+    pub fn create_conn(&mut self, bdaddr: BdAddr, params: &CreateConnParams, timeout: c_int) -> Result<ConnComplete> {
+        let mut param = bdaddr.bytes()?.into_vec();
+        param.extend_from_slice(&params.bytes()?);
+        let data = self.send_req_matching(OGF_LINK_CTL, OCF_CREATE_CONN,
+            EVT_CONN_COMPLETE as c_int,
+            &param,
+            timeout,
+            |_, data| (&*data).read_as::<ConnComplete>()
+                .map(|(complete, _)| complete.bdaddr == bdaddr)
+                .unwrap_or(false),
+        )?;
+        let (complete, _) = (&*data).read_as::<ConnComplete>()?;
+        Ok(complete)
+    }
+
+    /// Stop paging `bdaddr`.
+    ///
+    /// The attempt ends with a Connection Complete event, which is not waited for.
+    pub fn create_conn_cancel(&mut self, bdaddr: BdAddr, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LINK_CTL, OCF_CREATE_CONN_CANCEL, &bdaddr.bytes()?, timeout)
+            .map(|_| ())
+    }
+}
+
+impl Adapter {
+    /// Connect to a BR/EDR device.
+    ///
+    /// Waits up to the adapter timeout for the connection to be established and returns the
+    /// new connection. Paging can take several seconds, longer than the default timeout.
+    pub fn connect(&mut self, addr: BdAddr, params: &CreateConnParams) -> Result<Connection<'_>> {
+        let complete = self.socket.create_conn(addr, params, self.timeout)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(Connection::new(&mut self.socket, complete.handle))
+    }
+}
+
+// Synchronous connections
+
+bitflags! {
+    /// Packet types allowed on a synchronous connection
+    ///
+    /// The EDR flags are inverted: setting them forbids the packet type.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct SyncPacketTypes: u16 {
+        const HV1 = 0x0001;
+        const HV2 = 0x0002;
+        const HV3 = 0x0004;
+        const EV3 = 0x0008;
+        const EV4 = 0x0010;
+        const EV5 = 0x0020;
+        const NO_2EV3 = 0x0040;
+        const NO_3EV3 = 0x0080;
+        const NO_2EV5 = 0x0100;
+        const NO_3EV5 = 0x0200;
+    }
+}
+
+/// Retransmission policy of an eSCO link
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RetransmissionEffort {
+    None = 0x00,
+    PowerConsumption = 0x01,
+    LinkQuality = 0x02,
+    DontCare = 0xFF,
+}
+
+/// Air mode of an established synchronous connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AirMode {
+    MuLaw = 0x00,
+    ALaw = 0x01,
+    Cvsd = 0x02,
+    Transparent = 0x03,
+}
+
+impl TryFrom<u8> for AirMode {
+    type Error = Error;
+
+    fn try_from(air_mode: u8) -> Result<Self> {
+        match air_mode {
+            0x00 => Ok(AirMode::MuLaw),
+            0x01 => Ok(AirMode::ALaw),
+            0x02 => Ok(AirMode::Cvsd),
+            0x03 => Ok(AirMode::Transparent),
+            _ => Err(Error::new(InvalidData, "Unknown air mode")),
+        }
+    }
+}
+
+/// Parameters for setting up or accepting a synchronous connection.
+///
+/// Bandwidths are in octets per second.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyncConnParams {
+    pub transmit_bandwidth: u32,
+    pub receive_bandwidth: u32,
+    /// Maximum latency, or `None` if any latency is acceptable
+    pub max_latency: Option<Duration>,
+    pub voice_setting: VoiceSetting,
+    pub retransmission_effort: RetransmissionEffort,
+    pub packet_type: SyncPacketTypes,
+}
+
+impl WriteTo for &SyncConnParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let max_latency = match self.max_latency {
+            Some(latency) => to_units(latency, 1000)?,
+            None => 0xFFFF,
+        };
+        Ok(w.write_as(self.transmit_bandwidth)?
+            + w.write_as(self.receive_bandwidth)?
+            + w.write_as(max_latency)?
+            + w.write_as(u16::from(self.voice_setting))?
+            + w.write_as(self.retransmission_effort as u8)?
+            + w.write_as(self.packet_type.bits())?)
+    }
+}
+
+/// Parameters of a Synchronous Connection Complete event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyncConnComplete {
+    pub status: u8,
+    pub handle: u16,
+    pub bdaddr: BdAddr,
+    pub link_type: LinkType,
+    pub transmission_interval: Duration,
+    pub retransmission_window: Duration,
+    pub rx_packet_length: u16,
+    pub tx_packet_length: u16,
+    pub air_mode: AirMode,
+}
+
+impl ReadFrom for SyncConnComplete {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (status, status_size) = r.read_as::<u8>()?;
+        let (handle, handle_size) = r.read_as::<u16>()?;
+        let (bdaddr, bdaddr_size) = r.read_as::<BdAddr>()?;
+        let (link_type, link_type_size) = r.read_as::<u8>()?;
+        let (transmission_interval, transmission_interval_size) = r.read_as::<u8>()?;
+        let (retransmission_window, retransmission_window_size) = r.read_as::<u8>()?;
+        let (rx_packet_length, rx_packet_length_size) = r.read_as::<u16>()?;
+        let (tx_packet_length, tx_packet_length_size) = r.read_as::<u16>()?;
+        let (air_mode, air_mode_size) = r.read_as::<u8>()?;
+        Ok((SyncConnComplete {
+            status,
+            handle,
+            bdaddr,
+            link_type: LinkType::try_from(link_type)?,
+            transmission_interval: from_units(transmission_interval.into(), SLOT_US),
+            retransmission_window: from_units(retransmission_window.into(), SLOT_US),
+            rx_packet_length,
+            tx_packet_length,
+            air_mode: AirMode::try_from(air_mode)?,
+        }, status_size + handle_size + bdaddr_size + link_type_size + transmission_interval_size
+            + retransmission_window_size + rx_packet_length_size + tx_packet_length_size + air_mode_size))
+    }
+}
+
+/// Parameters of a Connection Request event, sent when a remote device wants to connect
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ConnRequest {
+    pub bdaddr: BdAddr,
+    pub class: ClassOfDevice,
+    pub link_type: LinkType,
+}
+
+impl ConnRequest {
+    /// Return whether the request is for a SCO or eSCO connection
+    pub fn is_sync(&self) -> bool {
+        self.link_type != LinkType::Acl
+    }
+}
+
+impl ReadFrom for ConnRequest {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (bdaddr, bdaddr_size) = r.read_as::<BdAddr>()?;
+        let (class, class_size) = r.read_as::<ClassOfDevice>()?;
+        let (link_type, link_type_size) = r.read_as::<u8>()?;
+        Ok((ConnRequest {
+            bdaddr,
+            class,
+            link_type: LinkType::try_from(link_type)?,
+        }, bdaddr_size + class_size + link_type_size))
+    }
+}
+
+/// Reason for rejecting a synchronous connection request
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SyncRejectReason {
+    /// No resources are left for another connection, such as when all audio links are in use
+    LimitedResources = 0x0D,
+    SecurityReasons = 0x0E,
+    /// The remote device is not one the host accepts connections from
+    UnacceptableBdAddr = 0x0F,
+}
+
+/// Parameters for setting up or accepting a synchronous connection with explicit codec
+/// configuration.
+///
+/// Transmit and receive parameters describe the air interface, while input and output
+/// parameters describe the data exchanged with the host. Bandwidths are in octets per second.
+/// Codec frame sizes are in octets, while coded data sizes, sample positions and transport unit
+/// sizes are in bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EnhancedSyncConnParams {
+    pub transmit_bandwidth: u32,
+    pub receive_bandwidth: u32,
+    pub transmit_coding_format: CodingFormat,
+    pub receive_coding_format: CodingFormat,
+    /// Size of a codec frame sent over the air, in octets
+    pub transmit_codec_frame_size: u16,
+    /// Size of a codec frame received over the air, in octets
+    pub receive_codec_frame_size: u16,
+    pub input_bandwidth: u32,
+    pub output_bandwidth: u32,
+    pub input_coding_format: CodingFormat,
+    pub output_coding_format: CodingFormat,
+    /// Size of a sample of input data, in bits
+    pub input_coded_data_size: u16,
+    /// Size of a sample of output data, in bits
+    pub output_coded_data_size: u16,
+    pub input_pcm_data_format: PcmDataFormat,
+    pub output_pcm_data_format: PcmDataFormat,
+    /// Bit position of the most significant bit of a PCM input sample within its payload
+    pub input_pcm_sample_payload_msb_position: u8,
+    /// Bit position of the most significant bit of a PCM output sample within its payload
+    pub output_pcm_sample_payload_msb_position: u8,
+    pub input_data_path: DataPath,
+    pub output_data_path: DataPath,
+    /// Size of a transport unit carrying input data, in bits, or zero if not applicable
+    pub input_transport_unit_size: u8,
+    /// Size of a transport unit carrying output data, in bits, or zero if not applicable
+    pub output_transport_unit_size: u8,
+    /// Maximum latency, or `None` if any latency is acceptable
+    pub max_latency: Option<Duration>,
+    pub packet_type: SyncPacketTypes,
+    pub retransmission_effort: RetransmissionEffort,
+}
+
+impl WriteTo for &EnhancedSyncConnParams {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let max_latency = match self.max_latency {
+            Some(latency) => to_units(latency, 1000)?,
+            None => 0xFFFF,
+        };
+        Ok(w.write_as(self.transmit_bandwidth)?
+            + w.write_as(self.receive_bandwidth)?
+            + w.write_as(&self.transmit_coding_format)?
+            + w.write_as(&self.receive_coding_format)?
+            + w.write_as(self.transmit_codec_frame_size)?
+            + w.write_as(self.receive_codec_frame_size)?
+            + w.write_as(self.input_bandwidth)?
+            + w.write_as(self.output_bandwidth)?
+            + w.write_as(&self.input_coding_format)?
+            + w.write_as(&self.output_coding_format)?
+            + w.write_as(self.input_coded_data_size)?
+            + w.write_as(self.output_coded_data_size)?
+            + w.write_as(u8::from(self.input_pcm_data_format))?
+            + w.write_as(u8::from(self.output_pcm_data_format))?
+            + w.write_as(self.input_pcm_sample_payload_msb_position)?
+            + w.write_as(self.output_pcm_sample_payload_msb_position)?
+            + w.write_as(u8::from(self.input_data_path))?
+            + w.write_as(u8::from(self.output_data_path))?
+            + w.write_as(self.input_transport_unit_size)?
+            + w.write_as(self.output_transport_unit_size)?
+            + w.write_as(max_latency)?
+            + w.write_as(self.packet_type.bits())?
+            + w.write_as(self.retransmission_effort as u8)?)
+    }
+}
+
+impl Socket {
+    /// Send a synchronous connection command and wait for the Synchronous Connection Complete
+    /// event accepted by `matches`.
+    ///
+    /// Events that cannot be parsed may be about other links, so they are passed over.
+    fn sync_conn_request<F>(&mut self, ocf: u16, param: &[u8], timeout: c_int, matches: F) -> Result<SyncConnComplete>
+    where F: Fn(&SyncConnComplete) -> bool {
+        let data = self.send_req_matching(OGF_LINK_CTL, ocf,
+            EVT_SYNC_CONN_COMPLETE as c_int,
+            param,
+            timeout,
+            |_, data| (&*data).read_as::<SyncConnComplete>()
+                .is_ok_and(|(complete, _)| matches(&complete)),
+        )?;
+        let (complete, _) = (&*data).read_as::<SyncConnComplete>()?;
+        Ok(complete)
+    }
+
+    /// Accept an incoming synchronous connection request from `bdaddr`.
+    ///
+    /// Waits for the Synchronous Connection Complete event of the new connection.
+    pub fn accept_sync_conn_request(&mut self, bdaddr: BdAddr, params: &SyncConnParams, timeout: c_int) -> Result<SyncConnComplete> {
+        let mut param = bdaddr.bytes()?.into_vec();
+        param.extend_from_slice(&params.bytes()?);
+        let complete = self.sync_conn_request(OCF_ACCEPT_SYNC_CONN_REQ, &param, timeout,
+            |complete| complete.bdaddr == bdaddr)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(complete)
+    }
+
+    /// Accept an incoming synchronous connection request from `bdaddr` with explicit codec
+    /// configuration.
+    pub fn enhanced_accept_sync_conn_request(&mut self, bdaddr: BdAddr, params: &EnhancedSyncConnParams, timeout: c_int) -> Result<SyncConnComplete> {
+        let mut param = bdaddr.bytes()?.into_vec();
+        param.extend_from_slice(&params.bytes()?);
+        let complete = self.sync_conn_request(OCF_ENHANCED_ACCEPT_SYNC_CONN_REQ, &param, timeout,
+            |complete| complete.bdaddr == bdaddr)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(complete)
+    }
+
+    /// Wait for a Connection Request event from a remote device.
+    ///
+    /// The kernel also sees the request, and answers it itself if no one else does in time.
+    pub fn wait_conn_request(&mut self, timeout: c_int) -> Result<ConnRequest> {
+        let old_filter = self.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        filter.set_event(EVT_CONN_REQUEST)?;
+        self.set_filter(&filter)?;
+
+        let result = (|s: &mut Socket| loop {
+            let request = s.recv_event_map(timeout, |event, data| {
+                (event == EVT_CONN_REQUEST).then(|| (&*data).read_as::<ConnRequest>())
+            })?;
+            if let Some(request) = request {
+                return request.map(|(request, _)| request);
+            }
+        })(self);
+
+        self.set_filter(&old_filter)?;
+        result
+    }
+
+    /// Reject an incoming synchronous connection request from `bdaddr`.
+    ///
+    /// Waits for the Synchronous Connection Complete event that reports the rejection.
+    pub fn reject_sync_conn_request(&mut self, bdaddr: BdAddr, reason: SyncRejectReason, timeout: c_int) -> Result<()> {
+        let mut param = bdaddr.bytes()?.into_vec();
+        param.push(reason as u8);
+        // The status is not checked: a rejected request is reported with the reject reason as
+        // the status of the Synchronous Connection Complete event.
+        self.sync_conn_request(OCF_REJECT_SYNC_CONN_REQ, &param, timeout,
+            |complete| complete.bdaddr == bdaddr)
+            .map(|_| ())
+    }
+}
+
+impl Connection<'_> {
+    /// Set up a SCO or eSCO connection on top of this ACL connection.
+    ///
+    /// Waits for the Synchronous Connection Complete event and returns the parameters of the new
+    /// connection.
+    pub fn setup_sync_conn(&mut self, params: &SyncConnParams, timeout: c_int) -> Result<SyncConnComplete> {
+        // The event names the peer rather than the ACL link, so the peer tells it apart from
+        // setups on other links.
+        let bdaddr = self.peer_address()?;
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&params.bytes()?);
+        let complete = self.socket.sync_conn_request(OCF_SETUP_SYNC_CONN, &param, timeout,
+            |complete| complete.bdaddr == bdaddr)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(complete)
+    }
+}
+
+impl Connection<'_> {
+    /// Set up a SCO or eSCO connection with explicit codec configuration, as needed for mSBC or
+    /// codecs offloaded to the controller.
+    pub fn enhanced_setup_sync_conn(&mut self, params: &EnhancedSyncConnParams, timeout: c_int) -> Result<SyncConnComplete> {
+        // The event names the peer rather than the ACL link, so the peer tells it apart from
+        // setups on other links.
+        let bdaddr = self.peer_address()?;
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&params.bytes()?);
+        let complete = self.socket.sync_conn_request(OCF_ENHANCED_SETUP_SYNC_CONN, &param, timeout,
+            |complete| complete.bdaddr == bdaddr)?;
+        if complete.status != 0 {
+            return Err(StatusError(complete.status).into());
+        }
+        Ok(complete)
+    }
+}
@@ -0,0 +1,436 @@
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Error, Read, Result, Write};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::time::Duration;
+
+use super::addr::BdAddr;
+use super::connection::{Connection, Role};
+use super::error::StatusError;
+use super::io::{ReadAs, ReadFrom, WriteAs, WriteTo};
+use super::socket::Socket;
+use super::units::{from_units, to_units, SLOT_US};
+
+const OGF_LINK_POLICY: u16 = 0x02;
+const OCF_HOLD_MODE: u16 = 0x0001;
+const OCF_SNIFF_MODE: u16 = 0x0003;
+const OCF_EXIT_SNIFF_MODE: u16 = 0x0004;
+const OCF_PARK_MODE: u16 = 0x0005;
+const OCF_EXIT_PARK_MODE: u16 = 0x0006;
+const OCF_QOS_SETUP: u16 = 0x0007;
+const OCF_ROLE_DISCOVERY: u16 = 0x0009;
+const OCF_SWITCH_ROLE: u16 = 0x000B;
+const OCF_READ_LINK_POLICY: u16 = 0x000C;
+const OCF_WRITE_LINK_POLICY: u16 = 0x000D;
+const OCF_READ_DEFAULT_LINK_POLICY: u16 = 0x000E;
+const OCF_WRITE_DEFAULT_LINK_POLICY: u16 = 0x000F;
+const OCF_FLOW_SPECIFICATION: u16 = 0x0010;
+const OCF_SNIFF_SUBRATING: u16 = 0x0011;
+
+const EVT_QOS_SETUP_COMPLETE: u8 = 0x0D;
+const EVT_ROLE_CHANGE: u8 = 0x12;
+const EVT_MODE_CHANGE: u8 = 0x14;
+const EVT_FLOW_SPEC_COMPLETE: u8 = 0x21;
+
+/// Parameters of a Role Change event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RoleChange {
+    pub status: u8,
+    pub bdaddr: BdAddr,
+    pub role: Role,
+}
+
+impl ReadFrom for RoleChange {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (status, status_size) = r.read_as::<u8>()?;
+        let (bdaddr, bdaddr_size) = r.read_as::<BdAddr>()?;
+        let (role, role_size) = r.read_as::<Role>()?;
+        Ok((RoleChange { status, bdaddr, role }, status_size + bdaddr_size + role_size))
+    }
+}
+
+impl Connection<'_> {
+    /// Return the role of the local device on this connection.
+    pub fn role(&mut self, timeout: c_int) -> Result<Role> {
+        let handle = self.handle();
+        let data = self.socket.execute(OGF_LINK_POLICY, OCF_ROLE_DISCOVERY, &handle.to_le_bytes(), timeout)?;
+        let mut r: &[u8] = &data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (role, _) = r.read_as::<Role>()?;
+        Ok(role)
+    }
+
+    /// Request a role switch with the device at `addr` and wait for the Role Change event.
+    ///
+    /// Returns the new role of the local device.
+    pub fn switch_role(&mut self, addr: BdAddr, role: Role, timeout: c_int) -> Result<Role> {
+        let mut param = addr.bytes()?.into_vec();
+        param.push(role as u8);
+
+        let data = self.socket.send_req_matching(OGF_LINK_POLICY, OCF_SWITCH_ROLE,
+            EVT_ROLE_CHANGE as c_int,
+            &param,
+            timeout,
+            |_, data| data.get(1..7) == Some(addr.as_bytes()),
+        )?;
+        let (change, _) = (&*data).read_as::<RoleChange>()?;
+        if change.status != 0 {
+            return Err(StatusError(change.status).into());
+        }
+        Ok(change.role)
+    }
+}
+
+bitflags! {
+    /// Link modes a controller may use on a connection
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct LinkPolicy: u16 {
+        const ROLE_SWITCH = 0x0001;
+        const HOLD_MODE = 0x0002;
+        const SNIFF_MODE = 0x0004;
+        const PARK_STATE = 0x0008;
+    }
+}
+
+impl Connection<'_> {
+    /// Read the link policy settings of this connection.
+    pub fn link_policy(&mut self, timeout: c_int) -> Result<LinkPolicy> {
+        let handle = self.handle();
+        let data = self.socket.execute(OGF_LINK_POLICY, OCF_READ_LINK_POLICY, &handle.to_le_bytes(), timeout)?;
+        let mut r: &[u8] = &data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (policy, _) = r.read_as::<u16>()?;
+        Ok(LinkPolicy::from_bits_retain(policy))
+    }
+
+    /// Write the link policy settings of this connection.
+    pub fn set_link_policy(&mut self, policy: LinkPolicy, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&policy.bits().to_le_bytes());
+        self.socket.execute(OGF_LINK_POLICY, OCF_WRITE_LINK_POLICY, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+impl Socket {
+    /// Read the link policy settings applied to new connections.
+    pub fn read_default_link_policy(&mut self, timeout: c_int) -> Result<LinkPolicy> {
+        let data = self.execute(OGF_LINK_POLICY, OCF_READ_DEFAULT_LINK_POLICY, &[], timeout)?;
+        let (policy, _) = (&*data).read_as::<u16>()?;
+        Ok(LinkPolicy::from_bits_retain(policy))
+    }
+
+    /// Write the link policy settings applied to new connections.
+    pub fn write_default_link_policy(&mut self, policy: LinkPolicy, timeout: c_int) -> Result<()> {
+        self.execute(OGF_LINK_POLICY, OCF_WRITE_DEFAULT_LINK_POLICY, &policy.bits().to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+}
+
+// Low power modes
+
+/// Current mode of a BR/EDR connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Active = 0x00,
+    Hold = 0x01,
+    Sniff = 0x02,
+    Park = 0x03,
+}
+
+impl TryFrom<u8> for Mode {
+    type Error = Error;
+
+    fn try_from(mode: u8) -> Result<Self> {
+        match mode {
+            0x00 => Ok(Mode::Active),
+            0x01 => Ok(Mode::Hold),
+            0x02 => Ok(Mode::Sniff),
+            0x03 => Ok(Mode::Park),
+            _ => Err(Error::new(InvalidData, "Unknown mode")),
+        }
+    }
+}
+
+/// Parameters of a Mode Change event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ModeChange {
+    pub status: u8,
+    pub handle: u16,
+    pub mode: Mode,
+    /// Hold time, sniff interval or beacon interval, depending on the mode
+    pub interval: Duration,
+}
+
+impl ReadFrom for ModeChange {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (status, status_size) = r.read_as::<u8>()?;
+        let (handle, handle_size) = r.read_as::<u16>()?;
+        let (mode, mode_size) = r.read_as::<u8>()?;
+        let (interval, interval_size) = r.read_as::<u16>()?;
+        Ok((ModeChange {
+            status,
+            handle,
+            mode: Mode::try_from(mode)?,
+            interval: from_units(interval.into(), SLOT_US),
+        }, status_size + handle_size + mode_size + interval_size))
+    }
+}
+
+impl Connection<'_> {
+    /// Send a mode command for this connection and wait for its Mode Change event.
+    fn change_mode(&mut self, ocf: u16, param: &[u8], timeout: c_int) -> Result<ModeChange> {
+        let handle = self.handle().to_le_bytes();
+        let data = self.socket.send_req_matching(OGF_LINK_POLICY, ocf,
+            EVT_MODE_CHANGE as c_int,
+            param,
+            timeout,
+            |_, data| data.get(1..3) == Some(&handle),
+        )?;
+        let (change, _) = (&*data).read_as::<ModeChange>()?;
+        if change.status != 0 {
+            return Err(StatusError(change.status).into());
+        }
+        Ok(change)
+    }
+
+    /// Place this connection in hold mode.
+    ///
+    /// The connection returns to active mode by itself once the hold time expires.
+    pub fn hold_mode(&mut self, max_interval: Duration, min_interval: Duration, timeout: c_int) -> Result<ModeChange> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&to_units(max_interval, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(min_interval, SLOT_US)?.to_le_bytes());
+        self.change_mode(OCF_HOLD_MODE, &param, timeout)
+    }
+
+    /// Place this connection in sniff mode.
+    ///
+    /// `attempt` and `sniff_timeout` are numbers of receive slots. Returns the Mode Change event
+    /// carrying the negotiated sniff interval.
+    pub fn sniff_mode(&mut self, max_interval: Duration, min_interval: Duration, attempt: u16, sniff_timeout: u16, timeout: c_int) -> Result<ModeChange> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&to_units(max_interval, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(min_interval, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&attempt.to_le_bytes());
+        param.extend_from_slice(&sniff_timeout.to_le_bytes());
+        self.change_mode(OCF_SNIFF_MODE, &param, timeout)
+    }
+
+    /// Return this connection from sniff mode to active mode.
+    pub fn exit_sniff_mode(&mut self, timeout: c_int) -> Result<ModeChange> {
+        let param = self.handle().to_le_bytes();
+        self.change_mode(OCF_EXIT_SNIFF_MODE, &param, timeout)
+    }
+
+    /// Place this connection in park state with the given beacon interval bounds.
+    pub fn park_state(&mut self, beacon_max_interval: Duration, beacon_min_interval: Duration, timeout: c_int) -> Result<ModeChange> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&to_units(beacon_max_interval, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(beacon_min_interval, SLOT_US)?.to_le_bytes());
+        self.change_mode(OCF_PARK_MODE, &param, timeout)
+    }
+
+    /// Return this connection from park state to active mode.
+    pub fn exit_park_state(&mut self, timeout: c_int) -> Result<ModeChange> {
+        let param = self.handle().to_le_bytes();
+        self.change_mode(OCF_EXIT_PARK_MODE, &param, timeout)
+    }
+
+    /// Configure sniff subrating on this connection.
+    pub fn sniff_subrating(&mut self, max_latency: Duration, min_remote_timeout: Duration, min_local_timeout: Duration, timeout: c_int) -> Result<()> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.extend_from_slice(&to_units(max_latency, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(min_remote_timeout, SLOT_US)?.to_le_bytes());
+        param.extend_from_slice(&to_units(min_local_timeout, SLOT_US)?.to_le_bytes());
+        self.socket.execute(OGF_LINK_POLICY, OCF_SNIFF_SUBRATING, &param, timeout)
+            .map(|_| ())
+    }
+}
+
+// Quality of service
+
+/// Level of service requested for a link
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ServiceType {
+    NoTraffic = 0x00,
+    BestEffort = 0x01,
+    Guaranteed = 0x02,
+}
+
+impl TryFrom<u8> for ServiceType {
+    type Error = Error;
+
+    fn try_from(service_type: u8) -> Result<Self> {
+        match service_type {
+            0x00 => Ok(ServiceType::NoTraffic),
+            0x01 => Ok(ServiceType::BestEffort),
+            0x02 => Ok(ServiceType::Guaranteed),
+            _ => Err(Error::new(InvalidData, "Unknown service type")),
+        }
+    }
+}
+
+/// Direction of traffic described by a flow specification
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlowDirection {
+    Outgoing = 0x00,
+    Incoming = 0x01,
+}
+
+impl TryFrom<u8> for FlowDirection {
+    type Error = Error;
+
+    fn try_from(direction: u8) -> Result<Self> {
+        match direction {
+            0x00 => Ok(FlowDirection::Outgoing),
+            0x01 => Ok(FlowDirection::Incoming),
+            _ => Err(Error::new(InvalidData, "Unknown flow direction")),
+        }
+    }
+}
+
+/// Convert a duration to whole microseconds for QoS parameters.
+fn to_micros(duration: Duration) -> Result<u32> {
+    duration.as_micros().try_into()
+        .map_err(|_| Error::new(InvalidInput, "Duration out of range"))
+}
+
+/// Quality of service parameters used by QoS Setup.
+///
+/// Rates and bandwidths are in octets per second.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Qos {
+    pub service_type: ServiceType,
+    pub token_rate: u32,
+    pub peak_bandwidth: u32,
+    pub latency: Duration,
+    pub delay_variation: Duration,
+}
+
+impl ReadFrom for Qos {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (service_type, service_type_size) = r.read_as::<u8>()?;
+        let (token_rate, token_rate_size) = r.read_as::<u32>()?;
+        let (peak_bandwidth, peak_bandwidth_size) = r.read_as::<u32>()?;
+        let (latency, latency_size) = r.read_as::<u32>()?;
+        let (delay_variation, delay_variation_size) = r.read_as::<u32>()?;
+        Ok((Qos {
+            service_type: ServiceType::try_from(service_type)?,
+            token_rate,
+            peak_bandwidth,
+            latency: Duration::from_micros(latency.into()),
+            delay_variation: Duration::from_micros(delay_variation.into()),
+        }, service_type_size + token_rate_size + peak_bandwidth_size + latency_size + delay_variation_size))
+    }
+}
+
+impl WriteTo for &Qos {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(self.service_type as u8)?
+            + w.write_as(self.token_rate)?
+            + w.write_as(self.peak_bandwidth)?
+            + w.write_as(to_micros(self.latency)?)?
+            + w.write_as(to_micros(self.delay_variation)?)?)
+    }
+}
+
+/// Flow specification parameters for one direction of a link.
+///
+/// Rates and bandwidths are in octets per second and the bucket size is in octets.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FlowSpec {
+    pub direction: FlowDirection,
+    pub service_type: ServiceType,
+    pub token_rate: u32,
+    pub token_bucket_size: u32,
+    pub peak_bandwidth: u32,
+    pub access_latency: Duration,
+}
+
+impl ReadFrom for FlowSpec {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (direction, direction_size) = r.read_as::<u8>()?;
+        let (service_type, service_type_size) = r.read_as::<u8>()?;
+        let (token_rate, token_rate_size) = r.read_as::<u32>()?;
+        let (token_bucket_size, token_bucket_size_size) = r.read_as::<u32>()?;
+        let (peak_bandwidth, peak_bandwidth_size) = r.read_as::<u32>()?;
+        let (access_latency, access_latency_size) = r.read_as::<u32>()?;
+        Ok((FlowSpec {
+            direction: FlowDirection::try_from(direction)?,
+            service_type: ServiceType::try_from(service_type)?,
+            token_rate,
+            token_bucket_size,
+            peak_bandwidth,
+            access_latency: Duration::from_micros(access_latency.into()),
+        }, direction_size + service_type_size + token_rate_size + token_bucket_size_size
+            + peak_bandwidth_size + access_latency_size))
+    }
+}
+
+impl WriteTo for &FlowSpec {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        Ok(w.write_as(self.direction as u8)?
+            + w.write_as(self.service_type as u8)?
+            + w.write_as(self.token_rate)?
+            + w.write_as(self.token_bucket_size)?
+            + w.write_as(self.peak_bandwidth)?
+            + w.write_as(to_micros(self.access_latency)?)?)
+    }
+}
+
+impl Connection<'_> {
+    /// Request quality of service parameters for this connection.
+    ///
+    /// Waits for the QoS Setup Complete event and returns the parameters the controller agreed
+    /// to.
+    pub fn qos_setup(&mut self, qos: &Qos, timeout: c_int) -> Result<Qos> {
+        let handle = self.handle().to_le_bytes();
+        let mut param = handle.to_vec();
+        param.push(0); // Unused flags
+        param.extend_from_slice(&qos.bytes()?);
+
+        let data = self.socket.send_req_matching(OGF_LINK_POLICY, OCF_QOS_SETUP,
+            EVT_QOS_SETUP_COMPLETE as c_int,
+            &param,
+            timeout,
+            |_, data| data.get(1..3) == Some(&handle),
+        )?;
+        let mut r: &[u8] = &data;
+        let (status, _) = r.read_as::<u8>()?;
+        if status != 0 {
+            return Err(StatusError(status).into());
+        }
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (_flags, _) = r.read_as::<u8>()?;
+        let (qos, _) = r.read_as::<Qos>()?;
+        Ok(qos)
+    }
+
+    /// Specify the traffic flow in one direction of this connection.
+    ///
+    /// Waits for the Flow Specification Complete event and returns the flow specification the
+    /// controller agreed to.
+    pub fn flow_specification(&mut self, flow: &FlowSpec, timeout: c_int) -> Result<FlowSpec> {
+        let handle = self.handle().to_le_bytes();
+        let mut param = handle.to_vec();
+        param.push(0); // Unused flags
+        param.extend_from_slice(&flow.bytes()?);
+
+        let data = self.socket.send_req_matching(OGF_LINK_POLICY, OCF_FLOW_SPECIFICATION,
+            EVT_FLOW_SPEC_COMPLETE as c_int,
+            &param,
+            timeout,
+            |_, data| data.get(1..3) == Some(&handle),
+        )?;
+        let mut r: &[u8] = &data;
+        let (status, _) = r.read_as::<u8>()?;
+        if status != 0 {
+            return Err(StatusError(status).into());
+        }
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (_flags, _) = r.read_as::<u8>()?;
+        let (flow, _) = r.read_as::<FlowSpec>()?;
+        Ok(flow)
+    }
+}
@@ -0,0 +1,62 @@
+/// Define an enum of assigned numbers with a catch-all `Other` variant for unknown values.
+#[cfg_attr(not(feature = "std"), allow(unused_macros))]
+macro_rules! code_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident = $value:expr),* $(,)? }) => {
+        code_enum!(
+            @derive(Copy, Clone, Debug, PartialEq, Eq, Hash)
+            $(#[$meta])* $name { $($variant = $value,)* }
+        );
+    };
+    // Derive only the given traits, for enums comparing their values some other way
+    (@derive($($derive:ident),*) $(#[$meta:meta])* $name:ident { $($variant:ident = $value:expr),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive($($derive),*)]
+        pub enum $name {
+            $($variant,)*
+            Other(u8),
+        }
+
+        impl From<u8> for $name {
+            fn from(value: u8) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    _ => $name::Other(value),
+                }
+            }
+        }
+
+        impl From<$name> for u8 {
+            fn from(value: $name) -> u8 {
+                match value {
+                    $($name::$variant => $value,)*
+                    $name::Other(value) => value,
+                }
+            }
+        }
+    }
+}
+
+/// Declare items that need the standard library, leaving them out without the `std` feature.
+macro_rules! with_std {
+    ($($item:item)*) => {
+        $(
+            #[cfg(feature = "std")]
+            $item
+        )*
+    }
+}
+
+/// Helper macro to execute a system call that returns an `io::Result`.
+/// Copied from socket2.
+#[cfg(feature = "std")]
+macro_rules! syscall {
+    ($fn: ident ( $($arg: expr),* $(,)* ) ) => {{
+        #[allow(unused_unsafe)]
+        let res = unsafe { libc::$fn($($arg, )*) };
+        if res == -1 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(res)
+        }
+    }};
+}
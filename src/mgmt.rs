@@ -0,0 +1,233 @@
+use libc::{c_int, ETIMEDOUT};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+use socket2::Socket as Socket2;
+
+use super::error::{MalformedPacket, MgmtStatusError};
+use super::socket::{bind_channel, HCI_CHANNEL_CONTROL};
+
+/// Index of commands and events not about any controller
+pub const MGMT_INDEX_NONE: u16 = 0xFFFF;
+/// Length of the code, index and length fields preceding each command and event
+const MGMT_HEADER_SIZE: usize = 6;
+/// Largest packet the management channel delivers
+const MAX_PACKET_SIZE: usize = MGMT_HEADER_SIZE + u16::MAX as usize;
+
+const MGMT_OP_READ_VERSION: u16 = 0x0001;
+const MGMT_OP_READ_INDEX_LIST: u16 = 0x0003;
+
+const MGMT_EV_CMD_COMPLETE: u16 = 0x0001;
+const MGMT_EV_CMD_STATUS: u16 = 0x0002;
+
+/// Header of a command or event on the management channel
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MgmtHeader {
+    /// Opcode of a command, or code of an event
+    pub code: u16,
+    /// Index of the controller the packet is about, or `MGMT_INDEX_NONE`
+    pub index: u16,
+}
+
+/// An event received on the management channel
+#[derive(Clone, Debug)]
+pub struct MgmtEvent {
+    pub header: MgmtHeader,
+    /// Parameters of the event
+    pub data: Box<[u8]>,
+}
+
+/// Version of the management interface of the kernel
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MgmtVersion {
+    pub version: u8,
+    pub revision: u16,
+}
+
+/// Socket on the management channel, through which bluetoothd configures controllers.
+///
+/// Commands changing settings need `CAP_NET_ADMIN`. Every management socket receives the
+/// events of every controller, so responses to commands are told apart by their opcode and
+/// index.
+#[derive(Debug)]
+pub struct MgmtSocket {
+    socket: Socket2,
+}
+
+impl MgmtSocket {
+    pub fn new() -> Result<MgmtSocket> {
+        let socket = bind_channel(MGMT_INDEX_NONE, HCI_CHANNEL_CONTROL)?;
+        Ok(MgmtSocket { socket })
+    }
+
+    /// Send a command to a controller, or to none with `MGMT_INDEX_NONE`.
+    pub fn send(&mut self, opcode: u16, index: u16, params: &[u8]) -> Result<()> {
+        let len = u16::try_from(params.len()).map_err(|_| Error::from(ErrorKind::InvalidInput))?;
+        let mut packet = Vec::with_capacity(MGMT_HEADER_SIZE + params.len());
+        packet.extend_from_slice(&opcode.to_le_bytes());
+        packet.extend_from_slice(&index.to_le_bytes());
+        packet.extend_from_slice(&len.to_le_bytes());
+        packet.extend_from_slice(params);
+        (&self.socket).write_all(&packet)
+    }
+
+    /// Receive the next event. An event whose length does not match its header fails with
+    /// `MalformedPacket`.
+    pub fn recv(&mut self) -> Result<MgmtEvent> {
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let size = (&self.socket).read(&mut buf)?;
+        let header = parse_header(&buf[..size]).ok_or(MalformedPacket)?;
+        Ok(MgmtEvent { header, data: buf[MGMT_HEADER_SIZE..size].into() })
+    }
+
+    /// Send a command and wait up to `timeout` milliseconds for its Command Complete or
+    /// Command Status event, or forever if `timeout` is negative. Returns the return
+    /// parameters, which are empty when the kernel answered with Command Status.
+    ///
+    /// Other events received meanwhile are dropped. A failure status is returned as a
+    /// `MgmtStatusError`.
+    pub fn request(&mut self, opcode: u16, index: u16, params: &[u8], timeout: c_int) -> Result<Box<[u8]>> {
+        let deadline = u64::try_from(timeout).ok()
+            .map(|ms| Instant::now() + Duration::from_millis(ms));
+        self.send(opcode, index, params)?;
+
+        let result = loop {
+            let remaining = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => Some(remaining),
+                    _ => break Err(Error::from_raw_os_error(ETIMEDOUT)),
+                },
+                None => None,
+            };
+            self.socket.set_read_timeout(remaining)?;
+
+            let event = match self.recv() {
+                Ok(event) => event,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+                Err(e) => break Err(e),
+            };
+            if event.header.index != index {
+                continue;
+            }
+            match parse_response(&event) {
+                Some(Ok((code, data))) if code == opcode => break Ok(data),
+                Some(Err(e)) => break Err(e),
+                _ => (),
+            }
+        };
+
+        self.socket.set_read_timeout(None)?;
+        result
+    }
+
+    /// Read the version of the management interface.
+    pub fn read_version(&mut self, timeout: c_int) -> Result<MgmtVersion> {
+        let data = self.request(MGMT_OP_READ_VERSION, MGMT_INDEX_NONE, &[], timeout)?;
+        match *data {
+            [version, r0, r1, ..] => Ok(MgmtVersion { version, revision: u16::from_le_bytes([r0, r1]) }),
+            _ => Err(MalformedPacket.into()),
+        }
+    }
+
+    /// Read the indices of the controllers known to the kernel.
+    pub fn read_index_list(&mut self, timeout: c_int) -> Result<Vec<u16>> {
+        let data = self.request(MGMT_OP_READ_INDEX_LIST, MGMT_INDEX_NONE, &[], timeout)?;
+        parse_index_list(&data)
+    }
+
+    /// Set whether receiving fails instead of blocking when no event is ready.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+}
+
+impl AsRawFd for MgmtSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// Parse the header of a packet whose length field matches the data following it.
+fn parse_header(packet: &[u8]) -> Option<MgmtHeader> {
+    let field = |i: usize| packet.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let (code, index, len) = (field(0)?, field(2)?, field(4)?);
+    (packet.len() - MGMT_HEADER_SIZE == usize::from(len)).then_some(MgmtHeader { code, index })
+}
+
+/// Return the opcode and return parameters of a Command Complete or Command Status event, or
+/// `None` for other events. A failure status is returned as an error.
+fn parse_response(event: &MgmtEvent) -> Option<Result<(u16, Box<[u8]>)>> {
+    if !matches!(event.header.code, MGMT_EV_CMD_COMPLETE | MGMT_EV_CMD_STATUS) {
+        return None;
+    }
+    let response = match *event.data {
+        [o0, o1, 0, ref data @ ..] => Ok((u16::from_le_bytes([o0, o1]), data.into())),
+        [_, _, status, ..] => Err(MgmtStatusError(status).into()),
+        _ => Err(MalformedPacket.into()),
+    };
+    Some(response)
+}
+
+/// Parse the return parameters of Read Controller Index List.
+fn parse_index_list(data: &[u8]) -> Result<Vec<u16>> {
+    let (count, indices) = match data {
+        [c0, c1, indices @ ..] => (usize::from(u16::from_le_bytes([*c0, *c1])), indices),
+        _ => return Err(MalformedPacket.into()),
+    };
+    if indices.len() != count * 2 {
+        return Err(MalformedPacket.into());
+    }
+    Ok(indices.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(code: u16, data: &[u8]) -> MgmtEvent {
+        MgmtEvent { header: MgmtHeader { code, index: MGMT_INDEX_NONE }, data: data.into() }
+    }
+
+    #[test]
+    fn packets_with_wrong_lengths_are_rejected() {
+        assert_eq!(parse_header(&[0x01, 0x00, 0xFF, 0xFF]), None);
+        assert_eq!(parse_header(&[0x01, 0x00, 0xFF, 0xFF, 0x02, 0x00, 0x01]), None);
+        assert_eq!(parse_header(&[0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01]),
+            Some(MgmtHeader { code: 0x0001, index: 0 }));
+    }
+
+    #[test]
+    fn command_complete_carries_return_parameters() {
+        let (opcode, data) = parse_response(&event(MGMT_EV_CMD_COMPLETE, &[0x01, 0x00, 0x00, 0x01, 0x16, 0x00]))
+            .unwrap().unwrap();
+        assert_eq!(opcode, MGMT_OP_READ_VERSION);
+        assert_eq!(&*data, &[0x01, 0x16, 0x00]);
+
+        let (opcode, data) = parse_response(&event(MGMT_EV_CMD_STATUS, &[0x05, 0x00, 0x00])).unwrap().unwrap();
+        assert_eq!(opcode, 0x0005);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn failure_status_is_an_error() {
+        let e = parse_response(&event(MGMT_EV_CMD_STATUS, &[0x05, 0x00, 0x14])).unwrap().unwrap_err();
+        let status = e.get_ref().and_then(|e| e.downcast_ref::<MgmtStatusError>());
+        assert_eq!(status, Some(&MgmtStatusError(0x14)));
+
+        let e = parse_response(&event(MGMT_EV_CMD_COMPLETE, &[0x05, 0x00])).unwrap().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn other_events_are_not_responses() {
+        assert!(parse_response(&event(0x0004, &[0x01, 0x00, 0x00])).is_none());
+    }
+
+    #[test]
+    fn index_list_counts_its_entries() {
+        assert_eq!(parse_index_list(&[0x02, 0x00, 0x00, 0x00, 0x01, 0x00]).unwrap(), vec![0, 1]);
+        assert_eq!(parse_index_list(&[0x00, 0x00]).unwrap(), Vec::<u16>::new());
+        assert!(parse_index_list(&[0x02, 0x00, 0x00, 0x00]).is_err());
+        assert!(parse_index_list(&[0x01]).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::io::{Read, Result};
+use std::os::fd::{AsRawFd, RawFd};
+use socket2::Socket as Socket2;
+
+use super::error::MalformedPacket;
+use super::event_loop::PacketSource;
+use super::socket::{bind_channel, HCI_CHANNEL_MONITOR};
+
+/// Device index of sockets not bound to any device
+const HCI_DEV_NONE: u16 = 0xFFFF;
+/// Length of the opcode, index and length fields preceding each frame
+const MONITOR_HEADER_SIZE: usize = 6;
+/// Largest frame the monitor channel delivers, an ISO data packet with its headers
+const MAX_FRAME_SIZE: usize = MONITOR_HEADER_SIZE + 4 + 16384;
+
+const MONITOR_COMMAND_PKT: u16 = 0x0002;
+const MONITOR_EVENT_PKT: u16 = 0x0003;
+const MONITOR_ACL_TX_PKT: u16 = 0x0004;
+const MONITOR_ACL_RX_PKT: u16 = 0x0005;
+const MONITOR_SCO_TX_PKT: u16 = 0x0006;
+const MONITOR_SCO_RX_PKT: u16 = 0x0007;
+const MONITOR_ISO_TX_PKT: u16 = 0x0012;
+const MONITOR_ISO_RX_PKT: u16 = 0x0013;
+
+/// Header of a frame received on the monitor channel
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MonitorHeader {
+    /// Kind of the frame, such as 0x0003 for an event
+    pub opcode: u16,
+    /// Index of the device the frame is about, or 0xFFFF for none
+    pub index: u16,
+}
+
+impl MonitorHeader {
+    /// Return the packet type indicator of the HCI packet the frame carries, if it carries one
+    pub fn packet_type(&self) -> Option<u8> {
+        match self.opcode {
+            MONITOR_COMMAND_PKT => Some(0x01),
+            MONITOR_ACL_TX_PKT | MONITOR_ACL_RX_PKT => Some(0x02),
+            MONITOR_SCO_TX_PKT | MONITOR_SCO_RX_PKT => Some(0x03),
+            MONITOR_EVENT_PKT => Some(0x04),
+            MONITOR_ISO_TX_PKT | MONITOR_ISO_RX_PKT => Some(0x05),
+            _ => None,
+        }
+    }
+
+    /// Return whether the frame carries a packet sent by the host to the controller
+    pub fn is_sent(&self) -> bool {
+        matches!(self.opcode, MONITOR_COMMAND_PKT | MONITOR_ACL_TX_PKT | MONITOR_SCO_TX_PKT | MONITOR_ISO_TX_PKT)
+    }
+}
+
+/// A frame received on the monitor channel
+#[derive(Clone, Debug)]
+pub struct MonitorFrame {
+    pub header: MonitorHeader,
+    /// Data of the frame, such as the HCI packet without its packet type indicator
+    pub data: Box<[u8]>,
+}
+
+/// Socket on the monitor channel, receiving a copy of the packets of every device, like
+/// btmon does. Needs `CAP_NET_RAW`.
+///
+/// A monitor socket can be registered with an `EventLoop`, which is then passed the HCI
+/// packets of the frames. The handler can tell which device a packet belongs to from
+/// `last_header`.
+#[derive(Debug)]
+pub struct MonitorSocket {
+    socket: Socket2,
+    last_header: Option<MonitorHeader>,
+}
+
+impl MonitorSocket {
+    pub fn new() -> Result<MonitorSocket> {
+        let socket = bind_channel(HCI_DEV_NONE, HCI_CHANNEL_MONITOR)?;
+        Ok(MonitorSocket { socket, last_header: None })
+    }
+
+    /// Receive the next frame. A frame whose length does not match its header fails with
+    /// `MalformedPacket`.
+    pub fn recv(&mut self) -> Result<MonitorFrame> {
+        let mut buf = vec![0; MAX_FRAME_SIZE];
+        let size = self.recv_frame(&mut buf)?;
+        let header = self.last_header.ok_or(MalformedPacket)?;
+        Ok(MonitorFrame { header, data: buf[MONITOR_HEADER_SIZE..size].into() })
+    }
+
+    /// Return the header of the last frame received
+    pub fn last_header(&self) -> Option<MonitorHeader> {
+        self.last_header
+    }
+
+    /// Set whether receiving fails instead of blocking when no frame is ready.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    /// Receive one frame into `buf`, recording its header if it is well formed, and return its
+    /// length with the header.
+    fn recv_frame(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let size = (&self.socket).read(buf)?;
+        self.last_header = parse_header(&buf[..size]);
+        Ok(size)
+    }
+}
+
+/// Parse the header of a frame whose length field matches the data following it.
+fn parse_header(frame: &[u8]) -> Option<MonitorHeader> {
+    let field = |i: usize| frame.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let (opcode, index, len) = (field(0)?, field(2)?, field(4)?);
+    (frame.len() - MONITOR_HEADER_SIZE == usize::from(len)).then_some(MonitorHeader { opcode, index })
+}
+
+/// Turn a frame at the start of `buf` into the HCI packet it carries, preceded by its packet
+/// type indicator, and return the length of the packet, or 0 if the frame carries none.
+fn frame_to_packet(header: MonitorHeader, buf: &mut [u8], size: usize) -> usize {
+    match header.packet_type() {
+        Some(packet_type) => {
+            buf[MONITOR_HEADER_SIZE - 1] = packet_type;
+            buf.copy_within(MONITOR_HEADER_SIZE - 1..size, 0);
+            size - (MONITOR_HEADER_SIZE - 1)
+        }
+        None => 0,
+    }
+}
+
+/// Frames not carrying HCI packets, such as notes about new devices, and malformed frames are
+/// passed on empty, so the event loop drops them.
+impl PacketSource for MonitorSocket {
+    fn recv_packet(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let size = self.recv_frame(buf)?;
+        match self.last_header {
+            Some(header) => Ok(frame_to_packet(header, buf, size)),
+            None => Ok(0),
+        }
+    }
+}
+
+impl AsRawFd for MonitorSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_with_wrong_lengths_are_rejected() {
+        assert_eq!(parse_header(&[0x03, 0x00, 0x00, 0x00]), None);
+        assert_eq!(parse_header(&[0x03, 0x00, 0x00, 0x00, 0x02, 0x00, 0x0E]), None);
+        assert_eq!(parse_header(&[0x03, 0x00, 0x01, 0x00, 0x01, 0x00, 0x0E]),
+            Some(MonitorHeader { opcode: 0x0003, index: 1 }));
+    }
+
+    #[test]
+    fn events_become_h4_packets() {
+        let mut buf = [0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x0E, 0x01, 0x01];
+        let header = parse_header(&buf).unwrap();
+        let size = frame_to_packet(header, &mut buf, 9);
+        assert_eq!(&buf[..size], &[0x04, 0x0E, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn index_notes_carry_no_packet() {
+        let mut buf = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let header = parse_header(&buf).unwrap();
+        assert_eq!(frame_to_packet(header, &mut buf, 6), 0);
+    }
+}
@@ -0,0 +1,468 @@
+use libc::{c_int};
+use std::io::{Error, Read, Result};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+
+use super::addr::BdAddr;
+use super::codec::{self, EventBody};
+use super::connection::Connection;
+use super::error::StatusError;
+use super::filter::HciFilter;
+use super::io::{read_bytes, ReadAs, ReadFrom, WriteTo};
+use super::socket::{EVT_CMD_COMPLETE, EVT_CMD_STATUS, HCI_EVENT_PKT};
+
+const OGF_LINK_CTL: u16 = 0x01;
+const OCF_LINK_KEY_REPLY: u16 = 0x000B;
+const OCF_LINK_KEY_NEG_REPLY: u16 = 0x000C;
+const OCF_PIN_CODE_REPLY: u16 = 0x000D;
+const OCF_PIN_CODE_NEG_REPLY: u16 = 0x000E;
+const OCF_AUTH_REQUESTED: u16 = 0x0011;
+const OCF_IO_CAPABILITY_REPLY: u16 = 0x002B;
+const OCF_USER_CONFIRM_REPLY: u16 = 0x002C;
+const OCF_USER_CONFIRM_NEG_REPLY: u16 = 0x002D;
+const OCF_USER_PASSKEY_REPLY: u16 = 0x002E;
+const OCF_USER_PASSKEY_NEG_REPLY: u16 = 0x002F;
+const OCF_REMOTE_OOB_DATA_NEG_REPLY: u16 = 0x0033;
+const OCF_IO_CAPABILITY_NEG_REPLY: u16 = 0x0034;
+
+/// Commands answering pairing events, whose failures end pairing
+const REPLY_OCFS: [u16; 11] = [
+    OCF_LINK_KEY_REPLY, OCF_LINK_KEY_NEG_REPLY, OCF_PIN_CODE_REPLY, OCF_PIN_CODE_NEG_REPLY,
+    OCF_IO_CAPABILITY_REPLY, OCF_USER_CONFIRM_REPLY, OCF_USER_CONFIRM_NEG_REPLY,
+    OCF_USER_PASSKEY_REPLY, OCF_USER_PASSKEY_NEG_REPLY, OCF_REMOTE_OOB_DATA_NEG_REPLY,
+    OCF_IO_CAPABILITY_NEG_REPLY,
+];
+
+const EVT_AUTH_COMPLETE: u8 = 0x06;
+const EVT_PIN_CODE_REQ: u8 = 0x16;
+const EVT_LINK_KEY_REQ: u8 = 0x17;
+const EVT_LINK_KEY_NOTIFY: u8 = 0x18;
+const EVT_IO_CAPABILITY_REQUEST: u8 = 0x31;
+const EVT_IO_CAPABILITY_RESPONSE: u8 = 0x32;
+const EVT_USER_CONFIRM_REQUEST: u8 = 0x33;
+const EVT_USER_PASSKEY_REQUEST: u8 = 0x34;
+const EVT_REMOTE_OOB_DATA_REQUEST: u8 = 0x35;
+const EVT_SIMPLE_PAIRING_COMPLETE: u8 = 0x36;
+const EVT_USER_PASSKEY_NOTIFY: u8 = 0x3B;
+
+/// Longest PIN code of legacy pairing
+const MAX_PIN_LEN: usize = 16;
+/// Largest passkey, six decimal digits
+const MAX_PASSKEY: u32 = 999_999;
+
+/// Input and output capabilities of a device, which select the pairing method
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IoCapability {
+    DisplayOnly = 0x00,
+    #[default]
+    DisplayYesNo = 0x01,
+    KeyboardOnly = 0x02,
+    NoInputNoOutput = 0x03,
+}
+
+impl TryFrom<u8> for IoCapability {
+    type Error = Error;
+
+    fn try_from(capability: u8) -> Result<Self> {
+        match capability {
+            0x00 => Ok(IoCapability::DisplayOnly),
+            0x01 => Ok(IoCapability::DisplayYesNo),
+            0x02 => Ok(IoCapability::KeyboardOnly),
+            0x03 => Ok(IoCapability::NoInputNoOutput),
+            _ => Err(Error::new(InvalidData, "Unknown IO capability")),
+        }
+    }
+}
+
+/// Bonding and man-in-the-middle protection requested during Secure Simple Pairing
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AuthenticationRequirements {
+    NoBonding = 0x00,
+    NoBondingMitm = 0x01,
+    DedicatedBonding = 0x02,
+    DedicatedBondingMitm = 0x03,
+    GeneralBonding = 0x04,
+    #[default]
+    GeneralBondingMitm = 0x05,
+}
+
+impl TryFrom<u8> for AuthenticationRequirements {
+    type Error = Error;
+
+    fn try_from(requirements: u8) -> Result<Self> {
+        match requirements {
+            0x00 => Ok(AuthenticationRequirements::NoBonding),
+            0x01 => Ok(AuthenticationRequirements::NoBondingMitm),
+            0x02 => Ok(AuthenticationRequirements::DedicatedBonding),
+            0x03 => Ok(AuthenticationRequirements::DedicatedBondingMitm),
+            0x04 => Ok(AuthenticationRequirements::GeneralBonding),
+            0x05 => Ok(AuthenticationRequirements::GeneralBondingMitm),
+            _ => Err(Error::new(InvalidData, "Unknown authentication requirements")),
+        }
+    }
+}
+
+code_enum!(
+    /// Type of a BR/EDR link key
+    LinkKeyType {
+        Combination = 0x00,
+        LocalUnit = 0x01,
+        RemoteUnit = 0x02,
+        DebugCombination = 0x03,
+        UnauthenticatedP192 = 0x04,
+        AuthenticatedP192 = 0x05,
+        ChangedCombination = 0x06,
+        UnauthenticatedP256 = 0x07,
+        AuthenticatedP256 = 0x08,
+    }
+);
+
+/// Link key shared with a remote device after pairing
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LinkKey {
+    pub address: BdAddr,
+    pub key: [u8; 16],
+    pub key_type: LinkKeyType,
+}
+
+impl ReadFrom for LinkKey {
+    /// Read the parameters of a Link Key Notification event.
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (address, _) = r.read_as::<BdAddr>()?;
+        let mut key = [0u8; 16];
+        read_bytes(&mut r, &mut key)?;
+        let (key_type, _) = r.read_as::<u8>()?;
+        Ok((LinkKey { address, key, key_type: key_type.into() }, 23))
+    }
+}
+
+/// IO capabilities announced by the remote device
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RemoteIoCapability {
+    pub io_capability: IoCapability,
+    pub oob_data_present: bool,
+    pub authentication: AuthenticationRequirements,
+}
+
+/// Answers to the questions a pairing asks the user.
+///
+/// Each method is called while pairing waits for the answer, so implementations that prompt a
+/// user block the pairing until the user responds.
+pub trait PairingAgent {
+    /// Return whether the user confirms that `value` is shown on both devices.
+    ///
+    /// Also called with pairing methods that need no user input, where returning true accepts
+    /// the pairing.
+    fn confirm(&mut self, address: BdAddr, value: u32) -> bool;
+
+    /// Return the passkey shown on the remote device, or `None` to reject pairing.
+    fn passkey(&mut self, address: BdAddr) -> Option<u32>;
+
+    /// Show a passkey for the user to type on the remote device.
+    fn display_passkey(&mut self, _address: BdAddr, _passkey: u32) {}
+
+    /// Return the PIN code for legacy pairing, or `None` to reject pairing.
+    fn pin_code(&mut self, address: BdAddr) -> Option<Vec<u8>>;
+
+    /// Return a link key stored from an earlier pairing, or `None` to pair again.
+    fn link_key(&mut self, _address: BdAddr) -> Option<LinkKey> {
+        None
+    }
+}
+
+/// Parameters sent in the IO capability exchange of Secure Simple Pairing
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PairingParams {
+    pub io_capability: IoCapability,
+    pub authentication: AuthenticationRequirements,
+}
+
+/// Progress of a pairing
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PairingState {
+    /// Authentication has not been requested yet
+    Idle,
+    /// Waiting for the controller to ask for a link key or start pairing
+    Authenticating,
+    /// Exchanging IO capabilities for Secure Simple Pairing
+    IoCapabilityExchange,
+    /// Comparing a numeric value, or accepting pairing without user input
+    NumericComparison,
+    /// Entering or displaying a passkey
+    PasskeyEntry,
+    /// Legacy pairing with a PIN code
+    PinEntry,
+    /// A link key was created or supplied, waiting for authentication to complete
+    KeyExchanged,
+    /// The link is authenticated
+    Complete,
+    /// Pairing failed with an error code
+    Failed(u8),
+}
+
+/// An event relevant to pairing, copied out of the receive buffer
+enum Received {
+    /// A reply command or Authentication Requested failed
+    CommandFailed(u8),
+    Event(u8, Box<[u8]>),
+    Ignored,
+}
+
+/// Pairing with the remote device of a BR/EDR connection.
+///
+/// The pairing answers Link Key Request, IO Capability Request, PIN Code Request and user
+/// confirmation events for the remote device, asking the agent when user input is needed.
+/// While the pairing exists, the socket filter only passes pairing events. The previous filter
+/// is restored when the pairing is dropped.
+pub struct Pairing<'c, 'a, A: PairingAgent> {
+    connection: &'c mut Connection<'a>,
+    address: BdAddr,
+    params: PairingParams,
+    agent: A,
+    timeout: c_int,
+    old_filter: HciFilter,
+    state: PairingState,
+    remote: Option<RemoteIoCapability>,
+    link_key: Option<LinkKey>,
+}
+
+impl<'c, 'a, A: PairingAgent> Pairing<'c, 'a, A> {
+    /// Prepare to pair with `address`, the remote device of `connection`.
+    ///
+    /// Each wait for an event gives up after `timeout` milliseconds, or never if the timeout is
+    /// not positive.
+    pub fn new(connection: &'c mut Connection<'a>, address: BdAddr, params: PairingParams, agent: A, timeout: c_int) -> Result<Self> {
+        let socket = connection.socket();
+        let old_filter = socket.get_filter()?;
+        let mut filter = HciFilter::default();
+        filter.set_type(HCI_EVENT_PKT)?;
+        for event in [
+            EVT_CMD_COMPLETE, EVT_CMD_STATUS, EVT_AUTH_COMPLETE, EVT_PIN_CODE_REQ,
+            EVT_LINK_KEY_REQ, EVT_LINK_KEY_NOTIFY, EVT_IO_CAPABILITY_REQUEST,
+            EVT_IO_CAPABILITY_RESPONSE, EVT_USER_CONFIRM_REQUEST, EVT_USER_PASSKEY_REQUEST,
+            EVT_REMOTE_OOB_DATA_REQUEST, EVT_SIMPLE_PAIRING_COMPLETE, EVT_USER_PASSKEY_NOTIFY,
+        ] {
+            filter.set_event(event)?;
+        }
+        socket.set_filter(&filter)?;
+
+        Ok(Pairing {
+            connection,
+            address,
+            params,
+            agent,
+            timeout,
+            old_filter,
+            state: PairingState::Idle,
+            remote: None,
+            link_key: None,
+        })
+    }
+
+    /// Return the progress of the pairing
+    pub fn state(&self) -> PairingState {
+        self.state
+    }
+
+    /// Return the IO capabilities of the remote device, once they were exchanged
+    pub fn remote_io_capability(&self) -> Option<&RemoteIoCapability> {
+        self.remote.as_ref()
+    }
+
+    /// Return the link key, once it was created or supplied by the agent
+    pub fn link_key(&self) -> Option<&LinkKey> {
+        self.link_key.as_ref()
+    }
+
+    /// Return the agent answering pairing questions
+    pub fn agent(&mut self) -> &mut A {
+        &mut self.agent
+    }
+
+    /// Send a reply to a pairing event without waiting for its Command Complete event, which
+    /// is checked by `step` so that pairing events following it are not filtered out.
+    fn reply(&mut self, ocf: u16, param: &[u8]) -> Result<()> {
+        self.connection.socket().send_cmd(OGF_LINK_CTL, ocf, param)
+            .map(|_| ())
+    }
+
+    /// Request authentication of the connection, which starts pairing if no link key is known.
+    pub fn start(&mut self) -> Result<()> {
+        let handle = self.connection.handle();
+        self.connection.socket().send_cmd(OGF_LINK_CTL, OCF_AUTH_REQUESTED, &handle.to_le_bytes())?;
+        self.state = PairingState::Authenticating;
+        Ok(())
+    }
+
+    /// Wait for the next pairing event of the remote device and answer it, returning the new
+    /// state.
+    pub fn step(&mut self) -> Result<PairingState> {
+        let received = self.connection.socket().recv_event_body(self.timeout, |event| match *event {
+            EventBody::CommandComplete { ncmd: _, opcode, params }
+            if codec::ogf(opcode) == OGF_LINK_CTL && REPLY_OCFS.contains(&codec::ocf(opcode)) => {
+                match params.first() {
+                    Some(&status) if status != 0 => Received::CommandFailed(status),
+                    _ => Received::Ignored,
+                }
+            }
+            EventBody::CommandStatus { status, ncmd: _, opcode }
+            if opcode == codec::opcode(OGF_LINK_CTL, OCF_AUTH_REQUESTED) && status != 0 => {
+                Received::CommandFailed(status)
+            }
+            EventBody::Other { code, params } => Received::Event(code, params.into()),
+            _ => Received::Ignored,
+        })?;
+
+        match received {
+            Received::CommandFailed(status) => self.state = PairingState::Failed(status),
+            Received::Event(code, params) => self.handle_event(code, &params)?,
+            Received::Ignored => (),
+        }
+        Ok(self.state)
+    }
+
+    fn handle_event(&mut self, code: u8, params: &[u8]) -> Result<()> {
+        let mut r = params;
+        if code == EVT_AUTH_COMPLETE {
+            let (status, _) = r.read_as::<u8>()?;
+            let (handle, _) = r.read_as::<u16>()?;
+            if handle == self.connection.handle() {
+                self.state = match status {
+                    0 => PairingState::Complete,
+                    _ => PairingState::Failed(status),
+                };
+            }
+            return Ok(());
+        }
+        if code == EVT_SIMPLE_PAIRING_COMPLETE {
+            let (status, _) = r.read_as::<u8>()?;
+            let (address, _) = r.read_as::<BdAddr>()?;
+            if address == self.address && status != 0 {
+                self.state = PairingState::Failed(status);
+            }
+            return Ok(());
+        }
+
+        // Every other pairing event starts with the address of the remote device.
+        let (address, _) = r.read_as::<BdAddr>()?;
+        if address != self.address {
+            return Ok(());
+        }
+        let addr = address.bytes()?.into_vec();
+        match code {
+            EVT_LINK_KEY_REQ => match self.agent.link_key(address) {
+                Some(link_key) => {
+                    let mut param = addr;
+                    param.extend_from_slice(&link_key.key);
+                    self.reply(OCF_LINK_KEY_REPLY, &param)?;
+                    self.link_key = Some(link_key);
+                    self.state = PairingState::KeyExchanged;
+                }
+                None => self.reply(OCF_LINK_KEY_NEG_REPLY, &addr)?,
+            },
+            EVT_PIN_CODE_REQ => {
+                self.state = PairingState::PinEntry;
+                match self.agent.pin_code(address) {
+                    Some(pin) => {
+                        if pin.is_empty() || pin.len() > MAX_PIN_LEN {
+                            self.reply(OCF_PIN_CODE_NEG_REPLY, &addr)?;
+                            return Err(Error::new(InvalidInput, "Invalid PIN code length"));
+                        }
+                        let mut param = addr;
+                        param.push(pin.len() as u8);
+                        param.extend_from_slice(&pin);
+                        param.resize(6 + 1 + MAX_PIN_LEN, 0);
+                        self.reply(OCF_PIN_CODE_REPLY, &param)?;
+                    }
+                    None => self.reply(OCF_PIN_CODE_NEG_REPLY, &addr)?,
+                }
+            }
+            EVT_IO_CAPABILITY_REQUEST => {
+                self.state = PairingState::IoCapabilityExchange;
+                let mut param = addr;
+                // Out-of-band pairing data is not supported.
+                param.extend_from_slice(&[self.params.io_capability as u8, 0x00, self.params.authentication as u8]);
+                self.reply(OCF_IO_CAPABILITY_REPLY, &param)?;
+            }
+            EVT_IO_CAPABILITY_RESPONSE => {
+                let (io_capability, _) = r.read_as::<u8>()?;
+                let (oob_data_present, _) = r.read_as::<u8>()?;
+                let (authentication, _) = r.read_as::<u8>()?;
+                self.remote = Some(RemoteIoCapability {
+                    io_capability: IoCapability::try_from(io_capability)?,
+                    oob_data_present: oob_data_present != 0,
+                    authentication: AuthenticationRequirements::try_from(authentication)?,
+                });
+            }
+            EVT_USER_CONFIRM_REQUEST => {
+                self.state = PairingState::NumericComparison;
+                let (value, _) = r.read_as::<u32>()?;
+                let ocf = if self.agent.confirm(address, value) {
+                    OCF_USER_CONFIRM_REPLY
+                } else {
+                    OCF_USER_CONFIRM_NEG_REPLY
+                };
+                self.reply(ocf, &addr)?;
+            }
+            EVT_USER_PASSKEY_REQUEST => {
+                self.state = PairingState::PasskeyEntry;
+                match self.agent.passkey(address).filter(|&passkey| passkey <= MAX_PASSKEY) {
+                    Some(passkey) => {
+                        let mut param = addr;
+                        param.extend_from_slice(&passkey.to_le_bytes());
+                        self.reply(OCF_USER_PASSKEY_REPLY, &param)?;
+                    }
+                    None => self.reply(OCF_USER_PASSKEY_NEG_REPLY, &addr)?,
+                }
+            }
+            EVT_USER_PASSKEY_NOTIFY => {
+                self.state = PairingState::PasskeyEntry;
+                let (passkey, _) = r.read_as::<u32>()?;
+                self.agent.display_passkey(address, passkey);
+            }
+            EVT_REMOTE_OOB_DATA_REQUEST => {
+                self.reply(OCF_REMOTE_OOB_DATA_NEG_REPLY, &addr)?;
+            }
+            EVT_LINK_KEY_NOTIFY => {
+                let (link_key, _) = (&*params).read_as::<LinkKey>()?;
+                self.link_key = Some(link_key);
+                self.state = PairingState::KeyExchanged;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Pair until the link is authenticated, returning the link key.
+    ///
+    /// Authentication is requested first if `start` was not called.
+    pub fn run(&mut self) -> Result<LinkKey> {
+        if self.state == PairingState::Idle {
+            self.start()?;
+        }
+        loop {
+            match self.step()? {
+                PairingState::Complete => {
+                    return self.link_key
+                        .ok_or_else(|| Error::new(InvalidData, "Authenticated without a link key"));
+                }
+                PairingState::Failed(status) => return Err(StatusError(status).into()),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<A: PairingAgent> Drop for Pairing<'_, '_, A> {
+    fn drop(&mut self) {
+        let _ = self.connection.socket().set_filter(&self.old_filter);
+    }
+}
+
+impl Connection<'_> {
+    /// Pair with `address`, the remote device of this connection, returning the link key.
+    ///
+    /// Uses Secure Simple Pairing or legacy PIN pairing, whichever the devices support.
+    pub fn pair<A: PairingAgent>(&mut self, address: BdAddr, params: PairingParams, agent: A, timeout: c_int) -> Result<LinkKey> {
+        Pairing::new(self, address, params, agent, timeout)?.run()
+    }
+}
@@ -0,0 +1,25 @@
+/// Most buffers kept for reuse by one pool
+const MAX_POOLED_BUFFERS: usize = 4;
+
+/// Receive buffers recycled between reads, so packets can be received without allocating.
+#[derive(Debug, Default)]
+pub(crate) struct BufferPool {
+    buffers: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// Take a zeroed buffer of the given length, reusing a returned buffer if there is one.
+    pub fn take(&mut self, len: usize) -> Vec<u8> {
+        let mut buf = self.buffers.pop().unwrap_or_default();
+        buf.clear();
+        buf.resize(len, 0);
+        buf
+    }
+
+    /// Return a buffer for reuse.
+    pub fn give(&mut self, buf: Vec<u8>) {
+        if self.buffers.len() < MAX_POOLED_BUFFERS {
+            self.buffers.push(buf);
+        }
+    }
+}
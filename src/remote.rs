@@ -0,0 +1,172 @@
+#[cfg(feature = "bredr")]
+use bitflags::bitflags;
+use libc::{c_int};
+use std::io::{Read, Result};
+
+use super::company::CompanyId;
+use super::connection::Connection;
+use super::error::{MalformedPacket, StatusError};
+use super::io::{ReadAs, ReadFrom};
+use super::version::LmpVersion;
+
+const OGF_LINK_CTL: u16 = 0x01;
+#[cfg(feature = "bredr")]
+const OCF_READ_REMOTE_FEATURES: u16 = 0x001B;
+#[cfg(feature = "bredr")]
+const OCF_READ_REMOTE_EXT_FEATURES: u16 = 0x001C;
+const OCF_READ_REMOTE_VERSION: u16 = 0x001D;
+
+#[cfg(feature = "bredr")]
+const EVT_READ_REMOTE_FEATURES_COMPLETE: u8 = 0x0B;
+const EVT_READ_REMOTE_VERSION_COMPLETE: u8 = 0x0C;
+#[cfg(feature = "bredr")]
+const EVT_READ_REMOTE_EXT_FEATURES_COMPLETE: u8 = 0x23;
+
+/// Versions and manufacturer of a remote controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RemoteVersion {
+    pub version: LmpVersion,
+    pub company: CompanyId,
+    /// Revision of the link manager or link layer implementation, defined by the manufacturer
+    pub subversion: u16,
+}
+
+impl ReadFrom for RemoteVersion {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (version, version_size) = r.read_as::<u8>()?;
+        let (company, company_size) = r.read_as::<u16>()?;
+        let (subversion, subversion_size) = r.read_as::<u16>()?;
+        Ok((RemoteVersion {
+            version: version.into(),
+            company: company.into(),
+            subversion,
+        }, version_size + company_size + subversion_size))
+    }
+}
+
+#[cfg(feature = "bredr")]
+bitflags! {
+    /// Features on page 0 of the LMP feature mask of a BR/EDR controller
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct LmpFeatures: u64 {
+        const THREE_SLOT_PACKETS = 1 << 0;
+        const FIVE_SLOT_PACKETS = 1 << 1;
+        const ENCRYPTION = 1 << 2;
+        const SLOT_OFFSET = 1 << 3;
+        const TIMING_ACCURACY = 1 << 4;
+        const ROLE_SWITCH = 1 << 5;
+        const HOLD_MODE = 1 << 6;
+        const SNIFF_MODE = 1 << 7;
+        const POWER_CONTROL_REQUESTS = 1 << 9;
+        const CHANNEL_QUALITY_DRIVEN_DATA_RATE = 1 << 10;
+        const SCO_LINK = 1 << 11;
+        const HV2_PACKETS = 1 << 12;
+        const HV3_PACKETS = 1 << 13;
+        const U_LAW = 1 << 14;
+        const A_LAW = 1 << 15;
+        const CVSD = 1 << 16;
+        const PAGING_PARAMETER_NEGOTIATION = 1 << 17;
+        const POWER_CONTROL = 1 << 18;
+        const TRANSPARENT_SCO_DATA = 1 << 19;
+        const BROADCAST_ENCRYPTION = 1 << 23;
+        const EDR_ACL_2M = 1 << 25;
+        const EDR_ACL_3M = 1 << 26;
+        const ENHANCED_INQUIRY_SCAN = 1 << 27;
+        const INTERLACED_INQUIRY_SCAN = 1 << 28;
+        const INTERLACED_PAGE_SCAN = 1 << 29;
+        const RSSI_WITH_INQUIRY_RESULTS = 1 << 30;
+        const EV3_PACKETS = 1 << 31;
+        const EV4_PACKETS = 1 << 32;
+        const EV5_PACKETS = 1 << 33;
+        const AFH_CAPABLE_PERIPHERAL = 1 << 35;
+        const AFH_CLASSIFICATION_PERIPHERAL = 1 << 36;
+        const BREDR_NOT_SUPPORTED = 1 << 37;
+        const LE_SUPPORTED = 1 << 38;
+        const THREE_SLOT_EDR_ACL = 1 << 39;
+        const FIVE_SLOT_EDR_ACL = 1 << 40;
+        const SNIFF_SUBRATING = 1 << 41;
+        const PAUSE_ENCRYPTION = 1 << 42;
+        const AFH_CAPABLE_CENTRAL = 1 << 43;
+        const AFH_CLASSIFICATION_CENTRAL = 1 << 44;
+        const EDR_ESCO_2M = 1 << 45;
+        const EDR_ESCO_3M = 1 << 46;
+        const THREE_SLOT_EDR_ESCO = 1 << 47;
+        const EXTENDED_INQUIRY_RESPONSE = 1 << 48;
+        const SIMULTANEOUS_LE_BREDR = 1 << 49;
+        const SECURE_SIMPLE_PAIRING = 1 << 51;
+        const ENCAPSULATED_PDU = 1 << 52;
+        const ERRONEOUS_DATA_REPORTING = 1 << 53;
+        const NON_FLUSHABLE_PACKET_BOUNDARY_FLAG = 1 << 54;
+        const LINK_SUPERVISION_TIMEOUT_CHANGED_EVENT = 1 << 56;
+        const VARIABLE_INQUIRY_TX_POWER_LEVEL = 1 << 57;
+        const ENHANCED_POWER_CONTROL = 1 << 58;
+        const EXTENDED_FEATURES = 1 << 63;
+    }
+}
+
+/// One page of the LMP feature mask of a remote controller
+#[cfg(feature = "bredr")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RemoteFeaturesPage {
+    pub page: u8,
+    /// Highest page the remote controller has
+    pub max_page: u8,
+    /// Feature bits of the page. Page 0 can be read as `LmpFeatures`.
+    pub features: u64,
+}
+
+impl Connection<'_> {
+    /// Send a link control command for this connection and wait for the completion event of
+    /// the same connection, returning its parameters after the status and handle.
+    fn remote_request(&mut self, ocf: u16, param: &[u8], event: u8, timeout: c_int) -> Result<Box<[u8]>> {
+        let handle = self.handle().to_le_bytes();
+        let data = self.socket.send_req_matching(OGF_LINK_CTL, ocf,
+            event as c_int,
+            param,
+            timeout,
+            |_, data| data.get(1..3) == Some(&handle),
+        )?;
+        match data.split_first() {
+            Some((0, params)) => Ok(params.get(2..).unwrap_or_default().into()),
+            Some((&status, _)) => Err(StatusError(status).into()),
+            None => Err(MalformedPacket.into()),
+        }
+    }
+
+    /// Read the versions and manufacturer of the remote controller.
+    ///
+    /// Works on BR/EDR and LE connections. The controller asks the remote device only once
+    /// per connection and answers later requests from memory.
+    pub fn read_remote_version(&mut self, timeout: c_int) -> Result<RemoteVersion> {
+        let handle = self.handle().to_le_bytes();
+        let data = self.remote_request(OCF_READ_REMOTE_VERSION, &handle, EVT_READ_REMOTE_VERSION_COMPLETE, timeout)?;
+        let (version, _) = (&*data).read_as::<RemoteVersion>()?;
+        Ok(version)
+    }
+
+    /// Read page 0 of the LMP features supported by the remote controller of a BR/EDR
+    /// connection.
+    #[cfg(feature = "bredr")]
+    pub fn read_remote_features(&mut self, timeout: c_int) -> Result<LmpFeatures> {
+        let handle = self.handle().to_le_bytes();
+        let data = self.remote_request(OCF_READ_REMOTE_FEATURES, &handle, EVT_READ_REMOTE_FEATURES_COMPLETE, timeout)?;
+        let (features, _) = (&*data).read_as::<u64>()?;
+        Ok(LmpFeatures::from_bits_retain(features))
+    }
+
+    /// Read a page of the LMP features supported by the remote controller of a BR/EDR
+    /// connection.
+    ///
+    /// Needs `LmpFeatures::EXTENDED_FEATURES` on both sides.
+    #[cfg(feature = "bredr")]
+    pub fn read_remote_extended_features(&mut self, page: u8, timeout: c_int) -> Result<RemoteFeaturesPage> {
+        let mut param = self.handle().to_le_bytes().to_vec();
+        param.push(page);
+        let data = self.remote_request(OCF_READ_REMOTE_EXT_FEATURES, &param, EVT_READ_REMOTE_EXT_FEATURES_COMPLETE, timeout)?;
+        let mut r = &*data;
+        let (page, _) = r.read_as::<u8>()?;
+        let (max_page, _) = r.read_as::<u8>()?;
+        let (features, _) = r.read_as::<u64>()?;
+        Ok(RemoteFeaturesPage { page, max_page, features })
+    }
+}
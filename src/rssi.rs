@@ -0,0 +1,168 @@
+use libc::{c_int};
+#[cfg(feature = "le")]
+use libc::{ETIMEDOUT};
+use std::io::{Result};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::connection::Connection;
+use super::io::ReadAs;
+#[cfg(feature = "le")]
+use super::le::{PowerControlPhy, PowerReportReason};
+
+const OGF_STATUS_PARAM: u16 = 0x05;
+const OCF_READ_RSSI: u16 = 0x0005;
+
+/// A signal strength measurement on a connection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RssiSample {
+    pub handle: u16,
+    /// Received signal strength
+    ///
+    /// On LE links this is the absolute strength in dBm. On BR/EDR links it is the difference in
+    /// dB to the golden receive power range, where zero means the signal is within the range.
+    pub rssi: i8,
+    /// Transmit power of the remote device in dBm, if it was reported on an LE link
+    pub remote_tx_power: Option<i8>,
+    pub time: Instant,
+}
+
+impl RssiSample {
+    /// Return the path loss in dB, if the transmit power of the remote device is known
+    pub fn path_loss(&self) -> Option<i16> {
+        self.remote_tx_power.map(|tx_power| i16::from(tx_power) - i16::from(self.rssi))
+    }
+}
+
+impl<'a> Connection<'a> {
+    /// Read the received signal strength on this connection.
+    pub fn read_rssi(&mut self, timeout: c_int) -> Result<i8> {
+        let handle = self.handle();
+        let data = self.socket.execute(OGF_STATUS_PARAM, OCF_READ_RSSI, &handle.to_le_bytes(), timeout)?;
+        let mut r: &[u8] = &data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (rssi, _) = r.read_as::<i8>()?;
+        Ok(rssi)
+    }
+
+    /// Sample the signal strength on this connection every `interval`.
+    ///
+    /// On LE connections established by this crate, transmit power reporting is turned on if
+    /// the controller supports it, so samples also carry the transmit power of the remote
+    /// device. Reporting is turned off again when the monitor is dropped.
+    pub fn monitor_rssi(&mut self, interval: Duration, timeout: c_int) -> Result<RssiMonitor<'_, 'a>> {
+        let mut monitor = RssiMonitor {
+            connection: self,
+            interval,
+            timeout,
+            next: Instant::now(),
+            power_reporting: false,
+            remote_tx_power: None,
+        };
+        monitor.start_power_reporting()?;
+        Ok(monitor)
+    }
+}
+
+/// Signal strength samples of a connection, taken at a fixed interval.
+///
+/// Between samples of LE connections, the monitor listens for transmit power reports of the
+/// remote device. Reports that arrive while the signal strength is being read are missed.
+pub struct RssiMonitor<'c, 'a> {
+    connection: &'c mut Connection<'a>,
+    interval: Duration,
+    timeout: c_int,
+    /// Time of the next sample
+    next: Instant,
+    /// Whether transmit power reporting was turned on for the monitor
+    power_reporting: bool,
+    remote_tx_power: Option<i8>,
+}
+
+impl RssiMonitor<'_, '_> {
+    /// Turn on transmit power reporting on LE links, and read the current remote transmit
+    /// power. Controllers without LE Power Control are sampled with Read RSSI alone.
+    #[cfg(feature = "le")]
+    fn start_power_reporting(&mut self) -> Result<()> {
+        if self.connection.le_info().is_none() {
+            return Ok(());
+        }
+        if self.connection.le_set_tx_power_reporting(false, true, self.timeout).is_err() {
+            return Ok(());
+        }
+        self.power_reporting = true;
+        let phy = PowerControlPhy::default();
+        if let Ok(report) = self.connection.le_read_remote_tx_power(phy, self.timeout) {
+            self.remote_tx_power = report.tx_power;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "le"))]
+    fn start_power_reporting(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Wait up to `wait` for a transmit power report from the remote device.
+    #[cfg(feature = "le")]
+    fn wait_power_report(&mut self, wait: Duration) -> Result<()> {
+        let wait = c_int::try_from(wait.as_millis()).unwrap_or(c_int::MAX).max(1);
+        let report = match self.connection.le_wait_tx_power_report(wait) {
+            Err(e) if e.raw_os_error() == Some(ETIMEDOUT) => return Ok(()),
+            result => result?,
+        };
+        if report.status == 0 && report.reason != PowerReportReason::LocalChange {
+            self.remote_tx_power = report.tx_power;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "le"))]
+    fn wait_power_report(&mut self, _wait: Duration) -> Result<()> {
+        Ok(())
+    }
+
+    /// Wait for the next sample time and read the signal strength.
+    pub fn next_sample(&mut self) -> Result<RssiSample> {
+        loop {
+            let wait = self.next.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                break;
+            }
+            if self.power_reporting {
+                self.wait_power_report(wait)?;
+            } else {
+                thread::sleep(wait);
+            }
+        }
+
+        let rssi = self.connection.read_rssi(self.timeout)?;
+        let time = Instant::now();
+        // Skip sample times that were missed rather than sampling in a burst.
+        self.next = (self.next + self.interval).max(time);
+        Ok(RssiSample {
+            handle: self.connection.handle(),
+            rssi,
+            remote_tx_power: self.remote_tx_power,
+            time,
+        })
+    }
+}
+
+/// Iterate over samples until reading the signal strength fails.
+impl Iterator for RssiMonitor<'_, '_> {
+    type Item = RssiSample;
+
+    fn next(&mut self) -> Option<RssiSample> {
+        self.next_sample().ok()
+    }
+}
+
+impl Drop for RssiMonitor<'_, '_> {
+    fn drop(&mut self) {
+        #[cfg(feature = "le")]
+        if self.power_reporting {
+            let _ = self.connection.le_set_tx_power_reporting(false, false, self.timeout);
+        }
+    }
+}
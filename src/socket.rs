@@ -1,40 +1,46 @@
-use libc::{AF_BLUETOOTH, c_int, c_ushort, c_void, EAGAIN, EINTR, EIO, ETIMEDOUT, poll, pollfd, POLLIN, sa_family_t, sockaddr_storage, socklen_t, SOCK_CLOEXEC, SOCK_RAW};
-use std::io::{Error, IoSlice, Read, Result, Write};
-use std::ptr::{addr_of, addr_of_mut, copy_nonoverlapping};
+use libc::{AF_BLUETOOTH, c_int, c_short, c_ushort, c_void, CMSG_DATA, CMSG_FIRSTHDR, CMSG_NXTHDR, EAGAIN, EFD_CLOEXEC, EFD_NONBLOCK, EINTR, EIO, ESHUTDOWN, ETIMEDOUT, iovec, msghdr, poll, pollfd, POLLIN, POLLOUT, sa_family_t, sockaddr_storage, socklen_t, SOCK_CLOEXEC, SOCK_RAW, timeval};
+use std::io::{Error, IoSlice, Read, Result};
+use std::io::ErrorKind::{Interrupted, InvalidData, WouldBlock, WriteZero};
+use std::ptr::{addr_of, addr_of_mut, copy_nonoverlapping, read_unaligned};
 use std::mem::{MaybeUninit, zeroed};
-use std::os::fd::AsRawFd;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use socket2::{Domain, Protocol, Socket as Socket2, SockAddr, Type};
 
+use super::codec::{self, EventBody, Packet};
+use super::error::{Capability, CommandError, CommandPhase, MalformedPacket, PermissionDenied, StatusError};
 use super::filter::HciFilter;
-use super::io::{ReadAs, ReadFrom, WriteAs, WriteTo};
+use super::pool::BufferPool;
+use super::stats::{Counters, Stats};
+use super::trace;
 
 const SOL_HCI: c_int = 0;
 const HCI_FILTER: c_int = 2;
+const HCI_TIME_STAMP: c_int = 3;
+/// Type of the control message carrying the kernel receive time
+const HCI_CMSG_TSTAMP: c_int = 0x0002;
 const HCI_MAX_EVENT_SIZE: usize = 260;
 
-const HCI_COMMAND_PKT: u8 = 0x01;
-const HCI_EVENT_PKT: u8 = 0x04;
+pub(crate) use super::codec::{EVT_CMD_COMPLETE, EVT_CMD_STATUS, HCI_ACLDATA_PKT, HCI_EVENT_PKT};
+#[cfg(feature = "iso")]
+pub(crate) use super::codec::HCI_ISODATA_PKT;
 
-const EVT_CMD_COMPLETE: u8 = 0x0E;
-const EVT_CMD_STATUS: u8 = 0x0F;
+/// Length of the header of an ACL data packet, including the packet type
+const ACL_HEADER_SIZE: usize = 5;
 
 const PROTO_HCI: c_int = 1;
 
-
-/// Helper macro to execute a system call that returns an `io::Result`.
-/// Copied from socket2.
-macro_rules! syscall {
-    ($fn: ident ( $($arg: expr),* $(,)* ) ) => {{
-        #[allow(unused_unsafe)]
-        let res = unsafe { libc::$fn($($arg, )*) };
-        if res == -1 {
-            Err(Error::last_os_error())
-        } else {
-            Ok(res)
-        }
-    }};
-}
-
+/// Channel of sockets sharing the device with the kernel
+const HCI_CHANNEL_RAW: u16 = 0;
+/// Channel of sockets with exclusive access to a device that is down
+pub(crate) const HCI_CHANNEL_USER: u16 = 1;
+/// Channel of sockets receiving a copy of the traffic of every device
+#[cfg(feature = "monitor")]
+pub(crate) const HCI_CHANNEL_MONITOR: u16 = 2;
+/// Channel of sockets sending management commands to the kernel
+#[cfg(feature = "mgmt")]
+pub(crate) const HCI_CHANNEL_CONTROL: u16 = 3;
 
 
 #[repr(C)]
@@ -60,51 +66,237 @@ impl HCIAddr {
 }
 
 
+/// Open an HCI socket bound to a channel of a device.
+pub(crate) fn bind_channel(device_id: u16, channel: u16) -> Result<Socket2> {
+    let hci_domain = Domain::from(AF_BLUETOOTH);
+    let hci_type = Type::from(SOCK_RAW | SOCK_CLOEXEC);
+    let hci_protocol = Protocol::from(PROTO_HCI);
+
+    let socket = Socket2::new(hci_domain, hci_type, Some(hci_protocol))
+        .map_err(PermissionDenied::check(Capability::CapNetRaw))?;
+
+    let address = HCIAddr {
+        family: AF_BLUETOOTH as sa_family_t,
+        device: device_id,
+        channel,
+    };
+
+    // The user channel takes the device from the kernel, so it needs more privileges.
+    let needs = if channel == HCI_CHANNEL_USER { Capability::CapNetAdmin } else { Capability::CapNetRaw };
+    socket.bind(&address.as_sock_addr())
+        .map_err(PermissionDenied::check(needs))?;
+    Ok(socket)
+}
+
+
+/// Time an event or data packet was received
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timestamp {
+    /// When the packet was read from the socket
+    pub received: Instant,
+    /// When the kernel received the packet from the controller, if kernel timestamps are
+    /// enabled with `set_kernel_timestamps`
+    pub kernel: Option<SystemTime>,
+}
+
+/// Shuts a socket down from another thread.
+///
+/// Once shut down, waits of the socket in progress and all later ones fail with `ESHUTDOWN`,
+/// and event loops drop the socket. Cloned handles shut down the same socket.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle(Arc<OwnedFd>);
+
+impl ShutdownHandle {
+    fn new() -> Result<Self> {
+        let fd = syscall!(eventfd(0, EFD_CLOEXEC | EFD_NONBLOCK))?;
+        // The descriptor was just created and is owned by nothing else.
+        Ok(ShutdownHandle(Arc::new(unsafe { OwnedFd::from_raw_fd(fd) })))
+    }
+
+    /// Shut the socket down, waking the waits in progress.
+    pub fn shutdown(&self) -> Result<()> {
+        let one = 1u64;
+        syscall!(write(self.0.as_raw_fd(), addr_of!(one).cast(), size_of::<u64>()))
+            .map(|_| ())
+    }
+
+    /// Return whether the socket was shut down
+    pub fn is_shutdown(&self) -> bool {
+        let mut p = pollfd { fd: self.0.as_raw_fd(), events: POLLIN, revents: 0 };
+        unsafe { poll(&mut p, 1, 0) > 0 }
+    }
+}
+
+impl AsRawFd for ShutdownHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
 /// HCI Socket
-pub struct Socket(Socket2);
+pub struct Socket {
+    socket: Socket2,
+    pub(crate) pool: BufferPool,
+    pub(crate) stats: Counters,
+    /// Whether received ACL packets are acknowledged to the controller
+    pub(crate) host_flow_control: bool,
+    /// Whether the kernel attaches its receive time to packets
+    kernel_timestamps: bool,
+    /// Receive time of the last event or data packet
+    last_timestamp: Option<Timestamp>,
+    /// Milliseconds to wait for room to send a packet, or 0 to fail at once
+    send_timeout: c_int,
+    /// Filter of the socket when it was opened, restored by `shutdown`
+    initial_filter: HciFilter,
+    shutdown: ShutdownHandle,
+}
 
 
 impl Socket {
     pub fn new(device_id: u16) -> Result<Socket> {
-        let hci_domain = Domain::from(AF_BLUETOOTH);
-        let hci_type = Type::from(SOCK_RAW | SOCK_CLOEXEC);
-        let hci_protocol = Protocol::from(PROTO_HCI);
-    
-    	let socket = Socket2::new(hci_domain, hci_type, Some(hci_protocol))?;
-        
-        // If a device id is specified, 
-        let address = HCIAddr {
-            family: AF_BLUETOOTH as sa_family_t,
-            device: device_id,
-            channel: 0u16,
+        let socket = bind_channel(device_id, HCI_CHANNEL_RAW)?;
+
+        let mut socket = Socket {
+            socket,
+            pool: BufferPool::default(),
+            stats: Counters::default(),
+            host_flow_control: false,
+            kernel_timestamps: false,
+            last_timestamp: None,
+            send_timeout: 0,
+            initial_filter: HciFilter::default(),
+            shutdown: ShutdownHandle::new()?,
         };
-        
-        socket.bind(&address.as_sock_addr())?;
-        
-        Ok(Socket(socket))
+        socket.initial_filter = socket.get_filter()?;
+        Ok(socket)
+    }
+
+    /// Return the id of the device the socket is bound to, such as 0 for hci0.
+    pub fn device_id(&self) -> Result<u16> {
+        let address = self.socket.local_addr()?;
+        if (address.len() as usize) < size_of::<HCIAddr>() {
+            return Err(Error::from_raw_os_error(EIO));
+        }
+        // The address was filled in by the kernel and is at least as long as an HCIAddr.
+        let address = unsafe { read_unaligned(address.as_ptr().cast::<HCIAddr>()) };
+        Ok(address.device)
+    }
+
+    /// Return a handle to shut the socket down from another thread, such as one running an
+    /// `EventLoop` the socket is registered with.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Shut the socket down and close it, reporting errors that dropping it would ignore.
+    ///
+    /// Waits of the socket in other threads through a `ShutdownHandle` are woken and fail with
+    /// `ESHUTDOWN`. The filter the socket was opened with is then restored, in case the
+    /// descriptor is shared with another process.
+    pub fn shutdown(self) -> Result<()> {
+        self.shutdown.shutdown()?;
+        self.set_filter(&self.initial_filter)?;
+        let fd = self.socket.into_raw_fd();
+        syscall!(close(fd)).map(|_| ())
     }
 
     pub fn send(&self, buf: &[u8]) -> Result<usize> {
-        self.0.send(buf)
+        let size = self.socket.send(buf)
+            .map_err(PermissionDenied::check(Capability::CapNetRaw))?;
+        self.count_sent(buf.first().copied(), size);
+        Ok(size)
     }
     pub fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<usize> {
-        self.0.send_vectored(bufs)
+        let size = self.socket.send_vectored(bufs)
+            .map_err(PermissionDenied::check(Capability::CapNetRaw))?;
+        let packet_type = bufs.iter().find_map(|buf| buf.first().copied());
+        self.count_sent(packet_type, size);
+        Ok(size)
+    }
+
+    /// Send one packet gathered from several buffers, failing instead of sending part of it.
+    ///
+    /// Each send on an HCI socket is a separate packet, so the rest of a short write cannot
+    /// be sent after it. Interrupted sends are retried.
+    ///
+    /// If the socket has no room for the packet, waits up to the send timeout for room.
+    pub fn send_all_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let deadline = Instant::now() + Duration::from_millis(self.send_timeout as u64);
+        loop {
+            match self.send_vectored(bufs) {
+                Ok(size) if size == len => return Ok(size),
+                Ok(_) => return Err(Error::new(WriteZero, "Packet was truncated by the socket")),
+                Err(e) if e.kind() == Interrupted => continue,
+                Err(e) if e.kind() == WouldBlock && self.send_timeout > 0 => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(Error::from_raw_os_error(ETIMEDOUT));
+                    }
+                    // Round up, so a remaining fraction of a millisecond is still waited for.
+                    let remaining = remaining.as_micros().div_ceil(1000);
+                    poll_with_timeout(self, POLLOUT, c_int::try_from(remaining).unwrap_or(c_int::MAX))?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Set how long sending a packet waits for room in the socket, in milliseconds.
+    ///
+    /// With a timeout of 0, the default, a send on a full non-blocking socket fails at once
+    /// with `WouldBlock`. Otherwise it fails with `ETIMEDOUT` once the timeout passes.
+    pub fn set_send_timeout(&mut self, timeout: c_int) {
+        self.send_timeout = timeout.max(0);
+    }
+
+    /// Return how long sending a packet waits for room in the socket, in milliseconds
+    pub fn send_timeout(&self) -> c_int {
+        self.send_timeout
+    }
+
+    /// Set whether sending and receiving fail instead of blocking when the socket is not ready.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    /// Count the data of a sent ACL packet.
+    fn count_sent(&self, packet_type: Option<u8>, size: usize) {
+        if packet_type == Some(HCI_ACLDATA_PKT) {
+            self.stats.acl_sent(size.saturating_sub(ACL_HEADER_SIZE));
+        }
+    }
+
+    /// Return the traffic counted since the socket was opened or the counters were reset.
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// Set the traffic counters back to zero.
+    pub fn reset_stats(&self) {
+        self.stats.reset();
     }
 
     pub fn recv(&self, buf: &mut [MaybeUninit<u8>]) -> Result<usize> {
-        self.0.recv(buf)
+        self.socket.recv(buf)
     }
 }
 
 impl AsRawFd for Socket {
     fn as_raw_fd(&self) -> c_int {
-        self.0.as_raw_fd()
+        self.socket.as_raw_fd()
     }
 }
 
-impl <'a> Read for &'a mut Socket {
+impl AsRawFd for &mut Socket {
+    fn as_raw_fd(&self) -> c_int {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl Read for &mut Socket {
      fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-         self.0.read(buf)
+         self.socket.read(buf)
     }
 }
 
@@ -115,7 +307,7 @@ impl Socket {
         let mut filter_size = size_of::<HciFilter>() as socklen_t;
 
     	syscall!(getsockopt(
-            self.0.as_raw_fd(),
+            self.socket.as_raw_fd(),
             SOL_HCI,
             HCI_FILTER,
             addr_of_mut!(filter) as *mut c_void,
@@ -127,161 +319,144 @@ impl Socket {
         let filter_size = size_of::<HciFilter>() as socklen_t;
 
     	syscall!(setsockopt(
-            self.0.as_raw_fd(),
+            self.socket.as_raw_fd(),
             SOL_HCI,
             HCI_FILTER,
             addr_of!(filter).cast(),
-            filter_size))
-            .map(|_| ())
+            filter_size))?;
+        trace::filter(filter);
+        Ok(())
     }
 }
 
-// Receiving events
-
+// Receive timestamps
 
-#[derive(Copy, Clone, Debug, Default)]
-struct EventHeader {
-    event: u8,
-    _plen: u8,
-}
-
-impl ReadFrom for EventHeader {
-    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
-        let (event, event_size) = r.read_as::<u8>()?;
-        let (plen, plen_size) = r.read_as::<u8>()?;
-        Ok((EventHeader { event, _plen: plen }, event_size + plen_size))
+impl Socket {
+    /// Ask the kernel to attach the time it received each packet from the controller.
+    ///
+    /// The kernel time is then included in the timestamps of received events and data packets.
+    pub fn set_kernel_timestamps(&mut self, enable: bool) -> Result<()> {
+        let enable = enable as c_int;
+        syscall!(setsockopt(
+            self.socket.as_raw_fd(),
+            SOL_HCI,
+            HCI_TIME_STAMP,
+            addr_of!(enable).cast(),
+            size_of::<c_int>() as socklen_t))?;
+        self.kernel_timestamps = enable != 0;
+        Ok(())
     }
-}
 
+    /// Return the time the last event was received, such as the Command Complete event of
+    /// the last command executed. ACL and ISO data packets received since then replace it.
+    pub fn last_event_timestamp(&self) -> Option<Timestamp> {
+        self.last_timestamp
+    }
 
-// We only implement these two basic events.
-#[derive(Copy, Clone, Debug)]
-enum EventBody {
-    Unsupported,
-    CmdComplete {
-        _ncmd: u8,
-        opcode: u16,
-    },
-    CmdStatus {
-        _status: u8,
-        _ncmd: u8,
-        opcode: u16,
-    },
-}
-
-impl EventBody {
-    /// Return the numeric code for an event
-    pub fn _code(&self) -> u8 {
-        match self {
-            EventBody::Unsupported { .. } => 0x00,
-            EventBody::CmdComplete { .. } => 0x0E,
-            EventBody::CmdStatus { .. } => 0x0F,
+    /// Receive one packet with the kernel receive time attached to it.
+    fn recv_with_kernel_time(&self, buf: &mut [u8]) -> Result<(usize, Option<SystemTime>)> {
+        let mut iov = iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+        // Room for one control message holding a timeval, aligned for cmsghdr.
+        let mut control = [0u64; 8];
+        // All fields not set below are null or zero.
+        let mut msg: msghdr = unsafe { zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = size_of_val(&control) as _;
+
+        let size = syscall!(recvmsg(self.socket.as_raw_fd(), &mut msg, 0))? as usize;
+
+        let mut kernel = None;
+        // The control messages were written by the kernel within the buffer given to it.
+        unsafe {
+            let mut cmsg = CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == SOL_HCI && (*cmsg).cmsg_type == HCI_CMSG_TSTAMP {
+                    let tv = read_unaligned(CMSG_DATA(cmsg).cast::<timeval>());
+                    kernel = Some(UNIX_EPOCH + Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000));
+                }
+                cmsg = CMSG_NXTHDR(&msg, cmsg);
+            }
         }
+        Ok((size, kernel))
     }
-}
-
-#[derive(Clone, Debug)]
-struct Event {
-    _header: EventHeader,
-    body: EventBody,
-    data: Box<[u8]>,
-}
 
-impl Event {
-    /// Create a new event from a header and body.
-    pub fn new(header: EventHeader, body: EventBody, data: Box<[u8]>) -> Self {
-        Event { _header: header, body, data }
+    /// Receive one packet like `recv_timestamped`, retrying interrupted receives and waiting
+    /// for the socket again, until `deadline`, if it had nothing to read after all.
+    fn recv_retrying(&mut self, buf: &mut [u8], deadline: Deadline) -> Result<usize> {
+        loop {
+            match self.recv_timestamped(buf) {
+                Err(e) if e.kind() == Interrupted => continue,
+                Err(e) if e.kind() == WouldBlock => deadline.poll(self)?,
+                result => return result,
+            }
+        }
     }
-}
-
-impl ReadFrom for Event {
-
-    /// Receive an event from a socket.
-    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
-        let mut buf = [0u8; HCI_MAX_EVENT_SIZE];
 
-        // Read segmented data for an entire event.
-        // TODO: recv can be interrupted by EAGAIN and EINTR. We should retry in these scenarios.
-        let size = r.read(&mut buf)?;
-
-        // Get slice of buffer
-        let mut buf_r: &[u8] = &buf[..size];
-        
-        // Skip the first byte. Not sure what it is.
-        buf_r = &buf_r[1..];
-        
-        // Read the header.
-        let (header, _) = buf_r.read_as::<EventHeader>()?;
-
-        // Parse event body from stream
-        match header.event {
-            0x0E => {
-                let (ncmd, _) = buf_r.read_as::<u8>()?;
-                let (opcode, _) = buf_r.read_as::<u16>()?;
-                Ok((Event::new(
-                            header,
-                            EventBody::CmdComplete { _ncmd: ncmd, opcode },
-                            buf_r.into()),
-                            size))
-            },
-            0x0F => {
-                let (status, _) = buf_r.read_as::<u8>()?;
-                let (ncmd, _) = buf_r.read_as::<u8>()?;
-                let (opcode, _) = buf_r.read_as::<u16>()?;
-                Ok((Event::new(
-                            header,
-                            EventBody::CmdStatus { _status: status, _ncmd: ncmd, opcode },
-                            buf_r.into()),
-                            size))
-            },
-            _ => Ok((Event::new(
-                            header,
-                            EventBody::Unsupported,
-                            buf_r.into()),
-                            size))
-        }
+    /// Receive one packet and record its receive time as the time of the last event.
+    pub(crate) fn recv_timestamped(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let (size, kernel) = if self.kernel_timestamps {
+            self.recv_with_kernel_time(buf)?
+        } else {
+            ((&mut *self).read(buf)?, None)
+        };
+        self.last_timestamp = Some(Timestamp { received: Instant::now(), kernel });
+        Ok(size)
     }
 }
 
+// Receiving events
 
+impl Socket {
+    /// Receive one event into a pooled buffer and pass it to `f`, so its parameters are only
+    /// copied if `f` keeps them.
+    fn recv_event_with<T, F>(&mut self, deadline: Deadline, f: F) -> Result<T>
+    where F: FnOnce(&EventBody) -> T {
+        let mut buf = self.pool.take(HCI_MAX_EVENT_SIZE);
+        let result = self.recv_retrying(&mut buf, deadline)
+            .and_then(|size| {
+                let event = Packet::parse(&buf[..size])
+                    .and_then(|packet| match packet {
+                        Packet::Event { code, params } => EventBody::parse(code, params).map(Some),
+                        _ => Ok(None),
+                    })
+                    .inspect_err(|_| self.stats.malformed())?
+                    .ok_or_else(|| Error::from_raw_os_error(EIO))?;
+                self.stats.event_received(event.code());
+                trace::event(&event);
+                Ok(f(&event))
+            });
+        self.pool.give(buf);
+        result
+    }
 
+    /// Wait for the next event that passes the socket filter, and pass its code and parameters
+    /// to `f` without copying them.
+    pub(crate) fn recv_event_map<T, F>(&mut self, timeout: c_int, f: F) -> Result<T>
+    where F: FnOnce(u8, &[u8]) -> T {
+        self.recv_event_body(timeout, |event| f(event.code(), event.params()))
+    }
 
-
-
-// hci_send_cmd(int dd, uint16_t ogf, uint16_t ocf, uint8_t plen, void *param);
-
-/// Header for a command. Should be sent before any commands.
-struct CommandHeader {
-	opcode: u16,
-    _plen: u8,
-}
-impl WriteTo for &CommandHeader {
-    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
-        let opcode_size = w.write_as(self.opcode)?;
-        let plen_size = w.write_as(self._plen)?;
-        Ok(opcode_size + plen_size)
+    /// Wait for the next event that passes the socket filter, and pass it to `f` parsed, so
+    /// the opcodes of Command Complete and Command Status events can be checked.
+    pub(crate) fn recv_event_body<T, F>(&mut self, timeout: c_int, f: F) -> Result<T>
+    where F: FnOnce(&EventBody) -> T {
+        let deadline = Deadline::new(timeout);
+        deadline.poll(self)?;
+        self.recv_event_with(deadline, f)
     }
 }
 
+
 impl Socket {
     pub fn send_cmd(&self, ogf: u16, ocf: u16, param: &[u8]) -> Result<usize> {
-        let cmd_type = [HCI_COMMAND_PKT];
-        let cmd_hdr = CommandHeader {
-            opcode: cmd_opcode_pack(ogf, ocf).to_le(),
-            _plen: param.len().try_into().unwrap(),
-        }.bytes()?;
-        // Convert cmd_hdr to a byte slice
-
-        let mut bufs = vec![
-            IoSlice::new(&cmd_type),
-            IoSlice::new(&cmd_hdr),
-        ];
-
-        if param.len() > 0 {
-            bufs.push(IoSlice::new(param));
-        }
-
-        self.send_vectored(bufs.as_slice())
+        let opcode = codec::opcode(ogf, ocf);
+        let packet = Packet::Command { opcode, params: param }.encode()?;
+        let size = self.send_all_vectored(&[IoSlice::new(&packet)])?;
+        self.stats.command_sent();
+        trace::command(opcode, param);
+        Ok(size)
     }
 }
 
@@ -289,22 +464,58 @@ impl Socket {
 
 // hci_send_req(int dd, struct hci_request *req, int timeout);
 
-/// Construct an opcode from ogf and ocf.
-fn cmd_opcode_pack(ogf: u16, ocf: u16) -> u16 {
-    (ocf & 0x03ff) | (ogf << 10)
+/// Time by which a wait for events must end
+#[derive(Copy, Clone, Debug)]
+struct Deadline {
+    /// Timeout the deadline was set from, in milliseconds
+    timeout: c_int,
+    /// End of the wait, or `None` to wait indefinitely
+    at: Option<Instant>,
+}
+
+impl Deadline {
+    /// Set a deadline `timeout` milliseconds from now. A timeout of 0 or less waits
+    /// indefinitely.
+    fn new(timeout: c_int) -> Self {
+        let at = (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+        Deadline { timeout, at }
+    }
+
+    /// Wait until the socket is readable, for no longer than the time left.
+    ///
+    /// Fails with `ETIMEDOUT` once the deadline has passed, even if events are waiting, so a
+    /// stream of unrelated events cannot extend the wait.
+    fn poll(&self, socket: &Socket) -> Result<()> {
+        let wait = match self.at {
+            Some(at) => {
+                let remaining = at.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    socket.stats.timeout();
+                    trace::timeout(self.timeout);
+                    return Err(Error::from_raw_os_error(ETIMEDOUT));
+                }
+                // Round up, so a remaining fraction of a millisecond is still waited for.
+                c_int::try_from(remaining.as_micros().div_ceil(1000)).unwrap_or(c_int::MAX)
+            }
+            None => -1,
+        };
+        poll_with_timeout(socket, POLLIN, wait)
+    }
 }
 
-/// Returns whether the socket is ready to use.
-fn poll_with_timeout(socket: &Socket, timeout: c_int) -> Result<()> {
+/// Returns whether the socket is ready for `events`, such as `POLLIN` for reading or
+/// `POLLOUT` for writing. A negative timeout waits indefinitely.
+///
+/// Fails with `ESHUTDOWN` once the socket is shut down through its `ShutdownHandle`.
+fn poll_with_timeout(socket: &Socket, events: c_short, timeout: c_int) -> Result<()> {
     let mut n: c_int;
 
-    let mut p = pollfd {
-        fd: socket.as_raw_fd(),
-        events: POLLIN,
-        revents: 0,
-    };
+    let mut p = [
+        pollfd { fd: socket.as_raw_fd(), events, revents: 0 },
+        pollfd { fd: socket.shutdown.as_raw_fd(), events: POLLIN, revents: 0 },
+    ];
 
-    while unsafe {n = poll(&mut p, 1, timeout); n} < 0 {
+    while unsafe {n = poll(p.as_mut_ptr(), p.len() as _, timeout); n} < 0 {
         let e = Error::last_os_error();
         match e.raw_os_error().unwrap() {
             EAGAIN | EINTR => (), // Read again
@@ -314,73 +525,194 @@ fn poll_with_timeout(socket: &Socket, timeout: c_int) -> Result<()> {
         }
     }
 
-    if n == 0 {
-        // Socket is not ready for reading.
+    if p[1].revents != 0 {
+        Err(Error::from_raw_os_error(ESHUTDOWN))
+    } else if n == 0 {
+        // Socket is not ready.
+        socket.stats.timeout();
+        trace::timeout(timeout);
         Err(Error::from_raw_os_error(ETIMEDOUT)) // Timed out
     } else {
-        // Socket is ready for reading.
+        // Socket is ready.
         Ok(())
     }
 }
 
 impl Socket {
-    pub fn send_req(&mut self, ogf: u16, ocf: u16, _event: c_int, command: &[u8], mut timeout: c_int) -> Result<Box<[u8]>> {
-        let mut size = 0;
-        let opcode: u16 = cmd_opcode_pack(ogf, ocf).to_le();
+    pub fn send_req(&mut self, ogf: u16, ocf: u16, event: c_int, command: &[u8], timeout: c_int) -> Result<Box<[u8]>> {
+        self.send_req_matching(ogf, ocf, event, command, timeout, |_, _| true)
+    }
+
+    /// Send a command and wait for its response, like `send_req`, but only accept an event with
+    /// code `event` if `matches` returns true for its code and parameters.
+    pub fn send_req_matching<F>(&mut self, ogf: u16, ocf: u16, event: c_int, command: &[u8], timeout: c_int, matches: F) -> Result<Box<[u8]>>
+    where F: Fn(u8, &[u8]) -> bool {
+        self.send_req_event(ogf, ocf, event, command, timeout, matches)
+            .map(|(_, data)| data)
+    }
+
+    /// Send a command and wait for its response, like `send_req_matching`, and return the code
+    /// of the event that answered with its parameters, so a Command Complete can be told apart
+    /// from the requested event.
+    pub(crate) fn send_req_event<F>(&mut self, ogf: u16, ocf: u16, event: c_int, command: &[u8], timeout: c_int, matches: F) -> Result<(u8, Box<[u8]>)>
+    where F: Fn(u8, &[u8]) -> bool {
+        let opcode = codec::opcode(ogf, ocf);
+        #[cfg(feature = "tracing")]
+        let _span = trace::request_span(opcode).entered();
+
+        let send_error = CommandError::wrap(opcode, CommandPhase::Send);
 
         // Get old filter
-        let old_filter = self.get_filter()?;
+        let old_filter = self.get_filter().map_err(send_error)?;
 
-        // Set a new filter to catch CMD_STATUS and CMD_COMPLETE.
+        // Set a new filter to catch CMD_STATUS, CMD_COMPLETE and the requested event.
         let mut new_filter = HciFilter::default();
-        new_filter.set_type(HCI_EVENT_PKT)?;
-        new_filter.set_event(EVT_CMD_STATUS)?;
-        new_filter.set_event(EVT_CMD_COMPLETE)?;
-	    new_filter.set_opcode(opcode); // opcode?
-	    self.set_filter(&new_filter)?;
-
-        // Send the command through the socket.
-        size += self.send_cmd(ogf, ocf, command)?;
-
-        // Wait for a result after 10 polls.
-        let result = (|mut s: &mut Socket| {
-            for _ in 0..10 {
-
-                // Poll with timeout
-                if timeout > 0 {
-                    // Poll repeatedly
-                    poll_with_timeout(s, timeout)?;
-
-                    timeout -= 10;
-                    if timeout < 0 {
-                        timeout = 0;
-                    }
-                }
+        let sent = (|s: &mut Socket| {
+            new_filter.set_type(HCI_EVENT_PKT)?;
+            new_filter.set_event(EVT_CMD_STATUS)?;
+            new_filter.set_event(EVT_CMD_COMPLETE)?;
+            if event > 0 {
+                new_filter.set_event(event as u8)?;
+            }
+            new_filter.set_opcode(opcode); // opcode?
+            s.set_filter(&new_filter)?;
 
+            // Send the command through the socket.
+            s.send_cmd(ogf, ocf, command)
+        })(self);
+        if let Err(e) = sent {
+            let _ = self.set_filter(&old_filter);
+            return Err(send_error(e));
+        }
 
-                let (response, response_size) = s.read_as::<Event>()?;
-                size += response_size;
-                // When receiving, match based on response.header.event
-                match response.body {
-                    EventBody::CmdStatus{ _status: _, _ncmd: _, opcode: r_opcode }
+        // Wait for a result until the deadline, however many other events arrive first.
+        let deadline = Deadline::new(timeout);
+        let result: Result<(u8, Box<[u8]>)> = (|s: &mut Socket| {
+            loop {
+                // Poll until the deadline, or until the socket is shut down
+                deadline.poll(s)
+                    .map_err(CommandError::wrap(opcode, CommandPhase::Poll))?;
+
+                // When receiving, match based on response.header.event. Parameters are only
+                // copied out of the receive buffer for the response.
+                let response = s.recv_event_with(deadline, |response| match *response {
+                    EventBody::CommandStatus { status, ncmd: _, opcode: r_opcode }
                     if r_opcode == opcode => {
-                        // Ignore the case in which the event listens for Event::CMD_STATUS.
-                        return Err(Error::from_raw_os_error(EIO));
+                        if status != 0 {
+                            return Some(Err(CommandError::new(opcode, CommandPhase::Status, StatusError(status).into()).into()));
+                        }
+                        // Keep waiting unless the caller is listening for the status itself.
+                        if event == EVT_CMD_STATUS as c_int {
+                            return Some(Ok((EVT_CMD_STATUS, Box::default())));
+                        }
+                        None
                     },
-                    EventBody::CmdComplete{ _ncmd: _, opcode: r_opcode } if r_opcode == opcode => {
-                        return Ok(response.data);
+                    EventBody::CommandComplete { ncmd: _, opcode: r_opcode, params } if r_opcode == opcode => {
+                        Some(Ok((EVT_CMD_COMPLETE, params.into())))
                     }
-                    _ => (),
+                    EventBody::Other { code, params }
+                    if event > 0
+                        && code as c_int == event
+                        && matches(code, params) => {
+                        Some(Ok((code, params.into())))
+                    }
+                    _ => None,
+                }).map_err(|e| {
+                    let phase = if e.kind() == InvalidData { CommandPhase::Parse } else { CommandPhase::Poll };
+                    CommandError::wrap(opcode, phase)(e)
+                })?;
+                if let Some(result) = response {
+                    return result;
                 }
             }
-            Err(Error::from_raw_os_error(ETIMEDOUT))
         })(self);
         
-        // Restore old filter.
-	    self.set_filter(&old_filter)?;
-        
+        // Restore old filter. An error of the command itself takes precedence.
+        let restored = self.set_filter(&old_filter);
+        let result = result?;
+        restored.map_err(CommandError::wrap(opcode, CommandPhase::Restore))?;
+
         // Return result from waiting.
-        result
+        Ok(result)
+    }
+
+    /// Wait for the next event that passes the socket filter.
+    ///
+    /// Returns the event code and parameters. For Command Complete and Command Status events,
+    /// the parameters start after the opcode.
+    pub fn recv_event(&mut self, timeout: c_int) -> Result<(u8, Box<[u8]>)> {
+        self.recv_event_map(timeout, |event, data| (event, data.into()))
+    }
+
+    /// Receive the events about the connection with the given handle, dropping the others.
+    ///
+    /// The kernel filter cannot select events by handle, so events are checked after they
+    /// are received. Only events that pass the socket filter are seen. Each event is waited for
+    /// up to `timeout` milliseconds.
+    pub fn events_for_handle(&mut self, handle: u16, timeout: c_int) -> HandleEvents<'_> {
+        HandleEvents { socket: self, handle, timeout }
+    }
+
+    /// Wait for the next event that passes the socket filter, like `recv_event`, and return it
+    /// with the time it was received.
+    pub fn recv_event_timestamped(&mut self, timeout: c_int) -> Result<(u8, Box<[u8]>, Timestamp)> {
+        let (event, data) = self.recv_event(timeout)?;
+        let timestamp = self.last_timestamp.ok_or_else(|| Error::from_raw_os_error(EIO))?;
+        Ok((event, data, timestamp))
+    }
+
+    /// Send a command and wait for its Command Complete event.
+    ///
+    /// Returns the return parameters following the status. Errors are wrapped in a
+    /// `CommandError` naming the command and the step that failed; a failure status reported by
+    /// the controller is a `StatusError` within it.
+    pub fn execute(&mut self, ogf: u16, ocf: u16, command: &[u8], timeout: c_int) -> Result<Box<[u8]>> {
+        let opcode = codec::opcode(ogf, ocf);
+        let data = self.send_req(ogf, ocf, 0, command, timeout)?;
+        match data.split_first() {
+            Some((0, params)) => Ok(params.into()),
+            Some((&status, _)) => Err(CommandError::new(opcode, CommandPhase::Status, StatusError(status).into()).into()),
+            None => Err(CommandError::new(opcode, CommandPhase::Parse, MalformedPacket.into()).into()),
+        }
+    }
+}
+
+
+/// Events about one connection, received through `Socket::events_for_handle`
+pub struct HandleEvents<'a> {
+    socket: &'a mut Socket,
+    handle: u16,
+    timeout: c_int,
+}
+
+impl HandleEvents<'_> {
+    /// Wait for the next event about the connection, and return its code and parameters.
+    pub fn next_event(&mut self) -> Result<(u8, Box<[u8]>)> {
+        self.next_event_timestamped().map(|(event, data, _)| (event, data))
+    }
+
+    /// Wait for the next event about the connection, like `next_event`, and return it with
+    /// the time it was received.
+    pub fn next_event_timestamped(&mut self) -> Result<(u8, Box<[u8]>, Timestamp)> {
+        let handle = self.handle;
+        loop {
+            let found = self.socket.recv_event_body(self.timeout, |event| {
+                event.has_handle(handle).then(|| (event.code(), event.params().into()))
+            })?;
+            if let Some((event, data)) = found {
+                let timestamp = self.socket.last_timestamp.ok_or_else(|| Error::from_raw_os_error(EIO))?;
+                return Ok((event, data, timestamp));
+            }
+        }
+    }
+}
+
+/// Iterate over events about the connection until an error or timeout occurs.
+impl Iterator for HandleEvents<'_> {
+    type Item = (u8, Box<[u8]>);
+
+    fn next(&mut self) -> Option<(u8, Box<[u8]>)> {
+        self.next_event().ok()
     }
 }
 
@@ -394,17 +726,17 @@ impl Socket {
 // tagging system from scratch. So if it feels redundant to be writing Rust code just to interpret
 // bytes as types, it's probably a necessaray part of the process.
 
-const OGF_HOST_CTL: u16 = 0x03;
-const OCF_WRITE_CLASS_OF_DEV: u16 = 0x0024;
 
-impl Socket {
-    pub fn write_class_of_dev(&mut self, class: u32, timeout: c_int) -> Result<()> {
-        self.send_req(OGF_HOST_CTL, OCF_WRITE_CLASS_OF_DEV,
-            0, // Unused
-            &class.to_le_bytes()[..3],
-            timeout,
-        ).map(|_| ())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutdown_handle_is_shared_by_clones() {
+        let handle = ShutdownHandle::new().unwrap();
+        let clone = handle.clone();
+        assert!(!clone.is_shutdown());
+        handle.shutdown().unwrap();
+        assert!(clone.is_shutdown());
     }
 }
-
-
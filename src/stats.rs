@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+/// Counters of the traffic on a socket, for monitoring the health of a controller
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub commands_sent: u64,
+    /// Number of events received for each event code that was seen
+    pub events_received: BTreeMap<u8, u64>,
+    /// ACL data received, excluding packet headers
+    pub acl_bytes_received: u64,
+    /// ACL data sent, excluding packet headers
+    pub acl_bytes_sent: u64,
+    /// Times the controller did not respond within the timeout
+    pub timeouts: u64,
+    /// Packets that could not be parsed
+    pub malformed_packets: u64,
+}
+
+impl Stats {
+    /// Return the number of events received of any type
+    pub fn total_events(&self) -> u64 {
+        self.events_received.values().sum()
+    }
+}
+
+/// Counters updated by a socket as it sends and receives packets.
+///
+/// Atomics let the counters be updated through shared references, which is how commands and
+/// data are sent.
+#[derive(Debug)]
+pub(crate) struct Counters {
+    commands_sent: AtomicU64,
+    events_received: [AtomicU64; 256],
+    acl_bytes_received: AtomicU64,
+    acl_bytes_sent: AtomicU64,
+    timeouts: AtomicU64,
+    malformed_packets: AtomicU64,
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Counters {
+            commands_sent: AtomicU64::new(0),
+            events_received: [const { AtomicU64::new(0) }; 256],
+            acl_bytes_received: AtomicU64::new(0),
+            acl_bytes_sent: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            malformed_packets: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Counters {
+    pub fn command_sent(&self) {
+        self.commands_sent.fetch_add(1, Relaxed);
+    }
+
+    pub fn event_received(&self, code: u8) {
+        self.events_received[code as usize].fetch_add(1, Relaxed);
+    }
+
+    pub fn acl_received(&self, len: usize) {
+        self.acl_bytes_received.fetch_add(len as u64, Relaxed);
+    }
+
+    pub fn acl_sent(&self, len: usize) {
+        self.acl_bytes_sent.fetch_add(len as u64, Relaxed);
+    }
+
+    pub fn timeout(&self) {
+        self.timeouts.fetch_add(1, Relaxed);
+    }
+
+    pub fn malformed(&self) {
+        self.malformed_packets.fetch_add(1, Relaxed);
+    }
+
+    /// Read the current value of every counter.
+    pub fn snapshot(&self) -> Stats {
+        let events_received = (0..=u8::MAX)
+            .map(|code| (code, self.events_received[code as usize].load(Relaxed)))
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        Stats {
+            commands_sent: self.commands_sent.load(Relaxed),
+            events_received,
+            acl_bytes_received: self.acl_bytes_received.load(Relaxed),
+            acl_bytes_sent: self.acl_bytes_sent.load(Relaxed),
+            timeouts: self.timeouts.load(Relaxed),
+            malformed_packets: self.malformed_packets.load(Relaxed),
+        }
+    }
+
+    /// Set every counter back to zero.
+    pub fn reset(&self) {
+        self.commands_sent.store(0, Relaxed);
+        for count in &self.events_received {
+            count.store(0, Relaxed);
+        }
+        self.acl_bytes_received.store(0, Relaxed);
+        self.acl_bytes_sent.store(0, Relaxed);
+        self.timeouts.store(0, Relaxed);
+        self.malformed_packets.store(0, Relaxed);
+    }
+}
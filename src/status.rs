@@ -0,0 +1,43 @@
+use libc::{c_int};
+use std::io::{Result};
+
+use super::connection::Connection;
+use super::host_ctl::ChannelMap;
+use super::io::ReadAs;
+
+const OGF_STATUS_PARAM: u16 = 0x05;
+const OCF_READ_FAILED_CONTACT_COUNTER: u16 = 0x0001;
+const OCF_RESET_FAILED_CONTACT_COUNTER: u16 = 0x0002;
+const OCF_READ_AFH_MAP: u16 = 0x0006;
+
+impl Connection<'_> {
+    /// Read the number of consecutive failed contacts with the remote device.
+    pub fn failed_contact_counter(&mut self, timeout: c_int) -> Result<u16> {
+        let handle = self.handle();
+        let data = self.socket.execute(OGF_STATUS_PARAM, OCF_READ_FAILED_CONTACT_COUNTER, &handle.to_le_bytes(), timeout)?;
+        let mut r: &[u8] = &data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (counter, _) = r.read_as::<u16>()?;
+        Ok(counter)
+    }
+
+    /// Reset the failed contact counter to zero.
+    pub fn reset_failed_contact_counter(&mut self, timeout: c_int) -> Result<()> {
+        let handle = self.handle();
+        self.socket.execute(OGF_STATUS_PARAM, OCF_RESET_FAILED_CONTACT_COUNTER, &handle.to_le_bytes(), timeout)
+            .map(|_| ())
+    }
+
+    /// Read the AFH channel map in use on the connection.
+    ///
+    /// Returns whether AFH is enabled on the connection and the map of used channels.
+    pub fn read_afh_channel_map(&mut self, timeout: c_int) -> Result<(bool, ChannelMap)> {
+        let handle = self.handle();
+        let data = self.socket.execute(OGF_STATUS_PARAM, OCF_READ_AFH_MAP, &handle.to_le_bytes(), timeout)?;
+        let mut r: &[u8] = &data;
+        let (_handle, _) = r.read_as::<u16>()?;
+        let (mode, _) = r.read_as::<u8>()?;
+        let (map, _) = r.read_as::<ChannelMap>()?;
+        Ok((mode != 0, map))
+    }
+}
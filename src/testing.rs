@@ -0,0 +1,107 @@
+use libc::{c_int};
+use std::io::{Error, Read, Result};
+use std::io::ErrorKind::{InvalidData};
+
+use super::codec;
+use super::io::{read_bytes, ReadAs, ReadFrom};
+use super::socket::Socket;
+
+const OGF_TESTING_CMD: u16 = 0x06;
+const OCF_READ_LOOPBACK_MODE: u16 = 0x0001;
+const OCF_WRITE_LOOPBACK_MODE: u16 = 0x0002;
+const OCF_ENABLE_DEVICE_UNDER_TEST_MODE: u16 = 0x0003;
+const OCF_WRITE_SIMPLE_PAIRING_DEBUG_MODE: u16 = 0x0004;
+
+const EVT_LOOPBACK_COMMAND: u8 = 0x19;
+
+impl Socket {
+    /// Put the local device in Device Under Test mode, so a tester can control it over the air.
+    ///
+    /// The device leaves test mode when it is reset.
+    pub fn enable_device_under_test_mode(&mut self, timeout: c_int) -> Result<()> {
+        self.execute(OGF_TESTING_CMD, OCF_ENABLE_DEVICE_UNDER_TEST_MODE, &[], timeout)
+            .map(|_| ())
+    }
+
+    /// Enable or disable Simple Pairing debug mode, which makes the controller use the debug
+    /// key pair so pairing can be decrypted by a sniffer.
+    pub fn write_simple_pairing_debug_mode(&mut self, enable: bool, timeout: c_int) -> Result<()> {
+        self.execute(OGF_TESTING_CMD, OCF_WRITE_SIMPLE_PAIRING_DEBUG_MODE, &[enable as u8], timeout)
+            .map(|_| ())
+    }
+}
+
+// Loopback
+
+/// Loopback mode of the controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoopbackMode {
+    None = 0x00,
+    Local = 0x01,
+    Remote = 0x02,
+}
+
+impl TryFrom<u8> for LoopbackMode {
+    type Error = Error;
+
+    fn try_from(mode: u8) -> Result<Self> {
+        match mode {
+            0x00 => Ok(LoopbackMode::None),
+            0x01 => Ok(LoopbackMode::Local),
+            0x02 => Ok(LoopbackMode::Remote),
+            _ => Err(Error::new(InvalidData, "Unknown loopback mode")),
+        }
+    }
+}
+
+/// Command returned to the host by a Loopback Command event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoopbackCommand {
+    pub opcode: u16,
+    pub params: Box<[u8]>,
+}
+
+impl LoopbackCommand {
+    /// Return the opcode group field
+    pub fn ogf(&self) -> u16 {
+        codec::ogf(self.opcode)
+    }
+
+    /// Return the opcode command field
+    pub fn ocf(&self) -> u16 {
+        codec::ocf(self.opcode)
+    }
+}
+
+impl ReadFrom for LoopbackCommand {
+    fn read_from<R: Read>(mut r: R) -> Result<(Self, usize)> {
+        let (opcode, opcode_size) = r.read_as::<u16>()?;
+        let (plen, plen_size) = r.read_as::<u8>()?;
+        let mut params = vec![0u8; plen as usize];
+        read_bytes(&mut r, &mut params)?;
+        Ok((LoopbackCommand { opcode, params: params.into_boxed_slice() }, opcode_size + plen_size + plen as usize))
+    }
+}
+
+impl Socket {
+    /// Read the loopback mode of the controller.
+    pub fn read_loopback_mode(&mut self, timeout: c_int) -> Result<LoopbackMode> {
+        let data = self.execute(OGF_TESTING_CMD, OCF_READ_LOOPBACK_MODE, &[], timeout)?;
+        let (mode, _) = (&*data).read_as::<u8>()?;
+        LoopbackMode::try_from(mode)
+    }
+
+    /// Write the loopback mode of the controller.
+    pub fn write_loopback_mode(&mut self, mode: LoopbackMode, timeout: c_int) -> Result<()> {
+        self.execute(OGF_TESTING_CMD, OCF_WRITE_LOOPBACK_MODE, &[mode as u8], timeout)
+            .map(|_| ())
+    }
+
+    /// Send a command in local loopback mode and wait for the controller to return it in a
+    /// Loopback Command event.
+    pub fn loopback_command(&mut self, ogf: u16, ocf: u16, param: &[u8], timeout: c_int) -> Result<LoopbackCommand> {
+        let data = self.send_req(ogf, ocf, EVT_LOOPBACK_COMMAND as c_int, param, timeout)?;
+        let (command, _) = (&*data).read_as::<LoopbackCommand>()?;
+        Ok(command)
+    }
+}
@@ -0,0 +1,104 @@
+//! Structured logging of HCI traffic through `tracing`.
+//!
+//! Every function here compiles to nothing without the `tracing` feature, so call sites need
+//! no configuration of their own.
+
+#[cfg(feature = "tracing")]
+use super::codec::{event_name, le_event_name, opcode_name};
+use super::codec::EventBody;
+use super::filter::HciFilter;
+
+#[cfg(feature = "tracing")]
+const EVT_LE_META_EVENT: u8 = 0x3E;
+
+#[cfg(feature = "tracing")]
+fn command_name(opcode: u16) -> &'static str {
+    opcode_name(opcode).unwrap_or("Unknown")
+}
+
+/// Open a span covering a command and the wait for its response.
+#[cfg(feature = "tracing")]
+pub(crate) fn request_span(opcode: u16) -> tracing::Span {
+    tracing::debug_span!("hci_request",
+        opcode = %format_args!("{:#06x}", opcode),
+        command = command_name(opcode))
+}
+
+/// Log a command sent to the controller.
+#[inline]
+pub(crate) fn command(opcode: u16, params: &[u8]) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        opcode = %format_args!("{:#06x}", opcode),
+        command = command_name(opcode),
+        len = params.len(),
+        "Sent command");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (opcode, params);
+}
+
+/// Log an event received from the controller.
+#[inline]
+pub(crate) fn event(event: &EventBody) {
+    #[cfg(feature = "tracing")]
+    match *event {
+        EventBody::CommandComplete { ncmd, opcode, params } => tracing::debug!(
+            opcode = %format_args!("{:#06x}", opcode),
+            command = command_name(opcode),
+            status = params.first().copied(),
+            ncmd,
+            "Received Command Complete"),
+        EventBody::CommandStatus { status, ncmd, opcode } => tracing::debug!(
+            opcode = %format_args!("{:#06x}", opcode),
+            command = command_name(opcode),
+            status,
+            ncmd,
+            "Received Command Status"),
+        EventBody::Other { code: EVT_LE_META_EVENT, params } => {
+            let subevent = params.first().copied();
+            tracing::debug!(
+                code = %format_args!("{:#04x}", EVT_LE_META_EVENT),
+                subevent = subevent.and_then(le_event_name).unwrap_or("Unknown"),
+                len = params.len(),
+                "Received event")
+        }
+        EventBody::Other { code, params } => tracing::debug!(
+            code = %format_args!("{:#04x}", code),
+            event = event_name(code).unwrap_or("Unknown"),
+            len = params.len(),
+            "Received event"),
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = event;
+}
+
+/// Log a change of the socket filter.
+#[inline]
+pub(crate) fn filter(filter: &HciFilter) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        type_mask = %format_args!("{:#010x}", filter.get_type_mask()),
+        event_mask = %format_args!("{:#018x}", filter.get_event_mask()),
+        opcode = %format_args!("{:#06x}", filter.get_opcode()),
+        "Set socket filter");
+    #[cfg(not(feature = "tracing"))]
+    let _ = filter;
+}
+
+/// Log a failure to restore the socket filter after a result was already received.
+#[inline]
+pub(crate) fn filter_restore_failed(error: &std::io::Error) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(%error, "Could not restore the socket filter");
+    #[cfg(not(feature = "tracing"))]
+    let _ = error;
+}
+
+/// Log a timeout while waiting for the controller.
+#[inline]
+pub(crate) fn timeout(timeout_ms: i32) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(timeout_ms, "Timed out waiting for the controller");
+    #[cfg(not(feature = "tracing"))]
+    let _ = timeout_ms;
+}
@@ -0,0 +1,47 @@
+use std::io::{Error, Read, Result};
+#[cfg(feature = "le")]
+use std::io::Write;
+use std::io::ErrorKind::{InvalidInput};
+use std::time::Duration;
+
+use super::io::read_bytes;
+
+/// Length of a baseband slot in microseconds
+pub(crate) const SLOT_US: u64 = 625;
+
+/// Convert a duration to a whole number of `unit_us` microsecond units that fits in a u16.
+pub(crate) fn to_units(duration: Duration, unit_us: u64) -> Result<u16> {
+    let units = duration.as_micros() / unit_us as u128;
+    units.try_into()
+        .map_err(|_| Error::new(InvalidInput, "Duration out of range"))
+}
+
+/// Convert a duration to a whole number of `unit_us` microsecond units that fits in a u32.
+#[cfg(feature = "bredr")]
+pub(crate) fn to_units_u32(duration: Duration, unit_us: u64) -> Result<u32> {
+    let units = duration.as_micros() / unit_us as u128;
+    units.try_into()
+        .map_err(|_| Error::new(InvalidInput, "Duration out of range"))
+}
+
+/// Convert a number of `unit_us` microsecond units to a duration.
+pub(crate) fn from_units(units: u32, unit_us: u64) -> Duration {
+    Duration::from_micros(units as u64 * unit_us)
+}
+
+/// Write a 24-bit interval in units of `unit_us` microseconds.
+#[cfg(feature = "le")]
+pub(crate) fn write_interval_u24<W: Write>(w: &mut W, interval: Duration, unit_us: u64) -> Result<usize> {
+    let units = interval.as_micros() / unit_us as u128;
+    if units > 0xFF_FFFF {
+        return Err(Error::new(InvalidInput, "Duration out of range"));
+    }
+    w.write(&(units as u32).to_le_bytes()[..3])
+}
+
+/// Read a 24-bit interval in units of `unit_us` microseconds.
+pub(crate) fn read_interval_u24<R: Read>(r: &mut R, unit_us: u64) -> Result<Duration> {
+    let mut bytes = [0u8; 4];
+    read_bytes(r, &mut bytes[..3])?;
+    Ok(from_units(u32::from_le_bytes(bytes), unit_us))
+}
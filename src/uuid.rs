@@ -0,0 +1,262 @@
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{Error, Result, Write};
+use std::io::ErrorKind::{InvalidData, InvalidInput};
+use std::str::FromStr;
+
+use super::io::WriteTo;
+
+/// Mask of the bits a 16- or 32-bit UUID replaces in the base UUID
+const SHORT_MASK: u128 = 0xFFFFFFFF << 96;
+
+/// A Bluetooth UUID.
+///
+/// 16- and 32-bit UUIDs are aliases for 128-bit UUIDs made by placing them in the top bits of
+/// the Bluetooth base UUID. The UUID is stored in its 128-bit form, so the same UUID compares
+/// equal whichever form it was read in.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Uuid(u128);
+
+impl Uuid {
+    /// The Bluetooth base UUID, 00000000-0000-1000-8000-00805F9B34FB
+    pub const BASE: Uuid = Uuid(0x00000000_0000_1000_8000_00805F9B34FB);
+
+    /// Create a UUID from its 16-bit form.
+    pub const fn from_u16(uuid: u16) -> Self {
+        Uuid::from_u32(uuid as u32)
+    }
+
+    /// Create a UUID from its 32-bit form.
+    pub const fn from_u32(uuid: u32) -> Self {
+        Uuid(Uuid::BASE.0 | (uuid as u128) << 96)
+    }
+
+    /// Create a UUID from its 128-bit value
+    pub const fn from_u128(uuid: u128) -> Self {
+        Uuid(uuid)
+    }
+
+    /// Return the 128-bit value
+    pub const fn as_u128(&self) -> u128 {
+        self.0
+    }
+
+    /// Return the 32-bit form, if the UUID is derived from the base UUID
+    pub const fn as_u32(&self) -> Option<u32> {
+        if self.0 & !SHORT_MASK == Uuid::BASE.0 {
+            Some((self.0 >> 96) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Return the 16-bit form, if the UUID is derived from the base UUID and fits
+    pub const fn as_u16(&self) -> Option<u16> {
+        match self.as_u32() {
+            Some(uuid) if uuid <= u16::MAX as u32 => Some(uuid as u16),
+            _ => None,
+        }
+    }
+
+    /// Return the length in bytes of the shortest form of the UUID: 2, 4 or 16
+    pub const fn encoded_len(&self) -> usize {
+        if self.as_u16().is_some() {
+            2
+        } else if self.as_u32().is_some() {
+            4
+        } else {
+            16
+        }
+    }
+
+    /// Read a UUID in little-endian order from 2, 4 or 16 bytes.
+    pub fn from_le_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.len() {
+            2 => Ok(Uuid::from_u16(u16::from_le_bytes(bytes.try_into().unwrap()))),
+            4 => Ok(Uuid::from_u32(u32::from_le_bytes(bytes.try_into().unwrap()))),
+            16 => Ok(Uuid::from_u128(u128::from_le_bytes(bytes.try_into().unwrap()))),
+            _ => Err(Error::new(InvalidData, "Invalid UUID length")),
+        }
+    }
+
+    /// Return the shortest form of the UUID in little-endian order
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        match (self.as_u16(), self.as_u32()) {
+            (Some(uuid), _) => uuid.to_le_bytes().to_vec(),
+            (None, Some(uuid)) => uuid.to_le_bytes().to_vec(),
+            (None, None) => self.0.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+impl From<u16> for Uuid {
+    fn from(uuid: u16) -> Self {
+        Uuid::from_u16(uuid)
+    }
+}
+
+impl From<u32> for Uuid {
+    fn from(uuid: u32) -> Self {
+        Uuid::from_u32(uuid)
+    }
+}
+
+impl From<u128> for Uuid {
+    fn from(uuid: u128) -> Self {
+        Uuid::from_u128(uuid)
+    }
+}
+
+impl From<Uuid> for u128 {
+    fn from(uuid: Uuid) -> Self {
+        uuid.0
+    }
+}
+
+/// Write the shortest form of the UUID.
+impl WriteTo for Uuid {
+    fn write_to<W: Write>(self, w: &mut W) -> Result<usize> {
+        let bytes = self.to_le_bytes();
+        w.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl Display for Uuid {
+    /// Format the 128-bit form, like "0000180f-0000-1000-8000-00805f9b34fb".
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let u = self.0;
+        write!(f, "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            u >> 96,
+            (u >> 80) & 0xFFFF,
+            (u >> 64) & 0xFFFF,
+            (u >> 48) & 0xFFFF,
+            u & 0xFFFF_FFFF_FFFF)
+    }
+}
+
+impl Debug for Uuid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.as_u32() {
+            Some(uuid) if uuid <= u16::MAX as u32 => write!(f, "Uuid(0x{:04X})", uuid),
+            Some(uuid) => write!(f, "Uuid(0x{:08X})", uuid),
+            None => write!(f, "Uuid({})", self),
+        }
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = Error;
+
+    /// Parse a UUID written as 4 or 8 hexadecimal digits, like "180F", or in the hyphenated
+    /// 128-bit form.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::new(InvalidInput, "Invalid UUID");
+        // Reject signs, which from_str_radix accepts.
+        let parse = |digits: &str| if digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            u128::from_str_radix(digits, 16).map_err(|_| invalid())
+        } else {
+            Err(invalid())
+        };
+
+        match s.len() {
+            4 | 8 => Ok(Uuid::from_u32(parse(s)? as u32)),
+            36 => {
+                let groups: Vec<&str> = s.split('-').collect();
+                let lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+                if lengths != [8, 4, 4, 4, 12] {
+                    return Err(invalid());
+                }
+                Ok(Uuid(parse(&groups.concat())?))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 128-bit UUID not derived from the base UUID
+    const CUSTOM: u128 = 0x6E400001_B5A3_F393_E0A9_E50E24DCCA9E;
+
+    #[test]
+    fn short_forms_round_trip() {
+        let uuid = Uuid::from_le_bytes(&[0x0F, 0x18]).unwrap();
+        assert_eq!(uuid, Uuid::from_u16(0x180F));
+        assert_eq!(uuid.as_u128(), 0x0000180F_0000_1000_8000_00805F9B34FB);
+        assert_eq!(uuid.encoded_len(), 2);
+        assert_eq!(uuid.to_le_bytes(), [0x0F, 0x18]);
+
+        let uuid = Uuid::from_le_bytes(&[0x78, 0x56, 0x34, 0x12]).unwrap();
+        assert_eq!(uuid, Uuid::from_u32(0x12345678));
+        assert_eq!(uuid.encoded_len(), 4);
+        assert_eq!(uuid.to_le_bytes(), [0x78, 0x56, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn long_form_round_trips() {
+        let bytes = CUSTOM.to_le_bytes();
+        let uuid = Uuid::from_le_bytes(&bytes).unwrap();
+        assert_eq!(uuid, Uuid::from_u128(CUSTOM));
+        assert_eq!(uuid.encoded_len(), 16);
+        assert_eq!(uuid.to_le_bytes(), bytes);
+    }
+
+    #[test]
+    fn long_forms_of_short_uuids_are_shortened() {
+        let uuid = Uuid::from_le_bytes(&Uuid::from_u16(0x180F).as_u128().to_le_bytes()).unwrap();
+        assert_eq!(uuid.to_le_bytes(), [0x0F, 0x18]);
+    }
+
+    #[test]
+    fn other_lengths_are_rejected() {
+        assert!(Uuid::from_le_bytes(&[]).is_err());
+        assert!(Uuid::from_le_bytes(&[0x0F, 0x18, 0x00]).is_err());
+    }
+
+    #[test]
+    fn uuids_off_the_base_have_no_short_form() {
+        assert_eq!(Uuid::from_u128(CUSTOM).as_u32(), None);
+        assert_eq!(Uuid::from_u128(CUSTOM).as_u16(), None);
+        // Only the top 32 bits may differ from the base UUID.
+        assert_eq!(Uuid::from_u128(Uuid::BASE.as_u128() ^ 1).as_u32(), None);
+
+        let uuid = Uuid::from_u32(0x00010000);
+        assert_eq!(uuid.as_u32(), Some(0x00010000));
+        assert_eq!(uuid.as_u16(), None);
+    }
+
+    #[test]
+    fn hyphenated_form_round_trips() {
+        let uuid = Uuid::from_u16(0x180F);
+        assert_eq!(uuid.to_string(), "0000180f-0000-1000-8000-00805f9b34fb");
+        assert_eq!("0000180F-0000-1000-8000-00805F9B34FB".parse::<Uuid>().unwrap(), uuid);
+
+        let uuid = Uuid::from_u128(CUSTOM);
+        assert_eq!(uuid.to_string(), "6e400001-b5a3-f393-e0a9-e50e24dcca9e");
+        assert_eq!(uuid.to_string().parse::<Uuid>().unwrap(), uuid);
+    }
+
+    #[test]
+    fn short_strings_parse() {
+        assert_eq!("180F".parse::<Uuid>().unwrap(), Uuid::from_u16(0x180F));
+        assert_eq!("12345678".parse::<Uuid>().unwrap(), Uuid::from_u32(0x12345678));
+    }
+
+    #[test]
+    fn signs_are_rejected() {
+        assert!("+180F".parse::<Uuid>().is_err());
+        assert!("+1234567".parse::<Uuid>().is_err());
+        assert!("+0000180-0000-1000-8000-00805f9b34fb".parse::<Uuid>().is_err());
+    }
+
+    #[test]
+    fn wrong_group_lengths_are_rejected() {
+        assert!("0000180f0-000-1000-8000-00805f9b34fb".parse::<Uuid>().is_err());
+        assert!("0000180f-0000-1000-80000-0805f9b34fb".parse::<Uuid>().is_err());
+        assert!("0000180f-0000-1000-8000-00805f9b34f".parse::<Uuid>().is_err());
+        assert!("0000180f-0000-1000-8000-00805f9b34fb0".parse::<Uuid>().is_err());
+        assert!("0000180f00000-1000-8000-00805f9b34fb".parse::<Uuid>().is_err());
+    }
+}
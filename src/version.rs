@@ -0,0 +1,130 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, Hasher};
+
+/// Declare an enum of Core Specification versions, ordered by their numeric code.
+macro_rules! version_enum {
+    ($(#[$meta:meta])* $name:ident) => {
+        code_enum!(
+            @derive(Copy, Clone, Debug)
+            $(#[$meta])*
+            $name {
+                V1_0b = 0x00,
+                V1_1 = 0x01,
+                V1_2 = 0x02,
+                V2_0 = 0x03,
+                V2_1 = 0x04,
+                V3_0 = 0x05,
+                V4_0 = 0x06,
+                V4_1 = 0x07,
+                V4_2 = 0x08,
+                V5_0 = 0x09,
+                V5_1 = 0x0A,
+                V5_2 = 0x0B,
+                V5_3 = 0x0C,
+                V5_4 = 0x0D,
+                V6_0 = 0x0E,
+                V6_1 = 0x0F,
+            }
+        );
+
+        /// Versions are equal if their codes are, so `Other` with the code of a known version
+        /// equals that version.
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                u8::from(*self) == u8::from(*other)
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                u8::from(*self).hash(state);
+            }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        /// Versions are ordered by their code, so unknown versions newer than the known ones
+        /// compare greater.
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                u8::from(*self).cmp(&u8::from(*other))
+            }
+        }
+
+        impl Display for $name {
+            /// Format the release, like "5.3", or the code of an unknown version.
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                match release(u8::from(*self)) {
+                    Some(release) => f.write_str(release),
+                    None => write!(f, "0x{:02X}", u8::from(*self)),
+                }
+            }
+        }
+    };
+}
+
+/// Return the Core Specification release with the given version code
+fn release(code: u8) -> Option<&'static str> {
+    Some(match code {
+        0x00 => "1.0b",
+        0x01 => "1.1",
+        0x02 => "1.2",
+        0x03 => "2.0",
+        0x04 => "2.1",
+        0x05 => "3.0",
+        0x06 => "4.0",
+        0x07 => "4.1",
+        0x08 => "4.2",
+        0x09 => "5.0",
+        0x0A => "5.1",
+        0x0B => "5.2",
+        0x0C => "5.3",
+        0x0D => "5.4",
+        0x0E => "6.0",
+        0x0F => "6.1",
+        _ => return None,
+    })
+}
+
+version_enum!(
+    /// Version of the Core Specification the HCI of a controller implements
+    HciVersion
+);
+
+version_enum!(
+    /// Version of the Core Specification the link manager or link layer of a controller
+    /// implements
+    LmpVersion
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(version: HciVersion) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        version.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn other_with_a_known_code_equals_the_version() {
+        assert_eq!(HciVersion::Other(0x09), HciVersion::V5_0);
+        assert_eq!(HciVersion::Other(0x09).cmp(&HciVersion::V5_0), Ordering::Equal);
+        assert_eq!(hash(HciVersion::Other(0x09)), hash(HciVersion::V5_0));
+    }
+
+    #[test]
+    fn unknown_versions_compare_greater() {
+        assert_ne!(HciVersion::Other(0x20), HciVersion::V6_1);
+        assert!(HciVersion::Other(0x20) > HciVersion::V6_1);
+    }
+}